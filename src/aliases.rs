@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::store::xattr_store;
+use crate::weight::split_weight;
+use crate::{Result, Searcher, Store, XTagError, XTags};
+
+/// Table of tag synonyms and their canonical tag.
+///
+/// Different tools and people use different spellings for the same thing, like `b&w`, `bw` and
+/// `blackandwhite`. Searches compiled with SearchOptions::tag_aliases see tags in canonical form,
+/// so searching `bw` finds files tagged with any synonym. Lookups are exact and case-sensitive.
+///
+/// # Example
+///
+/// ```
+/// let aliases = xtag::AliasMap::from_csv("bw,b&w,blackandwhite\n").unwrap();
+/// assert_eq!(aliases.canonical("blackandwhite"), "bw");
+/// assert_eq!(aliases.canonical("color"), "color");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AliasMap {
+    canonical: HashMap<String, String>,
+}
+
+impl AliasMap {
+    /// Returns new empty AliasMap.
+    pub fn new() -> Self {
+        AliasMap::default()
+    }
+
+    /// Adds alias of canonical tag.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Aliases if alias already has another canonical tag
+    pub fn insert(&mut self, alias: &str, canonical: &str) -> Result<()> {
+        if alias == canonical {
+            return Ok(());
+        }
+        match self.canonical.get(alias) {
+            Some(existing) if existing != canonical => Err(XTagError::Aliases(format!(
+                "{alias:?} is alias of {existing:?} and {canonical:?}"
+            ))),
+            _ => {
+                self.canonical
+                    .insert(alias.to_string(), canonical.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Canonical form of tag, tag itself if it is no alias.
+    pub fn canonical<'a>(&'a self, tag: &'a str) -> &'a str {
+        self.canonical.get(tag).map_or(tag, String::as_str)
+    }
+
+    /// Iterates over all aliases and their canonical tags.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.canonical
+            .iter()
+            .map(|(alias, canonical)| (alias.as_str(), canonical.as_str()))
+    }
+
+    /// Number of aliases.
+    pub fn len(&self) -> usize {
+        self.canonical.len()
+    }
+
+    /// Returns true if there are no aliases.
+    pub fn is_empty(&self) -> bool {
+        self.canonical.is_empty()
+    }
+
+    /// Returns tags with all aliases replaced by their canonical tags.
+    ///
+    /// Weights stay with their tags. If a file has both, the value of the canonical tag is kept.
+    pub fn canonicalize(&self, tags: &XTags) -> XTags {
+        let mut result = XTags::with_capacity(tags.len());
+        let mut aliased = Vec::new();
+        for (tag, value) in tags {
+            let (plain, _) = split_weight(tag);
+            match self.canonical.get(plain) {
+                Some(canonical) => aliased.push((canonical.clone() + &tag[plain.len()..], value)),
+                None => {
+                    result.insert(tag.clone(), value.clone());
+                }
+            }
+        }
+        for (tag, value) in aliased {
+            result.entry(tag).or_insert_with(|| value.clone());
+        }
+        result
+    }
+
+    /// Parses lines of comma separated tags, the canonical tag first and its aliases after it.
+    ///
+    /// Fields may be quoted with `"`, doubled quotes inside stand for one. Empty lines and lines
+    /// starting with `#` are skipped.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Aliases if a line is invalid or an alias has several canonical tags
+    pub fn from_csv(csv: &str) -> Result<Self> {
+        let mut aliases = AliasMap::new();
+        for (index, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = split_csv(line).unwrap_or_default();
+            fields.retain(|field| !field.is_empty());
+            let Some((canonical, synonyms)) = fields.split_first() else {
+                return Err(XTagError::Aliases(format!("invalid line {}", index + 1)));
+            };
+            for alias in synonyms {
+                aliases.insert(alias, canonical)?;
+            }
+        }
+        Ok(aliases)
+    }
+
+    /// Parses TOML table of canonical tags and their aliases.
+    ///
+    /// Only the subset needed for alias tables is understood: keys, bare or quoted, assigned a
+    /// string or an array of strings on one line, like `bw = ["b&w", "blackandwhite"]`. Comments
+    /// and a `[aliases]` header are skipped.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Aliases if a line is invalid or an alias has several canonical tags
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        let mut aliases = AliasMap::new();
+        for (index, line) in toml.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line == "[aliases]" {
+                continue;
+            }
+            let (canonical, synonyms) = parse_toml_line(line)
+                .ok_or_else(|| XTagError::Aliases(format!("invalid line {}", index + 1)))?;
+            for alias in synonyms {
+                aliases.insert(&alias, &canonical)?;
+            }
+        }
+        Ok(aliases)
+    }
+
+    /// Reads alias table from file, TOML if its extension is `toml`, CSV otherwise.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if the file can't be read
+    /// - XTagError::Aliases if the content is invalid
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        match path.extension() {
+            Some(extension) if extension == "toml" => AliasMap::from_toml(&content),
+            _ => AliasMap::from_csv(&content),
+        }
+    }
+}
+
+// Fields of one CSV line, None if a quote isn't closed
+//
+// Unquoted fields are trimmed, quoted ones are kept as they are.
+pub(crate) fn split_csv(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    let mut closed = false;
+    let finish = |field: &mut String, closed: bool| match closed {
+        true => std::mem::take(field),
+        false => std::mem::take(field).trim().to_string(),
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => {
+                quoted = false;
+                closed = true;
+            }
+            '"' if !closed && field.trim().is_empty() => {
+                quoted = true;
+                field.clear();
+            }
+            ',' if !quoted => {
+                fields.push(finish(&mut field, closed));
+                closed = false;
+            }
+            c if closed && c.is_whitespace() => (),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(finish(&mut field, closed));
+    Some(fields)
+}
+
+// Key and strings of `key = "a"` or `key = ["a", "b"]`
+pub(crate) fn parse_toml_line(line: &str) -> Option<(String, Vec<String>)> {
+    let (key, rest) = match line.strip_prefix('"') {
+        Some(quoted) => {
+            let (key, rest) = parse_toml_string(quoted)?;
+            (key, rest.trim_start().strip_prefix('=')?)
+        }
+        None => {
+            let (key, rest) = line.split_once('=')?;
+            (key.trim().to_string(), rest)
+        }
+    };
+    let rest = rest.trim();
+    let mut values = Vec::new();
+    let rest = match rest.strip_prefix('[') {
+        Some(mut rest) => loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                break after;
+            }
+            let (value, after) = parse_toml_string(rest.strip_prefix('"')?)?;
+            values.push(value);
+            let after = after.trim_start();
+            rest = after.strip_prefix(',').unwrap_or(after);
+            if !after.starts_with([',', ']']) {
+                return None;
+            }
+        },
+        None => {
+            let (value, after) = parse_toml_string(rest.strip_prefix('"')?)?;
+            values.push(value);
+            after
+        }
+    };
+    let rest = rest.trim_start();
+    (!key.is_empty() && (rest.is_empty() || rest.starts_with('#'))).then_some((key, values))
+}
+
+// String up to the closing quote and the text after it
+fn parse_toml_string(string: &str) -> Option<(String, &str)> {
+    let mut result = String::new();
+    let mut chars = string.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((result, &string[index + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => result.push('\n'),
+                't' => result.push('\t'),
+                c @ ('"' | '\\') => result.push(c),
+                _ => return None,
+            },
+            c => result.push(c),
+        }
+    }
+    None
+}
+
+/// Get tags for file with aliases replaced by their canonical tags, see AliasMap::canonicalize.
+///
+/// # Errors
+///
+/// Same as get_tags
+pub fn get_tags_canonical(path: &Path, aliases: &AliasMap) -> Result<XTags> {
+    get_tags_canonical_with_store(path, aliases, xattr_store())
+}
+
+/// Get tags for file in store with aliases replaced, see get_tags_canonical.
+pub fn get_tags_canonical_with_store(
+    path: &Path,
+    aliases: &AliasMap,
+    store: &dyn Store,
+) -> Result<XTags> {
+    Ok(aliases.canonicalize(&store.get_tags(path)?))
+}
+
+/// Rewrites stored tags of file to canonical form, returns true if they changed.
+///
+/// # Errors
+///
+/// Same as get_tags and set_tags
+pub fn normalize_tags(path: &Path, aliases: &AliasMap) -> Result<bool> {
+    normalize_tags_with_store(path, aliases, xattr_store())
+}
+
+/// Rewrites tags of file in store to canonical form, see normalize_tags.
+pub fn normalize_tags_with_store(
+    path: &Path,
+    aliases: &AliasMap,
+    store: &dyn Store,
+) -> Result<bool> {
+    let tags = store.get_tags(path)?;
+    let canonical = aliases.canonicalize(&tags);
+    if canonical == tags {
+        return Ok(false);
+    }
+    if canonical.is_empty() {
+        store.delete_tags(path)?;
+    } else {
+        store.set_tags(path, &canonical)?;
+    }
+    Ok(true)
+}
+
+impl Searcher {
+    /// Returns new canonical Searcher.
+    ///
+    /// Tags are canonicalized with aliases before lhs is evaluated against them, like those of
+    /// compile_search_with with SearchOptions::tag_aliases.
+    pub fn new_canonical(lhs: Searcher, aliases: AliasMap) -> Searcher {
+        Searcher::Canonical {
+            lhs: Box::new(lhs),
+            aliases,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_tags, AliasMap};
+    use crate::{compile_search_with, csl_to_map, get_tags, Fixture, SearchOptions};
+
+    #[test]
+    fn alias_tables_are_parsed() {
+        let csv = "# canonical first\nbw, b&w ,\"black,white\"\n\nsepia,\"old \"\"brown\"\"\"\n";
+        let aliases = AliasMap::from_csv(csv).unwrap();
+        assert_eq!(aliases.len(), 3);
+        assert_eq!(aliases.canonical("black,white"), "bw");
+        assert_eq!(aliases.canonical("old \"brown\""), "sepia");
+
+        let toml = "[aliases]\nbw = [\"b&w\", \"black,white\"] # comment\n\"se pia\" = \"old\"\n";
+        let aliases = AliasMap::from_toml(toml).unwrap();
+        assert_eq!(aliases.canonical("b&w"), "bw");
+        assert_eq!(aliases.canonical("old"), "se pia");
+
+        assert!(AliasMap::from_csv("a,\"b").is_err());
+        assert!(AliasMap::from_csv("a,x\nb,x").is_err());
+        assert!(AliasMap::from_toml("a = [\"b\" \"c\"]").is_err());
+        assert!(AliasMap::from_toml("a = b").is_err());
+    }
+
+    #[test]
+    fn aliases_canonicalize_searches_and_files() {
+        let aliases = AliasMap::from_csv("bw,blackandwhite,mono\n").unwrap();
+        let options = SearchOptions {
+            tag_aliases: Some(aliases.clone()),
+            ..Default::default()
+        };
+        let search = compile_search_with("bw and not color", &options).unwrap();
+        assert!(search.is_match(&csl_to_map("blackandwhite").unwrap()));
+        assert!(search.is_match(&csl_to_map("mono#0.5").unwrap()));
+        assert!(!search.is_match(&csl_to_map("mono,color").unwrap()));
+
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("mono=1,bw=2,x")
+            .file("b")
+            .tags("x");
+        assert!(normalize_tags(&fixture.join("a"), &aliases).unwrap());
+        assert_eq!(
+            get_tags(&fixture.join("a")).unwrap(),
+            csl_to_map("bw=2,x").unwrap()
+        );
+        assert!(!normalize_tags(&fixture.join("b"), &aliases).unwrap());
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt::Display;
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::builder::{StringBuilder, TimestampNanosecondBuilder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use parquet::arrow::ArrowWriter;
+
+use crate::{Index, Result, XTagError};
+
+fn table_error(err: impl Display) -> XTagError {
+    XTagError::Arrow(err.to_string())
+}
+
+fn table_schema() -> Schema {
+    let timestamp = DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into()));
+    Schema::new(vec![
+        Field::new("path", DataType::Utf8, false),
+        Field::new("tag", DataType::Utf8, true),
+        Field::new("value", DataType::Utf8, true),
+        Field::new("ctime", timestamp, false),
+    ])
+}
+
+impl Index {
+    /// Returns index as table with one row per tag of every file.
+    ///
+    /// Columns are `path`, `tag`, `value` and `ctime`. Files without tags have one row with
+    /// null tag, tags without value a null value. ctime is the change time the index records,
+    /// which changes with the tags too. Rows are sorted by path and tag.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Arrow if the table can't be built
+    pub fn to_record_batch(&self) -> Result<RecordBatch> {
+        let mut paths = StringBuilder::new();
+        let mut tags = StringBuilder::new();
+        let mut values = StringBuilder::new();
+        let mut ctimes = TimestampNanosecondBuilder::new().with_timezone("UTC");
+        for (path, stamp, file_tags) in self.stamped() {
+            let path = path.to_string_lossy();
+            let ctime = stamp.ctime * 1_000_000_000 + stamp.ctime_nsec;
+            let mut sorted: Vec<_> = file_tags.iter().collect();
+            sorted.sort();
+            if sorted.is_empty() {
+                paths.append_value(&path);
+                tags.append_null();
+                values.append_null();
+                ctimes.append_value(ctime);
+            }
+            for (tag, value) in sorted {
+                paths.append_value(&path);
+                tags.append_value(tag);
+                values.append_option(value.as_deref());
+                ctimes.append_value(ctime);
+            }
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(paths.finish()),
+            Arc::new(tags.finish()),
+            Arc::new(values.finish()),
+            Arc::new(ctimes.finish()),
+        ];
+        RecordBatch::try_new(Arc::new(table_schema()), columns).map_err(table_error)
+    }
+
+    /// Writes index as Arrow IPC file, see to_record_batch.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// let index = xtag::Index::load(Path::new("photos")).unwrap();
+    /// let file = std::fs::File::create("tags.arrow").unwrap();
+    /// index.export_arrow(file).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Arrow if writing fails
+    pub fn export_arrow<W: Write>(&self, writer: W) -> Result<()> {
+        let batch = self.to_record_batch()?;
+        let mut writer = FileWriter::try_new(writer, &batch.schema()).map_err(table_error)?;
+        writer.write(&batch).map_err(table_error)?;
+        writer.finish().map_err(table_error)
+    }
+
+    /// Writes index as Parquet file, see to_record_batch.
+    ///
+    /// Parquet files can be queried directly, like `SELECT tag, count(*) FROM 'tags.parquet'
+    /// GROUP BY tag` in DuckDB.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Arrow if writing fails
+    pub fn export_parquet<W: Write + Send>(&self, writer: W) -> Result<()> {
+        let batch = self.to_record_batch()?;
+        let mut writer = ArrowWriter::try_new(writer, batch.schema(), None).map_err(table_error)?;
+        writer.write(&batch).map_err(table_error)?;
+        writer.close().map_err(table_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Cursor;
+
+    use arrow_array::cast::AsArray;
+    use arrow_array::Array;
+    use arrow_ipc::reader::FileReader;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use crate::{Fixture, Index, WalkOptions};
+
+    #[test]
+    fn index_is_exported_as_tables() {
+        let fixture = Fixture::new().file("a").tags("rating=5,photo").file("b");
+        let index = Index::build(fixture.path(), &WalkOptions::default()).unwrap();
+        let batch = index.to_record_batch().unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        let tags = batch.column_by_name("tag").unwrap().as_string::<i32>();
+        let values = batch.column_by_name("value").unwrap().as_string::<i32>();
+        assert_eq!(tags.value(0), "photo");
+        assert!(values.is_null(0));
+        assert_eq!(values.value(1), "5");
+        assert!(tags.is_null(2));
+
+        let mut arrow = Vec::new();
+        index.export_arrow(&mut arrow).unwrap();
+        let read: Vec<_> = FileReader::try_new(Cursor::new(arrow), None)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0], batch);
+
+        let path = fixture.join("tags.parquet");
+        index.export_parquet(File::create(&path).unwrap()).unwrap();
+        let read: Vec<_> = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].num_rows(), 3);
+        assert_eq!(read[0].column(1), batch.column(1));
+    }
+}
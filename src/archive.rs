@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use zip::result::ZipError;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::read_only::ensure_writable;
+use crate::{
+    export_tree, import_tree, walk, ImportPolicy, ImportReport, Result, TagDump, WalkOptions,
+    XTagError,
+};
+
+/// Entry of ZIP archives holding the tags of the other entries, as CSV of TagDump::to_csv.
+pub const ZIP_TAGS_NAME: &str = "META-INF/xtags.csv";
+
+fn archive_error(err: ZipError) -> XTagError {
+    match err {
+        ZipError::Io(err) => XTagError::File(err),
+        err => XTagError::Archive(err.to_string()),
+    }
+}
+
+// Name of path relative to root inside the archive, always with `/` between components
+fn member_name(root: &Path, path: &Path) -> Result<String> {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let parts: Option<Vec<&str>> = relative.iter().map(|part| part.to_str()).collect();
+    parts
+        .map(|parts| parts.join("/"))
+        .ok_or_else(|| XTagError::NonUnicodePath(path.to_path_buf()))
+}
+
+/// Packs all files below root into a ZIP archive written to writer, with their tags.
+///
+/// Files are deflated with their permissions, their tags go to the entry ZIP_TAGS_NAME, which
+/// other programs extract as ordinary file. Returns the packed tags.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::fs::File;
+/// # use std::path::Path;
+/// let file = File::create("project.zip").unwrap();
+/// let options = xtag::WalkOptions::default();
+/// let dump = xtag::pack_zip(Path::new("project"), file, &options).unwrap();
+/// println!("{} tagged files", dump.entries.len());
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::NonUnicodePath if a path isn't valid unicode
+/// - XTagError::Archive if writing the archive fails
+/// - Same as export_tree
+pub fn pack_zip<W: Write + Seek>(root: &Path, writer: W, options: &WalkOptions) -> Result<TagDump> {
+    let dump = export_tree(root, options)?;
+    let mut zip = ZipWriter::new(writer);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for path in walk(root, options) {
+        let path = path?;
+        let mode = fs::metadata(&path)?.permissions().mode();
+        zip.start_file(member_name(root, &path)?, deflated.unix_permissions(mode))
+            .map_err(archive_error)?;
+        io::copy(&mut File::open(&path)?, &mut zip)?;
+    }
+    zip.start_file(ZIP_TAGS_NAME, deflated)
+        .map_err(archive_error)?;
+    zip.write_all(dump.to_csv()?.as_bytes())?;
+    zip.finish().map_err(archive_error)?;
+    Ok(dump)
+}
+
+/// Tags stored in ZIP archive by pack_zip, empty for archives without them.
+///
+/// # Errors
+///
+/// - XTagError::Archive if reader is no valid archive
+/// - XTagError::Dump if the stored tags are invalid
+pub fn read_zip_tags<R: Read + Seek>(reader: R) -> Result<TagDump> {
+    let mut archive = ZipArchive::new(reader).map_err(archive_error)?;
+    read_tags_entry(&mut archive)
+}
+
+fn read_tags_entry<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<TagDump> {
+    let mut csv = String::new();
+    match archive.by_name(ZIP_TAGS_NAME) {
+        Ok(mut entry) => entry.read_to_string(&mut csv)?,
+        Err(ZipError::FileNotFound) => return Ok(TagDump::default()),
+        Err(err) => return Err(archive_error(err)),
+    };
+    TagDump::from_csv(&csv)
+}
+
+/// Extracts ZIP archive into dest and gives the files their tags stored by pack_zip.
+///
+/// Existing files are overwritten, their tags are combined with the stored ones by policy.
+/// Entries with paths leaving dest, like `../a`, are skipped, and so are tags of files not
+/// extracted. Archives without tags are only extracted.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::fs::File;
+/// # use std::path::Path;
+/// let file = File::open("project.zip").unwrap();
+/// let report = xtag::unpack_zip(file, Path::new("project"), xtag::ImportPolicy::Replace).unwrap();
+/// assert!(report.failed.is_empty());
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Archive if reader is no valid archive
+/// - XTagError::File if extracting fails
+/// - XTagError::Dump if the stored tags are invalid
+/// - XTagError::ReadOnly in read-only mode
+pub fn unpack_zip<R: Read + Seek>(
+    reader: R,
+    dest: &Path,
+    policy: ImportPolicy,
+) -> Result<ImportReport> {
+    ensure_writable(dest)?;
+    let mut archive = ZipArchive::new(reader).map_err(archive_error)?;
+    let mut extracted: HashSet<PathBuf> = HashSet::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(archive_error)?;
+        let Some(name) = entry.enclosed_name() else {
+            continue;
+        };
+        if entry.name() == ZIP_TAGS_NAME {
+            continue;
+        }
+        let path = dest.join(&name);
+        if entry.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(&path)?)?;
+        if let Some(mode) = entry.unix_mode() {
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+        }
+        extracted.insert(name);
+    }
+    let mut dump = read_tags_entry(&mut archive)?;
+    dump.entries.retain(|entry| extracted.contains(&entry.path));
+    Ok(import_tree(dest, &dump, policy))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+
+    use super::{pack_zip, read_zip_tags, unpack_zip};
+    use crate::{csl_to_map, get_tags, Fixture, ImportPolicy, WalkOptions};
+
+    #[test]
+    fn tags_survive_zip_archives() {
+        let fixture = Fixture::new()
+            .file("project/a")
+            .tags("photo,rating=4")
+            .file("project/sub/b")
+            .tags("draft")
+            .file("project/untagged");
+        fs::write(fixture.join("project/a"), "content").unwrap();
+        let mut zip = Cursor::new(Vec::new());
+        let dump = pack_zip(&fixture.join("project"), &mut zip, &WalkOptions::default()).unwrap();
+        assert_eq!(dump.entries.len(), 2);
+        zip.set_position(0);
+        assert_eq!(read_zip_tags(&mut zip).unwrap(), dump);
+
+        zip.set_position(0);
+        let dest = fixture.join("copy");
+        let report = unpack_zip(&mut zip, &dest, ImportPolicy::Replace).unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(fs::read_to_string(dest.join("a")).unwrap(), "content");
+        assert_eq!(
+            get_tags(&dest.join("a")).unwrap(),
+            csl_to_map("photo,rating=4").unwrap()
+        );
+        assert_eq!(
+            get_tags(&dest.join("sub/b")).unwrap(),
+            csl_to_map("draft").unwrap()
+        );
+        assert!(get_tags(&dest.join("untagged")).unwrap().is_empty());
+        assert!(!dest.join("META-INF").exists());
+    }
+}
@@ -0,0 +1,172 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tokio::task;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+
+use crate::reader::TagReader;
+use crate::walk::TreeTags;
+use crate::{
+    delete_tags, get_tags, set_tags, walk, Result, Searcher, WalkOptions, XTagError, XTags,
+};
+
+// Matches found ahead of the consumer of search_dir_async
+const SEARCH_BUFFER: usize = 64;
+
+// Runs f on the blocking thread pool of the runtime, panics of f are passed on
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    match task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(err) => match err.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            // Only happens while the runtime shuts down
+            Err(err) => Err(XTagError::File(io::Error::other(err))),
+        },
+    }
+}
+
+/// Like get_tags, without blocking the tokio runtime it is called from.
+///
+/// The attribute is read on the blocking thread pool of the runtime.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # async fn example() -> xtag::Result<()> {
+/// let tags = xtag::get_tags_async(Path::new("a.jpg")).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Same as get_tags
+pub async fn get_tags_async(path: &Path) -> Result<XTags> {
+    let path = path.to_path_buf();
+    blocking(move || get_tags(&path)).await
+}
+
+/// Like set_tags, without blocking the tokio runtime, see get_tags_async.
+///
+/// # Errors
+///
+/// Same as set_tags
+pub async fn set_tags_async(path: &Path, tags: &XTags) -> Result<()> {
+    let path = path.to_path_buf();
+    let tags = tags.clone();
+    blocking(move || set_tags(&path, &tags)).await
+}
+
+/// Like delete_tags, without blocking the tokio runtime, see get_tags_async.
+///
+/// # Errors
+///
+/// Same as delete_tags
+pub async fn delete_tags_async(path: &Path) -> Result<()> {
+    let path = path.to_path_buf();
+    blocking(move || delete_tags(&path)).await
+}
+
+/// Like search_dir, as stream of matching files for tokio.
+///
+/// The tree is walked on the blocking thread pool of the runtime, with attributes read like
+/// read_tags, so in batches through io_uring with the `uring` feature. Walking stays a few
+/// matches ahead of the consumer and stops when the stream is dropped. Has to be called from
+/// within a tokio runtime.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use std::sync::Arc;
+/// use tokio_stream::StreamExt;
+///
+/// # async fn example() -> xtag::Result<()> {
+/// let searcher = Arc::new(xtag::compile_search("photo").unwrap());
+/// let options = xtag::WalkOptions::default();
+/// let mut found = xtag::search_dir_async(Path::new("photos"), searcher, &options);
+/// while let Some(path) = found.next().await {
+///     println!("{}", path?.display());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn search_dir_async(
+    root: &Path,
+    searcher: Arc<Searcher>,
+    options: &WalkOptions,
+) -> impl Stream<Item = Result<PathBuf>> {
+    let (sender, receiver) = mpsc::channel(SEARCH_BUFFER);
+    let root = root.to_path_buf();
+    let options = options.clone();
+    task::spawn_blocking(move || {
+        // Errors of the walk are sent right away, sending fails once the stream is dropped
+        let paths = walk(&root, &options).filter_map(|path| match path {
+            Ok(path) => Some(path),
+            Err(err) => {
+                let _ = sender.blocking_send(Err(err));
+                None
+            }
+        });
+        let tree = TreeTags::new(&root, &options);
+        for (path, tags) in TagReader::new(paths, options.store.clone()) {
+            let found = match tree.recover(&path, tags) {
+                Ok(Some(tags)) if searcher.is_match(&tags) => Ok(path),
+                Ok(_) => continue,
+                Err(err) => Err(err),
+            };
+            if sender.blocking_send(found).is_err() {
+                return;
+            }
+        }
+    });
+    ReceiverStream::new(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::runtime::Builder;
+    use tokio_stream::StreamExt;
+
+    use super::{delete_tags_async, get_tags_async, search_dir_async, set_tags_async};
+    use crate::{compile_search, csl_to_map, Fixture, WalkOptions};
+
+    #[test]
+    fn tags_are_read_written_and_searched_async() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("photo")
+            .file("sub/b")
+            .tags("photo,year=2023")
+            .file("c");
+        let runtime = Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let path = fixture.join("c");
+            let tags = csl_to_map("photo,draft").unwrap();
+            set_tags_async(&path, &tags).await.unwrap();
+            assert_eq!(get_tags_async(&path).await.unwrap(), tags);
+
+            let searcher = Arc::new(compile_search("photo").unwrap());
+            let stream = search_dir_async(fixture.path(), searcher, &WalkOptions::default());
+            let mut found: Vec<_> = stream.map(Result::unwrap).collect().await;
+            found.sort();
+            assert_eq!(
+                found,
+                [fixture.join("a"), fixture.join("c"), fixture.join("sub/b")]
+            );
+
+            delete_tags_async(&path).await.unwrap();
+            assert!(get_tags_async(&path).await.unwrap().is_empty());
+        });
+    }
+}
@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::parse_tags::validate_tags;
+use crate::walk::TreeTags;
+use crate::{walk, Result, WalkOptions, XTagError, XTags};
+
+/// Rule deriving tags from file paths.
+///
+/// # Example
+///
+/// ```
+/// let rule = xtag::AutotagRule::new(r"(?P<year>\d{4})-\d{2}-\d{2}", "year=$year,dated").unwrap();
+/// let tags = rule.apply("holidays/2023-08-01 beach.jpg").unwrap().unwrap();
+/// assert_eq!(tags.get("year"), Some(&Some("2023".to_string())));
+/// assert_eq!(tags.get("dated"), Some(&None));
+/// ```
+#[derive(Debug, Clone)]
+pub struct AutotagRule {
+    regex: Regex,
+    template: Vec<(String, Option<String>)>,
+}
+
+impl AutotagRule {
+    /// Returns new AutotagRule.
+    ///
+    /// pattern is a regular expression searched in the path, it is not anchored. template is a
+    /// comma separated list of tag=value pairs. Tags and values may reference capture groups of
+    /// pattern like `$1` or `$name`.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if pattern is not a valid regular expression
+    pub fn new(pattern: &str, template: &str) -> Result<Self> {
+        let regex = Regex::new(pattern)?;
+        let template = template
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.split_once('=') {
+                Some((tag, value)) => (tag.trim().to_string(), Some(value.trim().to_string())),
+                None => (pair.to_string(), None),
+            })
+            .collect();
+        Ok(AutotagRule { regex, template })
+    }
+
+    /// Returns tags for path or None if pattern doesn't match.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::InvalidTags if the expanded tags can't be stored
+    pub fn apply(&self, path: &str) -> Result<Option<XTags>> {
+        let Some(captures) = self.regex.captures(path) else {
+            return Ok(None);
+        };
+        let mut tags = XTags::new();
+        for (tag, value) in &self.template {
+            let mut expanded_tag = String::new();
+            captures.expand(tag, &mut expanded_tag);
+            let expanded_value = value.as_ref().map(|value| {
+                let mut expanded = String::new();
+                captures.expand(value, &mut expanded);
+                expanded
+            });
+            tags.insert(expanded_tag, expanded_value);
+        }
+        validate_tags(&tags)?;
+        Ok(Some(tags))
+    }
+}
+
+/// Applies rules to all files below root.
+///
+/// Rules are matched against the path relative to root. Tags of all matching rules are added to
+/// the tags of the file, later rules win on conflicting values. Files are only written if their
+/// tags change.
+///
+/// Returns number of changed files.
+///
+/// # Errors
+///
+/// Stops at the first file that can't be read, tagged or written, unless options allow skipping
+/// it.
+pub fn autotag(root: &Path, rules: &[AutotagRule], options: &WalkOptions) -> Result<usize> {
+    let mut changed = 0;
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        let path = path?;
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let relative = relative
+            .to_str()
+            .ok_or_else(|| XTagError::NonUnicodePath(relative.to_path_buf()))?;
+
+        let mut new_tags = XTags::new();
+        for rule in rules {
+            if let Some(tags) = rule.apply(relative)? {
+                new_tags.extend(tags);
+            }
+        }
+        if new_tags.is_empty() {
+            continue;
+        }
+
+        if add_changed_tags(&tree, &path, new_tags)? {
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+// Adds tags to file of tree, writing only if something changes. Returns true if file was
+// written, false if it was skipped.
+pub(crate) fn add_changed_tags(tree: &TreeTags, path: &Path, new_tags: XTags) -> Result<bool> {
+    let Some(mut tags) = tree.get(path)? else {
+        return Ok(false);
+    };
+    let mut is_changed = false;
+    for (tag, value) in new_tags {
+        if tags.get(&tag) != Some(&value) {
+            tags.insert(tag, value);
+            is_changed = true;
+        }
+    }
+    Ok(is_changed && tree.set(path, &tags)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AutotagRule;
+
+    #[test]
+    fn autotag_expands_captures() {
+        let rule = AutotagRule::new(r"(?P<artist>\w+) - (\w+)\.mp3$", "artist=$artist,$2").unwrap();
+        let tags = rule.apply("music/abba - waterloo.mp3").unwrap().unwrap();
+        assert_eq!(tags.get("artist"), Some(&Some("abba".to_string())));
+        assert_eq!(tags.get("waterloo"), Some(&None));
+        assert!(rule.apply("music/cover.jpg").unwrap().is_none());
+    }
+
+    #[test]
+    fn autotag_rejects_unstorable_tags() {
+        let rule = AutotagRule::new(r"(.*)\.txt", "$1").unwrap();
+        assert!(rule.apply("a b,c.txt").is_err());
+        // Values are escaped when stored
+        let rule = AutotagRule::new(r"(.*)\.txt", "name=$1").unwrap();
+        let tags = rule.apply("a b,c.txt").unwrap().unwrap();
+        assert_eq!(tags.get("name"), Some(&Some("a b,c".to_string())));
+    }
+}
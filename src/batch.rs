@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+
+use crate::manifest::apply_operation;
+use crate::{
+    delete_tags, get_tags, set_tags, ManifestOperation, ManifestOptions, XTagError, XTags,
+};
+
+/// Tag changes of many files applied together.
+///
+/// Failing files don't stop the batch, their errors are collected in the BatchReport. With
+/// record_previous, the tags of every file are saved before it is changed, so a whole batch
+/// can be undone with BatchReport::rollback.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// use xtag::{Batch, ManifestOperation};
+///
+/// let tags = xtag::csl_to_map("archived").unwrap();
+/// let report = Batch::new()
+///     .record_previous(true)
+///     .add(Path::new("a.jpg"), ManifestOperation::Add(tags.clone()))
+///     .add(Path::new("b.jpg"), ManifestOperation::Add(tags))
+///     .commit();
+/// if !report.failed.is_empty() {
+///     report.rollback();
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct Batch {
+    changes: Vec<(PathBuf, ManifestOperation)>,
+    record: bool,
+}
+
+impl Batch {
+    /// Returns new empty Batch.
+    pub fn new() -> Self {
+        Batch::default()
+    }
+
+    /// Adds change of file, changes are applied in the order they were added.
+    pub fn add(mut self, path: &Path, operation: ManifestOperation) -> Self {
+        self.changes.push((path.to_path_buf(), operation));
+        self
+    }
+
+    /// Saves tags of files before changing them, needed for rollback.
+    pub fn record_previous(mut self, record: bool) -> Self {
+        self.record = record;
+        self
+    }
+
+    /// Number of changes.
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Returns true if there are no changes.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Applies all changes.
+    pub fn commit(self) -> BatchReport {
+        let mut report = BatchReport {
+            previous: self.record.then(Vec::new),
+            ..Default::default()
+        };
+        let options = ManifestOptions::default();
+        for (path, operation) in self.changes {
+            let previous = match self.record {
+                true => match get_tags(&path) {
+                    Ok(tags) => Some(tags),
+                    Err(error) => {
+                        report.failed.push(BatchFailure { path, error });
+                        continue;
+                    }
+                },
+                false => None,
+            };
+            match apply_operation(&path, &operation, &options) {
+                Ok(()) => {
+                    if let (Some(saved), Some(previous)) = (&mut report.previous, previous) {
+                        saved.push((path.clone(), previous));
+                    }
+                    report.succeeded.push(path);
+                }
+                Err(error) => report.failed.push(BatchFailure { path, error }),
+            }
+        }
+        report
+    }
+}
+
+/// Change that couldn't be applied.
+#[derive(Debug)]
+pub struct BatchFailure {
+    /// Path of the file.
+    pub path: PathBuf,
+
+    /// What went wrong.
+    pub error: XTagError,
+}
+
+/// Outcome of Batch::commit or BatchReport::rollback.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Files changed successfully, in order of the changes.
+    pub succeeded: Vec<PathBuf>,
+
+    /// Files that failed, in order of the changes.
+    pub failed: Vec<BatchFailure>,
+
+    previous: Option<Vec<(PathBuf, XTags)>>,
+}
+
+impl BatchReport {
+    /// Returns true if the batch recorded previous tags.
+    pub fn can_rollback(&self) -> bool {
+        self.previous.is_some()
+    }
+
+    /// Restores tags of all successfully changed files, the last change first.
+    ///
+    /// Does nothing if the batch didn't record previous tags. Files that fail to restore are
+    /// reported, the others are restored anyway.
+    pub fn rollback(&self) -> BatchReport {
+        let mut report = BatchReport::default();
+        for (path, tags) in self.previous.iter().flatten().rev() {
+            let result = match tags.is_empty() {
+                true => delete_tags(path),
+                false => set_tags(path, tags),
+            };
+            match result {
+                Ok(()) => report.succeeded.push(path.clone()),
+                Err(error) => report.failed.push(BatchFailure {
+                    path: path.clone(),
+                    error,
+                }),
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Batch;
+    use crate::{csl_to_map, get_tags, Fixture, ManifestOperation};
+
+    #[test]
+    fn failures_are_collected_and_batches_rolled_back() {
+        let fixture = Fixture::new().file("a").tags("photo").file("b").tags("x");
+        let tags = csl_to_map("archived").unwrap();
+        let add = || ManifestOperation::Add(tags.clone());
+
+        let report = Batch::new()
+            .record_previous(true)
+            .add(&fixture.join("a"), add())
+            .add(&fixture.join("missing"), add())
+            .add(&fixture.join("b"), ManifestOperation::Clear)
+            .commit();
+        assert_eq!(report.succeeded.len(), 2);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].path, fixture.join("missing"));
+        assert_eq!(get_tags(&fixture.join("a")).unwrap().len(), 2);
+        assert!(get_tags(&fixture.join("b")).unwrap().is_empty());
+
+        let rolled_back = report.rollback();
+        assert_eq!(rolled_back.succeeded.len(), 2);
+        assert_eq!(
+            get_tags(&fixture.join("a")).unwrap(),
+            csl_to_map("photo").unwrap()
+        );
+        assert_eq!(
+            get_tags(&fixture.join("b")).unwrap(),
+            csl_to_map("x").unwrap()
+        );
+
+        let report = Batch::new().add(&fixture.join("a"), add()).commit();
+        assert!(!report.can_rollback());
+        assert!(report.rollback().succeeded.is_empty());
+    }
+}
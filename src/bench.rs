@@ -0,0 +1,175 @@
+use std::time::{Duration, Instant};
+
+use crate::visit::Evaluator;
+use crate::{Searcher, SearcherVisitor, XTags};
+
+/// Evaluations of one node of a search, see bench_query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeProfile {
+    /// Node as displayed by Searcher, operators include their operands.
+    pub term: String,
+
+    /// Level below the root, 0 for the root.
+    pub depth: usize,
+
+    /// How often the node was evaluated, nodes skipped by short-circuiting count less.
+    pub evaluations: usize,
+
+    /// Evaluations that matched.
+    pub matches: usize,
+
+    /// Time of all evaluations including the operands, and the measuring itself.
+    pub time: Duration,
+}
+
+impl NodeProfile {
+    /// Share of evaluations that matched, 0 for nodes never evaluated.
+    pub fn match_rate(&self) -> f64 {
+        match self.evaluations {
+            0 => 0.0,
+            evaluations => self.matches as f64 / evaluations as f64,
+        }
+    }
+}
+
+/// Result of bench_query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryProfile {
+    /// Number of tag maps in the sample.
+    pub samples: usize,
+
+    /// Tag maps matching the search.
+    pub matches: usize,
+
+    /// Time of Searcher::is_match for the whole sample, without measuring the nodes.
+    pub time: Duration,
+
+    /// All nodes of the search in pre-order, the root first and operands after their operator.
+    pub nodes: Vec<NodeProfile>,
+}
+
+// Profiles of the nodes in pre-order, with the start times of the nodes being evaluated
+#[derive(Default)]
+struct Profiler {
+    nodes: Vec<NodeProfile>,
+    depth: usize,
+    starts: Vec<Instant>,
+}
+
+impl SearcherVisitor<'_> for Profiler {
+    fn enter(&mut self, searcher: &Searcher) -> bool {
+        self.nodes.push(NodeProfile {
+            term: searcher.to_string(),
+            depth: self.depth,
+            evaluations: 0,
+            matches: 0,
+            time: Duration::ZERO,
+        });
+        self.depth += 1;
+        true
+    }
+
+    fn leave(&mut self, _: &Searcher) {
+        self.depth -= 1;
+    }
+}
+
+// Short-circuits like Searcher::is_match
+impl Evaluator for Profiler {
+    fn enter(&mut self, _: usize, _: &Searcher) {
+        self.starts.push(Instant::now());
+    }
+
+    fn leave(&mut self, id: usize, _: &Searcher, matched: bool) {
+        let start = self.starts.pop().expect("node was entered");
+        let node = &mut self.nodes[id];
+        node.evaluations += 1;
+        node.matches += usize::from(matched);
+        node.time += start.elapsed();
+    }
+}
+
+/// Evaluates searcher on sample, counting and timing the evaluations of every node.
+///
+/// Compares formulations of the same search, like which operand of `and` to test first: the
+/// operand matching less often belongs first, as the other one is skipped then. Node times
+/// include the measuring overhead, QueryProfile::time is taken without it.
+///
+/// # Example
+///
+/// ```
+/// let sample: Vec<xtag::XTags> = ["photo", "photo,beach", "video"]
+///     .into_iter()
+///     .map(|tags| xtag::csl_to_map(tags).unwrap())
+///     .collect();
+/// let search = xtag::compile_search("photo and beach").unwrap();
+/// let profile = xtag::bench_query(&search, &sample);
+/// assert_eq!(profile.matches, 1);
+/// assert_eq!(profile.nodes[2].evaluations, 2);
+/// ```
+pub fn bench_query(searcher: &Searcher, sample: &[XTags]) -> QueryProfile {
+    let start = Instant::now();
+    let matches = sample
+        .iter()
+        .filter(|tags| searcher.is_match(*tags))
+        .count();
+    let time = start.elapsed();
+
+    let mut profiler = Profiler::default();
+    searcher.visit(&mut profiler);
+    for tags in sample {
+        searcher.evaluate_with(tags, &mut profiler);
+    }
+    QueryProfile {
+        samples: sample.len(),
+        matches,
+        time,
+        nodes: profiler.nodes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bench_query;
+    use crate::{compile_search, compile_search_with, csl_to_map, SearchOptions, Searcher, XTags};
+
+    #[test]
+    fn nodes_are_counted_with_short_circuits() {
+        let sample: Vec<XTags> = ["a,b", "a", "b", "c", "A,c"]
+            .into_iter()
+            .map(|tags| csl_to_map(tags).unwrap())
+            .collect();
+        let profile = bench_query(&compile_search("(a and b) or not c").unwrap(), &sample);
+        assert_eq!((profile.samples, profile.matches), (5, 3));
+        let counts: Vec<(usize, usize, usize)> = profile
+            .nodes
+            .iter()
+            .map(|node| (node.depth, node.evaluations, node.matches))
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                (0, 5, 3),
+                (1, 5, 1),
+                (2, 5, 2),
+                (2, 2, 1),
+                (1, 4, 2),
+                (2, 4, 2)
+            ]
+        );
+        assert_eq!(
+            profile.nodes[3].term,
+            Searcher::new_tag("b").unwrap().to_string()
+        );
+        assert_eq!(profile.nodes[2].match_rate(), 0.4);
+
+        let options = SearchOptions {
+            fold: true,
+            ..Default::default()
+        };
+        let folded = compile_search_with("a", &options).unwrap();
+        let profile = bench_query(&folded, &sample);
+        assert_eq!(profile.nodes.len(), 2);
+        assert_eq!(profile.nodes[1].matches, 3);
+    }
+}
@@ -1,18 +1,594 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::ffi::OsString;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
 
+use crate::aliases::parse_toml_line;
 use crate::compile_search;
 use crate::error::XTagError;
+use crate::read_only::ensure_writable;
+use crate::walk::TreeTags;
 use crate::Result;
-use crate::Searcher;
+use crate::{compile_search_with, walk, Index, ResultSet, SearchOptions, WalkOptions};
+use crate::{Searcher, SearcherVisitor};
+
+/// Extension of bookmark files in bookmark directories, `recent.bookmark` is bookmark `recent`.
+pub const BOOKMARK_EXTENSION: &str = "bookmark";
 
 /// Get bookmark from filesystem
 ///
-/// Bookmark is a symbolic link with the filter term as link
-pub fn get_bookmark(path: &PathBuf) -> Result<Searcher> {
-    let term = fs::read_link(path)?
+/// Bookmark is a symbolic link with the filter term as link, or a regular file. Files hold the
+/// term in their first line that is neither empty nor a comment starting with `#`, or are TOML
+/// like `query = "photo and year > 2020"` with an optional `description`.
+pub fn get_bookmark(path: &Path) -> Result<Searcher> {
+    compile_search(&read_bookmark(path)?)
+}
+
+/// Description of bookmark file, None for files without one and symbolic links.
+///
+/// # Errors
+///
+/// - XTagError::Bookmark if path is no valid bookmark
+pub fn get_bookmark_description(path: &Path) -> Result<Option<String>> {
+    if fs::symlink_metadata(path)?.is_symlink() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    let (_, description) = parse_bookmark_file(&content).ok_or_else(|| invalid(path))?;
+    Ok(description)
+}
+
+/// Stores searcher as bookmark file in TOML format, replacing path if it exists.
+///
+/// The term is written as shown by Display, with referenced bookmarks expanded. Searchers
+/// compiled with folding or aliases can't be written, the term doesn't know those options.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let search = xtag::compile_search("photo and year > 2020").unwrap();
+/// xtag::save_bookmark(Path::new("recent.bookmark"), &search).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Unrepresentable if searcher uses folding or aliases, or its term doesn't
+///   compile to the same search
+/// - XTagError::File if the file can't be written
+/// - XTagError::ReadOnly in read-only mode
+pub fn save_bookmark(path: &Path, searcher: &Searcher) -> Result<()> {
+    ensure_writable(path)?;
+    let mut options = OptionsUsed::default();
+    searcher.visit(&mut options);
+    if let Some(option) = options.0 {
+        return Err(XTagError::Unrepresentable(format!(
+            "{option} of {searcher}"
+        )));
+    }
+    let term = searcher.to_string();
+    if compile_search(&term).map(|saved| saved.to_string()).ok() != Some(term.clone()) {
+        return Err(XTagError::Unrepresentable(term));
+    }
+    replace_with_file(path, &format!("query = {}\n", toml_string(&term)))
+}
+
+// First option of a search that its term doesn't show
+#[derive(Default)]
+struct OptionsUsed(Option<&'static str>);
+
+impl SearcherVisitor<'_> for OptionsUsed {
+    fn enter(&mut self, searcher: &Searcher) -> bool {
+        match searcher {
+            Searcher::Folded { .. } => self.0 = Some("folding"),
+            Searcher::Canonical { .. } => self.0 = Some("aliases"),
+            _ => {}
+        }
+        self.0.is_none()
+    }
+}
+
+// Hidden sibling of path, renamed over path to replace it without a moment it's missing
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+// Replaces path by a symbolic link to term, path is unchanged if that fails
+fn replace_with_symlink(path: &Path, term: &str) -> Result<()> {
+    let temp = temp_path(path);
+    match fs::remove_file(&temp) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+        _ => {}
+    }
+    symlink(term, &temp)?;
+    fs::rename(&temp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })?;
+    Ok(())
+}
+
+// Replaces path by a file with content, path is unchanged if that fails
+fn replace_with_file(path: &Path, content: &str) -> Result<()> {
+    let temp = temp_path(path);
+    fs::write(&temp, content)?;
+    fs::rename(&temp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })?;
+    Ok(())
+}
+
+fn invalid(path: &Path) -> XTagError {
+    XTagError::Bookmark(path.as_os_str().to_os_string())
+}
+
+fn toml_string(string: &str) -> String {
+    let escaped = string
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{escaped}\"")
+}
+
+// Lines that are neither empty nor comments with their index
+fn content_lines(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+}
+
+// Query and description of bookmark file, TOML if its first line is a key with a string
+fn parse_bookmark_file(content: &str) -> Option<(String, Option<String>)> {
+    let mut lines = content_lines(content);
+    let (_, first) = lines.next()?;
+    if parse_toml_line(first).is_none() {
+        return Some((first.to_string(), None));
+    }
+    let mut fields = HashMap::new();
+    for (_, line) in content_lines(content) {
+        let (key, mut values) = parse_toml_line(line)?;
+        if values.len() != 1 {
+            return None;
+        }
+        fields.insert(key, values.pop()?);
+    }
+    Some((fields.remove("query")?, fields.remove("description")))
+}
+
+// Returns the filter term of bookmark
+pub(crate) fn read_bookmark(path: &Path) -> Result<String> {
+    if !fs::symlink_metadata(path)?.is_symlink() {
+        let content = fs::read_to_string(path)?;
+        let (query, _) = parse_bookmark_file(&content).ok_or_else(|| invalid(path))?;
+        return Ok(query);
+    }
+    fs::read_link(path)?
         .into_os_string()
         .into_string()
-        .map_err(|string| XTagError::Bookmark(string))?;
-    compile_search(&term)
+        .map_err(XTagError::Bookmark)
+}
+
+// Replaces the filter term of bookmark, other lines of bookmark files are kept
+pub(crate) fn write_bookmark(path: &Path, term: &str) -> Result<()> {
+    ensure_writable(path)?;
+    if fs::symlink_metadata(path)?.is_symlink() {
+        return replace_with_symlink(path, term);
+    }
+    let content = fs::read_to_string(path)?;
+    let is_toml = content_lines(&content)
+        .next()
+        .is_some_and(|(_, line)| parse_toml_line(line).is_some());
+    let replaced = content_lines(&content).find_map(|(index, line)| match is_toml {
+        true => parse_toml_line(line)
+            .filter(|(key, _)| key == "query")
+            .map(|_| index),
+        false => Some(index),
+    });
+    let replaced = replaced.ok_or_else(|| invalid(path))?;
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    lines[replaced] = match is_toml {
+        true => format!("query = {}", toml_string(term)),
+        false => term.to_string(),
+    };
+    replace_with_file(path, &(lines.join("\n") + "\n"))
+}
+
+// Terms of all bookmarks of dir, keyed by file name
+fn read_bookmark_terms(dir: &Path) -> Result<BTreeMap<String, String>> {
+    let mut terms = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = if entry.file_type()?.is_symlink() {
+            entry.file_name()
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == BOOKMARK_EXTENSION)
+        {
+            path.file_stem().unwrap_or_default().to_os_string()
+        } else {
+            continue;
+        };
+        let name = name.into_string().map_err(XTagError::Bookmark)?;
+        terms.insert(name, read_bookmark(&path)?);
+    }
+    Ok(terms)
+}
+
+// Compiles all bookmarks of dir, keyed by file name
+fn read_bookmarks(dir: &Path) -> Result<BTreeMap<String, Searcher>> {
+    let registry = BookmarkRegistry::load(&[dir])?;
+    registry
+        .names()
+        .map(|name| Ok((name.to_string(), registry.get(name)?)))
+        .collect()
+}
+
+/// Bookmarks by name, so search terms can reference them like `{recent}`.
+///
+/// Bookmarks may reference other bookmarks of the registry. References forming a cycle are
+/// reported as XTagError::BookmarkCycle. Set SearchOptions::bookmarks to resolve names in
+/// compile_search_with.
+///
+/// # Example
+///
+/// ```
+/// let mut registry = xtag::BookmarkRegistry::new();
+/// registry.insert("recent", "year > 2020");
+/// registry.insert("recent photos", "photo and {recent}");
+/// let search = registry.get("recent photos").unwrap();
+/// assert!(search.is_match(&xtag::csl_to_map("photo,year=2023").unwrap()));
+///
+/// registry.insert("recent", "{recent photos}");
+/// assert!(registry.get("recent").is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BookmarkRegistry {
+    terms: BTreeMap<String, String>,
+}
+
+impl BookmarkRegistry {
+    /// Returns empty registry.
+    pub fn new() -> Self {
+        BookmarkRegistry::default()
+    }
+
+    /// Directory of bookmarks of the user, `$XDG_CONFIG_HOME/xtag/bookmarks` or
+    /// `~/.config/xtag/bookmarks`.
+    pub fn user_dir() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".config")))?;
+        Some(config.join("xtag").join("bookmarks"))
+    }
+
+    /// Returns registry of the bookmarks in dirs, missing dirs are skipped.
+    ///
+    /// Of bookmarks with the same name in several dirs the one in the first dir wins.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if a dir can't be read
+    /// - XTagError::Bookmark if a bookmark has no valid name or term
+    pub fn load<P: AsRef<Path>>(dirs: &[P]) -> Result<Self> {
+        let mut registry = BookmarkRegistry::new();
+        for dir in dirs {
+            registry.add_dir(dir.as_ref())?;
+        }
+        Ok(registry)
+    }
+
+    /// Adds the bookmarks of dir, keeping bookmarks of the same name already present.
+    ///
+    /// # Errors
+    ///
+    /// Same as load
+    pub fn add_dir(&mut self, dir: &Path) -> Result<()> {
+        let terms = match read_bookmark_terms(dir) {
+            Err(XTagError::File(err)) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            terms => terms?,
+        };
+        for (name, term) in terms {
+            self.terms.entry(name).or_insert(term);
+        }
+        Ok(())
+    }
+
+    /// Adds or replaces bookmark name with term.
+    pub fn insert(&mut self, name: &str, term: &str) {
+        self.terms.insert(name.to_string(), term.to_string());
+    }
+
+    /// Term of bookmark name as written, None if there is no such bookmark.
+    pub fn term(&self, name: &str) -> Option<&str> {
+        self.terms.get(name).map(String::as_str)
+    }
+
+    /// Names of all bookmarks in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.terms.keys().map(String::as_str)
+    }
+
+    /// Compiles bookmark name with references to other bookmarks resolved.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Bookmark if there is no bookmark name
+    /// - XTagError::BookmarkCycle if bookmarks reference each other
+    /// - XTagError::Parser if a term is invalid
+    pub fn get(&self, name: &str) -> Result<Searcher> {
+        if self.term(name).is_none() {
+            return Err(XTagError::Bookmark(name.into()));
+        }
+        let options = SearchOptions {
+            bookmarks: self.clone(),
+            ..Default::default()
+        };
+        // Referenced like in terms, so cycles are reported starting at name
+        compile_search_with(&format!("{{{name}}}"), &options)
+    }
+}
+
+/// Evaluate all bookmarks in dir against the files below root
+///
+/// Files are walked and their tags read only once for all bookmarks. Returns the matching files
+/// per bookmark name. Bookmarks are symbolic links and files with extension BOOKMARK_EXTENSION
+/// named by their stem, other entries of dir are ignored.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let options = xtag::WalkOptions::default();
+/// let results = xtag::evaluate_bookmarks(Path::new("searches"), Path::new("photos"), &options);
+/// for (name, result) in results.unwrap() {
+///     println!("{name}: {}", result.len());
+/// }
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Bookmark or XTagError::Parser if a bookmark is invalid
+/// - Same as walk and get_tags, files without attribute support are handled by options
+pub fn evaluate_bookmarks(
+    dir: &Path,
+    root: &Path,
+    options: &WalkOptions,
+) -> Result<BTreeMap<String, ResultSet>> {
+    let bookmarks = read_bookmarks(dir)?;
+    let mut results: BTreeMap<String, ResultSet> = bookmarks
+        .keys()
+        .map(|name| (name.clone(), ResultSet::new()))
+        .collect();
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        let path = path?;
+        let Some(tags) = tree.get(&path)? else {
+            continue;
+        };
+        for (name, searcher) in &bookmarks {
+            if searcher.is_match(&tags) {
+                results.get_mut(name).unwrap().push(path.clone());
+            }
+        }
+    }
+    Ok(results)
+}
+
+impl Index {
+    /// Evaluate all bookmarks in dir against the index, like evaluate_bookmarks.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Bookmark or XTagError::Parser if a bookmark is invalid
+    pub fn evaluate_bookmarks(&self, dir: &Path) -> Result<BTreeMap<String, ResultSet>> {
+        Ok(read_bookmarks(dir)?
+            .into_iter()
+            .map(|(name, searcher)| (name, self.query(&searcher)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    use super::{
+        evaluate_bookmarks, get_bookmark, get_bookmark_description, save_bookmark, write_bookmark,
+        BookmarkRegistry,
+    };
+    use crate::{
+        compile_search, compile_search_with, csl_to_map, Fixture, Index, SearchOptions, Searcher,
+        SearcherVisitor, WalkOptions, XTagError,
+    };
+
+    #[test]
+    fn bookmarks_are_evaluated_in_one_pass() {
+        let fixture = Fixture::new()
+            .file("photos/a")
+            .tags("photo,year=2023")
+            .file("photos/b")
+            .tags("photo,year=2019")
+            .file("photos/c")
+            .tags("note");
+        let dir = fixture.join("searches");
+        fs::create_dir(&dir).unwrap();
+        symlink("photo", dir.join("all photos")).unwrap();
+        symlink("year > 2020", dir.join("recent")).unwrap();
+        symlink("video", dir.join("videos")).unwrap();
+        fs::write(dir.join("README"), "not a bookmark").unwrap();
+
+        let root = fixture.join("photos");
+        let options = WalkOptions::default();
+        let results = evaluate_bookmarks(&dir, &root, &options).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results["all photos"].len(), 2);
+        assert_eq!(results["recent"].paths(), &[root.join("a")]);
+        assert!(results["videos"].is_empty());
+
+        let index = Index::build(&root, &options).unwrap();
+        assert_eq!(index.evaluate_bookmarks(&dir).unwrap()["recent"].len(), 1);
+    }
+
+    #[test]
+    fn registries_resolve_names_and_detect_cycles() {
+        let fixture = Fixture::new();
+        let (user, system) = (fixture.join("user"), fixture.join("system"));
+        fs::create_dir(&user).unwrap();
+        fs::create_dir(&system).unwrap();
+        symlink("year > 2020", user.join("recent")).unwrap();
+        symlink("year > 2000", system.join("recent")).unwrap();
+        symlink("photo and {recent}", system.join("recent photos")).unwrap();
+
+        let registry = BookmarkRegistry::load(&[&user, &system, &fixture.join("missing")]).unwrap();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            ["recent", "recent photos"]
+        );
+        let search = registry.get("recent photos").unwrap();
+        assert!(search.is_match(&csl_to_map("photo,year=2023").unwrap()));
+        assert!(!search.is_match(&csl_to_map("photo,year=2019").unwrap()));
+        assert!(matches!(registry.get("old"), Err(XTagError::Bookmark(_))));
+
+        let mut registry = registry;
+        registry.insert("recent", "{recent photos} or new");
+        match registry.get("recent") {
+            Err(XTagError::BookmarkCycle(cycle)) => {
+                assert_eq!(cycle, ["recent", "recent photos", "recent"])
+            }
+            _ => panic!("cycle not detected"),
+        }
+
+        let own = fixture.join("own");
+        symlink(format!("a or {{{}}}", own.display()), &own).unwrap();
+        let result = compile_search(&format!("{{{}}}", own.display()));
+        assert!(matches!(result, Err(XTagError::BookmarkCycle(_))));
+    }
+
+    #[test]
+    fn expansions_keep_their_bookmark() {
+        let mut registry = BookmarkRegistry::new();
+        registry.insert("recent", "year > 2020");
+        registry.insert("recent photos", "photo and {recent}");
+        let options = SearchOptions {
+            bookmarks: registry,
+            ..Default::default()
+        };
+        let search = compile_search_with("{recent photos} and not draft", &options).unwrap();
+
+        #[derive(Default)]
+        struct Names(Vec<String>);
+        impl SearcherVisitor<'_> for Names {
+            fn enter(&mut self, searcher: &Searcher) -> bool {
+                if let Searcher::Bookmark { name, .. } = searcher {
+                    self.0.push(name.clone());
+                }
+                true
+            }
+        }
+        let mut names = Names::default();
+        search.visit(&mut names);
+        assert_eq!(names.0, ["recent photos", "recent"]);
+
+        let trace = search.explain(&csl_to_map("photo,year=2019").unwrap());
+        assert_eq!(
+            trace.to_string(),
+            "- and\n\
+             \x20 - {recent photos}\n\
+             \x20   - and\n\
+             \x20     + ^(?:photo)$: matched by photo\n\
+             \x20     - {recent}\n\
+             \x20       - ^(?:year)$ > 2020: value \"2019\" of year isn't > 2020\n\
+             \x20 + not\n\
+             \x20   - ^(?:draft)$: no tag matches\n"
+        );
+    }
+
+    #[test]
+    fn bookmark_files_hold_terms_with_comments() {
+        let fixture = Fixture::new();
+        let (plain, toml, saved) = (
+            fixture.join("plain.bookmark"),
+            fixture.join("toml.bookmark"),
+            fixture.join("saved.bookmark"),
+        );
+        fs::write(
+            &plain,
+            "# recent photos\n\n  photo and year > 2020\nignored\n",
+        )
+        .unwrap();
+        fs::write(
+            &toml,
+            "# shared\nquery = \"photo\"\ndescription = \"All photos\"\n",
+        )
+        .unwrap();
+        let tags = csl_to_map("photo,year=2023").unwrap();
+        assert!(get_bookmark(&plain).unwrap().is_match(&tags));
+        assert_eq!(get_bookmark_description(&plain).unwrap(), None);
+        assert!(get_bookmark(&toml).unwrap().is_match(&tags));
+        assert_eq!(
+            get_bookmark_description(&toml).unwrap().as_deref(),
+            Some("All photos")
+        );
+
+        let search = compile_search(r"title == a\d+ or year < 2000").unwrap();
+        save_bookmark(&saved, &search).unwrap();
+        assert_eq!(
+            get_bookmark(&saved).unwrap().to_string(),
+            search.to_string()
+        );
+        // Values the grammar has no characters for survive as escapes
+        let search = Searcher::new_and(
+            Searcher::new_equal("title", "a b, c=d").unwrap(),
+            Searcher::new_descendant(r"place|x\ #y", ':').unwrap(),
+        );
+        save_bookmark(&saved, &search).unwrap();
+        let loaded = get_bookmark(&saved).unwrap();
+        assert_eq!(loaded.to_string(), search.to_string());
+        let tags = csl_to_map(r"title=a b\, c\=d,place:sub").unwrap();
+        assert!(search.is_match(&tags) && loaded.is_match(&tags));
+        assert!(!loaded.is_match(&csl_to_map("title=a b,place:sub").unwrap()));
+        let options = SearchOptions {
+            fold: true,
+            ..Default::default()
+        };
+        let folded = compile_search_with("photo", &options).unwrap();
+        let result = save_bookmark(&saved, &folded);
+        assert!(matches!(result, Err(XTagError::Unrepresentable(_))));
+        assert_eq!(
+            get_bookmark(&saved).unwrap().to_string(),
+            search.to_string()
+        );
+
+        write_bookmark(&plain, "video").unwrap();
+        write_bookmark(&toml, "photo or video").unwrap();
+        assert_eq!(
+            fs::read_to_string(&plain).unwrap(),
+            "# recent photos\n\nvideo\nignored\n"
+        );
+        assert_eq!(
+            get_bookmark_description(&toml).unwrap().as_deref(),
+            Some("All photos")
+        );
+
+        let registry = BookmarkRegistry::load(&[fixture.path()]).unwrap();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            ["plain", "saved", "toml"]
+        );
+        assert_eq!(registry.term("toml"), Some("photo or video"));
+
+        fs::write(&plain, "# only comments\n").unwrap();
+        assert!(matches!(get_bookmark(&plain), Err(XTagError::Bookmark(_))));
+    }
 }
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::manifest::apply_operation;
+use crate::{ManifestOperation, ManifestOptions, Result, Store, XTagError};
+
+/// Options for bulk_apply.
+#[derive(Debug, Clone)]
+pub struct BulkOptions {
+    /// Maximum number of files written at the same time, at least 1.
+    pub concurrency: usize,
+
+    /// How often a file is tried again after a transient error.
+    pub retries: u32,
+
+    /// Wait before the first retry, doubled for every further retry.
+    pub backoff: Duration,
+
+    /// Expand placeholders like `{mtime}` in values per file, see interpolate_tags.
+    pub interpolate: bool,
+
+    /// Thread pool the files are written in, instead of concurrency threads of their own.
+    #[cfg(feature = "parallel")]
+    pub pool: Option<Arc<rayon::ThreadPool>>,
+
+    /// Backend of the written tags, None for the `user.xtag` attribute like set_tags.
+    pub store: Option<Arc<dyn Store + Send + Sync>>,
+}
+
+impl Default for BulkOptions {
+    fn default() -> Self {
+        BulkOptions {
+            concurrency: 4,
+            retries: 3,
+            backoff: Duration::from_millis(100),
+            interpolate: false,
+            #[cfg(feature = "parallel")]
+            pool: None,
+            store: None,
+        }
+    }
+}
+
+/// File that couldn't be written.
+#[derive(Debug)]
+pub struct BulkFailure {
+    /// Path of the file.
+    pub path: PathBuf,
+
+    /// Number of attempts made.
+    pub attempts: u32,
+
+    /// Error of the last attempt.
+    pub error: XTagError,
+}
+
+/// Outcome of bulk_apply.
+#[derive(Debug, Default)]
+pub struct BulkReport {
+    /// Files written successfully, in order of the changes.
+    pub succeeded: Vec<PathBuf>,
+
+    /// Files that failed, in order of the changes.
+    pub failed: Vec<BulkFailure>,
+
+    /// Number of retries over all files.
+    pub retries: usize,
+}
+
+// Errors that may go away by waiting
+fn is_transient(err: &XTagError) -> bool {
+    match err {
+        XTagError::File(err) => matches!(
+            err.kind(),
+            io::ErrorKind::Interrupted
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::StorageFull
+        ),
+        _ => false,
+    }
+}
+
+// Returns number of attempts and result of the last one
+fn apply_with_retry(
+    path: &Path,
+    operation: &ManifestOperation,
+    options: &BulkOptions,
+) -> (u32, Result<()>) {
+    let mut attempts = 0;
+    let mut backoff = options.backoff;
+    let manifest_options = ManifestOptions {
+        interpolate: options.interpolate,
+        store: options.store.clone(),
+        ..Default::default()
+    };
+    loop {
+        attempts += 1;
+        let result = apply_operation(path, operation, &manifest_options);
+        match result {
+            Err(err) if is_transient(&err) && attempts <= options.retries => {
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            result => return (attempts, result),
+        }
+    }
+}
+
+/// Applies tag changes to many files.
+///
+/// Files are written by up to options.concurrency threads, or in options.pool. Transient errors like interrupted
+/// calls or a full disk are retried with growing delays, all other errors fail the file at
+/// once. Failing files don't stop the others, the report tells which files were written.
+/// Several changes of the same path are applied one after the other in their order.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::PathBuf;
+/// let tags = xtag::csl_to_map("archived").unwrap();
+/// let changes = vec![
+///     (PathBuf::from("a.txt"), xtag::ManifestOperation::Add(tags.clone())),
+///     (PathBuf::from("b.txt"), xtag::ManifestOperation::Add(tags)),
+/// ];
+/// let report = xtag::bulk_apply(&changes, &xtag::BulkOptions::default());
+/// for failure in report.failed {
+///     eprintln!("{}: {}", failure.path.display(), failure.error);
+/// }
+/// ```
+pub fn bulk_apply(changes: &[(PathBuf, ManifestOperation)], options: &BulkOptions) -> BulkReport {
+    // Changes of the same file are applied in order by one thread, so they don't overwrite
+    // each other with tags read before the other change
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of: HashMap<&Path, usize> = HashMap::new();
+    for (index, (path, _)) in changes.iter().enumerate() {
+        let group = *group_of.entry(path).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(index);
+    }
+
+    let outcomes = Mutex::new(Vec::with_capacity(changes.len()));
+    for_each_group(&groups, options, |group| {
+        for &index in group {
+            let (path, operation) = &changes[index];
+            let outcome = apply_with_retry(path, operation, options);
+            outcomes.lock().unwrap().push((index, outcome));
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by_key(|(index, _)| *index);
+    let mut report = BulkReport::default();
+    for (index, (attempts, result)) in outcomes {
+        let path = changes[index].0.clone();
+        report.retries += attempts as usize - 1;
+        match result {
+            Ok(()) => report.succeeded.push(path),
+            Err(error) => report.failed.push(BulkFailure {
+                path,
+                attempts,
+                error,
+            }),
+        }
+    }
+    report
+}
+
+// Calls apply for every group, in options.pool if set
+fn for_each_group<F>(groups: &[Vec<usize>], options: &BulkOptions, apply: F)
+where
+    F: Fn(&[usize]) + Sync,
+{
+    #[cfg(feature = "parallel")]
+    if let Some(pool) = &options.pool {
+        use rayon::prelude::*;
+
+        return pool.install(|| groups.par_iter().for_each(|group| apply(group)));
+    }
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for _ in 0..options.concurrency.clamp(1, groups.len().max(1)) {
+            scope.spawn(|| {
+                while let Some(group) = groups.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    apply(group);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use std::path::PathBuf;
+
+    use super::{bulk_apply, is_transient};
+    use crate::{csl_to_map, get_tags, BulkOptions, Fixture, ManifestOperation, XTagError};
+
+    #[test]
+    fn only_temporary_errors_are_retried() {
+        let err = |kind| XTagError::File(io::Error::from(kind));
+        assert!(is_transient(&err(io::ErrorKind::Interrupted)));
+        assert!(is_transient(&err(io::ErrorKind::StorageFull)));
+        assert!(!is_transient(&err(io::ErrorKind::NotFound)));
+        assert!(!is_transient(&XTagError::InvalidTags(String::new())));
+    }
+
+    #[test]
+    fn changes_of_one_file_are_all_applied() {
+        let fixture = Fixture::new().file("a").file("b");
+        let mut changes: Vec<(PathBuf, ManifestOperation)> = (0..16)
+            .map(|n| {
+                let tags = csl_to_map(&format!("t{n}")).unwrap();
+                (fixture.join("a"), ManifestOperation::Add(tags))
+            })
+            .collect();
+        changes.insert(3, (fixture.join("b"), ManifestOperation::Clear));
+        let options = BulkOptions {
+            concurrency: 8,
+            ..Default::default()
+        };
+        let report = bulk_apply(&changes, &options);
+        assert!(report.failed.is_empty());
+        assert_eq!(report.succeeded[3], fixture.join("b"));
+        assert_eq!(get_tags(&fixture.join("a")).unwrap().len(), 16);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn changes_are_applied_in_pool_of_caller() {
+        let fixture = Fixture::new().file("a").file("b");
+        let changes: Vec<(PathBuf, ManifestOperation)> = ["a", "b", "a"]
+            .iter()
+            .enumerate()
+            .map(|(n, name)| {
+                let tags = csl_to_map(&format!("t{n}")).unwrap();
+                (fixture.join(name), ManifestOperation::Add(tags))
+            })
+            .collect();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let options = BulkOptions {
+            pool: Some(std::sync::Arc::new(pool)),
+            ..Default::default()
+        };
+        let report = bulk_apply(&changes, &options);
+        assert!(report.failed.is_empty());
+        assert_eq!(
+            get_tags(&fixture.join("a")).unwrap(),
+            csl_to_map("t0,t2").unwrap()
+        );
+        assert_eq!(
+            get_tags(&fixture.join("b")).unwrap(),
+            csl_to_map("t1").unwrap()
+        );
+    }
+}
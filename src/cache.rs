@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::walk::TreeTags;
+use crate::{get_tags, walk, Result, ResultSet, Searcher, WalkOptions, XTagError, XTags};
+
+static CACHE_HEADER: &str = "xtag-search-cache 1";
+
+// Attribute changes update ctime, content changes too. Like get_tags, symlinks aren't followed,
+// tags of a link are those of the link itself and don't change with its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileStamp {
+    pub(crate) ctime: i64,
+    pub(crate) ctime_nsec: i64,
+}
+
+impl FileStamp {
+    pub(crate) fn of(path: &Path) -> Result<Self> {
+        let metadata = fs::symlink_metadata(path)?;
+        Ok(FileStamp {
+            ctime: metadata.ctime(),
+            ctime_nsec: metadata.ctime_nsec(),
+        })
+    }
+}
+
+// Outcome of one search for every file below root
+type Outcomes = HashMap<PathBuf, (FileStamp, bool)>;
+
+/// Cache for results of searches over directory trees.
+///
+/// Remembers for every file of a searched tree whether it matched, together with its change
+/// time. Repeating the same search over the same root only reads tags of files changed since.
+/// Searches are identified by their Display form, so equivalent terms share entries.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let cache_file = Path::new("search.cache");
+/// let mut cache = xtag::SearchCache::load(cache_file).unwrap_or_default();
+/// let searcher = xtag::compile_search("photo").unwrap();
+/// let result = cache
+///     .search(&searcher, Path::new("."), &xtag::WalkOptions::default())
+///     .unwrap();
+/// cache.save(cache_file).unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct SearchCache {
+    searches: HashMap<(String, PathBuf), Outcomes>,
+}
+
+impl SearchCache {
+    /// Returns new empty SearchCache.
+    pub fn new() -> Self {
+        SearchCache::default()
+    }
+
+    /// Searches files below root, reusing cached outcomes of unchanged files.
+    ///
+    /// # Errors
+    ///
+    /// Same as walk and get_tags, files without attribute support are handled by options
+    pub fn search(
+        &mut self,
+        searcher: &Searcher,
+        root: &Path,
+        options: &WalkOptions,
+    ) -> Result<ResultSet> {
+        let key = (searcher.to_string(), root.to_path_buf());
+        let old = self.searches.remove(&key).unwrap_or_default();
+        let mut new = Outcomes::with_capacity(old.len());
+        let mut result = ResultSet::new();
+        let tree = TreeTags::new(root, options);
+        for path in walk(root, options) {
+            let path = path?;
+            let stamp = FileStamp::of(&path)?;
+            let is_match = match old.get(&path) {
+                Some((old_stamp, is_match)) if *old_stamp == stamp => *is_match,
+                _ => match tree.get(&path)? {
+                    Some(tags) => searcher.is_match(&tags),
+                    None => continue,
+                },
+            };
+            if is_match {
+                result.push(path.clone());
+            }
+            new.insert(path, (stamp, is_match));
+        }
+        self.searches.insert(key, new);
+        Ok(result)
+    }
+
+    /// Forgets everything.
+    pub fn clear(&mut self) {
+        self.searches.clear();
+    }
+
+    /// Forgets outcomes of path, so it is evaluated again by the next search.
+    pub fn invalidate(&mut self, path: &Path) {
+        for outcomes in self.searches.values_mut() {
+            outcomes.remove(path);
+        }
+    }
+
+    /// Loads cache written by save.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if the file can't be read
+    /// - XTagError::Cache if the content is no valid cache
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        if lines.next().transpose()?.as_deref() != Some(CACHE_HEADER) {
+            return Err(XTagError::Cache("unknown format".to_string()));
+        }
+
+        let mut cache = SearchCache::new();
+        let mut current: Option<&mut Outcomes> = None;
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.splitn(5, '\t').collect();
+            match fields.as_slice() {
+                ["search", term, root] => {
+                    let key = (term.to_string(), PathBuf::from(root));
+                    current = Some(cache.searches.entry(key).or_default());
+                }
+                ["", ctime, ctime_nsec, is_match, path] => {
+                    let outcomes = current
+                        .as_mut()
+                        .ok_or_else(|| XTagError::Cache("file before search".to_string()))?;
+                    let stamp = FileStamp {
+                        ctime: ctime.parse()?,
+                        ctime_nsec: ctime_nsec.parse()?,
+                    };
+                    outcomes.insert(PathBuf::from(path), (stamp, *is_match == "1"));
+                }
+                _ => return Err(XTagError::Cache(format!("invalid line {line:?}"))),
+            }
+        }
+        Ok(cache)
+    }
+
+    /// Writes cache to file.
+    ///
+    /// Searches and files whose path isn't valid unicode or contains tabs or newlines are left
+    /// out, they are evaluated again next time.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{CACHE_HEADER}")?;
+        for ((term, root), outcomes) in &self.searches {
+            let Some(root) = storable(root) else {
+                continue;
+            };
+            if term.contains(['\t', '\n']) {
+                continue;
+            }
+            writeln!(writer, "search\t{term}\t{root}")?;
+            for (path, (stamp, is_match)) in outcomes {
+                if let Some(path) = storable(path) {
+                    let is_match = u8::from(*is_match);
+                    writeln!(
+                        writer,
+                        "\t{}\t{}\t{is_match}\t{path}",
+                        stamp.ctime, stamp.ctime_nsec
+                    )?;
+                }
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn storable(path: &Path) -> Option<&str> {
+    path.to_str().filter(|path| !path.contains(['\t', '\n']))
+}
+
+/// Small least recently used cache of parsed tags.
+///
+/// Meant for evaluating several searches over the same files with Searcher::is_match_path_cached.
+/// Entries are checked against the change time of their file, so changed tags are read again.
+#[derive(Debug)]
+pub struct TagCache {
+    capacity: usize,
+    // Last use is a counter, the smallest one is evicted first
+    entries: HashMap<PathBuf, (FileStamp, XTags, u64)>,
+    uses: u64,
+}
+
+impl TagCache {
+    /// Returns new TagCache holding tags of up to capacity files, at least one.
+    pub fn new(capacity: usize) -> Self {
+        TagCache {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            uses: 0,
+        }
+    }
+
+    /// Returns tags of file, read again only if the file changed since it was cached.
+    ///
+    /// # Errors
+    ///
+    /// Same as get_tags
+    pub fn get_tags(&mut self, path: &Path) -> Result<&XTags> {
+        let stamp = FileStamp::of(path)?;
+        self.uses += 1;
+        let fresh = matches!(self.entries.get(path), Some((old, _, _)) if *old == stamp);
+        if !fresh {
+            let tags = get_tags(path)?;
+            if !self.entries.contains_key(path) && self.entries.len() >= self.capacity {
+                self.evict();
+            }
+            self.entries.insert(path.to_path_buf(), (stamp, tags, 0));
+        }
+        let entry = self.entries.get_mut(path).expect("entry inserted above");
+        entry.2 = self.uses;
+        Ok(&entry.1)
+    }
+
+    /// Number of cached files.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no file is cached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Forgets everything.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Forgets tags of path, so they are read again on the next access.
+    pub fn invalidate(&mut self, path: &Path) {
+        self.entries.remove(path);
+    }
+
+    fn evict(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, (_, _, used))| *used)
+            .map(|(path, _)| path.clone());
+        if let Some(oldest) = oldest {
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{FileStamp, SearchCache, TagCache};
+    use crate::{compile_search, get_tags, set_tags, AliasMap, Fixture, Searcher, WalkOptions};
+
+    #[test]
+    fn cache_survives_save_and_load() {
+        let mut cache = SearchCache::new();
+        let outcomes = cache
+            .searches
+            .entry(("^a$".to_string(), PathBuf::from("/root dir")))
+            .or_default();
+        let stamp = FileStamp {
+            ctime: 1,
+            ctime_nsec: 2,
+        };
+        outcomes.insert(PathBuf::from("/root dir/x y"), (stamp, true));
+        outcomes.insert(PathBuf::from("/root dir/z"), (stamp, false));
+        outcomes.insert(PathBuf::from("/root dir/t\tab"), (stamp, false));
+
+        let file = std::env::temp_dir().join(format!("xtag-cache-test-{}", std::process::id()));
+        cache.save(&file).unwrap();
+        let loaded = SearchCache::load(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        let outcomes = loaded.searches.values().next().unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[&PathBuf::from("/root dir/x y")], (stamp, true));
+    }
+
+    #[test]
+    fn folded_and_canonical_searches_are_cached_apart() {
+        let fixture = Fixture::new().file("a").tags("PHOTO").file("b").tags("img");
+        let options = WalkOptions::default();
+        let folded = Searcher::new_folded(compile_search("photo").unwrap());
+        let aliases = AliasMap::from_csv("photo,img\n").unwrap();
+        let canonical = Searcher::new_canonical(compile_search("photo").unwrap(), aliases);
+        let mut cache = SearchCache::new();
+        let plain = compile_search("photo").unwrap();
+        assert!(cache.search(&plain, fixture.path(), &options).unwrap().is_empty());
+        let found = cache.search(&folded, fixture.path(), &options).unwrap();
+        assert_eq!(found.len(), 1);
+        let found = cache.search(&canonical, fixture.path(), &options).unwrap();
+        assert_eq!(found.len(), 1);
+    }
+
+    #[test]
+    fn tag_cache_evicts_least_recently_used() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("a")
+            .file("b")
+            .tags("b")
+            .file("c")
+            .tags("c");
+        let mut cache = TagCache::new(2);
+        assert!(cache
+            .get_tags(&fixture.join("a"))
+            .unwrap()
+            .contains_key("a"));
+        cache.get_tags(&fixture.join("b")).unwrap();
+        cache.get_tags(&fixture.join("a")).unwrap();
+        cache.get_tags(&fixture.join("c")).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(cache.entries.contains_key(&fixture.join("a")));
+        assert!(!cache.entries.contains_key(&fixture.join("b")));
+
+        let tags = crate::csl_to_map("d").unwrap();
+        set_tags(&fixture.join("a"), &tags).unwrap();
+        assert!(cache
+            .get_tags(&fixture.join("a"))
+            .unwrap()
+            .contains_key("d"));
+
+        // Links never show tags of their target, so retagging the target can't make them stale
+        let link = fixture.join("link");
+        std::os::unix::fs::symlink(fixture.join("a"), &link).unwrap();
+        assert_eq!(cache.get_tags(&link).unwrap(), &get_tags(&link).unwrap());
+        set_tags(&fixture.join("a"), &crate::csl_to_map("e").unwrap()).unwrap();
+        assert_eq!(cache.get_tags(&link).unwrap(), &get_tags(&link).unwrap());
+    }
+}
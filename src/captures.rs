@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Mutex;
+
+use regex::{Captures, Regex};
+
+use crate::searcher::TagPairs;
+use crate::weight::split_weight;
+use crate::{Result, Searcher, XTagError};
+
+// References `$name`, `${name}` or `$1` of template with their byte ranges, escaped `\$` is
+// no reference
+pub(crate) fn references(template: &str) -> Vec<(Range<usize>, &str)> {
+    let bytes = template.as_bytes();
+    let is_name = |byte: &u8| byte.is_ascii_alphanumeric() || *byte == b'_';
+    let mut references = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 1,
+            b'$' if bytes.get(index + 1) == Some(&b'{') => {
+                let start = index + 2;
+                let len = bytes[start..].iter().take_while(|b| is_name(b)).count();
+                if len > 0 && bytes.get(start + len) == Some(&b'}') {
+                    references.push((index..start + len + 1, &template[start..start + len]));
+                    index = start + len;
+                }
+            }
+            b'$' => {
+                let start = index + 1;
+                let len = bytes[start..].iter().take_while(|b| is_name(b)).count();
+                if len > 0 {
+                    references.push((index..start + len, &template[start..start + len]));
+                    index = start + len - 1;
+                }
+            }
+            _ => (),
+        }
+        index += 1;
+    }
+    references
+}
+
+// Returns true if name is a group of regex by name or by number from 1, the whole match `$0`
+// is no reference
+fn is_group(regex: &Regex, name: &str) -> bool {
+    match name.parse::<usize>() {
+        Ok(index) => (1..regex.captures_len()).contains(&index),
+        Err(_) => regex.capture_names().flatten().any(|group| group == name),
+    }
+}
+
+// Captured text of group name, empty if it didn't participate in the match
+fn captured<'a>(captures: &'a Captures, name: &str) -> &'a str {
+    let group = match name.parse::<usize>() {
+        Ok(index) => captures.get(index),
+        Err(_) => captures.name(name),
+    };
+    group.map_or("", |group| group.as_str())
+}
+
+// Returns true if template references a group of tag_regex
+pub(crate) fn has_references(tag_regex: &Regex, template: &str) -> bool {
+    references(template)
+        .iter()
+        .any(|(_, name)| is_group(tag_regex, name))
+}
+
+// Part of a value template, regular expression text or a reference to a group
+#[derive(Debug, Clone)]
+enum Piece {
+    Text(String),
+    Group(String),
+}
+
+// Upper bound of expansions kept per template, beyond it the cache starts over
+const EXPANSIONS: usize = 1024;
+
+/// Value regex of Searcher::EqualCaptures with references to groups of its tag regex.
+///
+/// The template is split at its references and checked when the search is compiled. Captured
+/// text is inserted escaped as a group of its own, so every expansion is a valid regular
+/// expression. Expansions are compiled once per distinct captured text and kept for further
+/// matches.
+pub struct ValueTemplate {
+    template: String,
+    pieces: Vec<Piece>,
+    expansions: Mutex<HashMap<Vec<String>, Regex>>,
+}
+
+impl ValueTemplate {
+    // Splits template at references of groups of tag_regex
+    fn new(template: &str, tag_regex: &Regex) -> Result<Self> {
+        let mut pieces = Vec::new();
+        let mut last = 0;
+        for (range, name) in references(template) {
+            if is_group(tag_regex, name) {
+                pieces.push(Piece::Text(template[last..range.start].to_string()));
+                pieces.push(Piece::Group(name.to_string()));
+                last = range.end;
+            }
+        }
+        pieces.push(Piece::Text(template[last..].to_string()));
+        let template = ValueTemplate {
+            template: template.to_string(),
+            pieces,
+            expansions: Mutex::default(),
+        };
+        Regex::new(&template.expand(|_| "")).map_err(XTagError::Regex)?;
+        Ok(template)
+    }
+
+    /// Template as written, with references.
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    // Regular expression with references replaced by the escaped text of replace
+    fn expand<'a, F>(&self, mut replace: F) -> String
+    where
+        F: FnMut(&str) -> &'a str,
+    {
+        let mut expanded = String::with_capacity(self.template.len());
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => expanded.push_str(text),
+                Piece::Group(name) => {
+                    expanded.push_str("(?:");
+                    expanded.push_str(&regex::escape(replace(name)));
+                    expanded.push(')');
+                }
+            }
+        }
+        expanded
+    }
+
+    // Returns true if value matches template expanded with captures
+    fn is_match(&self, captures: &Captures, value: &str) -> bool {
+        let texts: Vec<String> = self
+            .pieces
+            .iter()
+            .filter_map(|piece| match piece {
+                Piece::Group(name) => Some(captured(captures, name).to_string()),
+                Piece::Text(_) => None,
+            })
+            .collect();
+        let mut expansions = self
+            .expansions
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if let Some(regex) = expansions.get(&texts) {
+            return regex.is_match(value);
+        }
+        // Valid as checked by new, unless captures exceed the size limit of regex
+        let Ok(regex) = Regex::new(&self.expand(|name| captured(captures, name))) else {
+            return false;
+        };
+        let matched = regex.is_match(value);
+        if expansions.len() >= EXPANSIONS {
+            expansions.clear();
+        }
+        expansions.insert(texts, regex);
+        matched
+    }
+}
+
+impl Clone for ValueTemplate {
+    fn clone(&self) -> Self {
+        ValueTemplate {
+            template: self.template.clone(),
+            pieces: self.pieces.clone(),
+            expansions: Mutex::default(),
+        }
+    }
+}
+
+impl fmt::Debug for ValueTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ValueTemplate")
+            .field(&self.template)
+            .finish()
+    }
+}
+
+// Searcher comparing values with template, Equal if it references no captures of tag_regex
+pub(crate) fn equal_searcher(tag_regex: Regex, value_regex: &str) -> Result<Searcher> {
+    if !has_references(&tag_regex, value_regex) {
+        let value_regex = Regex::new(value_regex).map_err(XTagError::Regex)?;
+        return Ok(Searcher::Equal {
+            tag_regex,
+            value_regex,
+        });
+    }
+    let value_template = ValueTemplate::new(value_regex, &tag_regex)?;
+    Ok(Searcher::EqualCaptures {
+        tag_regex,
+        value_template,
+    })
+}
+
+// Returns true if a value matches template expanded with the captures of its tag
+pub(crate) fn is_match_captures<T: TagPairs>(
+    tags: &T,
+    tag_regex: &Regex,
+    value_template: &ValueTemplate,
+) -> bool {
+    tags.pairs().any(|(tag, value)| {
+        let (Some(captures), Some(value)) = (tag_regex.captures(split_weight(tag).0), value) else {
+            return false;
+        };
+        value_template.is_match(&captures, split_weight(value).0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::references;
+    use crate::{compile_search, csl_to_map, Searcher};
+
+    #[test]
+    fn references_are_found() {
+        let found: Vec<&str> = references(r"a$n\$x${b_1}$1$")
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect();
+        assert_eq!(found, ["n", "b_1", "1"]);
+    }
+
+    #[test]
+    fn values_are_compared_with_captures_of_tags() {
+        for (term, tags, expected) in [
+            (r"(?P<n>disc\d) == $n", "disc1=disc1", true),
+            (r"(?P<n>disc\d) == $n", "disc1=disc2,disc2=disc1", false),
+            (r"(?P<n>disc\d) != $n", "disc1=disc2", true),
+            (r"lang:(?<l>\w+) == title_${l}", "lang:de=title_de", true),
+            (r"lang:(?<l>\w+) == title_${l}", "lang:de=title_en", false),
+            (r"lang:(\w+) == .*_$1", "lang:ab=x_ab#0.5", true),
+            (r"lang:(\w+) == .*_$1", "lang:ab=x_abc", false),
+            ("(?P<n>a) == x$m", "a=x", false),
+        ] {
+            let searcher = compile_search(term).unwrap();
+            let tags = csl_to_map(tags).unwrap();
+            assert_eq!(searcher.is_match(&tags), expected, "{term} {tags:?}");
+        }
+        assert!(matches!(
+            Searcher::new_equal("(?P<n>a)", "$n").unwrap(),
+            Searcher::EqualCaptures { .. }
+        ));
+        assert!(matches!(
+            Searcher::new_equal("a", "$n").unwrap(),
+            Searcher::Equal { .. }
+        ));
+        assert!(Searcher::new_equal("(?P<n>a)", "$n(").is_err());
+        // Captures are single atoms, `$0` is no reference
+        assert!(Searcher::new_equal("(a)", "x{$1}").is_err());
+        assert!(compile_search("(a+) == $1+")
+            .unwrap()
+            .is_match(&csl_to_map("aa=aaaa").unwrap()));
+        assert!(matches!(
+            Searcher::new_equal("(a)", "$0").unwrap(),
+            Searcher::Equal { .. }
+        ));
+    }
+}
@@ -0,0 +1,136 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::autotag::add_changed_tags;
+use crate::walk::TreeTags;
+use crate::{walk, Result, WalkOptions, XTags};
+
+/// Supported checksum algorithms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// CRC-32, fast but only good against accidental damage.
+    Crc32,
+
+    /// SHA-256.
+    Sha256,
+}
+
+static ALGORITHMS: [ChecksumAlgorithm; 2] = [ChecksumAlgorithm::Crc32, ChecksumAlgorithm::Sha256];
+
+impl ChecksumAlgorithm {
+    /// Tag holding the checksum as lowercase hex value.
+    pub fn tag(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32 => "checksum:crc32",
+            ChecksumAlgorithm::Sha256 => "checksum:sha256",
+        }
+    }
+}
+
+// Hex digest of content
+pub(crate) fn compute(path: &Path, algorithm: ChecksumAlgorithm) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut crc32 = crc32fast::Hasher::new();
+    let mut sha256 = Sha256::new();
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        match algorithm {
+            ChecksumAlgorithm::Crc32 => crc32.update(&buffer[..read]),
+            ChecksumAlgorithm::Sha256 => sha256.update(&buffer[..read]),
+        }
+    }
+    Ok(match algorithm {
+        ChecksumAlgorithm::Crc32 => format!("{:08x}", crc32.finalize()),
+        ChecksumAlgorithm::Sha256 => sha256
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect(),
+    })
+}
+
+/// Computes checksum of the content of file and stores it as tag.
+///
+/// Returns the checksum. The tag is only written if it changes.
+pub fn tag_checksum(path: &Path, algorithm: ChecksumAlgorithm) -> Result<String> {
+    let checksum = compute(path, algorithm)?;
+    let mut tags = XTags::new();
+    tags.insert(algorithm.tag().to_string(), Some(checksum.clone()));
+    add_changed_tags(&TreeTags::default(), path, tags)?;
+    Ok(checksum)
+}
+
+/// Outcome of verify_checksums.
+#[derive(Debug, Default)]
+pub struct ChecksumReport {
+    /// Number of files whose stored checksums all match.
+    pub verified: usize,
+
+    /// Number of files without stored checksums.
+    pub unchecked: usize,
+
+    /// Files with at least one stored checksum not matching their content.
+    pub mismatched: Vec<PathBuf>,
+}
+
+/// Compares stored checksums of all files below root with their content.
+///
+/// Every checksum tag of a file is checked.
+///
+/// # Errors
+///
+/// Same as walk and get_tags, files without attribute support are handled by options
+pub fn verify_checksums(root: &Path, options: &WalkOptions) -> Result<ChecksumReport> {
+    let mut report = ChecksumReport::default();
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        let path = path?;
+        let Some(tags) = tree.get(&path)? else {
+            continue;
+        };
+        let mut is_checked = false;
+        let mut is_match = true;
+        for algorithm in ALGORITHMS {
+            if let Some(Some(stored)) = tags.get(algorithm.tag()) {
+                is_checked = true;
+                is_match &= compute(&path, algorithm)? == *stored;
+            }
+        }
+        match (is_checked, is_match) {
+            (false, _) => report.unchecked += 1,
+            (true, true) => report.verified += 1,
+            (true, false) => report.mismatched.push(path),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tag_checksum, verify_checksums, ChecksumAlgorithm};
+    use crate::{Fixture, WalkOptions};
+
+    #[test]
+    fn checksums_detect_changed_content() {
+        let fixture = Fixture::new().file_with_content("a", "hello").file("b");
+        let file = fixture.join("a");
+
+        let crc32 = tag_checksum(&file, ChecksumAlgorithm::Crc32).unwrap();
+        assert_eq!(crc32, "3610a686");
+        let sha256 = tag_checksum(&file, ChecksumAlgorithm::Sha256).unwrap();
+        assert!(sha256.starts_with("2cf24dba"));
+        let report = verify_checksums(fixture.path(), &WalkOptions::default()).unwrap();
+        assert_eq!((report.verified, report.unchecked), (1, 1));
+
+        std::fs::write(&file, "hallo").unwrap();
+        let report = verify_checksums(fixture.path(), &WalkOptions::default()).unwrap();
+        assert_eq!(report.mismatched, vec![file]);
+    }
+}
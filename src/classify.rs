@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::mem::{discriminant, Discriminant};
+use std::path::Path;
+
+use crate::{get_tags, Result, Searcher, XTags};
+
+// Searcher with terms occurring in several searchers replaced by one shared term
+enum Plan {
+    And(Box<Plan>, Box<Plan>),
+    Or(Box<Plan>, Box<Plan>),
+    Not(Box<Plan>),
+    Term(usize),
+}
+
+// Variant and text identify equal terms, Display alone leaves out the separator of UNDER
+type TermKey = (Discriminant<Searcher>, String);
+
+fn term_key(searcher: &Searcher) -> TermKey {
+    let text = match searcher {
+        Searcher::Descendant { regex, separator } => format!("{regex}{separator}"),
+        searcher => searcher.to_string(),
+    };
+    (discriminant(searcher), text)
+}
+
+/// Finds which of many searches match a file, see classify.
+///
+/// Terms occurring in several searches, like `photo` in `photo and beach` and `photo and
+/// rating > 3`, are evaluated once per file. Folded and canonicalized searches are evaluated as
+/// one term.
+///
+/// # Example
+///
+/// ```
+/// use xtag::{compile_search, Classifier};
+///
+/// let searches = [
+///     compile_search("photo and beach").unwrap(),
+///     compile_search("photo and not beach").unwrap(),
+///     compile_search("video").unwrap(),
+/// ];
+/// let classifier = Classifier::new(&searches);
+/// let tags = xtag::csl_to_map("photo,beach").unwrap();
+/// assert_eq!(classifier.classify(&tags), [0]);
+/// ```
+pub struct Classifier<'a> {
+    plans: Vec<Plan>,
+    terms: Vec<&'a Searcher>,
+}
+
+impl<'a> Classifier<'a> {
+    /// Returns classifier for searchers, results are indices into searchers.
+    pub fn new(searchers: &'a [Searcher]) -> Self {
+        let mut classifier = Classifier {
+            plans: Vec::with_capacity(searchers.len()),
+            terms: Vec::new(),
+        };
+        let mut ids = HashMap::new();
+        for searcher in searchers {
+            let plan = classifier.plan(searcher, &mut ids);
+            classifier.plans.push(plan);
+        }
+        classifier
+    }
+
+    // Operators and bookmarks are resolved, other nodes like folded searches are terms
+    fn plan(&mut self, searcher: &'a Searcher, ids: &mut HashMap<TermKey, usize>) -> Plan {
+        let is_operator = |searcher: &Searcher| {
+            matches!(
+                searcher,
+                Searcher::And { .. }
+                    | Searcher::Or { .. }
+                    | Searcher::Not { .. }
+                    | Searcher::Bookmark { .. }
+            )
+        };
+        searcher.fold_nodes_with(is_operator, |node, operands| {
+            let mut operands = operands.into_iter().map(Box::new);
+            let mut operand = || operands.next().expect("operators have operands");
+            match node {
+                Searcher::And { .. } => Plan::And(operand(), operand()),
+                Searcher::Or { .. } => Plan::Or(operand(), operand()),
+                Searcher::Not { .. } => Plan::Not(operand()),
+                Searcher::Bookmark { .. } => *operand(),
+                term => {
+                    let id = *ids.entry(term_key(term)).or_insert_with(|| {
+                        self.terms.push(term);
+                        self.terms.len() - 1
+                    });
+                    Plan::Term(id)
+                }
+            }
+        })
+    }
+
+    /// Number of distinct terms evaluated per file at most.
+    pub fn terms(&self) -> usize {
+        self.terms.len()
+    }
+
+    /// Indices of the searchers matching tags in ascending order.
+    ///
+    /// With an Index, tags of a file are taken from Index::tags without reading the file.
+    pub fn classify(&self, tags: &XTags) -> Vec<usize> {
+        let mut results = vec![None; self.terms.len()];
+        self.plans
+            .iter()
+            .enumerate()
+            .filter(|(_, plan)| self.evaluate(plan, tags, &mut results))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Reads tags of file and classifies them.
+    ///
+    /// # Errors
+    ///
+    /// Same as get_tags
+    pub fn classify_path(&self, path: &Path) -> Result<Vec<usize>> {
+        Ok(self.classify(&get_tags(path)?))
+    }
+
+    // Short-circuits like Searcher::is_match, terms are evaluated on first use
+    fn evaluate(&self, plan: &Plan, tags: &XTags, results: &mut [Option<bool>]) -> bool {
+        match plan {
+            Plan::And(lhs, rhs) => {
+                self.evaluate(lhs, tags, results) && self.evaluate(rhs, tags, results)
+            }
+            Plan::Or(lhs, rhs) => {
+                self.evaluate(lhs, tags, results) || self.evaluate(rhs, tags, results)
+            }
+            Plan::Not(lhs) => !self.evaluate(lhs, tags, results),
+            Plan::Term(id) => *results[*id].get_or_insert_with(|| self.terms[*id].is_match(tags)),
+        }
+    }
+}
+
+/// Indices of the searchers matching tags, like saved searches or bookmarks a file belongs to.
+///
+/// Terms shared between searchers are evaluated once. To classify many files, create a
+/// Classifier once and reuse it.
+///
+/// # Example
+///
+/// ```
+/// let searches = [
+///     xtag::compile_search("invoice and paid").unwrap(),
+///     xtag::compile_search("invoice and not paid").unwrap(),
+/// ];
+/// let tags = xtag::csl_to_map("invoice").unwrap();
+/// assert_eq!(xtag::classify(&tags, &searches), [1]);
+/// ```
+pub fn classify(tags: &XTags, searchers: &[Searcher]) -> Vec<usize> {
+    Classifier::new(searchers).classify(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Classifier;
+    use crate::{compile_search, csl_to_map, Fixture, Searcher};
+
+    #[test]
+    fn classification_shares_terms() {
+        let searchers: Vec<Searcher> = [
+            "photo and rating > 3",
+            "photo and not (rating > 3)",
+            "not photo",
+            "under(a/b) or rating > 3",
+            "x == y or photo",
+        ]
+        .into_iter()
+        .map(|term| compile_search(term).unwrap())
+        .collect();
+        let classifier = Classifier::new(&searchers);
+        assert_eq!(classifier.terms(), 4);
+
+        for (tags, expected) in [
+            ("photo,rating=5", vec![0, 3, 4]),
+            ("photo,rating=2", vec![1, 4]),
+            ("a/b/c", vec![2, 3]),
+            ("x=y", vec![2, 4]),
+        ] {
+            let tags = csl_to_map(tags).unwrap();
+            assert_eq!(classifier.classify(&tags), expected);
+            let is_match: Vec<usize> = (0..searchers.len())
+                .filter(|&index| searchers[index].is_match(&tags))
+                .collect();
+            assert_eq!(is_match, expected);
+        }
+
+        let fixture = Fixture::new().file("a").tags("photo,rating=4");
+        assert_eq!(
+            classifier.classify_path(&fixture.join("a")).unwrap(),
+            [0, 3, 4]
+        );
+        assert!(Classifier::new(&[])
+            .classify(&csl_to_map("a").unwrap())
+            .is_empty());
+    }
+}
@@ -0,0 +1,368 @@
+use std::path::{Path, PathBuf};
+
+use crate::walk::TreeTags;
+use crate::{
+    compile_search_with, merge_tags_with_store, remove_tags_with_store, walk, BookmarkRegistry,
+    MergePolicy, NormalizationPolicy, Result, SearchOptions, Searcher, SidecarLayout, SidecarStore,
+    Store, TagLimits, WalkOptions, XTagError, XTags, XattrStore, XATTR_NAME,
+};
+
+/// Settings of a Client, built by chaining its methods.
+///
+/// Defaults behave like the free functions: tags in `user.xtag`, no normalization, no limits,
+/// case-sensitive searches and no bookmark directories.
+///
+/// # Example
+///
+/// ```
+/// use xtag::{NormalizationPolicy, SidecarLayout, TagLimits, XTagConfig};
+///
+/// let config = XTagConfig::new()
+///     .with_attribute("user.app.tags")
+///     .with_normalization(NormalizationPolicy {
+///         trim: true,
+///         ..Default::default()
+///     })
+///     .with_case_insensitive(true)
+///     .with_sidecar_fallback(SidecarLayout::PerFile)
+///     .with_limits(TagLimits {
+///         max_tags: Some(50),
+///         ..Default::default()
+///     });
+/// assert_eq!(config.attribute(), "user.app.tags");
+/// ```
+#[derive(Debug, Clone)]
+pub struct XTagConfig {
+    attribute: String,
+    normalization: NormalizationPolicy,
+    case_insensitive: bool,
+    sidecar_fallback: Option<SidecarLayout>,
+    limits: TagLimits,
+    max_term_length: Option<usize>,
+    bookmark_dirs: Vec<PathBuf>,
+}
+
+impl Default for XTagConfig {
+    fn default() -> Self {
+        XTagConfig {
+            attribute: XATTR_NAME.to_string(),
+            normalization: NormalizationPolicy::default(),
+            case_insensitive: false,
+            sidecar_fallback: None,
+            limits: TagLimits::default(),
+            max_term_length: None,
+            bookmark_dirs: Vec::new(),
+        }
+    }
+}
+
+impl XTagConfig {
+    /// Returns default configuration.
+    pub fn new() -> Self {
+        XTagConfig::default()
+    }
+
+    /// Stores tags in attribute, including its namespace like `user.xtag`.
+    pub fn with_attribute(mut self, attribute: &str) -> Self {
+        self.attribute = attribute.to_string();
+        self
+    }
+
+    /// Normalizes tags before writing them.
+    pub fn with_normalization(mut self, policy: NormalizationPolicy) -> Self {
+        self.normalization = policy;
+        self
+    }
+
+    /// Matches tags and values of searches case-insensitive.
+    pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Keeps tags in sidecar files of layout on file systems without extended attributes.
+    pub fn with_sidecar_fallback(mut self, layout: SidecarLayout) -> Self {
+        self.sidecar_fallback = Some(layout);
+        self
+    }
+
+    /// Rejects writing tags beyond limits with XTagError::TagLimit.
+    pub fn with_limits(mut self, limits: TagLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Rejects longer search terms, see SearchOptions::max_term_length.
+    pub fn with_max_term_length(mut self, max: usize) -> Self {
+        self.max_term_length = Some(max);
+        self
+    }
+
+    /// Adds directory of bookmarks, of bookmarks with the same name the first dir wins.
+    pub fn with_bookmark_dir(mut self, dir: &Path) -> Self {
+        self.bookmark_dirs.push(dir.to_path_buf());
+        self
+    }
+
+    /// Attribute holding the tags.
+    pub fn attribute(&self) -> &str {
+        &self.attribute
+    }
+
+    /// Policy applied to written tags.
+    pub fn normalization(&self) -> &NormalizationPolicy {
+        &self.normalization
+    }
+
+    /// Limits of written tags.
+    pub fn limits(&self) -> &TagLimits {
+        &self.limits
+    }
+
+    /// Directories of bookmarks in order of precedence.
+    pub fn bookmark_dirs(&self) -> &[PathBuf] {
+        &self.bookmark_dirs
+    }
+}
+
+/// Tag functions configured once by XTagConfig.
+///
+/// Methods mirror the free functions of the same name, but use the attribute, normalization,
+/// limits and bookmarks of the configuration. Client implements Store, so the functions with
+/// the suffix `_with_store` take it as well.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let config = xtag::XTagConfig::new()
+///     .with_case_insensitive(true)
+///     .with_bookmark_dir(Path::new("/etc/xtag/bookmarks"));
+/// let client = xtag::Client::new(config).unwrap();
+/// client
+///     .add_tags(Path::new("a.jpg"), &xtag::csl_to_map("photo").unwrap())
+///     .unwrap();
+/// let search = client.compile_search("PHOTO and {recent}").unwrap();
+/// for path in client.search_dir(Path::new("."), &search, &Default::default()) {
+///     println!("{}", path.unwrap().display());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Client {
+    config: XTagConfig,
+    xattr: XattrStore,
+    sidecar: Option<SidecarStore>,
+    options: SearchOptions,
+}
+
+impl Client {
+    /// Returns client of config, reading the bookmarks of its directories.
+    ///
+    /// # Errors
+    ///
+    /// Same as BookmarkRegistry::load
+    pub fn new(config: XTagConfig) -> Result<Self> {
+        let xattr = XattrStore::new(&config.attribute);
+        let sidecar = config
+            .sidecar_fallback
+            .map(|layout| SidecarStore::new(layout).with_xattr(xattr.clone()));
+        let options = SearchOptions {
+            case_insensitive: config.case_insensitive,
+            max_term_length: config.max_term_length,
+            bookmarks: BookmarkRegistry::load(&config.bookmark_dirs)?,
+            ..Default::default()
+        };
+        Ok(Client {
+            config,
+            xattr,
+            sidecar,
+            options,
+        })
+    }
+
+    /// Configuration of the client.
+    pub fn config(&self) -> &XTagConfig {
+        &self.config
+    }
+
+    /// Options for compiling search terms, with the bookmarks of the configuration.
+    pub fn search_options(&self) -> &SearchOptions {
+        &self.options
+    }
+
+    fn store(&self) -> &dyn Store {
+        match &self.sidecar {
+            Some(sidecar) => sidecar,
+            None => &self.xattr,
+        }
+    }
+
+    /// Get tags for file as map, see get_tags.
+    pub fn get_tags(&self, path: &Path) -> Result<XTags> {
+        self.store().get_tags(path)
+    }
+
+    /// Set tags for file from map after normalizing them, see set_tags.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::TagLimit if the normalized tags exceed the limits, nothing is written then
+    /// - Same as set_tags
+    pub fn set_tags(&self, path: &Path, tags: &XTags) -> Result<()> {
+        let tags = self.config.normalization.normalize(tags);
+        if let Some(violation) = self.config.limits.check(&tags).into_iter().next() {
+            return Err(XTagError::TagLimit(violation.to_string()));
+        }
+        self.store().set_tags(path, &tags)
+    }
+
+    /// Delete all tags for file, see delete_tags.
+    pub fn delete_tags(&self, path: &Path) -> Result<()> {
+        self.store().delete_tags(path)
+    }
+
+    /// Merge tags into the tags of file, see merge_tags.
+    ///
+    /// # Errors
+    ///
+    /// Same as set_tags
+    pub fn merge_tags(&self, path: &Path, tags: &XTags, policy: MergePolicy) -> Result<()> {
+        let tags = self.config.normalization.normalize(tags);
+        merge_tags_with_store(path, &tags, policy, self)
+    }
+
+    /// Add tags to file, replacing values of tags it already has, see add_tags.
+    ///
+    /// # Errors
+    ///
+    /// Same as set_tags
+    pub fn add_tags(&self, path: &Path, tags: &XTags) -> Result<()> {
+        self.merge_tags(path, tags, MergePolicy::Overwrite)
+    }
+
+    /// Remove tags from file, see remove_tags. Tags are normalized like written ones.
+    pub fn remove_tags(&self, path: &Path, tags: &[&str]) -> Result<()> {
+        let normalized: Vec<String> = tags
+            .iter()
+            .map(|tag| self.config.normalization.normalize_tag(tag))
+            .collect();
+        let normalized: Vec<&str> = normalized.iter().map(String::as_str).collect();
+        remove_tags_with_store(path, &normalized, self)
+    }
+
+    /// Compile search term with the options of the configuration, see compile_search_with.
+    pub fn compile_search(&self, term: &str) -> Result<Searcher> {
+        compile_search_with(term, &self.options)
+    }
+
+    /// Compiles bookmark name of the bookmark directories, see BookmarkRegistry::get.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Bookmark if there is no bookmark name
+    /// - Same as compile_search
+    pub fn bookmark(&self, name: &str) -> Result<Searcher> {
+        if self.options.bookmarks.term(name).is_none() {
+            return Err(XTagError::Bookmark(name.into()));
+        }
+        self.compile_search(&format!("{{{name}}}"))
+    }
+
+    /// Iterates over all files below root matching searcher, see search_dir.
+    pub fn search_dir<'a>(
+        &'a self,
+        root: &Path,
+        searcher: &'a Searcher,
+        options: &WalkOptions,
+    ) -> impl Iterator<Item = Result<PathBuf>> + 'a {
+        let tree = TreeTags::new(root, options);
+        walk(root, options).filter_map(move |path| {
+            let path = match path {
+                Ok(path) => path,
+                Err(err) => return Some(Err(err)),
+            };
+            match tree.recover(&path, self.get_tags(&path)) {
+                Ok(Some(tags)) if searcher.is_match(&tags) => Some(Ok(path)),
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            }
+        })
+    }
+}
+
+impl Store for Client {
+    fn get_tags(&self, path: &Path) -> Result<XTags> {
+        Client::get_tags(self, path)
+    }
+
+    fn set_tags(&self, path: &Path, tags: &XTags) -> Result<()> {
+        Client::set_tags(self, path, tags)
+    }
+
+    fn delete_tags(&self, path: &Path) -> Result<()> {
+        Client::delete_tags(self, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    use super::{Client, XTagConfig};
+    use crate::{csl_to_map, get_tags, Fixture, NormalizationPolicy, TagLimits, XTagError};
+
+    #[test]
+    fn clients_apply_their_configuration() {
+        let fixture = Fixture::new().file("a").file("b").tags("photo");
+        let bookmarks = fixture.join("bookmarks");
+        fs::create_dir(&bookmarks).unwrap();
+        symlink("photo and year > 2020", bookmarks.join("recent")).unwrap();
+        let config = XTagConfig::new()
+            .with_attribute("user.test.tags")
+            .with_normalization(NormalizationPolicy {
+                lowercase_tags: true,
+                ..Default::default()
+            })
+            .with_case_insensitive(true)
+            .with_limits(TagLimits {
+                max_tags: Some(2),
+                ..Default::default()
+            })
+            .with_bookmark_dir(&bookmarks);
+        let client = Client::new(config).unwrap();
+
+        let (a, b) = (fixture.join("a"), fixture.join("b"));
+        client
+            .set_tags(&a, &csl_to_map("Photo,Year=2023").unwrap())
+            .unwrap();
+        assert_eq!(
+            client.get_tags(&a).unwrap(),
+            csl_to_map("photo,year=2023").unwrap()
+        );
+        assert!(get_tags(&a).unwrap().is_empty());
+        assert!(client.get_tags(&b).unwrap().is_empty());
+        let result = client.add_tags(&a, &csl_to_map("draft").unwrap());
+        assert!(matches!(result, Err(XTagError::TagLimit(_))));
+        client.remove_tags(&a, &["YEAR"]).unwrap();
+        client
+            .add_tags(&a, &csl_to_map("year=2024").unwrap())
+            .unwrap();
+
+        let search = client.compile_search("PHOTO and {recent}").unwrap();
+        let found: Vec<_> = client
+            .search_dir(fixture.path(), &search, &Default::default())
+            .collect::<crate::Result<_>>()
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0], a);
+        assert!(client
+            .bookmark("recent")
+            .unwrap()
+            .is_match(&client.get_tags(&a).unwrap()));
+        assert!(matches!(
+            client.bookmark("old"),
+            Err(XTagError::Bookmark(_))
+        ));
+    }
+}
@@ -0,0 +1,67 @@
+use std::cmp::Ordering;
+
+use crate::fold;
+
+/// Order of string values when sorting and grouping, see ResultSet::sort_by_value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Collation {
+    /// Order of code points, uppercase before lowercase and `Ärger` after `Zorn`.
+    #[default]
+    Binary,
+
+    /// Compares values folded like fold, so `Ärger` sorts and groups with `arger`. Values
+    /// differing only in case or diacritics are ordered by code points.
+    Folded,
+
+    /// Unicode Collation Algorithm with the CLDR root order, which suits most languages. Groups
+    /// only join values that differ in nothing but code points, like composed and decomposed
+    /// umlauts.
+    #[cfg(feature = "collation")]
+    Unicode,
+}
+
+// Compares values, equal only if they belong in the same group unless tiebreak is set
+pub(crate) type Comparer = Box<dyn FnMut(&str, &str) -> Ordering>;
+
+impl Collation {
+    /// Compares a and b.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::cmp::Ordering;
+    /// use xtag::Collation;
+    ///
+    /// assert_eq!(Collation::Binary.compare("Ärger", "Zorn"), Ordering::Greater);
+    /// assert_eq!(Collation::Folded.compare("Ärger", "Zorn"), Ordering::Less);
+    /// ```
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.comparer(true)(a, b)
+    }
+
+    // Without tiebreak, values of one group compare equal
+    pub(crate) fn comparer(&self, tiebreak: bool) -> Comparer {
+        match self {
+            Collation::Binary => Box::new(|a: &str, b: &str| a.cmp(b)),
+            Collation::Folded => Box::new(move |a: &str, b: &str| {
+                let ordering = fold(a).cmp(&fold(b));
+                match tiebreak {
+                    true => ordering.then_with(|| a.cmp(b)),
+                    false => ordering,
+                }
+            }),
+            #[cfg(feature = "collation")]
+            Collation::Unicode => {
+                let mut collator = feruca::Collator::new(feruca::Tailoring::default(), true, false);
+                Box::new(move |a: &str, b: &str| {
+                    let ordering = collator.collate(a, b);
+                    match tiebreak {
+                        true => ordering.then_with(|| a.cmp(b)),
+                        false => ordering,
+                    }
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use crate::searcher::expand_regex;
+use crate::{renamed_keys, Result, XTagError, XTags};
+
+/// Tags that would end up with the same name when renaming, see rename_collisions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    /// Name all keys would be renamed to.
+    pub new_key: String,
+
+    /// Distinct keys of the original tags, sorted. Includes new_key itself if it exists and
+    /// isn't renamed.
+    pub keys: Vec<String>,
+}
+
+/// Predicts which tags rename would merge.
+///
+/// Renaming keeps only one value per new name, so several tags renamed to the same name, or a
+/// tag renamed to the name of a tag that stays, lose values silently. Returns one Collision per
+/// affected new name, sorted by it. Tags that are renamed themselves free their name, so
+/// swapping names like `a|b` doesn't collide.
+///
+/// # Example
+///
+/// ```
+/// let tags = xtag::csl_to_map("holiday=rome,vacation=paris,trip").unwrap();
+/// let collisions = xtag::rename_collisions("holiday|trip", "vacation", &tags).unwrap();
+/// assert_eq!(collisions[0].keys, ["holiday", "trip", "vacation"]);
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Regex if find is not a valid regular expression
+pub fn rename_collisions(find: &str, replace: &str, tags: &XTags) -> Result<Vec<Collision>> {
+    let re = Regex::new(&expand_regex(find)).map_err(XTagError::Regex)?;
+    Ok(collisions_with(&re, replace, tags))
+}
+
+pub(crate) fn collisions_with(re: &Regex, replace: &str, tags: &XTags) -> Vec<Collision> {
+    let renames = renamed_keys(re, replace, tags);
+    let mut targets: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (key, new_key) in &renames {
+        targets
+            .entry(new_key.clone())
+            .or_default()
+            .push(key.clone());
+    }
+    targets
+        .into_iter()
+        .filter_map(|(new_key, mut keys)| {
+            let stays =
+                tags.contains_key(&new_key) && !renames.iter().any(|(key, _)| *key == new_key);
+            if stays {
+                keys.push(new_key.clone());
+            }
+            (keys.len() > 1).then(|| {
+                keys.sort();
+                Collision { new_key, keys }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rename_collisions, Collision};
+    use crate::{csl_to_map, rename, rename_checked, XTagError};
+
+    #[test]
+    fn collisions_are_predicted_before_renaming() {
+        let tags = csl_to_map("a=1,b=2,c=3,x/a,x/b,y").unwrap();
+        let collisions = rename_collisions("(x/)?(a|b)", "$2", &tags).unwrap();
+        let expected = vec![
+            Collision {
+                new_key: "a".to_string(),
+                keys: vec!["a".to_string(), "x/a".to_string()],
+            },
+            Collision {
+                new_key: "b".to_string(),
+                keys: vec!["b".to_string(), "x/b".to_string()],
+            },
+        ];
+        assert_eq!(collisions, expected);
+        assert_eq!(rename("(x/)?(a|b)", "$2", tags.clone()).unwrap().len(), 4);
+
+        // Renamed tags free their name
+        assert!(rename_collisions("(a|b)", "new/$1", &tags)
+            .unwrap()
+            .is_empty());
+        assert_eq!(rename_collisions("a|b", "b", &tags).unwrap().len(), 1);
+        let chain = csl_to_map("a=1,aa=2").unwrap();
+        assert!(rename_collisions("(a*)", "${1}a", &chain)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            rename("(a*)", "${1}a", chain).unwrap(),
+            csl_to_map("aa=1,aaa=2").unwrap()
+        );
+
+        match rename_checked("y|c", "c", tags.clone()) {
+            Err(XTagError::RenameCollision(collisions)) => {
+                assert_eq!(collisions[0].keys, ["c", "y"])
+            }
+            result => panic!("{result:?}"),
+        }
+        assert_eq!(rename_checked("y", "z", tags).unwrap()["z"], None);
+    }
+}
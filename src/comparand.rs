@@ -0,0 +1,260 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Result, XTagError};
+
+/// Calendar date in ISO-8601 form `YYYY-MM-DD`.
+///
+/// Parsing ignores a time following the date, like in `2023-06-01T12:30:00`, so dates are compared
+/// day by day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    /// Returns new Date.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::ComparisonValue if month or day are out of range
+    pub fn new(year: i32, month: u8, day: u8) -> Result<Self> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return Err(XTagError::ComparisonValue(format!(
+                "{year:04}-{month:02}-{day:02}"
+            )));
+        }
+        Ok(Date { year, month, day })
+    }
+
+    /// Returns today's date in UTC.
+    pub fn today() -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Date::from_days((seconds / 86_400) as i64)
+    }
+
+    /// Returns date days after 1970-01-01, before it if negative.
+    pub fn from_days(days: i64) -> Self {
+        // Civil from days algorithm of Howard Hinnant, eras of 400 years start on March 1st
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u8;
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Date {
+            year: year as i32,
+            month,
+            day,
+        }
+    }
+
+    /// Number of days after 1970-01-01, negative before it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let date = xtag::Date::new(2024, 2, 29).unwrap();
+    /// assert_eq!(date.days(), 19_782);
+    /// assert_eq!(xtag::Date::from_days(date.days()), date);
+    /// ```
+    pub fn days(&self) -> i64 {
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month = i64::from(self.month);
+        let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl FromStr for Date {
+    type Err = XTagError;
+
+    fn from_str(string: &str) -> Result<Self> {
+        let invalid = || XTagError::ComparisonValue(string.to_string());
+        let date = match string.find(['T', ' ']) {
+            Some(end) => &string[..end],
+            None => string,
+        };
+        let mut parts = date.split('-');
+        let mut next = |len: usize| {
+            parts
+                .next()
+                .filter(|part| part.len() == len && part.bytes().all(|b| b.is_ascii_digit()))
+                .ok_or_else(invalid)
+        };
+        let year = next(4)?.parse().map_err(|_| invalid())?;
+        let month = next(2)?.parse().map_err(|_| invalid())?;
+        let day = next(2)?.parse().map_err(|_| invalid())?;
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Date::new(year, month, day).map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Right hand side of relational Searcher variants.
+///
+/// Tag values are interpreted with the type of the comparand. Integers also compare against
+/// float values. Values that can't be interpreted don't match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparand {
+    Int(i32),
+    Float(f64),
+    Date(Date),
+}
+
+impl Comparand {
+    /// Returns float comparand.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::ComparisonValue if value is NaN or infinite, search terms can't express them
+    pub fn float(value: f64) -> Result<Self> {
+        match value.is_finite() {
+            true => Ok(Comparand::Float(value)),
+            false => Err(XTagError::ComparisonValue(value.to_string())),
+        }
+    }
+
+    /// Compares tag value against comparand, None if value has another type.
+    pub fn compare(&self, tag_value: &str) -> Option<Ordering> {
+        match self {
+            Comparand::Int(value) => match tag_value.parse::<i32>() {
+                Ok(tag_value) => Some(tag_value.cmp(value)),
+                Err(_) => parse_float(tag_value)?.partial_cmp(&f64::from(*value)),
+            },
+            Comparand::Float(value) => parse_float(tag_value)?.partial_cmp(value),
+            Comparand::Date(value) => Some(tag_value.parse::<Date>().ok()?.cmp(value)),
+        }
+    }
+}
+
+// Rust also parses inf and NaN, those aren't numbers for tags
+pub(crate) fn parse_float(string: &str) -> Option<f64> {
+    string.parse::<f64>().ok().filter(|value| value.is_finite())
+}
+
+impl FromStr for Comparand {
+    type Err = XTagError;
+
+    /// Tries integer, float and date in this order.
+    fn from_str(string: &str) -> Result<Self> {
+        if let Ok(value) = string.parse::<i32>() {
+            Ok(Comparand::Int(value))
+        } else if let Some(value) = parse_float(string) {
+            Ok(Comparand::Float(value))
+        } else {
+            Ok(Comparand::Date(string.parse()?))
+        }
+    }
+}
+
+impl From<i32> for Comparand {
+    fn from(value: i32) -> Self {
+        Comparand::Int(value)
+    }
+}
+
+/// Unchecked, see Comparand::float.
+impl From<f64> for Comparand {
+    fn from(value: f64) -> Self {
+        Comparand::Float(value)
+    }
+}
+
+impl From<Date> for Comparand {
+    fn from(value: Date) -> Self {
+        Comparand::Date(value)
+    }
+}
+
+impl fmt::Display for Comparand {
+    /// Floats keep their decimal point, so they are parsed as floats again.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Comparand::Int(value) => write!(f, "{value}"),
+            Comparand::Float(value) => write!(f, "{value:?}"),
+            Comparand::Date(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::{Comparand, Date};
+
+    #[test]
+    fn comparands_are_parsed_in_order() {
+        assert_eq!("4".parse::<Comparand>().unwrap(), Comparand::Int(4));
+        assert_eq!("4.5".parse::<Comparand>().unwrap(), Comparand::Float(4.5));
+        assert_eq!(
+            "2024-02-29".parse::<Comparand>().unwrap(),
+            Comparand::Date(Date::new(2024, 2, 29).unwrap())
+        );
+        for invalid in ["2023-02-29", "2023-6-1", "inf", "abc", "2023-06-01-02"] {
+            assert!(invalid.parse::<Comparand>().is_err(), "{invalid}");
+        }
+        assert_eq!(Comparand::Float(5.0).to_string(), "5.0");
+        for invalid in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(Comparand::float(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn values_are_compared_with_comparand_type() {
+        let int = Comparand::Int(4);
+        assert_eq!(int.compare("4.5"), Some(Ordering::Greater));
+        assert_eq!(int.compare("3"), Some(Ordering::Less));
+        assert_eq!(int.compare("NaN"), None);
+        let date: Comparand = "2023-06-01".parse().unwrap();
+        assert_eq!(date.compare("2023-05-31T23:59"), Some(Ordering::Less));
+        assert_eq!(date.compare("2023-06-01"), Some(Ordering::Equal));
+        assert_eq!(date.compare("20230601"), None);
+    }
+
+    #[test]
+    fn dates_convert_to_days() {
+        assert_eq!(Date::from_days(0), Date::new(1970, 1, 1).unwrap());
+        assert_eq!(Date::from_days(-1), Date::new(1969, 12, 31).unwrap());
+        assert_eq!(Date::new(2000, 3, 1).unwrap().days(), 11_017);
+        for days in -800_000..800_000 {
+            assert_eq!(Date::from_days(days).days(), days);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::cmp::Ordering;
+
+use crate::searcher::get_values_by_tag_regex;
+use crate::visit::Evaluator;
+use crate::{Comparand, Result, Searcher, XTagError, XTags};
+
+/// What relational comparisons do with values that aren't of the comparand's type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ComparisonPolicy {
+    /// Value doesn't match, like is_match does.
+    #[default]
+    Ignore,
+
+    /// Evaluation fails with XTagError::ComparisonValue.
+    Error,
+
+    /// Value is compared as string against the comparand written like in search terms.
+    LexicographicFallback,
+}
+
+/// Tag value a relational comparison couldn't interpret.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkippedValue {
+    /// Tag of the value.
+    pub tag: String,
+
+    /// Value as stored, without weight.
+    pub value: String,
+
+    /// Right hand side of the comparison.
+    pub comparand: Comparand,
+}
+
+impl Searcher {
+    /// Evaluates Searcher against tags like is_match, handling uncomparable values by policy.
+    ///
+    /// Every value a relational comparison couldn't interpret is added to skipped, whatever the
+    /// policy, so data-quality problems become visible.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xtag::ComparisonPolicy;
+    ///
+    /// let search = xtag::compile_search("rating > 3").unwrap();
+    /// let tags = xtag::csl_to_map("rating=five").unwrap();
+    /// let mut skipped = Vec::new();
+    /// let policy = ComparisonPolicy::Ignore;
+    /// assert!(!search.is_match_with(&tags, policy, &mut skipped).unwrap());
+    /// assert_eq!(skipped[0].value, "five");
+    /// let policy = ComparisonPolicy::Error;
+    /// assert!(search.is_match_with(&tags, policy, &mut skipped).is_err());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::ComparisonValue for the first uncomparable value with ComparisonPolicy::Error
+    pub fn is_match_with(
+        &self,
+        tags: &XTags,
+        policy: ComparisonPolicy,
+        skipped: &mut Vec<SkippedValue>,
+    ) -> Result<bool> {
+        let mut comparisons = Comparisons {
+            policy,
+            skipped,
+            error: None,
+        };
+        let matched = self.evaluate_with(tags, &mut comparisons);
+        match comparisons.error {
+            Some(err) => Err(err),
+            None => Ok(matched),
+        }
+    }
+
+    // Evaluates a term without operands, relational ones by policy
+    fn compare_term(
+        &self,
+        tags: &XTags,
+        policy: ComparisonPolicy,
+        skipped: &mut Vec<SkippedValue>,
+    ) -> Result<bool> {
+        let (tag_regex, value, test): (_, _, fn(Ordering) -> bool) = match self {
+            Searcher::Less { tag_regex, value } => (tag_regex, value, Ordering::is_lt),
+            Searcher::LessEqual { tag_regex, value } => (tag_regex, value, Ordering::is_le),
+            Searcher::Greater { tag_regex, value } => (tag_regex, value, Ordering::is_gt),
+            Searcher::GreaterEqual { tag_regex, value } => (tag_regex, value, Ordering::is_ge),
+            _ => return Ok(self.is_match(tags)),
+        };
+        let mut matched = false;
+        for (tag, tag_value) in get_values_by_tag_regex(tags, tag_regex) {
+            let Some(tag_value) = tag_value else {
+                continue;
+            };
+            let ordering = match value.compare(tag_value) {
+                Some(ordering) => ordering,
+                None => {
+                    skipped.push(SkippedValue {
+                        tag: tag.to_string(),
+                        value: tag_value.to_string(),
+                        comparand: *value,
+                    });
+                    match policy {
+                        ComparisonPolicy::Ignore => continue,
+                        ComparisonPolicy::Error => {
+                            return Err(XTagError::ComparisonValue(format!(
+                                "{tag}={tag_value} can't be compared with {value}"
+                            )))
+                        }
+                        ComparisonPolicy::LexicographicFallback => {
+                            tag_value.cmp(value.to_string().as_str())
+                        }
+                    }
+                }
+            };
+            // Goes on after a match, so all uncomparable values are reported
+            matched |= test(ordering);
+        }
+        Ok(matched)
+    }
+}
+
+// Evaluates all operands without short-circuit, so all uncomparable values are reported, until
+// the first error
+struct Comparisons<'s> {
+    policy: ComparisonPolicy,
+    skipped: &'s mut Vec<SkippedValue>,
+    error: Option<XTagError>,
+}
+
+impl Evaluator for Comparisons<'_> {
+    fn short_circuit(&self) -> bool {
+        false
+    }
+
+    fn term(&mut self, _: usize, term: &Searcher, tags: &XTags) -> bool {
+        if self.error.is_some() {
+            return false;
+        }
+        term.compare_term(tags, self.policy, self.skipped)
+            .unwrap_or_else(|err| {
+                self.error = Some(err);
+                false
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ComparisonPolicy;
+    use crate::{compile_search, csl_to_map, XTagError};
+
+    #[test]
+    fn uncomparable_values_follow_policy() {
+        let search = compile_search("shot >= 2023-01-01 or not (rating < 3)").unwrap();
+        let tags = csl_to_map("shot=unknown,rating=2").unwrap();
+
+        let mut skipped = Vec::new();
+        let matched = search.is_match_with(&tags, ComparisonPolicy::Ignore, &mut skipped);
+        assert_eq!(matched.unwrap(), search.is_match(&tags));
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].tag, "shot");
+
+        let result = search.is_match_with(&tags, ComparisonPolicy::Error, &mut skipped);
+        assert!(matches!(result, Err(XTagError::ComparisonValue(_))));
+
+        let policy = ComparisonPolicy::LexicographicFallback;
+        let matched = search.is_match_with(&tags, policy, &mut skipped);
+        assert!(matched.unwrap());
+        assert_eq!(skipped.len(), 3);
+
+        // Operands after a decided AND or OR are still checked
+        let tags = csl_to_map("rating=2,shot=unknown,size=big").unwrap();
+        for term in ["rating > 3 and shot < 2000", "rating < 3 or size > 10"] {
+            let mut skipped = Vec::new();
+            let search = compile_search(term).unwrap();
+            let matched = search.is_match_with(&tags, ComparisonPolicy::Ignore, &mut skipped);
+            assert_eq!(matched.unwrap(), search.is_match(&tags));
+            assert_eq!(skipped.len(), 1, "{term}");
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::cmp::Reverse;
+
+use crate::{Index, TagStats};
+
+/// How typed text selects suggestions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SuggestMode {
+    /// Suggestions start with the text.
+    #[default]
+    Prefix,
+
+    /// Suggestions contain the characters of the text in order, like `ptb` for `photobook`.
+    /// Suggestions starting with the text come first.
+    Fuzzy,
+}
+
+impl SuggestMode {
+    // Returns rank of candidate, lower is better, None if it doesn't match
+    fn rank(self, text: &str, candidate: &str) -> Option<u8> {
+        let text = text.to_lowercase();
+        let candidate = candidate.to_lowercase();
+        if candidate.starts_with(&text) {
+            return Some(0);
+        }
+        if self == SuggestMode::Prefix {
+            return None;
+        }
+        let mut chars = candidate.chars();
+        text.chars()
+            .all(|c| chars.any(|candidate| candidate == c))
+            .then_some(1)
+    }
+}
+
+// Best limit candidates matching text, by rank, number of files and name
+fn suggest<'a, I>(
+    text: &str,
+    mode: SuggestMode,
+    limit: usize,
+    candidates: I,
+) -> Vec<(String, usize)>
+where
+    I: Iterator<Item = (&'a String, usize)>,
+{
+    let mut found: Vec<(u8, &String, usize)> = candidates
+        .filter_map(|(candidate, files)| Some((mode.rank(text, candidate)?, candidate, files)))
+        .collect();
+    found.sort_by_key(|(rank, candidate, files)| (*rank, Reverse(*files), *candidate));
+    found
+        .into_iter()
+        .take(limit)
+        .map(|(_, candidate, files)| (candidate.clone(), files))
+        .collect()
+}
+
+impl TagStats {
+    /// Returns up to limit known tags matching text with their number of files, most used first.
+    ///
+    /// Matching ignores case. Meant for autocompletion in editors, so keep the TagStats around
+    /// instead of collecting them for every key stroke.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xtag::SuggestMode;
+    ///
+    /// let tags = [
+    ///     xtag::csl_to_map("photo,photobook").unwrap(),
+    ///     xtag::csl_to_map("photo,place=rome").unwrap(),
+    /// ];
+    /// let stats: xtag::TagStats = tags.iter().collect();
+    /// assert_eq!(
+    ///     stats.suggest_tags("ph", SuggestMode::Prefix, 5),
+    ///     vec![("photo".to_string(), 2), ("photobook".to_string(), 1)]
+    /// );
+    /// assert_eq!(stats.suggest_tags("pce", SuggestMode::Fuzzy, 5)[0].0, "place");
+    /// ```
+    pub fn suggest_tags(
+        &self,
+        text: &str,
+        mode: SuggestMode,
+        limit: usize,
+    ) -> Vec<(String, usize)> {
+        suggest(
+            text,
+            mode,
+            limit,
+            self.tags().map(|(tag, count)| (tag, count.files)),
+        )
+    }
+
+    /// Returns up to limit known values of tag matching text, see suggest_tags.
+    pub fn suggest_values(
+        &self,
+        tag: &str,
+        text: &str,
+        mode: SuggestMode,
+        limit: usize,
+    ) -> Vec<(String, usize)> {
+        match self.tag(tag) {
+            Some(count) => suggest(
+                text,
+                mode,
+                limit,
+                count.values.iter().map(|(value, files)| (value, *files)),
+            ),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Index {
+    /// Returns TagStats of all indexed files.
+    ///
+    /// They are counted once and kept until the index changes, so repeated suggestions while
+    /// typing don't go over all files again.
+    pub fn stats(&self) -> &TagStats {
+        self.stats
+            .get_or_init(|| self.iter().map(|(_, tags)| tags).collect())
+    }
+
+    /// Returns up to limit tags of indexed files matching text, see TagStats::suggest_tags.
+    pub fn suggest_tags(
+        &self,
+        text: &str,
+        mode: SuggestMode,
+        limit: usize,
+    ) -> Vec<(String, usize)> {
+        self.stats().suggest_tags(text, mode, limit)
+    }
+
+    /// Returns up to limit values of tag matching text, see TagStats::suggest_values.
+    pub fn suggest_values(
+        &self,
+        tag: &str,
+        text: &str,
+        mode: SuggestMode,
+        limit: usize,
+    ) -> Vec<(String, usize)> {
+        self.stats().suggest_values(tag, text, mode, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SuggestMode;
+    use crate::{csl_to_map, set_tags, Fixture, Index, TagStats};
+
+    #[test]
+    fn suggestions_are_ranked() {
+        let tags = [
+            csl_to_map("Camera=canon,city=Rome,cat").unwrap(),
+            csl_to_map("camera=nikon,city=rome").unwrap(),
+            csl_to_map("camera=nikon,city=Roma").unwrap(),
+        ];
+        let stats: TagStats = tags.iter().collect();
+        let names = |suggestions: Vec<(String, usize)>| -> Vec<String> {
+            suggestions.into_iter().map(|(name, _)| name).collect()
+        };
+        assert_eq!(
+            names(stats.suggest_tags("ca", SuggestMode::Prefix, 5)),
+            vec!["camera", "Camera", "cat"]
+        );
+        assert_eq!(
+            names(stats.suggest_tags("cty", SuggestMode::Fuzzy, 5)),
+            vec!["city"]
+        );
+        assert_eq!(
+            names(stats.suggest_tags("c", SuggestMode::Fuzzy, 2)),
+            vec!["city", "camera"]
+        );
+        assert_eq!(
+            stats.suggest_values("camera", "", SuggestMode::Prefix, 5),
+            vec![("nikon".to_string(), 2)]
+        );
+        assert_eq!(
+            names(stats.suggest_values("city", "rm", SuggestMode::Fuzzy, 5)),
+            vec!["Roma", "Rome", "rome"]
+        );
+        assert!(stats
+            .suggest_values("missing", "", SuggestMode::Prefix, 5)
+            .is_empty());
+    }
+
+    #[test]
+    fn index_suggestions_follow_updates() {
+        let fixture = Fixture::new().file("a").tags("camera=canon");
+        let mut index = Index::build(fixture.path(), &Default::default()).unwrap();
+        let suggest = |index: &Index| index.suggest_values("camera", "", SuggestMode::Prefix, 5);
+        assert_eq!(suggest(&index), vec![("canon".to_string(), 1)]);
+
+        set_tags(&fixture.join("a"), &csl_to_map("camera=nikon").unwrap()).unwrap();
+        assert_eq!(suggest(&index), vec![("canon".to_string(), 1)]);
+        index.update([fixture.join("a")]).unwrap();
+        assert_eq!(suggest(&index), vec![("nikon".to_string(), 1)]);
+        index
+            .apply_manifest("a\tadd\tcity=rome\n".as_bytes())
+            .unwrap();
+        assert_eq!(
+            index.suggest_tags("ci", SuggestMode::Prefix, 5)[0].0,
+            "city"
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::autotag::add_changed_tags;
+use crate::walk::TreeTags;
+use crate::{csl_to_map, walk, Result, WalkOptions, XTags};
+
+// Magic bytes at given offset and resulting MIME type, first match wins
+static MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\xFF\xD8\xFF", "image/jpeg"),
+    (0, b"\x89PNG\r\n\x1A\n", "image/png"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (8, b"WEBP", "image/webp"),
+    (0, b"II*\0", "image/tiff"),
+    (0, b"MM\0*", "image/tiff"),
+    (0, b"BM", "image/bmp"),
+    (4, b"ftypheic", "image/heic"),
+    (4, b"ftypqt", "video/quicktime"),
+    (4, b"ftyp", "video/mp4"),
+    (0, b"\x1A\x45\xDF\xA3", "video/x-matroska"),
+    (8, b"AVI ", "video/x-msvideo"),
+    (8, b"WAVE", "audio/wav"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"\xFF\xFB", "audio/mpeg"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"\x1F\x8B", "application/gzip"),
+    (0, b"7z\xBC\xAF\x27\x1C", "application/x-7z-compressed"),
+    (0, b"\x7FELF", "application/x-executable"),
+];
+
+// Enough for all magic numbers and a quick text check
+const SNIFF_SIZE: usize = 512;
+
+/// Detects MIME type of file from its first bytes.
+///
+/// Knows common image, audio, video, document and archive formats. Files without known magic
+/// bytes are reported as `text/plain` if they start with valid UTF-8 without NUL bytes and as
+/// `application/octet-stream` otherwise.
+pub fn detect_mime_type(path: &Path) -> Result<&'static str> {
+    let mut buffer = Vec::with_capacity(SNIFF_SIZE);
+    File::open(path)?
+        .take(SNIFF_SIZE as u64)
+        .read_to_end(&mut buffer)?;
+    Ok(mime_type_of(&buffer))
+}
+
+fn mime_type_of(bytes: &[u8]) -> &'static str {
+    for (offset, magic, mime_type) in MAGIC {
+        if bytes.len() >= offset + magic.len() && &bytes[*offset..offset + magic.len()] == *magic {
+            return mime_type;
+        }
+    }
+    // A multi-byte character may be cut at the end of the buffer
+    let is_text = match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    };
+    if is_text && !bytes.contains(&0) {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// Rule adding tags to files of matching MIME type.
+#[derive(Debug, Clone)]
+pub struct MimeRule {
+    pattern: String,
+    tags: XTags,
+}
+
+impl MimeRule {
+    /// Returns new MimeRule.
+    ///
+    /// pattern is a MIME type like `image/png` or a whole top level type like `image/*`. tags is
+    /// a comma separated list of tag=value pairs.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Parser if tags is no valid list of tags
+    pub fn new(pattern: &str, tags: &str) -> Result<Self> {
+        Ok(MimeRule {
+            pattern: pattern.to_string(),
+            tags: csl_to_map(tags)?,
+        })
+    }
+
+    /// Returns true if mime_type matches pattern.
+    pub fn is_match(&self, mime_type: &str) -> bool {
+        match self.pattern.strip_suffix("/*") {
+            Some(top_level) => mime_type.split('/').next() == Some(top_level),
+            None => mime_type == self.pattern,
+        }
+    }
+}
+
+/// Returns tags of all rules matching the content of path.
+pub fn mime_tags(path: &Path, rules: &[MimeRule]) -> Result<XTags> {
+    let mime_type = detect_mime_type(path)?;
+    let mut tags = XTags::new();
+    for rule in rules.iter().filter(|rule| rule.is_match(mime_type)) {
+        tags.extend(rule.tags.clone());
+    }
+    Ok(tags)
+}
+
+/// Applies rules to a single file.
+///
+/// Returns true if the tags of the file changed.
+pub fn autotag_content_file(path: &Path, rules: &[MimeRule]) -> Result<bool> {
+    autotag_content_in(&TreeTags::default(), path, rules)
+}
+
+// Applies rules to a single file of tree
+fn autotag_content_in(tree: &TreeTags, path: &Path, rules: &[MimeRule]) -> Result<bool> {
+    let tags = mime_tags(path, rules)?;
+    if tags.is_empty() {
+        return Ok(false);
+    }
+    add_changed_tags(tree, path, tags)
+}
+
+/// Applies rules to all files below root.
+///
+/// Returns number of changed files.
+///
+/// # Errors
+///
+/// Stops at the first file that can't be read or written, unless options allow skipping it.
+pub fn autotag_content(root: &Path, rules: &[MimeRule], options: &WalkOptions) -> Result<usize> {
+    let mut changed = 0;
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        if autotag_content_in(&tree, &path?, rules)? {
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mime_type_of, MimeRule};
+
+    #[test]
+    fn mime_types_are_detected() {
+        assert_eq!(mime_type_of(b"\xFF\xD8\xFF\xE0rest"), "image/jpeg");
+        assert_eq!(mime_type_of(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(mime_type_of(b"hello w\xC3"), "text/plain");
+        assert_eq!(mime_type_of(b"\0\x01\x02"), "application/octet-stream");
+        assert_eq!(mime_type_of(b""), "text/plain");
+    }
+
+    #[test]
+    fn mime_rules_support_top_level_wildcard() {
+        let rule = MimeRule::new("image/*", "photo").unwrap();
+        assert!(rule.is_match("image/png"));
+        assert!(!rule.is_match("video/mp4"));
+        let rule = MimeRule::new("image/png", "photo").unwrap();
+        assert!(!rule.is_match("image/jpeg"));
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::store::store_or_xattr;
+use crate::walk::TreeTags;
+use crate::{
+    compile_search_with, walk, Result, ResultSet, SearchOptions, Store, WalkOptions, XTagError,
+    XTags,
+};
+
+// Longest accepted search term, protects against garbage on the socket
+const MAX_TERM_LENGTH: u32 = 64 * 1024;
+
+// Limits of regular expressions and nesting of terms from clients
+const MAX_REGEX_SIZE: usize = 1024 * 1024;
+const MAX_DEPTH: usize = 64;
+
+// Connections idle for longer are closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Connections served at the same time, further clients wait to be accepted
+const MAX_CONNECTIONS: usize = 64;
+
+const STATUS_OK: u8 = 0;
+const STATUS_ERROR: u8 = 1;
+
+/// Resident search service keeping the tags of a directory tree in memory.
+///
+/// Clients connect to a Unix socket and send a search term as big endian u32 length followed by
+/// UTF-8 bytes. The answer is a status byte, 0 for success followed by the u32 number of paths
+/// and each path as u32 length and bytes, or 1 for failure followed by a length prefixed error
+/// message. Every connection may send any number of terms and is served by a thread of its own,
+/// connections idle for a minute are closed. At most 64 connections are served at the same time,
+/// see with_max_connections. Terms come from untrusted clients, so they are compiled with limits
+/// of their length, nesting and size of regular expressions, and bookmarks may only be referenced
+/// by name, not by the path of a bookmark file.
+///
+/// Changes to files are not noticed on their own, they have to be announced with invalidate or,
+/// with feature watch, with the events of a TagWatcher through apply_events.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use std::os::unix::net::UnixListener;
+/// let daemon = xtag::Daemon::new(Path::new("/home/me"), &xtag::WalkOptions::default()).unwrap();
+/// let listener = UnixListener::bind("/run/user/1000/xtag.sock").unwrap();
+/// daemon.serve(&listener).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Daemon {
+    pub(crate) tags: RwLock<HashMap<PathBuf, XTags>>,
+    options: SearchOptions,
+    store: Option<Arc<dyn Store + Send + Sync>>,
+    max_connections: usize,
+}
+
+impl Daemon {
+    /// Reads tags of all files below root.
+    ///
+    /// # Errors
+    ///
+    /// Same as walk and get_tags, files without attribute support are handled by options
+    pub fn new(root: &Path, options: &WalkOptions) -> Result<Self> {
+        let mut tags = HashMap::new();
+        let tree = TreeTags::new(root, options);
+        for path in walk(root, options) {
+            let path = path?;
+            if let Some(file_tags) = tree.get(&path)? {
+                tags.insert(path, file_tags);
+            }
+        }
+        let store = options.store.clone();
+        let options = SearchOptions {
+            max_term_length: Some(MAX_TERM_LENGTH as usize),
+            max_regex_size: Some(MAX_REGEX_SIZE),
+            max_depth: Some(MAX_DEPTH),
+            registered_bookmarks_only: true,
+            ..Default::default()
+        };
+        Ok(Daemon {
+            tags: RwLock::new(tags),
+            options,
+            store,
+            max_connections: MAX_CONNECTIONS,
+        })
+    }
+
+    /// Compiles search terms with options instead of the default limits.
+    ///
+    /// Limits left at None accept any term and registered_bookmarks_only left at false lets
+    /// clients read any bookmark file, so they should stay set for untrusted clients.
+    pub fn with_search_options(mut self, options: SearchOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Serves at most max connections at the same time instead of 64, at least one.
+    pub fn with_max_connections(mut self, max: usize) -> Self {
+        self.max_connections = max.max(1);
+        self
+    }
+
+    /// Number of files known.
+    pub fn len(&self) -> usize {
+        self.tags.read().unwrap().len()
+    }
+
+    /// Returns true if no files are known.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads tags of path again, forgets it if it doesn't exist anymore.
+    pub fn invalidate(&self, path: &Path) -> Result<()> {
+        let tags = match store_or_xattr(&self.store).get_tags(path) {
+            Ok(tags) => Some(tags),
+            Err(XTagError::File(err)) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        let mut known = self.tags.write().unwrap();
+        match tags {
+            Some(tags) => known.insert(path.to_path_buf(), tags),
+            None => known.remove(path),
+        };
+        Ok(())
+    }
+
+    /// Searches the files in memory.
+    ///
+    /// Paths are returned in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// Same as compile_search_with with the options of the daemon
+    pub fn search(&self, term: &str) -> Result<ResultSet> {
+        let searcher = compile_search_with(term, &self.options)?;
+        let known = self.tags.read().unwrap();
+        Ok(known
+            .iter()
+            .filter(|(_, tags)| searcher.is_match(*tags))
+            .map(|(path, _)| path.clone())
+            .collect())
+    }
+
+    /// Answers requests of all clients connecting to listener, each in a thread of its own.
+    ///
+    /// While the most connections are open, further clients aren't accepted until one ends. Only
+    /// returns if accepting connections fails, after the open connections ended. Failures of
+    /// single connections just end them.
+    pub fn serve(&self, listener: &UnixListener) -> Result<()> {
+        let open = (Mutex::new(0), Condvar::new());
+        thread::scope(|scope| loop {
+            let count = open.0.lock().unwrap();
+            let mut count = open
+                .1
+                .wait_while(count, |count| *count >= self.max_connections)
+                .unwrap();
+            let (stream, _) = listener.accept()?;
+            *count += 1;
+            drop(count);
+            let open = &open;
+            scope.spawn(move || {
+                let result = self.handle(stream);
+                *open.0.lock().unwrap() -= 1;
+                open.1.notify_one();
+                result
+            });
+        })
+    }
+
+    /// Answers requests of one client until it disconnects or stays idle too long.
+    pub fn handle(&self, mut stream: UnixStream) -> Result<()> {
+        stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+        while let Some(term) = read_term(&mut stream)? {
+            let mut answer = Vec::new();
+            match self.search(&term) {
+                Ok(result) => {
+                    answer.push(STATUS_OK);
+                    answer.extend((result.len() as u32).to_be_bytes());
+                    for path in &result {
+                        push_bytes(&mut answer, path.as_os_str().as_bytes());
+                    }
+                }
+                Err(err) => {
+                    answer.push(STATUS_ERROR);
+                    push_bytes(&mut answer, err.to_string().as_bytes());
+                }
+            }
+            stream.write_all(&answer)?;
+        }
+        Ok(())
+    }
+}
+
+fn push_bytes(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.extend((bytes.len() as u32).to_be_bytes());
+    buffer.extend(bytes);
+}
+
+fn read_u32(stream: &mut impl Read) -> io::Result<u32> {
+    let mut length = [0; 4];
+    stream.read_exact(&mut length)?;
+    Ok(u32::from_be_bytes(length))
+}
+
+fn read_bytes(stream: &mut impl Read, length: u32) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; length as usize];
+    stream.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+// Returns None when the client closed the connection
+fn read_term(stream: &mut impl Read) -> Result<Option<String>> {
+    let length = match read_u32(stream) {
+        Ok(length) => length,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    if length > MAX_TERM_LENGTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "search term too long").into());
+    }
+    let term = String::from_utf8(read_bytes(stream, length)?)
+        .map_err(|err| XTagError::Charset(err.utf8_error()))?;
+    Ok(Some(term))
+}
+
+/// Sends search term to a Daemon listening at socket and returns its result.
+///
+/// # Errors
+///
+/// - XTagError::File if the daemon can't be reached
+/// - XTagError::Daemon if the daemon reports an error
+pub fn query_daemon(socket: &Path, term: &str) -> Result<ResultSet> {
+    let mut stream = UnixStream::connect(socket)?;
+    let mut request = Vec::new();
+    push_bytes(&mut request, term.as_bytes());
+    stream.write_all(&request)?;
+
+    let mut status = [0];
+    stream.read_exact(&mut status)?;
+    let count = read_u32(&mut stream)?;
+    if status[0] != STATUS_OK {
+        let message = read_bytes(&mut stream, count)?;
+        return Err(XTagError::Daemon(
+            String::from_utf8_lossy(&message).into_owned(),
+        ));
+    }
+    let mut result = ResultSet::new();
+    for _ in 0..count {
+        let length = read_u32(&mut stream)?;
+        let path = std::ffi::OsStr::from_bytes(&read_bytes(&mut stream, length)?).to_owned();
+        result.push(PathBuf::from(path));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{query_daemon, Daemon};
+    use crate::{csl_to_map, set_tags, Fixture, WalkOptions, XTagError};
+
+    #[test]
+    fn daemon_answers_over_socket() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("photo")
+            .file("b")
+            .tags("text");
+        let daemon = Daemon::new(fixture.path(), &WalkOptions::default()).unwrap();
+        assert_eq!(daemon.len(), 2);
+
+        set_tags(&fixture.join("b"), &csl_to_map("photo").unwrap()).unwrap();
+        assert_eq!(daemon.search("photo").unwrap().len(), 1);
+        daemon.invalidate(&fixture.join("b")).unwrap();
+        assert_eq!(daemon.search("photo").unwrap().len(), 2);
+
+        let socket = fixture.join("socket");
+        let listener = UnixListener::bind(&socket).unwrap();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let (stream, _) = listener.accept().unwrap();
+                daemon.handle(stream).unwrap();
+            });
+            assert_eq!(query_daemon(&socket, "photo").unwrap().len(), 2);
+        });
+        assert!(daemon.search("a b").is_err());
+        let nested = format!("{}photo{}", "(".repeat(100), ")".repeat(100));
+        assert!(matches!(
+            daemon.search(&nested),
+            Err(XTagError::QueryTooComplex(_))
+        ));
+    }
+
+    #[test]
+    fn daemon_serves_connections_concurrently() {
+        let fixture = Fixture::new().file("a").tags("photo");
+        let daemon = Daemon::new(fixture.path(), &WalkOptions::default()).unwrap();
+        let socket = fixture.join("socket");
+        let listener = UnixListener::bind(&socket).unwrap();
+        thread::spawn(move || daemon.serve(&listener));
+
+        // An idle client doesn't keep others waiting
+        let _idle = UnixStream::connect(&socket).unwrap();
+        assert_eq!(query_daemon(&socket, "photo").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn daemon_limits_connections() {
+        let fixture = Fixture::new().file("a").tags("photo");
+        let daemon = Daemon::new(fixture.path(), &WalkOptions::default())
+            .unwrap()
+            .with_max_connections(1);
+        let socket = fixture.join("socket");
+        let listener = UnixListener::bind(&socket).unwrap();
+        thread::spawn(move || daemon.serve(&listener));
+
+        let idle = UnixStream::connect(&socket).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let waiting = socket.clone();
+        thread::spawn(move || sender.send(query_daemon(&waiting, "photo").unwrap().len()));
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+        drop(idle);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok(1));
+    }
+
+    #[test]
+    fn daemon_rejects_paths_of_bookmarks() {
+        let fixture = Fixture::new().file("a").tags("photo");
+        let bookmark = fixture.join("bookmark");
+        symlink("photo", &bookmark).unwrap();
+        let daemon = Daemon::new(fixture.path(), &WalkOptions::default()).unwrap();
+        let term = format!("{{{}}}", bookmark.display());
+        assert!(matches!(
+            daemon.search(&term),
+            Err(XTagError::Bookmark(_))
+        ));
+        assert!(matches!(
+            daemon.search("{/etc/passwd}"),
+            Err(XTagError::Bookmark(_))
+        ));
+        // Outside of the daemon the bookmark is found
+        let search = crate::compile_search(&term).unwrap();
+        assert!(search.is_match(&csl_to_map("photo").unwrap()));
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::fmt::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+use crate::read_only::ensure_writable;
+use crate::{Result, ResultSet, Searcher};
+
+/// Fills directory with symbolic links to all files in results.
+///
+/// File managers show such a directory as a virtual folder of the search. Directory is created if
+/// needed. Symbolic links already in the directory are removed first, everything else is left
+/// untouched. Links are named like their targets, duplicate names get a ` (n)` suffix.
+///
+/// # Errors
+///
+/// - XTagError::ReadOnly in read-only mode
+/// - XTagError::File if the directory can't be changed or a result doesn't exist
+pub fn write_symlink_tree(dir: &Path, results: &ResultSet) -> Result<()> {
+    ensure_writable(dir)?;
+    fs::create_dir_all(dir)?;
+    let mut names: HashSet<OsString> = HashSet::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_symlink() {
+            fs::remove_file(entry.path())?;
+        } else {
+            names.insert(entry.file_name());
+        }
+    }
+
+    for path in results {
+        let target = fs::canonicalize(path)?;
+        let name = unique_name(&target, &mut names);
+        symlink(&target, dir.join(name))?;
+    }
+    Ok(())
+}
+
+// Returns file name of path, made unique against names
+fn unique_name(path: &Path, names: &mut HashSet<OsString>) -> OsString {
+    let stem = path.file_stem().unwrap_or_default();
+    let base = path.file_name().unwrap_or_default().to_os_string();
+    let mut name = base.clone();
+    let mut n = 2;
+    while names.contains(&name) {
+        name = stem.to_os_string();
+        name.push(format!(" ({n})"));
+        if let Some(extension) = path.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        n += 1;
+    }
+    names.insert(name.clone());
+    name
+}
+
+/// Returns content of a freedesktop.org `.desktop` link to a symlink tree.
+///
+/// Saved as file with `.desktop` extension the link shows up in GNOME, KDE and other desktops
+/// supporting the Desktop Entry Specification. The search term is kept in `X-XTag-Search` so
+/// tools can refresh the tree later.
+///
+/// Only a plain `Type=Link` entry pointing to the directory is produced, desktops don't run the
+/// search themselves. The directory has to be filled by write_symlink_tree and should be absolute,
+/// it is percent-encoded in the `file://` URL.
+pub fn desktop_entry(name: &str, searcher: &Searcher, dir: &Path) -> String {
+    format!(
+        "[Desktop Entry]\nType=Link\nName={}\nIcon=folder-saved-search\nURL=file://{}\nX-XTag-Search={}\n",
+        escape_value(name),
+        encode_path(dir),
+        escape_value(&searcher.to_string())
+    )
+}
+
+// Percent-encodes all bytes of path but unreserved characters of RFC 3986 and slashes
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => write!(encoded, "%{byte:02X}").unwrap(),
+        }
+    }
+    encoded
+}
+
+// Escapes characters not allowed literally in values of desktop entries
+fn escape_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    use super::{desktop_entry, encode_path, unique_name};
+    use crate::{compile_search, Searcher};
+
+    #[test]
+    fn desktop_entry_contains_search() {
+        let searcher = compile_search("a").unwrap();
+        let entry = desktop_entry("My\nSearch", &searcher, Path::new("/tmp/search"));
+        assert!(entry.contains("Name=My\\nSearch\n"));
+        assert!(entry.contains("URL=file:///tmp/search\n"));
+        assert!(entry.contains("X-XTag-Search=^(?:a)$\n"));
+
+        let folded = Searcher::new_folded(searcher);
+        let entry = desktop_entry("Folded", &folded, Path::new("/tmp/search"));
+        assert!(entry.contains("X-XTag-Search=FOLD(^(?:a)$)\n"));
+    }
+
+    #[test]
+    fn desktop_urls_are_percent_encoded() {
+        let path = Path::new("/tmp/my search/#1 100%/Ärger");
+        assert_eq!(
+            encode_path(path),
+            "/tmp/my%20search/%231%20100%25/%C3%84rger"
+        );
+        let searcher = compile_search("a").unwrap();
+        let entry = desktop_entry("Search", &searcher, path);
+        assert!(entry.contains("URL=file:///tmp/my%20search/%231%20100%25/%C3%84rger\n"));
+    }
+
+    #[test]
+    fn symlink_names_are_unique() {
+        let mut names = HashSet::new();
+        assert_eq!(unique_name(Path::new("/a/x.jpg"), &mut names), "x.jpg");
+        assert_eq!(unique_name(Path::new("/b/x.jpg"), &mut names), "x (2).jpg");
+        assert_eq!(unique_name(Path::new("/c/x.jpg"), &mut names), "x (3).jpg");
+        assert_eq!(unique_name(Path::new("/c/x"), &mut names), "x");
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::visit::Evaluator;
+use crate::weight::split_weight;
+use crate::{Searcher, XTags};
+
+/// Tag satisfying one term of a search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TermMatch {
+    /// Term as displayed by Searcher.
+    pub term: String,
+
+    /// Matching tag without weight.
+    pub tag: String,
+
+    /// Value of tag without weight, None for tags without value.
+    pub value: Option<String>,
+
+    /// Named capture groups of the tag and value expressions, the value wins for equal names.
+    pub captures: HashMap<String, String>,
+}
+
+/// Tags that made a search match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchDetails {
+    /// Matches of all terms that contributed, in term order.
+    pub matches: Vec<TermMatch>,
+}
+
+impl MatchDetails {
+    /// Returns value of the first capture group named name.
+    pub fn capture(&self, name: &str) -> Option<&str> {
+        self.matches
+            .iter()
+            .find_map(|term_match| term_match.captures.get(name))
+            .map(String::as_str)
+    }
+}
+
+// Collects the matches of evaluated nodes in a frame per open node, kept by the parent only if
+// the node matched
+struct MatchCollector {
+    frames: Vec<Vec<TermMatch>>,
+}
+
+impl Evaluator for MatchCollector {
+    fn enter(&mut self, _: usize, _: &Searcher) {
+        self.frames.push(Vec::new());
+    }
+
+    fn term(&mut self, _: usize, term: &Searcher, tags: &XTags) -> bool {
+        let matched = term.is_match(tags);
+        // Terms of the whole set of tags have no single tag to show
+        if matched && !matches!(term, Searcher::TagCount { .. }) {
+            if let Some(frame) = self.frames.last_mut() {
+                *frame = term.term_matches(tags);
+            }
+        }
+        matched
+    }
+
+    fn leave(&mut self, _: usize, node: &Searcher, matched: bool) {
+        let matches = self.frames.pop().unwrap_or_default();
+        if matched && !matches!(node, Searcher::Not { .. }) {
+            if let Some(frame) = self.frames.last_mut() {
+                frame.extend(matches);
+            }
+        }
+    }
+}
+
+impl Searcher {
+    /// Returns the tags satisfying the terms of Searcher, None if tags don't match.
+    ///
+    /// Terms for single tags contribute every matching tag. Terms below not contribute nothing,
+    /// or only contributes its first matching operand. Only named capture groups are extracted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use xtag::Searcher;
+    /// let date = Searcher::new_equal("date", r"(?P<y>\d{4})-.*").unwrap();
+    /// let search = Searcher::new_and(Searcher::new_tag("photo").unwrap(), date);
+    /// let tags = xtag::csl_to_map("photo,date=2023-06-01").unwrap();
+    /// let details = search.match_details(&tags).unwrap();
+    /// assert_eq!(details.capture("y"), Some("2023"));
+    /// ```
+    pub fn match_details(&self, tags: &XTags) -> Option<MatchDetails> {
+        let mut collector = MatchCollector {
+            frames: vec![Vec::new()],
+        };
+        if !self.evaluate_with(tags, &mut collector) {
+            return None;
+        }
+        let matches = collector.frames.pop().unwrap_or_default();
+        Some(MatchDetails { matches })
+    }
+
+    // Matches of a single term against every tag on its own
+    fn term_matches(&self, tags: &XTags) -> Vec<TermMatch> {
+        let mut singles: Vec<(&String, &Option<String>)> = tags
+            .iter()
+            .filter(|(tag, value)| {
+                let single: XTags = [(tag.to_string(), Option::clone(value))].into();
+                self.is_match(&single)
+            })
+            .collect();
+        singles.sort();
+        singles
+            .into_iter()
+            .map(|(tag, value)| self.term_match(tag, value.as_deref()))
+            .collect()
+    }
+
+    fn term_match(&self, tag: &str, value: Option<&str>) -> TermMatch {
+        let tag = split_weight(tag).0;
+        let value = value.map(|value| split_weight(value).0);
+        let mut captures = HashMap::new();
+        let (tag_regex, value_regex) = match self {
+            Searcher::Tag { regex } => (Some(regex), None),
+            Searcher::Equal {
+                tag_regex,
+                value_regex,
+            } => (Some(tag_regex), Some(value_regex)),
+            Searcher::AnyValue { value_regex } => (None, Some(value_regex)),
+            Searcher::EqualCaptures { tag_regex, .. }
+            | Searcher::Less { tag_regex, .. }
+            | Searcher::LessEqual { tag_regex, .. }
+            | Searcher::Greater { tag_regex, .. }
+            | Searcher::GreaterEqual { tag_regex, .. }
+            | Searcher::IsTrue { tag_regex }
+            | Searcher::IsFalse { tag_regex }
+            | Searcher::HasValue { tag_regex }
+            | Searcher::ValuelessTag { tag_regex } => (Some(tag_regex), None),
+            _ => (None, None),
+        };
+        if let Some(tag_regex) = tag_regex {
+            add_captures(tag_regex, tag, &mut captures);
+        }
+        if let (Some(value_regex), Some(value)) = (value_regex, value) {
+            add_captures(value_regex, value, &mut captures);
+        }
+        TermMatch {
+            term: self.to_string(),
+            tag: tag.to_string(),
+            value: value.map(str::to_string),
+            captures,
+        }
+    }
+}
+
+fn add_captures(regex: &Regex, haystack: &str, captures: &mut HashMap<String, String>) {
+    let Some(found) = regex.captures(haystack) else {
+        return;
+    };
+    for name in regex.capture_names().flatten() {
+        if let Some(group) = found.name(name) {
+            captures.insert(name.to_string(), group.as_str().to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compile_search, csl_to_map, Searcher};
+
+    #[test]
+    fn details_name_matching_tags() {
+        let tags = csl_to_map("a,b=1#0.5,c=2,d").unwrap();
+        let search = compile_search("(c > 1 or b == 1) and not (d == 3)").unwrap();
+        let search = Searcher::new_and(Searcher::new_tag("(?P<t>a|x)").unwrap(), search);
+        let details = search.match_details(&tags).unwrap();
+        assert_eq!(details.matches.len(), 2);
+        assert_eq!(details.matches[0].tag, "a");
+        assert_eq!(details.capture("t"), Some("a"));
+        assert_eq!(details.matches[1].tag, "c");
+        assert_eq!(details.matches[1].value.as_deref(), Some("2"));
+
+        let details = Searcher::new_equal("b", "(?P<v>.)")
+            .unwrap()
+            .match_details(&tags);
+        assert_eq!(details.unwrap().capture("v"), Some("1"));
+        assert!(compile_search("e").unwrap().match_details(&tags).is_none());
+    }
+}
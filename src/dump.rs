@@ -0,0 +1,590 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::BufRead;
+use std::path::{Component, Path, PathBuf};
+
+use crate::aliases::split_csv;
+use crate::manifest::parse_line;
+use crate::store::xattr_store;
+use crate::walk::TreeTags;
+use crate::{
+    csl_to_map, map_to_csl, walk, BatchFailure, ManifestOperation, Result, Searcher, Store,
+    WalkOptions, XTagError, XTags,
+};
+
+static CSV_HEADER: &str = "path,hash,tags";
+
+/// Tags of one file of a TagDump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpEntry {
+    /// Path of the file relative to the dumped root.
+    pub path: PathBuf,
+
+    /// Tags of the file.
+    pub tags: XTags,
+
+    /// Content hash as `algorithm:hex`, like `sha256:2cf2...`.
+    pub hash: Option<String>,
+}
+
+/// Tags of a whole tree, kept apart from the files.
+///
+/// Extended attributes are lost when files are copied to FAT or exFAT, by many backup tools and
+/// by most cloud storage. A dump written next to the files survives and import_tree puts the
+/// tags back, or merges them with tags added meanwhile.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let options = xtag::WalkOptions::default();
+/// let dump = xtag::export_tree(Path::new("/photos"), &options).unwrap();
+/// std::fs::write("/backup/photos.csv", dump.to_csv().unwrap()).unwrap();
+///
+/// let csv = std::fs::read_to_string("/backup/photos.csv").unwrap();
+/// let dump = xtag::TagDump::from_csv(&csv).unwrap();
+/// xtag::import_tree(Path::new("/copy/photos"), &dump, xtag::ImportPolicy::Merge);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagDump {
+    /// Files with tags, sorted by path after export_tree.
+    pub entries: Vec<DumpEntry>,
+}
+
+// Relative path as string for CSV and JSON
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| XTagError::NonUnicodePath(path.to_path_buf()))
+}
+
+// Quotes field if split_csv wouldn't return it unchanged
+fn quote_csv(field: &str) -> String {
+    if field.contains([',', '"']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl TagDump {
+    /// Returns lines `path,hash,tags` after a header, with tags as comma separated list.
+    ///
+    /// Fields containing commas or quotes are quoted with `"`, the hash is empty if there is
+    /// none.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::NonUnicodePath
+    /// - XTagError::Dump if a path contains a line break
+    pub fn to_csv(&self) -> Result<String> {
+        let mut csv = format!("{CSV_HEADER}\n");
+        for entry in &self.entries {
+            let path = path_str(&entry.path)?;
+            if path.contains(['\n', '\r']) {
+                return Err(XTagError::Dump(format!("line break in path {path:?}")));
+            }
+            csv += &format!(
+                "{},{},{}\n",
+                quote_csv(path),
+                quote_csv(entry.hash.as_deref().unwrap_or_default()),
+                quote_csv(&map_to_csl(&entry.tags))
+            );
+        }
+        Ok(csv)
+    }
+
+    /// Parses CSV of to_csv, the header is optional and empty lines are skipped.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Dump if a line is invalid
+    /// - XTagError::Parser if tags are invalid
+    pub fn from_csv(csv: &str) -> Result<Self> {
+        let mut dump = TagDump::default();
+        for (index, line) in csv.lines().enumerate() {
+            if line.trim().is_empty() || (index == 0 && line.trim() == CSV_HEADER) {
+                continue;
+            }
+            let invalid = || XTagError::Dump(format!("invalid line {}", index + 1));
+            let fields = split_csv(line).ok_or_else(invalid)?;
+            let [path, hash, tags] = <[String; 3]>::try_from(fields).map_err(|_| invalid())?;
+            if path.is_empty() {
+                return Err(invalid());
+            }
+            dump.entries.push(DumpEntry {
+                path: PathBuf::from(path),
+                tags: csl_to_map(&tags)?,
+                hash: (!hash.is_empty()).then_some(hash),
+            });
+        }
+        Ok(dump)
+    }
+
+    /// Reads tags from manifest like that of export_manifest, without touching any file.
+    ///
+    /// Lines are applied in order to the tags collected so far, so manifests with `add`,
+    /// `remove` and `clear` lines give the tags the files would have after apply_manifest. Files
+    /// left without tags are left out and entries are sorted by path.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if the manifest can't be read
+    /// - XTagError::Dump with the number of the first invalid line
+    pub fn from_manifest<R: BufRead>(reader: R) -> Result<Self> {
+        let mut files: BTreeMap<PathBuf, XTags> = BTreeMap::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let (path, operation) = match parse_line(&line) {
+                Ok(Some(entry)) => entry,
+                Ok(None) => continue,
+                Err(err) => {
+                    return Err(XTagError::Dump(format!(
+                        "invalid line {}: {err}",
+                        index + 1
+                    )));
+                }
+            };
+            let tags = files.entry(PathBuf::from(path)).or_default();
+            match operation {
+                ManifestOperation::Add(add) => tags.extend(add),
+                ManifestOperation::Remove(remove) => {
+                    tags.retain(|tag, _| !remove.contains_key(tag))
+                }
+                ManifestOperation::Set(set) => *tags = set,
+                ManifestOperation::Clear => tags.clear(),
+            }
+        }
+        let entries = files
+            .into_iter()
+            .filter(|(_, tags)| !tags.is_empty())
+            .map(|(path, tags)| DumpEntry {
+                path,
+                tags,
+                hash: None,
+            })
+            .collect();
+        Ok(TagDump { entries })
+    }
+
+    /// Reads dump from file, CSV if its extension is `csv`, JSON if it is `json`, a manifest
+    /// otherwise.
+    ///
+    /// JSON needs the feature json.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if the file can't be read
+    /// - Same as from_csv, from_json and from_manifest
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => TagDump::from_csv(&content),
+            #[cfg(feature = "json")]
+            Some("json") => TagDump::from_json(&content),
+            _ => TagDump::from_manifest(content.as_bytes()),
+        }
+    }
+
+    /// Entries whose tags match searcher, in order of the dump.
+    ///
+    /// Only the tags of the dump are searched, so backups and snapshots of other machines can be
+    /// queried without the files.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let manifest = "a.jpg\tset\tphoto,rating=5\nb.jpg\tset\tphoto\nb.jpg\tadd\trating=2\n";
+    /// let dump = xtag::TagDump::from_manifest(manifest.as_bytes()).unwrap();
+    /// let search = xtag::compile_search("photo and rating > 3").unwrap();
+    /// let found: Vec<_> = dump.search(&search).map(|entry| entry.path.clone()).collect();
+    /// assert_eq!(found, [std::path::PathBuf::from("a.jpg")]);
+    /// ```
+    pub fn search<'a>(&'a self, searcher: &'a Searcher) -> impl Iterator<Item = &'a DumpEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| searcher.is_match(&entry.tags))
+    }
+
+    /// Makes absolute paths below root relative to it, for dumps of tools listing absolute paths.
+    ///
+    /// import_tree only accepts relative paths, other absolute paths are left for it to reject.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// let mut dump = xtag::TagDump::from_keyword_list("/photos/a.jpg\tbeach").unwrap();
+    /// dump.make_relative(Path::new("/photos"));
+    /// assert_eq!(dump.entries[0].path, Path::new("a.jpg"));
+    /// ```
+    pub fn make_relative(&mut self, root: &Path) {
+        for entry in &mut self.entries {
+            if let Ok(relative) = entry.path.strip_prefix(root) {
+                entry.path = relative.to_path_buf();
+            }
+        }
+    }
+
+    /// Adds content hashes of all files below root, replacing existing ones.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if a file can't be read
+    #[cfg(feature = "checksum")]
+    pub fn add_hashes(&mut self, root: &Path, algorithm: crate::ChecksumAlgorithm) -> Result<()> {
+        for entry in &mut self.entries {
+            let checksum = crate::checksum::compute(&root.join(&entry.path), algorithm)?;
+            entry.hash = Some(format!("{}:{checksum}", hash_name(algorithm)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "checksum")]
+fn hash_name(algorithm: crate::ChecksumAlgorithm) -> &'static str {
+    algorithm.tag().trim_start_matches("checksum:")
+}
+
+// Returns false if file at path has other content than hash, unknown algorithms match
+#[cfg(feature = "checksum")]
+fn is_hash_match(path: &Path, hash: &str) -> Result<bool> {
+    use crate::ChecksumAlgorithm;
+
+    for algorithm in [ChecksumAlgorithm::Crc32, ChecksumAlgorithm::Sha256] {
+        if let Some(expected) = hash.strip_prefix(&format!("{}:", hash_name(algorithm))) {
+            return Ok(crate::checksum::compute(path, algorithm)? == expected);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(not(feature = "checksum"))]
+fn is_hash_match(_path: &Path, _hash: &str) -> Result<bool> {
+    Ok(true)
+}
+
+#[cfg(feature = "json")]
+impl TagDump {
+    /// Returns JSON object `{"files":[{"path":"a.jpg","hash":null,"tags":{"rating":"4"}}]}`.
+    ///
+    /// Tags without value are null.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::NonUnicodePath
+    pub fn to_json(&self) -> Result<String> {
+        let files = self
+            .entries
+            .iter()
+            .map(|entry| {
+                Ok(serde_json::json!({
+                    "path": path_str(&entry.path)?,
+                    "hash": entry.hash,
+                    "tags": entry.tags,
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(serde_json::json!({ "files": files }).to_string())
+    }
+
+    /// Parses JSON of to_json, a missing hash is the same as null.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Dump if json isn't valid JSON or no valid dump
+    pub fn from_json(json: &str) -> Result<Self> {
+        use serde_json::Value;
+
+        let invalid = |message: &str| XTagError::Dump(message.to_string());
+        let value: Value = serde_json::from_str(json).map_err(|err| invalid(&err.to_string()))?;
+        let files = value["files"]
+            .as_array()
+            .ok_or_else(|| invalid("files is no array"))?;
+        let mut dump = TagDump::default();
+        for file in files {
+            let path = file["path"]
+                .as_str()
+                .ok_or_else(|| invalid("path is no string"))?;
+            let hash = match &file["hash"] {
+                Value::Null => None,
+                Value::String(hash) => Some(hash.clone()),
+                _ => return Err(invalid("hash is no string")),
+            };
+            let mut tags = XTags::new();
+            for (tag, value) in file["tags"]
+                .as_object()
+                .ok_or_else(|| invalid("tags is no object"))?
+            {
+                let value = match value {
+                    Value::Null => None,
+                    Value::String(value) => Some(value.clone()),
+                    _ => return Err(invalid("tag value is no string")),
+                };
+                tags.insert(tag.clone(), value);
+            }
+            dump.entries.push(DumpEntry {
+                path: PathBuf::from(path),
+                tags,
+                hash,
+            });
+        }
+        Ok(dump)
+    }
+}
+
+/// Collects tags of all files below root with paths relative to root.
+///
+/// Files without tags are left out and entries are sorted by path, so dumps of unchanged trees
+/// are equal. Hashes are added by TagDump::add_hashes.
+///
+/// # Errors
+///
+/// Same as walk and get_tags, files without attribute support are handled by options
+pub fn export_tree(root: &Path, options: &WalkOptions) -> Result<TagDump> {
+    let mut dump = TagDump::default();
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        let path = path?;
+        let Some(tags) = tree.get(&path)? else {
+            continue;
+        };
+        if tags.is_empty() {
+            continue;
+        }
+        dump.entries.push(DumpEntry {
+            path: path.strip_prefix(root).unwrap_or(&path).to_path_buf(),
+            tags,
+            hash: None,
+        });
+    }
+    dump.entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(dump)
+}
+
+/// How import_tree combines tags of the dump with tags files already have.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportPolicy {
+    /// Replaces all tags of files with those of the dump.
+    Replace,
+
+    /// Adds tags of the dump, their values win over existing ones.
+    #[default]
+    Merge,
+
+    /// Adds only tags files don't have yet.
+    KeepExisting,
+}
+
+/// Outcome of import_tree.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// Number of files whose tags were changed.
+    pub imported: usize,
+
+    /// Number of files that already had the resulting tags.
+    pub unchanged: usize,
+
+    /// Files of the dump that don't exist below root.
+    pub missing: Vec<PathBuf>,
+
+    /// Files whose content doesn't match the hash of the dump, their tags are left alone.
+    pub mismatched: Vec<PathBuf>,
+
+    /// Files that failed.
+    pub failed: Vec<BatchFailure>,
+}
+
+/// Writes tags of dump to the files below root.
+///
+/// Entries with hash are only imported if the content of the file still matches, which needs
+/// the checksum feature, otherwise hashes are ignored. Failing files don't stop the import, they
+/// are collected in the report. Entries whose paths could leave root, like absolute ones or ones
+/// with `..`, fail with XTagError::Dump.
+pub fn import_tree(root: &Path, dump: &TagDump, policy: ImportPolicy) -> ImportReport {
+    import_tree_with_store(root, dump, policy, xattr_store())
+}
+
+/// Writes tags of dump to the files below root in store, see import_tree.
+pub fn import_tree_with_store(
+    root: &Path,
+    dump: &TagDump,
+    policy: ImportPolicy,
+    store: &dyn Store,
+) -> ImportReport {
+    let mut report = ImportReport::default();
+    for entry in &dump.entries {
+        let is_plain = |component| matches!(component, Component::Normal(_));
+        if entry.path.as_os_str().is_empty() || !entry.path.components().all(is_plain) {
+            report.failed.push(BatchFailure {
+                path: entry.path.clone(),
+                error: XTagError::Dump(format!("path {:?} is no plain relative path", entry.path)),
+            });
+            continue;
+        }
+        let path = root.join(&entry.path);
+        if !path.exists() {
+            report.missing.push(path);
+            continue;
+        }
+        match import_entry(&path, entry, policy, store) {
+            Ok(Some(true)) => report.imported += 1,
+            Ok(Some(false)) => report.unchanged += 1,
+            Ok(None) => report.mismatched.push(path),
+            Err(error) => report.failed.push(BatchFailure { path, error }),
+        }
+    }
+    report
+}
+
+// Returns whether tags changed, None if the hash doesn't match
+fn import_entry(
+    path: &Path,
+    entry: &DumpEntry,
+    policy: ImportPolicy,
+    store: &dyn Store,
+) -> Result<Option<bool>> {
+    if let Some(hash) = &entry.hash {
+        if !is_hash_match(path, hash)? {
+            return Ok(None);
+        }
+    }
+    let current = store.get_tags(path)?;
+    let mut tags = match policy {
+        ImportPolicy::Replace => XTags::new(),
+        ImportPolicy::Merge | ImportPolicy::KeepExisting => current.clone(),
+    };
+    for (tag, value) in &entry.tags {
+        if policy != ImportPolicy::KeepExisting || !tags.contains_key(tag) {
+            tags.insert(tag.clone(), value.clone());
+        }
+    }
+    if tags == current {
+        return Ok(Some(false));
+    }
+    if tags.is_empty() {
+        store.delete_tags(path)?;
+    } else {
+        store.set_tags(path, &tags)?;
+    }
+    Ok(Some(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{export_tree, import_tree, ImportPolicy, TagDump};
+    use crate::{csl_to_map, delete_tags, get_tags, set_tags, Fixture, WalkOptions, XTagError};
+
+    #[test]
+    fn dumps_restore_and_merge_tags() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("photo,rating=4")
+            .file("sub/ b,\"c\"")
+            .tags("x=1,2")
+            .file("untagged");
+        let dump = export_tree(fixture.path(), &WalkOptions::default()).unwrap();
+        assert_eq!(dump.entries.len(), 2);
+        let csv = dump.to_csv().unwrap();
+        assert_eq!(TagDump::from_csv(&csv).unwrap(), dump);
+        assert!(TagDump::from_csv("a,b").is_err());
+
+        let a = fixture.join("a");
+        delete_tags(&fixture.join("sub/ b,\"c\"")).unwrap();
+        set_tags(&a, &csl_to_map("rating=5,new").unwrap()).unwrap();
+        let report = import_tree(fixture.path(), &dump, ImportPolicy::KeepExisting);
+        assert_eq!((report.imported, report.unchanged), (2, 0));
+        assert_eq!(
+            get_tags(&a).unwrap(),
+            csl_to_map("photo,rating=5,new").unwrap()
+        );
+        assert_eq!(
+            get_tags(&fixture.join("sub/ b,\"c\"")).unwrap(),
+            csl_to_map("x=1,2").unwrap()
+        );
+
+        import_tree(fixture.path(), &dump, ImportPolicy::Merge);
+        assert_eq!(
+            get_tags(&a).unwrap(),
+            csl_to_map("photo,rating=4,new").unwrap()
+        );
+        let report = import_tree(fixture.path(), &dump, ImportPolicy::Replace);
+        assert_eq!((report.imported, report.unchanged), (1, 1));
+        assert_eq!(get_tags(&a).unwrap(), csl_to_map("photo,rating=4").unwrap());
+
+        let report = import_tree(&fixture.join("sub"), &dump, ImportPolicy::Merge);
+        assert_eq!(report.missing.len(), 2);
+    }
+
+    #[test]
+    fn dumps_cant_tag_files_outside_root() {
+        let fixture = Fixture::new().file("root/a").file("outside");
+        let outside = fixture.join("outside");
+        let csv = format!(
+            "path,hash,tags\n../outside,,x\n{},,x\n./a,,x\nroot/../a,,x\na,,x\n",
+            outside.display()
+        );
+        let dump = TagDump::from_csv(&csv).unwrap();
+        let report = import_tree(&fixture.join("root"), &dump, ImportPolicy::Merge);
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.failed.len(), 4);
+        assert!(matches!(report.failed[0].error, XTagError::Dump(_)));
+        assert!(get_tags(&outside).unwrap().is_empty());
+    }
+
+    #[test]
+    fn dumps_are_searched_without_files() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("photo,rating=4")
+            .file("b")
+            .tags("photo")
+            .file("c")
+            .tags("video");
+        let mut manifest = Vec::new();
+        crate::export_manifest(fixture.path(), &WalkOptions::default(), &mut manifest).unwrap();
+        manifest.extend(b"# later changes\nb\tadd\trating=5\nc\tclear\nd\tset\tphoto\n");
+        let path = fixture.join("backup.tsv");
+        std::fs::write(&path, manifest).unwrap();
+        std::fs::remove_file(fixture.join("a")).unwrap();
+        std::fs::remove_file(fixture.join("b")).unwrap();
+
+        let dump = TagDump::load(&path).unwrap();
+        assert_eq!(dump.entries.len(), 3);
+        let search = crate::compile_search("photo and not (rating < 5)").unwrap();
+        let found: Vec<_> = dump.search(&search).map(|entry| &entry.path).collect();
+        assert_eq!(found, [Path::new("b"), Path::new("d")]);
+
+        let result = TagDump::from_manifest("a\tset\tx\nb\tbogus\n".as_bytes());
+        assert!(matches!(result, Err(XTagError::Dump(message)) if message.contains("line 2")));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn dumps_convert_to_json() {
+        let csv = "path,hash,tags\na,sha256:00,\"photo,rating=4\"\nb,,x\n";
+        let dump = TagDump::from_csv(csv).unwrap();
+        assert_eq!(TagDump::from_json(&dump.to_json().unwrap()).unwrap(), dump);
+        assert!(TagDump::from_json(r#"{"files":[{"path":"a","tags":{"x":1}}]}"#).is_err());
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn changed_content_is_not_imported() {
+        let fixture = Fixture::new()
+            .file_with_content("a", "hello")
+            .tags("photo")
+            .file_with_content("b", "world")
+            .tags("photo");
+        let mut dump = export_tree(fixture.path(), &WalkOptions::default()).unwrap();
+        dump.add_hashes(fixture.path(), crate::ChecksumAlgorithm::Crc32)
+            .unwrap();
+        std::fs::write(fixture.join("b"), "changed").unwrap();
+        delete_tags(&fixture.join("a")).unwrap();
+        delete_tags(&fixture.join("b")).unwrap();
+        let report = import_tree(fixture.path(), &dump, ImportPolicy::Merge);
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.mismatched, vec![fixture.join("b")]);
+    }
+}
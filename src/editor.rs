@@ -0,0 +1,248 @@
+use std::fmt;
+use std::path::Path;
+use std::str;
+
+use crate::encoding::decode_attribute;
+use crate::parse_tags::escape_value;
+use crate::read_only::ensure_writable;
+use crate::{csl_to_map, Result, XTagError, XTags, XATTR_NAME};
+
+// One comma separated part of the attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entry {
+    // Parsed pair with its text as found
+    Pair {
+        tag: String,
+        value: Option<String>,
+        raw: String,
+    },
+
+    // Text xtag doesn't understand, kept as it is
+    Unknown(String),
+}
+
+impl Entry {
+    fn parse(raw: &str) -> Entry {
+        match csl_to_map(raw) {
+            Ok(tags) if tags.len() == 1 => {
+                let (tag, value) = tags.into_iter().next().unwrap();
+                Entry::Pair {
+                    tag,
+                    value,
+                    raw: raw.to_string(),
+                }
+            }
+            _ => Entry::Unknown(raw.to_string()),
+        }
+    }
+
+    fn raw(&self) -> &str {
+        match self {
+            Entry::Pair { raw, .. } | Entry::Unknown(raw) => raw,
+        }
+    }
+
+    fn is_tag(&self, name: &str) -> bool {
+        matches!(self, Entry::Pair { tag, .. } if tag == name)
+    }
+}
+
+// Parts of csl at commas not escaped by a backslash
+fn split_entries(csl: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (index, c) in csl.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ',' => {
+                entries.push(&csl[start..index]);
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    entries.push(&csl[start..]);
+    entries
+}
+
+/// Edits single tags of the raw attribute string.
+///
+/// set_tags rewrites the whole attribute from a map, dropping everything csl_to_map rejects
+/// and reordering the rest. Other programs writing user.xtag may store entries xtag doesn't
+/// understand. XTagsEditor only touches the edited pairs, unknown entries and the text and order
+/// of all other entries are kept.
+///
+/// # Example
+///
+/// ```
+/// let mut editor = xtag::XTagsEditor::new("b=1,+++,a");
+/// editor.set("b", Some("2")).unwrap();
+/// editor.set("c", None).unwrap();
+/// editor.remove("a");
+/// assert_eq!(editor.to_string(), "b=2,+++,c");
+/// assert_eq!(editor.unknown().collect::<Vec<_>>(), ["+++"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XTagsEditor {
+    entries: Vec<Entry>,
+}
+
+impl XTagsEditor {
+    /// Returns editor of comma separated list of tag=value pairs.
+    pub fn new(csl: &str) -> Self {
+        let entries = match csl {
+            "" => Vec::new(),
+            csl => split_entries(csl).into_iter().map(Entry::parse).collect(),
+        };
+        XTagsEditor { entries }
+    }
+
+    /// Returns editor of tags of file, empty if it has none.
+    ///
+    /// Attributes in the hex or base64 notation of setfattr are decoded, write stores them as
+    /// plain text.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if the attribute can't be read
+    /// - XTagError::Charset if the attribute isn't valid UTF-8
+    pub fn read(path: &Path) -> Result<Self> {
+        match xattr::get(path, XATTR_NAME).map_err(XTagError::File)? {
+            Some(value) => {
+                let value = decode_attribute(&value);
+                Ok(XTagsEditor::new(
+                    str::from_utf8(&value).map_err(XTagError::Charset)?,
+                ))
+            }
+            None => Ok(XTagsEditor::default()),
+        }
+    }
+
+    /// Writes edited attribute to file, removes it if nothing is left.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if the attribute can't be written
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if self.entries.is_empty() {
+            return crate::delete_tags(path);
+        }
+        ensure_writable(path)?;
+        xattr::set(path, XATTR_NAME, self.to_string().as_bytes()).map_err(XTagError::File)
+    }
+
+    /// Value of tag, None if there is no such tag.
+    pub fn get(&self, tag: &str) -> Option<Option<&str>> {
+        self.entries.iter().rev().find_map(|entry| match entry {
+            Entry::Pair {
+                tag: name, value, ..
+            } if name == tag => Some(value.as_deref()),
+            _ => None,
+        })
+    }
+
+    /// Sets value of tag, keeping its position if it exists and appending it otherwise.
+    ///
+    /// Other entries of the same tag are removed.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::InvalidTags if tag isn't a valid tag name
+    pub fn set(&mut self, tag: &str, value: Option<&str>) -> Result<()> {
+        let raw = match value {
+            Some(value) => format!("{tag}={}", escape_value(value)),
+            None => tag.to_string(),
+        };
+        let entry = Entry::parse(&raw);
+        if !entry.is_tag(tag) {
+            return Err(XTagError::InvalidTags(raw));
+        }
+        match self.entries.iter().position(|entry| entry.is_tag(tag)) {
+            Some(index) => {
+                // Entries before the first one of tag stay, so index remains valid
+                let mut is_first = true;
+                self.entries.retain(|entry| {
+                    let keep = is_first || !entry.is_tag(tag);
+                    is_first &= !entry.is_tag(tag);
+                    keep
+                });
+                self.entries[index] = entry;
+            }
+            None => self.entries.push(entry),
+        }
+        Ok(())
+    }
+
+    /// Removes all entries of tag, returns true if there were any.
+    pub fn remove(&mut self, tag: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| !entry.is_tag(tag));
+        self.entries.len() != len
+    }
+
+    /// Understood tags as map, the last entry wins for repeated tags like with csl_to_map.
+    pub fn tags(&self) -> XTags {
+        self.entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Pair { tag, value, .. } => Some((tag.clone(), value.clone())),
+                Entry::Unknown(_) => None,
+            })
+            .collect()
+    }
+
+    /// Entries that aren't understood, in their original order.
+    pub fn unknown(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().filter_map(|entry| match entry {
+            Entry::Unknown(raw) => Some(raw.as_str()),
+            Entry::Pair { .. } => None,
+        })
+    }
+
+    /// Returns true if there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl fmt::Display for XTagsEditor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, entry) in self.entries.iter().enumerate() {
+            if index > 0 {
+                f.write_str(",")?;
+            }
+            f.write_str(entry.raw())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::XTagsEditor;
+    use crate::{csl_to_map, get_tags, Fixture};
+
+    #[test]
+    fn edits_keep_untouched_entries() {
+        let mut editor = XTagsEditor::new("z, a = b\\,c ,{json},x=1,,x=2");
+        assert_eq!(editor.get("a"), Some(Some("b,c")));
+        assert_eq!(editor.get("x"), Some(Some("2")));
+        assert_eq!(editor.unknown().collect::<Vec<_>>(), ["{json}", ""]);
+        assert_eq!(editor.tags(), csl_to_map("z,a=b\\,c,x=2").unwrap());
+
+        editor.set("x", Some("3, 4")).unwrap();
+        assert!(editor.remove("z"));
+        assert!(!editor.remove("missing"));
+        assert!(editor.set("{bad}", None).is_err());
+        assert_eq!(editor.to_string(), " a = b\\,c ,{json},x=3\\, 4,");
+
+        let fixture = Fixture::new().file("a");
+        editor.write(&fixture.join("a")).unwrap();
+        let read = XTagsEditor::read(&fixture.join("a")).unwrap();
+        assert_eq!(read, editor);
+        XTagsEditor::new("").write(&fixture.join("a")).unwrap();
+        assert!(get_tags(&fixture.join("a")).unwrap().is_empty());
+    }
+}
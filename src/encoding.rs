@@ -0,0 +1,88 @@
+use std::borrow::Cow;
+
+// Removes what tools like to append and decodes the hex (0x) and base64 (0s) notation of
+// setfattr, if the result is text. A tag literally named like a valid hex or base64 encoding of
+// text is therefore read decoded.
+pub(crate) fn decode_attribute(value: &[u8]) -> Cow<'_, [u8]> {
+    let mut end = value.len();
+    while end > 0 && matches!(value[end - 1], b'\0' | b'\n' | b'\r') {
+        end -= 1;
+    }
+    let value = &value[..end];
+
+    let decoded = match value {
+        [b'0', b'x' | b'X', hex @ ..] => decode_hex(hex),
+        [b'0', b's' | b'S', base64 @ ..] => decode_base64(base64),
+        _ => None,
+    };
+    match decoded {
+        Some(decoded) if std::str::from_utf8(&decoded).is_ok() => {
+            Cow::Owned(decode_attribute(&decoded).into_owned())
+        }
+        _ => Cow::Borrowed(value),
+    }
+}
+
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if hex.is_empty() || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+pub(crate) fn decode_base64(base64: &[u8]) -> Option<Vec<u8>> {
+    let base64: Vec<u8> = base64.iter().copied().filter(|&c| c != b'=').collect();
+    if base64.is_empty() || base64.len() % 4 == 1 {
+        return None;
+    }
+    let sextets: Vec<u8> = base64
+        .iter()
+        .map(|&c| match c {
+            b'A'..=b'Z' => Some(c - b'A'),
+            b'a'..=b'z' => Some(c - b'a' + 26),
+            b'0'..=b'9' => Some(c - b'0' + 52),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    let mut decoded = Vec::with_capacity(sextets.len() * 3 / 4);
+    for chunk in sextets.chunks(4) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (index, &sextet)| {
+                bits | (u32::from(sextet) << (18 - 6 * index))
+            });
+        let bytes = bits.to_be_bytes();
+        decoded.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode_attribute;
+
+    fn decode(value: &[u8]) -> Vec<u8> {
+        decode_attribute(value).into_owned()
+    }
+
+    #[test]
+    fn attributes_are_decoded() {
+        assert_eq!(decode(b"a,b=1\n\0"), b"a,b=1");
+        assert_eq!(decode(b"0x612c623d31"), b"a,b=1");
+        assert_eq!(decode(b"0sYSxiPTE="), b"a,b=1");
+        assert_eq!(decode(b"0sYQ"), b"a");
+        assert_eq!(decode(b"0x612c623d310a"), b"a,b=1");
+        assert_eq!(decode(b"0xff"), b"0xff");
+        assert_eq!(decode(b"0x1"), b"0x1");
+        assert_eq!(decode(b"0sA"), b"0sA");
+        assert_eq!(decode(b"0x"), b"0x");
+    }
+}
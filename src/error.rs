@@ -1,8 +1,12 @@
 use std::ffi::OsString;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use thiserror::Error;
 
-use crate::Rule;
+#[cfg(feature = "full")]
+use crate::{Collision, SearchParseError, Violation};
 
 #[derive(Error, Debug)]
 pub enum XTagError {
@@ -12,21 +16,203 @@ pub enum XTagError {
     #[error("utf8 error")]
     Charset(#[from] ::std::str::Utf8Error), // FIXME check
 
+    #[cfg(feature = "full")]
     #[error("parse error")]
-    Parser(#[from] ::pest::error::Error<Rule>),
+    Parser(#[from] SearchParseError),
 
     // Used when grammar and parser implementation are incompatible
     #[error("parser implementation error {0}")]
     ParserImplementation(String),
 
+    #[cfg(feature = "full")]
     #[error("regex error")]
     Regex(#[from] ::regex::Error), // FIXME check
 
     #[error("int parser error")]
     IntParse(#[from] ::core::num::ParseIntError),
 
+    #[error("timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("index is locked by another process {0:?}")]
+    IndexLocked(PathBuf),
+
+    #[error("value is neither integer, float nor date {0:?}")]
+    ComparisonValue(String),
+
     #[error("no valid bookmark {0:?}")]
     Bookmark(OsString),
+
+    #[error("bookmarks reference each other {0:?}")]
+    BookmarkCycle(Vec<String>),
+
+    #[error("search can't be written as term: {0}")]
+    Unrepresentable(String),
+
+    #[error("path is not valid unicode {0:?}")]
+    NonUnicodePath(PathBuf),
+
+    #[error("tags can't be stored unchanged {0:?}")]
+    InvalidTags(String),
+
+    #[error("invalid tag group name {0:?}")]
+    InvalidGroup(String),
+
+    #[error("invalid view name {0:?}")]
+    InvalidView(String),
+
+    #[error("no view {0:?}")]
+    UnknownView(String),
+
+    #[error("no version {0} in history")]
+    UnknownVersion(usize),
+
+    #[error("no tombstone of tag {0}")]
+    NoTombstone(String),
+
+    #[error("daemon error: {0}")]
+    Daemon(String),
+
+    #[error("invalid cache: {0}")]
+    Cache(String),
+
+    #[error("search term too complex: {0}")]
+    QueryTooComplex(String),
+
+    #[error("invalid query set: {0}")]
+    QuerySet(String),
+
+    #[error("invalid query {name:?}")]
+    InvalidQuery {
+        name: String,
+        #[source]
+        source: Box<XTagError>,
+    },
+
+    #[error("invalid alias table: {0}")]
+    Aliases(String),
+
+    #[error("invalid journal: {0}")]
+    Journal(String),
+
+    #[error("invalid event log: {0}")]
+    EventLog(String),
+
+    #[error("invalid manifest line: {0}")]
+    Manifest(String),
+
+    #[error("invalid tag dump: {0}")]
+    Dump(String),
+
+    #[error("tag limit exceeded: {0}")]
+    TagLimit(String),
+
+    #[error("invalid value template: {0}")]
+    Template(String),
+
+    #[cfg(feature = "full")]
+    #[error("renaming would merge tags {0:?}")]
+    RenameCollision(Vec<Collision>),
+
+    #[error("invalid tag schema: {0}")]
+    InvalidSchema(String),
+
+    #[cfg(feature = "full")]
+    #[error("tags break schema {0:?}")]
+    SchemaViolation(Vec<Violation>),
+
+    #[error("tags of {0:?} can't be changed in read-only mode")]
+    ReadOnly(PathBuf),
+
+    #[error("no permission for attribute namespace {0:?}")]
+    NamespacePermission(String),
+
+    #[error("giving up on {path:?} after {attempts} attempts")]
+    RetriesExhausted {
+        path: PathBuf,
+        attempts: u32,
+        #[source]
+        source: Box<XTagError>,
+    },
+
+    #[error("rollback of {path:?} failed, its tags were {tags:?}")]
+    RollbackFailed {
+        path: PathBuf,
+        tags: String,
+        #[source]
+        source: Box<XTagError>,
+    },
+
+    #[cfg(feature = "json")]
+    #[error("invalid json ast: {0}")]
+    JsonAst(String),
+
+    #[cfg(feature = "interop")]
+    #[error("interop error: {0}")]
+    Interop(String),
+
+    #[cfg(feature = "literal")]
+    #[error("literal search syntax error: {0}")]
+    LiteralSyntax(String),
+
+    #[cfg(feature = "arrow")]
+    #[error("table export failed: {0}")]
+    Arrow(String),
+
+    #[cfg(feature = "zip")]
+    #[error("invalid archive: {0}")]
+    Archive(String),
+
+    #[cfg(feature = "watch")]
+    #[error("watching failed: {0}")]
+    Watch(String),
+
+    #[cfg(feature = "parallel")]
+    #[error("thread pool error")]
+    ThreadPool(#[from] ::rayon::ThreadPoolBuildError),
 }
 
 pub type Result<T> = std::result::Result<T, XTagError>;
+
+// XTagError has to stay usable across threads and with boxed error handlers
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync + 'static>() {}
+    assert_send_sync::<XTagError>();
+};
+
+/// Converts into io::Error.
+///
+/// Wrapped io errors are returned unchanged, everything else becomes an error of kind
+/// InvalidData with the XTagError as inner error.
+impl From<XTagError> for io::Error {
+    fn from(err: XTagError) -> Self {
+        match err {
+            XTagError::File(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    #[cfg(feature = "full")]
+    use crate::compile_search;
+
+    #[test]
+    fn io_error_keeps_kind() {
+        let err = io::Error::from(io::ErrorKind::NotFound);
+        let err = io::Error::from(super::XTagError::File(err));
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    #[cfg(feature = "full")]
+    fn io_error_wraps_other_errors() {
+        let err = io::Error::from(compile_search("a b").err().unwrap());
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let inner = err.get_ref().unwrap();
+        assert!(inner.source().is_some());
+    }
+}
@@ -0,0 +1,349 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::{Result, XTagError, XTags};
+
+/// Version of the event log format, the field `v` of every line.
+pub const EVENT_LOG_VERSION: u64 = 1;
+
+/// Change recorded in an event log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogChange {
+    /// Tag was added, `"event":"added"`.
+    Added { tag: String, value: Option<String> },
+
+    /// Tag was removed with its last value, `"event":"removed"`.
+    Removed { tag: String, value: Option<String> },
+
+    /// Value of tag changed, `"event":"changed"` with the previous value in `old`.
+    Changed {
+        tag: String,
+        old: Option<String>,
+        value: Option<String>,
+    },
+
+    /// File was deleted or moved away with unknown tags, `"event":"file_removed"` without tag.
+    FileRemoved,
+}
+
+/// Changes turning tags old into new, sorted by tag.
+pub fn tag_changes(old: &XTags, new: &XTags) -> Vec<LogChange> {
+    let mut changes: Vec<LogChange> = new
+        .iter()
+        .filter_map(|(tag, value)| match old.get(tag) {
+            None => Some(LogChange::Added {
+                tag: tag.clone(),
+                value: value.clone(),
+            }),
+            Some(old) if old != value => Some(LogChange::Changed {
+                tag: tag.clone(),
+                old: old.clone(),
+                value: value.clone(),
+            }),
+            Some(_) => None,
+        })
+        .collect();
+    changes.extend(
+        old.iter()
+            .filter(|(tag, _)| !new.contains_key(*tag))
+            .map(|(tag, value)| LogChange::Removed {
+                tag: tag.clone(),
+                value: value.clone(),
+            }),
+    );
+    changes.sort_by(|a, b| a.tag().cmp(&b.tag()));
+    changes
+}
+
+impl LogChange {
+    /// Changed tag, None for FileRemoved.
+    pub fn tag(&self) -> Option<&str> {
+        match self {
+            LogChange::Added { tag, .. }
+            | LogChange::Removed { tag, .. }
+            | LogChange::Changed { tag, .. } => Some(tag),
+            LogChange::FileRemoved => None,
+        }
+    }
+}
+
+/// One line of an event log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEvent {
+    /// Time of the change, written in whole seconds.
+    pub time: SystemTime,
+
+    /// Path of the file as given to the producer, absolute for Journaled.
+    pub path: PathBuf,
+
+    /// What changed.
+    pub change: LogChange,
+}
+
+fn invalid(line: &str, reason: &str) -> XTagError {
+    XTagError::EventLog(format!("{reason} in {line:?}"))
+}
+
+impl LogEvent {
+    /// Returns event as JSON object on one line, without line break.
+    ///
+    /// Every object has the fields `v` with EVENT_LOG_VERSION, `time` in seconds since 1970 UTC,
+    /// `path` and `event`, one of `added`, `removed`, `changed` and `file_removed`. Tag events
+    /// have `tag` and `value`, null for tags without value, `changed` also has `old`. Later
+    /// versions only add fields and events, which readers of this version ignore.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::path::PathBuf;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// use xtag::{LogChange, LogEvent};
+    ///
+    /// let event = LogEvent {
+    ///     time: UNIX_EPOCH + Duration::from_secs(1_709_210_096),
+    ///     path: PathBuf::from("/photos/a.jpg"),
+    ///     change: LogChange::Added { tag: "photo".to_string(), value: None },
+    /// };
+    /// assert_eq!(
+    ///     event.to_json_line().unwrap(),
+    ///     r#"{"event":"added","path":"/photos/a.jpg","tag":"photo","time":1709210096,"v":1,"value":null}"#
+    /// );
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::NonUnicodePath
+    pub fn to_json_line(&self) -> Result<String> {
+        let path = self
+            .path
+            .to_str()
+            .ok_or_else(|| XTagError::NonUnicodePath(self.path.clone()))?;
+        let time = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut object = json!({
+            "v": EVENT_LOG_VERSION,
+            "time": time.as_secs(),
+            "path": path,
+        });
+        let fields = match &self.change {
+            LogChange::Added { tag, value } => {
+                json!({"event": "added", "tag": tag, "value": value})
+            }
+            LogChange::Removed { tag, value } => {
+                json!({"event": "removed", "tag": tag, "value": value})
+            }
+            LogChange::Changed { tag, old, value } => {
+                json!({"event": "changed", "tag": tag, "old": old, "value": value})
+            }
+            LogChange::FileRemoved => json!({"event": "file_removed"}),
+        };
+        if let (Value::Object(object), Value::Object(fields)) = (&mut object, fields) {
+            object.extend(fields);
+        }
+        Ok(object.to_string())
+    }
+
+    /// Parses line of to_json_line, ignoring unknown fields.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::EventLog if line is no valid event or of a later version with unknown events
+    pub fn from_json_line(line: &str) -> Result<Self> {
+        let object: Value =
+            serde_json::from_str(line).map_err(|err| invalid(line, &err.to_string()))?;
+        let version = object["v"]
+            .as_u64()
+            .ok_or_else(|| invalid(line, "no version"))?;
+        let string = |field: &str| {
+            object[field]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| invalid(line, &format!("{field} is no string")))
+        };
+        let value = |field: &str| match &object[field] {
+            Value::Null => Ok(None),
+            Value::String(value) => Ok(Some(value.clone())),
+            _ => Err(invalid(line, &format!("{field} is no string"))),
+        };
+        let change = match string("event")?.as_str() {
+            "added" => LogChange::Added {
+                tag: string("tag")?,
+                value: value("value")?,
+            },
+            "removed" => LogChange::Removed {
+                tag: string("tag")?,
+                value: value("value")?,
+            },
+            "changed" => LogChange::Changed {
+                tag: string("tag")?,
+                old: value("old")?,
+                value: value("value")?,
+            },
+            "file_removed" => LogChange::FileRemoved,
+            event if version > EVENT_LOG_VERSION => {
+                return Err(invalid(
+                    line,
+                    &format!("event {event} of version {version}"),
+                ))
+            }
+            _ => return Err(invalid(line, "unknown event")),
+        };
+        let seconds = object["time"]
+            .as_u64()
+            .ok_or_else(|| invalid(line, "time is no number"))?;
+        Ok(LogEvent {
+            time: UNIX_EPOCH + Duration::from_secs(seconds),
+            path: PathBuf::from(string("path")?),
+            change,
+        })
+    }
+}
+
+/// File of tag change events in JSON lines, see LogEvent::to_json_line.
+///
+/// The format is versioned and documented, so other programs like sync daemons can follow it.
+/// Journaled and, with the `watch` feature, TagWatcher events can be written to it.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let log = xtag::EventLog::new(Path::new("/var/log/xtag.jsonl"));
+/// let journal = xtag::Journaled::new(Path::new("/var/lib/xtag/journal")).with_event_log(log);
+/// journal.set_tags(Path::new("a.jpg"), &xtag::csl_to_map("photo").unwrap()).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    path: PathBuf,
+}
+
+impl EventLog {
+    /// Returns EventLog appending to path, which is created on the first event.
+    pub fn new(path: &Path) -> Self {
+        EventLog {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends events as lines.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::NonUnicodePath if a path isn't valid unicode, nothing is written then
+    /// - XTagError::File if writing fails
+    pub fn append(&self, events: &[LogEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for event in events {
+            lines += &event.to_json_line()?;
+            lines.push('\n');
+        }
+        // One write, so appends of several processes don't interleave
+        let mut file = File::options().create(true).append(true).open(&self.path)?;
+        file.write_all(lines.as_bytes())?;
+        Ok(())
+    }
+
+    /// Appends events of a TagWatcher, stamped with the current time.
+    ///
+    /// # Errors
+    ///
+    /// Same as append
+    #[cfg(feature = "watch")]
+    pub fn append_tag_events(&self, events: &[crate::TagEvent]) -> Result<()> {
+        use crate::TagEvent;
+
+        let time = SystemTime::now();
+        let mut log_events = Vec::new();
+        for event in events {
+            let changes = match event {
+                TagEvent::Created { tags, .. } => tag_changes(&XTags::new(), tags),
+                TagEvent::TagsChanged { old, tags, .. } => tag_changes(old, tags),
+                TagEvent::Removed { .. } => vec![LogChange::FileRemoved],
+            };
+            log_events.extend(changes.into_iter().map(|change| LogEvent {
+                time,
+                path: event.path().to_path_buf(),
+                change,
+            }));
+        }
+        self.append(&log_events)
+    }
+
+    /// Events of the log, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::EventLog if a line is no valid event
+    pub fn read(&self) -> Result<Vec<LogEvent>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        content.lines().map(LogEvent::from_json_line).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventLog, LogChange, LogEvent};
+    use crate::{csl_to_map, Fixture, Journaled};
+
+    #[test]
+    fn journaled_changes_are_logged() {
+        let fixture = Fixture::new().file("a").tags("photo,rating=3,draft");
+        let log = EventLog::new(&fixture.join("events.jsonl"));
+        let journal = Journaled::new(&fixture.join("journal")).with_event_log(log.clone());
+        let file = fixture.join("a");
+        journal
+            .set_tags(&file, &csl_to_map("photo,rating=5,place=Rome").unwrap())
+            .unwrap();
+        journal.delete_tags(&file).unwrap();
+
+        let changes: Vec<LogChange> = log
+            .read()
+            .unwrap()
+            .into_iter()
+            .map(|event| event.change)
+            .collect();
+        let some = |value: &str| Some(value.to_string());
+        assert_eq!(
+            changes[..3],
+            [
+                LogChange::Removed {
+                    tag: "draft".to_string(),
+                    value: None
+                },
+                LogChange::Added {
+                    tag: "place".to_string(),
+                    value: some("Rome")
+                },
+                LogChange::Changed {
+                    tag: "rating".to_string(),
+                    old: some("3"),
+                    value: some("5")
+                },
+            ]
+        );
+        assert_eq!(changes.len(), 6);
+
+        let line = r#"{"v":2,"time":5,"path":"a","event":"added","tag":"x","value":"1","by":"me"}"#;
+        assert_eq!(
+            LogEvent::from_json_line(line).unwrap().path.to_str(),
+            Some("a")
+        );
+        let line = r#"{"v":2,"time":5,"path":"a","event":"renamed"}"#;
+        assert!(LogEvent::from_json_line(line).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::visit::Evaluator;
+use crate::walk::TreeTags;
+use crate::weight::split_weight;
+use crate::{walk, Comparand, Result, Searcher, WalkOptions, XTags};
+
+/// Evaluation of one term of a search, see Searcher::explain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchTrace {
+    /// Term as displayed by Searcher, or the operator like `and` for terms with operands.
+    pub term: String,
+
+    /// Whether the term matched.
+    pub matched: bool,
+
+    /// Tags that satisfied the term, or why it failed. Empty for operators.
+    pub reason: String,
+
+    /// Traces of the operands, in term order.
+    pub operands: Vec<MatchTrace>,
+}
+
+impl MatchTrace {
+    fn operator(term: &str, matched: bool, operands: Vec<MatchTrace>) -> Self {
+        MatchTrace {
+            term: term.to_string(),
+            matched,
+            reason: String::new(),
+            operands,
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let mark = if self.matched { '+' } else { '-' };
+        write!(f, "{:indent$}{mark} {}", "", self.term, indent = depth * 2)?;
+        if !self.reason.is_empty() {
+            write!(f, ": {}", self.reason)?;
+        }
+        writeln!(f)?;
+        self.operands
+            .iter()
+            .try_for_each(|operand| operand.write(f, depth + 1))
+    }
+}
+
+impl fmt::Display for MatchTrace {
+    /// One line per term, operands indented, `+` for matched and `-` for failed terms.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+// Tag and value as written in lists, weights removed
+fn pair(tag: &str, value: Option<&str>) -> String {
+    let tag = split_weight(tag).0;
+    match value {
+        Some(value) => format!("{tag}={}", split_weight(value).0),
+        None => tag.to_string(),
+    }
+}
+
+fn comparand_type(comparand: &Comparand) -> &'static str {
+    match comparand {
+        Comparand::Int(_) | Comparand::Float(_) => "number",
+        Comparand::Date(_) => "date",
+    }
+}
+
+// Builds the traces of evaluated nodes, operands collected in a frame per open node
+struct Tracer {
+    frames: Vec<Vec<MatchTrace>>,
+    reason: String,
+}
+
+impl Evaluator for Tracer {
+    fn short_circuit(&self) -> bool {
+        false
+    }
+
+    fn enter(&mut self, _: usize, _: &Searcher) {
+        self.frames.push(Vec::new());
+    }
+
+    fn term(&mut self, _: usize, term: &Searcher, tags: &XTags) -> bool {
+        let matched = term.is_match(tags);
+        self.reason = match matched {
+            true => term.match_reason(tags),
+            false => term.failure_reason(tags),
+        };
+        matched
+    }
+
+    fn leave(&mut self, _: usize, node: &Searcher, matched: bool) {
+        let operands = self.frames.pop().unwrap_or_default();
+        let mut trace = MatchTrace::operator(&node.label(), matched, operands);
+        if trace.operands.is_empty() {
+            trace.reason = std::mem::take(&mut self.reason);
+        }
+        if let Some(frame) = self.frames.last_mut() {
+            frame.push(trace);
+        }
+    }
+}
+
+impl Searcher {
+    /// Evaluates all terms against tags, recording why each one matched or failed.
+    ///
+    /// Unlike is_match, both operands of `and` and `or` are evaluated, so every failing term
+    /// shows up. Expansions of bookmarks are operators named by their reference like `{recent}`. Matched terms name the tags satisfying them, failed ones the reason for every
+    /// candidate tag, like a value that isn't a number.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let search = xtag::compile_search("photo and rating >= 4").unwrap();
+    /// let tags = xtag::csl_to_map("photo,rating=good").unwrap();
+    /// let trace = search.explain(&tags);
+    /// assert!(!trace.matched);
+    /// assert_eq!(
+    ///     trace.to_string(),
+    ///     "- and\n  + ^(?:photo)$: matched by photo\n  \
+    ///      - ^(?:rating)$ >= 4: value \"good\" of rating is no number\n"
+    /// );
+    /// ```
+    pub fn explain(&self, tags: &XTags) -> MatchTrace {
+        let mut tracer = Tracer {
+            frames: vec![Vec::new()],
+            reason: String::new(),
+        };
+        self.evaluate_with(tags, &mut tracer);
+        tracer
+            .frames
+            .pop()
+            .and_then(|mut traces| traces.pop())
+            .unwrap_or_else(|| MatchTrace::operator(&self.label(), false, Vec::new()))
+    }
+
+    // Tags satisfying term on their own
+    fn match_reason(&self, tags: &XTags) -> String {
+        if let Searcher::TagCount { .. } = self {
+            return format!("file has {} tags", tags.len());
+        }
+        let mut singles: Vec<String> = tags
+            .iter()
+            .filter(|(tag, value)| {
+                let single: XTags = [(tag.to_string(), Option::clone(value))].into();
+                self.is_match(&single)
+            })
+            .map(|(tag, value)| pair(tag, value.as_deref()))
+            .collect();
+        singles.sort();
+        format!("matched by {}", singles.join(", "))
+    }
+
+    fn failure_reason(&self, tags: &XTags) -> String {
+        let tag_regex = match self {
+            Searcher::TagCount { .. } => return format!("file has {} tags", tags.len()),
+            Searcher::AnyValue { .. } => return "no value matches".to_string(),
+            Searcher::Tag { .. } | Searcher::Descendant { .. } => {
+                return "no tag matches".to_string()
+            }
+            Searcher::Equal { tag_regex, .. }
+            | Searcher::EqualCaptures { tag_regex, .. }
+            | Searcher::Less { tag_regex, .. }
+            | Searcher::LessEqual { tag_regex, .. }
+            | Searcher::Greater { tag_regex, .. }
+            | Searcher::GreaterEqual { tag_regex, .. }
+            | Searcher::IsTrue { tag_regex }
+            | Searcher::IsFalse { tag_regex }
+            | Searcher::HasValue { tag_regex }
+            | Searcher::ValuelessTag { tag_regex } => tag_regex,
+            _ => return String::new(),
+        };
+        let mut candidates: Vec<(&str, Option<&str>)> = tags
+            .iter()
+            .map(|(tag, value)| (split_weight(tag).0, value.as_deref()))
+            .filter(|(tag, _)| tag_regex.is_match(tag))
+            .collect();
+        if candidates.is_empty() {
+            return "no tag matches".to_string();
+        }
+        candidates.sort();
+        candidates
+            .into_iter()
+            .map(|(tag, value)| match value {
+                Some(value) => self.value_failure(tag, split_weight(value).0),
+                None => match self {
+                    Searcher::ValuelessTag { .. } => String::new(),
+                    _ => format!("{tag} has no value"),
+                },
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    // Why value of tag matching the tag expression doesn't satisfy term
+    fn value_failure(&self, tag: &str, value: &str) -> String {
+        let (comparand, operator) = match self {
+            Searcher::Less { value, .. } => (value, "<"),
+            Searcher::LessEqual { value, .. } => (value, "<="),
+            Searcher::Greater { value, .. } => (value, ">"),
+            Searcher::GreaterEqual { value, .. } => (value, ">="),
+            Searcher::IsTrue { .. } => return format!("value {value:?} of {tag} isn't true"),
+            Searcher::IsFalse { .. } => return format!("value {value:?} of {tag} isn't false"),
+            Searcher::ValuelessTag { .. } => return format!("{tag} has value {value:?}"),
+            Searcher::Equal { value_regex, .. } => {
+                return format!(
+                    "value {value:?} of {tag} doesn't match {}",
+                    unanchored(value_regex)
+                )
+            }
+            _ => return format!("value {value:?} of {tag} doesn't match"),
+        };
+        match comparand.compare(value) {
+            None => format!(
+                "value {value:?} of {tag} is no {}",
+                comparand_type(comparand)
+            ),
+            Some(_) => format!("value {value:?} of {tag} isn't {operator} {comparand}"),
+        }
+    }
+}
+
+fn unanchored(regex: &Regex) -> &str {
+    crate::searcher::unexpand_regex(regex.as_str())
+}
+
+/// Iterates over all files below root matching searcher, with the reason they matched.
+///
+/// Like search_dir, but every match comes with its MatchTrace, for debugging saved searches.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let searcher = xtag::compile_search("photo and rating >= 4").unwrap();
+/// let options = xtag::WalkOptions::default();
+/// for found in xtag::explain_dir(Path::new("."), &searcher, &options) {
+///     let (path, trace) = found.unwrap();
+///     println!("{}\n{trace}", path.display());
+/// }
+/// ```
+pub fn explain_dir<'a>(
+    root: &Path,
+    searcher: &'a Searcher,
+    options: &WalkOptions,
+) -> impl Iterator<Item = Result<(PathBuf, MatchTrace)>> + 'a {
+    let tree = TreeTags::new(root, options);
+    walk(root, options).filter_map(move |path| {
+        let path = match path {
+            Ok(path) => path,
+            Err(err) => return Some(Err(err)),
+        };
+        match tree.get(&path) {
+            Ok(Some(tags)) if searcher.is_match(&tags) => Some(Ok((path, searcher.explain(&tags)))),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::explain_dir;
+    use crate::{compile_search, compile_search_with, csl_to_map, Fixture, SearchOptions};
+
+    #[test]
+    fn traces_tell_why_terms_failed() {
+        let tags = csl_to_map("rating=abc,year=2001,cam=x#0.5,draft").unwrap();
+        let search =
+            compile_search("(rating > 3 or year < 2000) and not draft! and cam == y and #tags > 1")
+                .unwrap();
+        let trace = search.explain(&tags);
+        assert!(!trace.matched);
+        assert_eq!(
+            trace.to_string(),
+            "- and\n\
+             \x20 - and\n\
+             \x20   - and\n\
+             \x20     - or\n\
+             \x20       - ^(?:rating)$ > 3: value \"abc\" of rating is no number\n\
+             \x20       - ^(?:year)$ < 2000: value \"2001\" of year isn't < 2000\n\
+             \x20     - not\n\
+             \x20       + ^(?:draft)$!: matched by draft\n\
+             \x20   - ^(?:cam)$ == ^(?:y)$: value \"x\" of cam doesn't match y\n\
+             \x20 + #tags >= 2: file has 4 tags\n"
+        );
+
+        let options = SearchOptions {
+            fold: true,
+            ..Default::default()
+        };
+        let search = compile_search_with("City == rome", &options).unwrap();
+        let trace = search.explain(&csl_to_map("city=Rome").unwrap());
+        assert!(trace.matched);
+        assert_eq!(trace.operands[0].reason, "matched by city=rome");
+    }
+
+    #[test]
+    fn matching_files_come_with_traces() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("photo,rating=5")
+            .file("b")
+            .tags("photo");
+        let search = compile_search("rating >= 4").unwrap();
+        let found: Vec<_> = explain_dir(fixture.path(), &search, &Default::default())
+            .collect::<crate::Result<_>>()
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, fixture.join("a"));
+        assert_eq!(found[0].1.reason, "matched by rating=5");
+    }
+}
@@ -0,0 +1,96 @@
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::PathBuf;
+
+use xattr::FileExt;
+
+use crate::read_only::ensure_writable;
+use crate::{map_to_csl, tags_from_bytes, Result, XTagError, XTags, XATTR_NAME};
+
+// Borrowed descriptor, xattr only implements FileExt for File
+struct Descriptor(RawFd);
+
+impl AsRawFd for Descriptor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl FileExt for Descriptor {}
+
+// Names the file of fd in errors
+fn fd_path(fd: RawFd) -> PathBuf {
+    PathBuf::from(format!("/proc/self/fd/{fd}"))
+}
+
+/// Get tags for open file as map, see get_tags.
+///
+/// Works on the file itself instead of resolving a path again, so the tags belong to the file
+/// that was opened even if it was renamed or replaced since.
+///
+/// # Example
+///
+/// ```no_run
+/// let file = std::fs::File::open("a.jpg").unwrap();
+/// let tags = xtag::get_tags_fd(&file).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Same as get_tags
+pub fn get_tags_fd(fd: &impl AsRawFd) -> Result<XTags> {
+    match Descriptor(fd.as_raw_fd()).get_xattr(XATTR_NAME) {
+        Ok(Some(value)) => tags_from_bytes(&value),
+        Ok(None) => Ok(XTags::new()),
+        Err(err) => Err(XTagError::File(err)),
+    }
+}
+
+/// Set tags for open file from map, see set_tags.
+///
+/// # Errors
+///
+/// Same as set_tags
+pub fn set_tags_fd(fd: &impl AsRawFd, tags: &XTags) -> Result<()> {
+    let fd = fd.as_raw_fd();
+    ensure_writable(&fd_path(fd))?;
+    let string = map_to_csl(tags);
+    Descriptor(fd)
+        .set_xattr(XATTR_NAME, string.as_bytes())
+        .map_err(XTagError::File)
+}
+
+/// Delete all tags for open file, see delete_tags.
+pub fn delete_tags_fd(fd: &impl AsRawFd) -> Result<()> {
+    let fd = fd.as_raw_fd();
+    ensure_writable(&fd_path(fd))?;
+    match Descriptor(fd).remove_xattr(XATTR_NAME) {
+        Ok(()) => Ok(()),
+        Err(err) if err.to_string().starts_with("No data available") => Ok(()),
+        Err(err) => Err(XTagError::File(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+
+    use super::{delete_tags_fd, get_tags_fd, set_tags_fd};
+    use crate::{csl_to_map, get_tags, Fixture};
+
+    #[test]
+    fn tags_follow_open_files() {
+        let fixture = Fixture::new().file("a").tags("photo");
+        let file = File::open(fixture.join("a")).unwrap();
+        assert_eq!(get_tags_fd(&file).unwrap(), csl_to_map("photo").unwrap());
+
+        // The descriptor keeps referring to the renamed file
+        fs::rename(fixture.join("a"), fixture.join("b")).unwrap();
+        let tags = csl_to_map("photo,rating=5").unwrap();
+        set_tags_fd(&file, &tags).unwrap();
+        assert_eq!(get_tags(&fixture.join("b")).unwrap(), tags);
+
+        delete_tags_fd(&file).unwrap();
+        delete_tags_fd(&file).unwrap();
+        assert!(get_tags_fd(&file).unwrap().is_empty());
+    }
+}
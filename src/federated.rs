@@ -0,0 +1,144 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::walk::search_tree;
+use crate::{Result, ResultSet, Searcher, WalkOptions, XTagError};
+
+/// Matching file of a federated search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FederatedMatch {
+    /// Label of the root the file was found in.
+    pub label: String,
+
+    /// Path of the file.
+    pub path: PathBuf,
+}
+
+/// Outcome of a federated search for one root.
+#[derive(Debug)]
+pub struct RootReport {
+    /// Label of the root.
+    pub label: String,
+
+    /// Path of the root.
+    pub root: PathBuf,
+
+    /// False if the file system of the root doesn't support extended attributes. Such roots
+    /// are not searched.
+    pub supports_tags: bool,
+
+    /// Number of matching files.
+    pub matches: usize,
+
+    /// Error that stopped searching this root.
+    pub error: Option<XTagError>,
+}
+
+/// Merged outcome of search_roots.
+#[derive(Debug, Default)]
+pub struct FederatedResult {
+    /// Matching files of all roots, in order of the roots.
+    pub matches: Vec<FederatedMatch>,
+
+    /// One report per root, in order of the roots.
+    pub roots: Vec<RootReport>,
+}
+
+impl FederatedResult {
+    /// Returns matching files of all roots without labels.
+    pub fn paths(&self) -> ResultSet {
+        self.matches
+            .iter()
+            .map(|found| found.path.clone())
+            .collect()
+    }
+}
+
+// Probes the root itself, files below share its file system for the most part
+fn supports_tags(root: &Path) -> Result<bool> {
+    match xattr::list(root) {
+        Ok(_) => Ok(xattr::SUPPORTED_PLATFORM),
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Searches several directory trees at once, like collections split over internal and
+/// external disks.
+///
+/// roots are pairs of label and path. Every root is searched even if others fail, failures are
+/// reported per root. Roots on file systems without extended attributes are skipped.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let searcher = xtag::compile_search("photo").unwrap();
+/// let roots = [("home", Path::new("/home/me/Pictures")), ("usb", Path::new("/media/usb"))];
+/// let result = xtag::search_roots(&searcher, &roots, &xtag::WalkOptions::default());
+/// for found in &result.matches {
+///     println!("{}: {}", found.label, found.path.display());
+/// }
+/// ```
+pub fn search_roots(
+    searcher: &Searcher,
+    roots: &[(&str, &Path)],
+    options: &WalkOptions,
+) -> FederatedResult {
+    let mut result = FederatedResult::default();
+    for (label, root) in roots {
+        let mut report = RootReport {
+            label: label.to_string(),
+            root: root.to_path_buf(),
+            supports_tags: false,
+            matches: 0,
+            error: None,
+        };
+        let found = supports_tags(root).and_then(|supported| {
+            report.supports_tags = supported;
+            if supported {
+                search_tree(searcher, root, options)
+            } else {
+                Ok(ResultSet::new())
+            }
+        });
+        match found {
+            Ok(found) => {
+                report.matches = found.len();
+                result
+                    .matches
+                    .extend(found.into_iter().map(|path| FederatedMatch {
+                        label: label.to_string(),
+                        path,
+                    }));
+            }
+            Err(err) => report.error = Some(err),
+        }
+        result.roots.push(report);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::search_roots;
+    use crate::{compile_search, Fixture, WalkOptions};
+
+    #[test]
+    fn federated_search_labels_matches_and_reports_failures() {
+        let fixture = Fixture::new().file("a").tags("photo");
+
+        let searcher = compile_search("photo").unwrap();
+        let roots = [
+            ("disk", fixture.path()),
+            ("gone", Path::new("/nonexistent/xtag")),
+        ];
+        let result = search_roots(&searcher, &roots, &WalkOptions::default());
+        assert_eq!(result.matches.len(), 1);
+        assert_eq!(result.matches[0].label, "disk");
+        assert_eq!(result.roots[0].matches, 1);
+        assert!(result.roots[1].error.is_some());
+    }
+}
@@ -0,0 +1,98 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{csl_to_map, set_tags};
+
+// Distinguishes fixtures of one process
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Temporary directory tree with tagged files for tests.
+///
+/// The directory is created in the temporary directory of the system and removed again when the
+/// Fixture is dropped. Building a fixture panics on any failure, like tests do.
+///
+/// # Example
+///
+/// ```
+/// let fixture = xtag::Fixture::new()
+///     .file("a.jpg")
+///     .tags("photo,rating=5")
+///     .file("docs/b.txt");
+/// let tags = xtag::get_tags(&fixture.join("a.jpg")).unwrap();
+/// assert!(tags.contains_key("photo"));
+/// ```
+#[derive(Debug)]
+pub struct Fixture {
+    root: PathBuf,
+    last: Option<PathBuf>,
+}
+
+impl Fixture {
+    /// Creates new empty directory.
+    pub fn new() -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let root = std::env::temp_dir().join(format!("xtag-fixture-{}-{id}", std::process::id()));
+        if root.exists() {
+            fs::remove_dir_all(&root).expect("stale fixture can't be removed");
+        }
+        fs::create_dir_all(&root).expect("fixture can't be created");
+        Fixture { root, last: None }
+    }
+
+    /// Adds empty file, creating missing parent directories.
+    pub fn file(self, path: &str) -> Self {
+        self.file_with_content(path, "")
+    }
+
+    /// Adds file with content, creating missing parent directories.
+    pub fn file_with_content(mut self, path: &str, content: &str) -> Self {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("fixture directory can't be created");
+        }
+        fs::write(&path, content).expect("fixture file can't be written");
+        self.last = Some(path);
+        self
+    }
+
+    /// Adds directory, creating missing parent directories.
+    pub fn dir(mut self, path: &str) -> Self {
+        let path = self.root.join(path);
+        fs::create_dir_all(&path).expect("fixture directory can't be created");
+        self.last = Some(path);
+        self
+    }
+
+    /// Sets tags of the file or directory added last.
+    ///
+    /// tags is a comma separated list of tag=value pairs.
+    pub fn tags(self, tags: &str) -> Self {
+        let path = self.last.as_ref().expect("tags need a file added before");
+        let tags = csl_to_map(tags).expect("fixture tags are invalid");
+        set_tags(path, &tags).expect("fixture tags can't be written");
+        self
+    }
+
+    /// Root directory of the tree.
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns path below root.
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.root.join(path)
+    }
+}
+
+impl Default for Fixture {
+    fn default() -> Self {
+        Fixture::new()
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
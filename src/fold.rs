@@ -0,0 +1,47 @@
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::XTags;
+
+/// Returns string without diacritics and in lowercase.
+///
+/// Applies compatibility decomposition (NFKD), drops combining marks and folds case, so
+/// `Malmö`, `MALMO` and `malmo` all become `malmo`.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(xtag::fold("Malmö"), "malmo");
+/// ```
+pub fn fold(string: &str) -> String {
+    string
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// Only drops diacritics, case is left to the regex engine so escapes like \D survive
+pub(crate) fn strip_marks(string: &str) -> String {
+    string.nfkd().filter(|c| !is_combining_mark(*c)).collect()
+}
+
+// Tags becoming equal by folding are merged, which one's value survives is undefined
+pub(crate) fn fold_tags(tags: &XTags) -> XTags {
+    tags.iter()
+        .map(|(tag, value)| (fold(tag), value.as_deref().map(fold)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fold, strip_marks};
+
+    #[test]
+    fn fold_removes_diacritics_and_case() {
+        assert_eq!(fold("Malmö"), "malmo");
+        assert_eq!(fold("Crème Brûlée"), "creme brulee");
+        assert_eq!(fold("ﬁle"), "file");
+        assert_eq!(strip_marks(r"Malmö\D"), r"Malmo\D");
+    }
+}
@@ -0,0 +1,212 @@
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::searcher::expand_regex;
+use crate::walk::TreeTags;
+use crate::{split_weight, walk, Date, Result, WalkOptions, XTagError};
+
+/// Rules which tags gc removes, nothing is removed by default.
+#[derive(Debug, Clone, Default)]
+pub struct GcPolicy {
+    /// Regular expressions of tags to remove, like `tmp:.*`.
+    pub remove: Vec<String>,
+
+    /// Regular expression of tags holding a date, like `review-until`, removed once their date
+    /// is more than max_age_days ago.
+    pub expiring: Option<String>,
+
+    /// Days tags matching expiring are kept after their date, 0 removes them the day after.
+    pub max_age_days: u32,
+
+    /// Regular expressions of all tags in the schema, other tags are removed. None keeps tags
+    /// not removed otherwise.
+    pub allowed: Option<Vec<String>>,
+
+    /// Report what would be removed, but don't change any file.
+    pub dry_run: bool,
+}
+
+/// Tags gc removed from one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcChange {
+    /// Path of the file.
+    pub path: PathBuf,
+
+    /// Removed tags in lexicographic order.
+    pub removed: Vec<String>,
+}
+
+/// Outcome of gc.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Number of files looked at.
+    pub scanned: usize,
+
+    /// Files with removed tags, or that would have had in a dry run.
+    pub changes: Vec<GcChange>,
+}
+
+impl GcReport {
+    /// Number of removed tags over all files.
+    pub fn removed(&self) -> usize {
+        self.changes.iter().map(|change| change.removed.len()).sum()
+    }
+}
+
+// Policy with compiled regular expressions
+struct Rules {
+    remove: Vec<Regex>,
+    expiring: Option<(Regex, i64)>,
+    allowed: Option<Vec<Regex>>,
+}
+
+fn compile(regexes: &[String]) -> Result<Vec<Regex>> {
+    regexes
+        .iter()
+        .map(|regex| Regex::new(&expand_regex(regex)).map_err(XTagError::Regex))
+        .collect()
+}
+
+impl Rules {
+    fn new(policy: &GcPolicy) -> Result<Self> {
+        let expiring = match &policy.expiring {
+            Some(regex) => {
+                let regex = Regex::new(&expand_regex(regex)).map_err(XTagError::Regex)?;
+                let oldest = Date::today().days() - i64::from(policy.max_age_days);
+                Some((regex, oldest))
+            }
+            None => None,
+        };
+        Ok(Rules {
+            remove: compile(&policy.remove)?,
+            expiring,
+            allowed: policy.allowed.as_deref().map(compile).transpose()?,
+        })
+    }
+
+    fn is_garbage(&self, tag: &str, value: Option<&str>) -> bool {
+        let tag = split_weight(tag).0;
+        if self.remove.iter().any(|regex| regex.is_match(tag)) {
+            return true;
+        }
+        if let Some(allowed) = &self.allowed {
+            if !allowed.iter().any(|regex| regex.is_match(tag)) {
+                return true;
+            }
+        }
+        match (&self.expiring, value) {
+            (Some((regex, oldest)), Some(value)) if regex.is_match(tag) => {
+                // Values that aren't dates never expire
+                split_weight(value)
+                    .0
+                    .parse::<Date>()
+                    .is_ok_and(|date| date.days() < *oldest)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Removes tags matching policy from all files below root.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let policy = xtag::GcPolicy {
+///     remove: vec!["tmp:.*".to_string()],
+///     expiring: Some("review-until".to_string()),
+///     max_age_days: 30,
+///     dry_run: true,
+///     ..Default::default()
+/// };
+/// let report = xtag::gc(Path::new("/photos"), &policy, &xtag::WalkOptions::default()).unwrap();
+/// println!("would remove {} tags", report.removed());
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Regex if a regular expression of policy is invalid
+/// - Same as walk, get_tags, set_tags and delete_tags, files without attribute support are
+///   handled by options
+pub fn gc(root: &Path, policy: &GcPolicy, options: &WalkOptions) -> Result<GcReport> {
+    let rules = Rules::new(policy)?;
+    let mut report = GcReport::default();
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        let path = path?;
+        report.scanned += 1;
+        let Some(mut tags) = tree.get(&path)? else {
+            continue;
+        };
+        let mut removed: Vec<String> = tags
+            .iter()
+            .filter(|(tag, value)| rules.is_garbage(tag, value.as_deref()))
+            .map(|(tag, _)| tag.clone())
+            .collect();
+        if removed.is_empty() {
+            continue;
+        }
+        removed.sort();
+        if !policy.dry_run {
+            tags.retain(|tag, _| !removed.contains(tag));
+            let written = match tags.is_empty() {
+                true => tree.delete(&path)?,
+                false => tree.set(&path, &tags)?,
+            };
+            if !written {
+                continue;
+            }
+        }
+        report.changes.push(GcChange { path, removed });
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gc, GcPolicy};
+    use crate::{csl_to_map, get_tags, Fixture, WalkOptions};
+
+    #[test]
+    fn garbage_is_removed_by_policy() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("tmp:upload,photo,review=2000-01-01")
+            .file("b")
+            .tags("photo,review=2999-01-01,typo")
+            .file("c")
+            .tags("tmp:x");
+        let mut policy = GcPolicy {
+            remove: vec!["tmp:.*".to_string()],
+            expiring: Some("review".to_string()),
+            max_age_days: 7,
+            allowed: Some(vec!["photo".to_string(), "review|tmp:.*".to_string()]),
+            dry_run: true,
+        };
+        let options = WalkOptions::default();
+        let report = gc(fixture.path(), &policy, &options).unwrap();
+        assert_eq!(report.scanned, 3);
+        assert_eq!(report.removed(), 4);
+        let a = report
+            .changes
+            .iter()
+            .find(|change| change.path == fixture.join("a"))
+            .unwrap();
+        assert_eq!(a.removed, vec!["review", "tmp:upload"]);
+        assert_eq!(get_tags(&fixture.join("c")).unwrap().len(), 1);
+
+        policy.dry_run = false;
+        assert_eq!(gc(fixture.path(), &policy, &options).unwrap().removed(), 4);
+        assert_eq!(
+            get_tags(&fixture.join("b")).unwrap(),
+            csl_to_map("photo,review=2999-01-01").unwrap()
+        );
+        assert!(get_tags(&fixture.join("c")).unwrap().is_empty());
+        assert!(gc(fixture.path(), &policy, &options)
+            .unwrap()
+            .changes
+            .is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{compile_search_with, Result, SearchOptions, Searcher};
+
+/// Translates shell glob into regular expression without anchors.
+///
+/// `*` matches any sequence, `?` any single character and `[...]` one of the enclosed
+/// characters or ranges, negated by a leading `!` or `^`. Everything else matches literally, so
+/// `photo.2024` no longer matches `photoX2024`. A `[` without closing `]` is taken literally.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(xtag::glob_to_regex("photo.*"), r"photo\..*");
+/// assert_eq!(xtag::glob_to_regex("[!a-c]?"), "[^a-c].");
+/// ```
+pub fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::new();
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index] {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => match translate_class(&chars[index + 1..]) {
+                Some((class, len)) => {
+                    regex.push_str(&class);
+                    index += len;
+                }
+                None => regex.push_str(r"\["),
+            },
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+        index += 1;
+    }
+    regex
+}
+
+// Returns class of chars following `[` and number of chars consumed including `]`
+fn translate_class(chars: &[char]) -> Option<(String, usize)> {
+    let mut class = String::from("[");
+    let mut index = 0;
+    if matches!(chars.first(), Some('!' | '^')) {
+        class.push('^');
+        index += 1;
+    }
+    let start = index;
+    loop {
+        match *chars.get(index)? {
+            // Leading ] is part of the class like in shells
+            ']' if index > start => break,
+            c @ ('\\' | '[' | ']' | '&' | '~') => {
+                class.push('\\');
+                class.push(c);
+            }
+            c => class.push(c),
+        }
+        index += 1;
+    }
+    class.push(']');
+    Some((class, index + 1))
+}
+
+/// Compiles search term like compile_search, with tags and values as shell globs.
+///
+/// # Example
+///
+/// ```
+/// let tags = xtag::csl_to_map("photoX2024,city=Rome").unwrap();
+/// assert!(!xtag::compile_search_glob("photo.2024").unwrap().is_match(&tags));
+/// assert!(xtag::compile_search_glob("photo* and city == R?me").unwrap().is_match(&tags));
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Parser
+pub fn compile_search_glob(term: &str) -> Result<Searcher> {
+    let options = SearchOptions {
+        glob: true,
+        ..Default::default()
+    };
+    compile_search_with(term, &options)
+}
+
+impl Searcher {
+    /// Returns new tag Searcher matching tags against shell glob, see glob_to_regex.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if glob translates to an invalid regular expression
+    pub fn new_tag_glob(glob: &str) -> Result<Self> {
+        Searcher::new_tag(&glob_to_regex(glob))
+    }
+
+    /// Returns new equal Searcher matching tags and values against shell globs.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if a glob translates to an invalid regular expression
+    pub fn new_equal_glob(tag_glob: &str, value_glob: &str) -> Result<Self> {
+        Searcher::new_equal(&glob_to_regex(tag_glob), &glob_to_regex(value_glob))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile_search_glob, glob_to_regex};
+    use crate::{csl_to_map, Searcher};
+
+    #[test]
+    fn globs_are_translated() {
+        assert_eq!(glob_to_regex("a+b(c)"), r"a\+b\(c\)");
+        assert_eq!(glob_to_regex("[]a]"), r"[\]a]");
+        assert_eq!(glob_to_regex("[^x"), r"\[\^x");
+        assert_eq!(glob_to_regex("ö*"), "ö.*");
+    }
+
+    #[test]
+    fn glob_searches_match_literally() {
+        let tags = csl_to_map("photoX2024,year=2024,camera:nikon,file=a.jpg").unwrap();
+        for (term, expected) in [
+            ("photo.2024", false),
+            ("photo?2024", true),
+            ("photo?20[0-9][0-9]", true),
+            ("photo?20[^2]4", false),
+            ("camera:*", true),
+            ("year == 20*", true),
+            ("year == 2?", false),
+            ("file == *.jpg and not (year != 2024)", true),
+            ("file == a.jp", false),
+        ] {
+            let searcher = compile_search_glob(term).unwrap();
+            assert_eq!(searcher.is_match(&tags), expected, "{term}");
+        }
+        let tags = csl_to_map("photoX2024").unwrap();
+        assert!(!Searcher::new_tag_glob("photo.2024")
+            .unwrap()
+            .is_match(&tags));
+        let tags = csl_to_map("a=b.c").unwrap();
+        assert!(Searcher::new_equal_glob("?", "*.c")
+            .unwrap()
+            .is_match(&tags));
+    }
+}
@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use crate::history::HISTORY_NAME;
+use crate::tombstone::TOMBSTONES_NAME;
+use crate::views::VIEWS_NAME;
+use crate::{delete_tags_from, get_tags_from, set_tags_to, Result, XTagError, XTags, XATTR_NAME};
+
+// Attributes below XATTR_NAME used for other purposes than groups
+fn is_reserved(group: &str) -> bool {
+    group == VIEWS_NAME || group == HISTORY_NAME || group == TOMBSTONES_NAME
+}
+
+// Attribute holding tags of group
+fn group_attribute(group: &str) -> Result<String> {
+    if group.is_empty() || group.contains('\0') || is_reserved(group) {
+        return Err(XTagError::InvalidGroup(group.to_string()));
+    }
+    Ok(format!("{XATTR_NAME}.{group}"))
+}
+
+/// Get tags of named group for file as map
+///
+/// Groups are stored in separate attributes like `user.xtag.work`, independent from the default
+/// tags in `user.xtag` and from each other.
+///
+/// # Errors
+///
+/// - XTagError::InvalidGroup if group is empty, contains NUL or is one of the reserved names
+///   `views`, `history` and `tombstones`
+pub fn get_group_tags(path: &Path, group: &str) -> Result<XTags> {
+    get_tags_from(path, &group_attribute(group)?)
+}
+
+/// Set tags of named group for file from map
+pub fn set_group_tags(path: &Path, group: &str, tags: &XTags) -> Result<()> {
+    set_tags_to(path, &group_attribute(group)?, tags)
+}
+
+/// Delete all tags of named group for file
+pub fn delete_group_tags(path: &Path, group: &str) -> Result<()> {
+    delete_tags_from(path, &group_attribute(group)?)
+}
+
+/// Names of all groups with tags for file, sorted
+pub fn list_groups(path: &Path) -> Result<Vec<String>> {
+    let prefix = format!("{XATTR_NAME}.");
+    let mut groups: Vec<String> = xattr::list(path)?
+        .filter_map(|name| {
+            name.to_str()
+                .and_then(|name| name.strip_prefix(&prefix))
+                .filter(|group| !is_reserved(group))
+                .map(str::to_string)
+        })
+        .collect();
+    groups.sort();
+    Ok(groups)
+}
+
+/// Get union of default tags and tags of all groups for file
+///
+/// If a tag appears more than once, the value of the default tags wins over groups, groups are
+/// ranked by name.
+pub fn get_all_tags(path: &Path) -> Result<XTags> {
+    let mut tags = XTags::new();
+    for group in list_groups(path)?.iter().rev() {
+        tags.extend(get_group_tags(path, group)?);
+    }
+    tags.extend(get_tags_from(path, XATTR_NAME)?);
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::group_attribute;
+
+    #[test]
+    fn group_names_are_checked() {
+        assert_eq!(group_attribute("work").unwrap(), "user.xtag.work");
+        assert!(group_attribute("").is_err());
+        assert!(group_attribute("a\0b").is_err());
+        assert!(group_attribute("views").is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use regex::Regex;
+
+use crate::searcher::expand_regex;
+use crate::{Result, Searcher, XTagError};
+
+/// Default separator of levels in hierarchical tags like `place/europe/italy`.
+pub const HIERARCHY_SEPARATOR: char = '/';
+
+/// Returns ancestors of hierarchical tag, the root first.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(
+///     xtag::ancestors("place/europe/italy"),
+///     vec!["place", "place/europe"]
+/// );
+/// assert!(xtag::ancestors("place").is_empty());
+/// ```
+pub fn ancestors(tag: &str) -> Vec<&str> {
+    ancestors_with(tag, HIERARCHY_SEPARATOR)
+}
+
+/// Returns ancestors of tag with levels divided by separator, the root first.
+///
+/// Empty levels, like from a leading separator, don't count as ancestors.
+pub fn ancestors_with(tag: &str, separator: char) -> Vec<&str> {
+    tag.match_indices(separator)
+        .map(|(index, _)| &tag[..index])
+        .filter(|ancestor| !ancestor.is_empty() && !ancestor.ends_with(separator))
+        .collect()
+}
+
+// Returns true if tag or one of its ancestors matches regex
+pub(crate) fn is_under(tag: &str, regex: &Regex, separator: char) -> bool {
+    regex.is_match(tag)
+        || ancestors_with(tag, separator)
+            .into_iter()
+            .any(|ancestor| regex.is_match(ancestor))
+}
+
+impl Searcher {
+    /// Returns new descendant Searcher.
+    ///
+    /// Matches when a tag or one of its ancestors matches the regular expression, so
+    /// `place/europe` finds `place/europe` and `place/europe/italy`, but not `place`. The
+    /// expression is expanded with anchors to match whole levels. This is `under(...)` in
+    /// search terms, or `under(..., ':')` with another separator than the one of the options.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use xtag::Searcher;
+    /// let tags = xtag::csl_to_map("place/europe/italy").unwrap();
+    /// let search = Searcher::new_descendant("place/europe", '/').unwrap();
+    /// assert!(search.is_match(&tags) == true);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if regex is not a valid regular expression
+    pub fn new_descendant(regex: &str, separator: char) -> Result<Self> {
+        let regex = Regex::new(&expand_regex(regex)).map_err(XTagError::Regex)?;
+        Ok(Searcher::Descendant { regex, separator })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ancestors, ancestors_with};
+    use crate::{compile_search, compile_search_with, csl_to_map, SearchOptions};
+
+    #[test]
+    fn ancestors_skip_empty_levels() {
+        assert_eq!(ancestors("a/b/c"), vec!["a", "a/b"]);
+        assert_eq!(ancestors("/a//b/"), vec!["/a", "/a//b"]);
+        assert_eq!(ancestors_with("a:b", ':'), vec!["a"]);
+    }
+
+    #[test]
+    fn under_matches_descendants() {
+        let search = compile_search("under(place/europe) and not photo").unwrap();
+        assert!(search.is_match(&csl_to_map("place/europe/italy").unwrap()));
+        assert!(search.is_match(&csl_to_map("place/europe").unwrap()));
+        assert!(!search.is_match(&csl_to_map("place").unwrap()));
+        assert!(!search.is_match(&csl_to_map("place/europeans").unwrap()));
+        assert!(!search.is_match(&csl_to_map("place/europe/italy,photo").unwrap()));
+
+        let options = SearchOptions {
+            hierarchy_separator: Some(':'),
+            case_insensitive: true,
+            ..Default::default()
+        };
+        let search = compile_search_with("UNDER(Place:.*)", &options).unwrap();
+        assert!(search.is_match(&csl_to_map("place:europe:italy").unwrap()));
+        assert!(!search.is_match(&csl_to_map("place").unwrap()));
+
+        // Printed searches keep their separator without the options
+        let search = compile_search(&search.to_string()).unwrap();
+        assert!(search.is_match(&csl_to_map("place:europe:italy").unwrap()));
+        let search = compile_search("under(a, '-')").unwrap();
+        assert!(search.is_match(&csl_to_map("a-b").unwrap()));
+        assert!(!search.is_match(&csl_to_map("a/b").unwrap()));
+    }
+}
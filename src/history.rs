@@ -0,0 +1,94 @@
+use std::path::Path;
+use std::str;
+
+use crate::read_only::ensure_writable;
+use crate::{
+    csl_to_map, delete_tags_from, get_tags, map_to_csl, set_tags, Result, XTagError, XTags,
+    XATTR_NAME,
+};
+
+// Name is reserved, no tag group may use it
+pub(crate) static HISTORY_NAME: &str = "history";
+
+fn history_attribute() -> String {
+    format!("{XATTR_NAME}.{HISTORY_NAME}")
+}
+
+// One version per line, newest first
+fn write_history(path: &Path, versions: &[XTags]) -> Result<()> {
+    ensure_writable(path)?;
+    let value: Vec<String> = versions.iter().map(map_to_csl).collect();
+    xattr::set(path, history_attribute(), value.join("\n").as_bytes())?;
+    Ok(())
+}
+
+/// Set tags for file from map, keeping the previous tags in its history
+///
+/// Up to keep versions are stored in the attribute `user.xtag.history`, older ones are dropped.
+/// With keep 0 the history is deleted.
+pub fn set_tags_with_history(path: &Path, tags: &XTags, keep: usize) -> Result<()> {
+    let mut versions = history(path)?;
+    versions.insert(0, get_tags(path)?);
+    versions.truncate(keep);
+    if versions.is_empty() {
+        delete_tags_from(path, &history_attribute())?;
+    } else {
+        write_history(path, &versions)?;
+    }
+    set_tags(path, tags)
+}
+
+/// Previous tags of file, newest first
+pub fn history(path: &Path) -> Result<Vec<XTags>> {
+    let Some(value) = xattr::get(path, history_attribute())? else {
+        return Ok(Vec::new());
+    };
+    str::from_utf8(&value)?
+        .split('\n')
+        .map(csl_to_map)
+        .collect()
+}
+
+/// Restore version n of the history of file, 0 being the newest
+///
+/// The replaced tags become the newest version, so restoring 0 twice undoes the restore. The
+/// length of the history stays the same.
+///
+/// # Errors
+///
+/// - XTagError::UnknownVersion if the history has no version n
+pub fn restore_version(path: &Path, n: usize) -> Result<()> {
+    let mut versions = history(path)?;
+    if n >= versions.len() {
+        return Err(XTagError::UnknownVersion(n));
+    }
+    let restored = versions.remove(n);
+    versions.insert(0, get_tags(path)?);
+    write_history(path, &versions)?;
+    set_tags(path, &restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{history, restore_version, set_tags_with_history};
+    use crate::{csl_to_map, get_tags, list_groups, Fixture};
+
+    #[test]
+    fn history_keeps_previous_versions() {
+        let fixture = Fixture::new().file("a");
+        let file = fixture.join("a");
+        for csl in ["a", "b", "c", "d"] {
+            set_tags_with_history(&file, &csl_to_map(csl).unwrap(), 2).unwrap();
+        }
+        let versions = history(&file).unwrap();
+        assert_eq!(versions.len(), 2);
+        assert!(versions[0].contains_key("c"));
+        assert!(versions[1].contains_key("b"));
+        assert!(list_groups(&file).unwrap().is_empty());
+
+        restore_version(&file, 1).unwrap();
+        assert!(get_tags(&file).unwrap().contains_key("b"));
+        assert!(history(&file).unwrap()[0].contains_key("d"));
+        assert!(restore_version(&file, 2).is_err());
+    }
+}
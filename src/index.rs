@@ -0,0 +1,599 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File, TryLockError};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cache::FileStamp;
+use crate::manifest::{parse_line, remove_by_name};
+use crate::query_plan::Postings;
+#[cfg(not(feature = "parallel"))]
+use crate::reader::TagReader;
+use crate::walk::TreeTags;
+use crate::{
+    csl_to_map, map_to_csl, walk, ManifestError, ManifestOperation, ManifestReport, Result,
+    TagDump, TagStats, WalkOptions, XTagError, XTags,
+};
+
+static INDEX_HEADER: &str = "xtag-index 1";
+
+/// Name of the index file in the root of an indexed tree.
+pub const INDEX_FILE_NAME: &str = ".xtag-index";
+
+/// Name of the file locked while the index is read or written, next to INDEX_FILE_NAME.
+pub const INDEX_LOCK_FILE_NAME: &str = ".xtag-index.lock";
+
+// New content is written here and renamed over the index
+static INDEX_TEMP_FILE_NAME: &str = ".xtag-index.tmp";
+
+// How long to wait for other processes holding the lock
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+const LOCK_BACKOFF: Duration = Duration::from_millis(10);
+
+// Change time of files known from manifests only, no real file has it
+const UNKNOWN_STAMP: FileStamp = FileStamp {
+    ctime: 0,
+    ctime_nsec: 0,
+};
+
+/// Persistent index of the tags of all files below a directory.
+///
+/// Queries are answered from the index without reading any attribute. Every file is stored with
+/// its change time, which is updated by tag changes too, so stale entries can be found and
+/// refreshed cheaply. The index is saved as INDEX_FILE_NAME in the root directory.
+///
+/// Several processes may share one index, like a watcher and an interactive tool. Loading and
+/// saving hold an advisory lock on INDEX_LOCK_FILE_NAME, shared for loading and exclusive for
+/// saving, and wait up to 10 seconds for other holders. Saves replace the file atomically, so
+/// loading always sees a complete index. Changes made between load and save of one process are
+/// overwritten by a later save of another process, use transaction to avoid that.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let root = Path::new("photos");
+/// let mut index = xtag::Index::load(root)
+///     .or_else(|_| xtag::Index::build(root, &xtag::WalkOptions::default()))
+///     .unwrap();
+/// index.refresh().unwrap();
+/// let result = index.query(&xtag::compile_search("photo").unwrap());
+/// index.save().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Index {
+    root: PathBuf,
+    options: WalkOptions,
+    entries: BTreeMap<PathBuf, (FileStamp, XTags)>,
+
+    // TagStats of entries for suggestions, reset when entries change
+    pub(crate) stats: OnceLock<TagStats>,
+
+    // Postings of entries for queries, reset when entries change
+    pub(crate) postings: OnceLock<Postings>,
+}
+
+impl Index {
+    /// Builds index of all files below root.
+    ///
+    /// With feature parallel, tags are read in the thread pool of options.
+    ///
+    /// # Errors
+    ///
+    /// Same as walk and get_tags, files without attribute support are handled by options
+    pub fn build(root: &Path, options: &WalkOptions) -> Result<Self> {
+        let mut index = Index {
+            root: root.to_path_buf(),
+            options: options.clone(),
+            entries: BTreeMap::new(),
+            stats: OnceLock::new(),
+            postings: OnceLock::new(),
+        };
+        index.refresh()?;
+        Ok(index)
+    }
+
+    /// Loads index saved in root, files are walked with default options on refresh.
+    ///
+    /// Use set_options to walk with others.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if the index file can't be read
+    /// - XTagError::Cache if the content is no valid index
+    /// - XTagError::IndexLocked if another process holds the lock too long
+    pub fn load(root: &Path) -> Result<Self> {
+        let _lock = lock(root, false)?;
+        Index::load_unlocked(root)
+    }
+
+    fn load_unlocked(root: &Path) -> Result<Self> {
+        let mut lines = BufReader::new(File::open(root.join(INDEX_FILE_NAME))?).lines();
+        if lines.next().transpose()?.as_deref() != Some(INDEX_HEADER) {
+            return Err(XTagError::Cache("unknown format".to_string()));
+        }
+
+        let mut entries = BTreeMap::new();
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.splitn(4, '\t').collect();
+            let [ctime, ctime_nsec, path, tags] = fields.as_slice() else {
+                return Err(XTagError::Cache(format!("invalid line {line:?}")));
+            };
+            let stamp = FileStamp {
+                ctime: ctime.parse()?,
+                ctime_nsec: ctime_nsec.parse()?,
+            };
+            entries.insert(root.join(path), (stamp, csl_to_map(tags)?));
+        }
+        Ok(Index {
+            root: root.to_path_buf(),
+            options: WalkOptions::default(),
+            entries,
+            stats: OnceLock::new(),
+            postings: OnceLock::new(),
+        })
+    }
+
+    /// Writes index to INDEX_FILE_NAME in root.
+    ///
+    /// Files whose relative path isn't valid unicode or contains tabs or newlines are left out,
+    /// they are read again by refresh.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if the index file can't be written
+    /// - XTagError::IndexLocked if another process holds the lock too long
+    pub fn save(&self) -> Result<()> {
+        let _lock = lock(&self.root, true)?;
+        self.save_unlocked()
+    }
+
+    fn save_unlocked(&self) -> Result<()> {
+        let temp = self.root.join(INDEX_TEMP_FILE_NAME);
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        writeln!(writer, "{INDEX_HEADER}")?;
+        for (path, (stamp, tags)) in &self.entries {
+            let Some(path) = path
+                .strip_prefix(&self.root)
+                .ok()
+                .and_then(Path::to_str)
+                .filter(|path| !path.contains(['\t', '\n']))
+            else {
+                continue;
+            };
+            writeln!(
+                writer,
+                "{}\t{}\t{path}\t{}",
+                stamp.ctime,
+                stamp.ctime_nsec,
+                map_to_csl(tags)
+            )?;
+        }
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        fs::rename(temp, self.root.join(INDEX_FILE_NAME))?;
+        Ok(())
+    }
+
+    /// Runs change on the index of root while holding the lock exclusively, then saves it.
+    ///
+    /// The index is loaded, or built with options if there is none yet. No other process can
+    /// load or save the index in between, so concurrent transactions don't lose changes.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// let options = xtag::WalkOptions::default();
+    /// xtag::Index::transaction(Path::new("photos"), &options, |index| {
+    ///     index.update([Path::new("photos/a.jpg")])
+    /// })
+    /// .unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::IndexLocked if another process holds the lock too long
+    /// - Same as load, build, save and change
+    pub fn transaction<T, F>(root: &Path, options: &WalkOptions, change: F) -> Result<T>
+    where
+        F: FnOnce(&mut Index) -> Result<T>,
+    {
+        let _lock = lock(root, true)?;
+        let mut index = match Index::load_unlocked(root) {
+            Ok(mut index) => {
+                index.set_options(options);
+                index
+            }
+            Err(XTagError::File(err)) if err.kind() == io::ErrorKind::NotFound => {
+                Index::build(root, options)?
+            }
+            Err(err) => return Err(err),
+        };
+        let result = change(&mut index)?;
+        index.save_unlocked()?;
+        Ok(result)
+    }
+
+    /// Reads tags of paths again and adds them to the index.
+    ///
+    /// Paths that don't exist anymore are removed from the index. With feature parallel, the
+    /// tags are read in the thread pool of the walk options, see WalkOptions::pool, otherwise
+    /// in batches like read_tags.
+    ///
+    /// # Errors
+    ///
+    /// Same as get_tags, files without attribute support are handled by options
+    pub fn update<I, P>(&mut self, paths: I) -> Result<()>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.stats.take();
+        self.postings.take();
+        let tree = TreeTags::new(&self.root, &self.options);
+        let paths = paths.into_iter().map(|path| path.as_ref().to_path_buf());
+        for (path, entry) in read_entries(&tree, paths.collect(), &self.options)? {
+            match entry? {
+                Some(entry) => self.entries.insert(path, entry),
+                None => self.entries.remove(&path),
+            };
+        }
+        Ok(())
+    }
+
+    /// Builds index of the tree root from manifest like that of export_manifest, without
+    /// touching the file system.
+    ///
+    /// For central indexes of trees on other machines, whose owners only send exports. Paths of
+    /// the manifest are relative to root. Files have no change time, so stale lists them and
+    /// refresh drops them unless they exist locally.
+    ///
+    /// # Errors
+    ///
+    /// Same as TagDump::from_manifest
+    pub fn from_manifest<R: BufRead>(root: &Path, reader: R) -> Result<Self> {
+        let entries = TagDump::from_manifest(reader)?
+            .entries
+            .into_iter()
+            .map(|entry| (root.join(entry.path), (UNKNOWN_STAMP, entry.tags)))
+            .collect();
+        Ok(Index {
+            root: root.to_path_buf(),
+            options: WalkOptions::default(),
+            entries,
+            stats: OnceLock::new(),
+            postings: OnceLock::new(),
+        })
+    }
+
+    /// Applies manifest of changes to the index like apply_manifest to files, without
+    /// touching the file system.
+    ///
+    /// Paths are relative to root. The whole manifest is validated first, if any line is
+    /// invalid nothing is applied and the report lists all invalid lines. Files not indexed
+    /// yet are added without change time, cleared files stay indexed without tags.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// let root = Path::new("/remote/photos");
+    /// let export = "a.jpg\tset\tphoto,rating=3\n";
+    /// let mut index = xtag::Index::from_manifest(root, export.as_bytes()).unwrap();
+    /// let patch = "a.jpg\tadd\trating=5\nb.jpg\tadd\tphoto\n";
+    /// let report = index.apply_manifest(patch.as_bytes()).unwrap();
+    /// assert_eq!(report.applied, 2);
+    /// let search = xtag::compile_search("rating > 4").unwrap();
+    /// assert_eq!(index.query(&search).paths(), &[root.join("a.jpg")]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if the manifest can't be read
+    pub fn apply_manifest<R: BufRead>(&mut self, reader: R) -> Result<ManifestReport> {
+        let mut report = ManifestReport::default();
+        let mut operations = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            match parse_line(&line?) {
+                Ok(Some((path, operation))) => operations.push((self.root.join(path), operation)),
+                Ok(None) => (),
+                Err(error) => report.errors.push(ManifestError {
+                    line: index + 1,
+                    error,
+                }),
+            }
+        }
+        if !report.errors.is_empty() {
+            return Ok(report);
+        }
+
+        self.stats.take();
+        self.postings.take();
+        for (path, operation) in operations {
+            let (_, tags) = self
+                .entries
+                .entry(path)
+                .or_insert_with(|| (UNKNOWN_STAMP, XTags::new()));
+            match operation {
+                ManifestOperation::Add(add) => tags.extend(add),
+                ManifestOperation::Remove(remove) => remove_by_name(tags, &remove),
+                ManifestOperation::Set(set) => *tags = set,
+                ManifestOperation::Clear => tags.clear(),
+            }
+            report.applied += 1;
+        }
+        Ok(report)
+    }
+
+    /// Indexed files changed or removed since they were indexed.
+    pub fn stale(&self) -> Vec<PathBuf> {
+        self.entries
+            .iter()
+            .filter(|(path, (stamp, _))| FileStamp::of(path).ok() != Some(*stamp))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Brings index up to date with the tree.
+    ///
+    /// Walks the tree and reads tags only of new and changed files.
+    ///
+    /// # Errors
+    ///
+    /// Same as walk and get_tags, files without attribute support are handled by options
+    pub fn refresh(&mut self) -> Result<()> {
+        let mut changed = Vec::new();
+        let mut seen = HashSet::new();
+        for path in walk(&self.root, &self.options) {
+            let path = path?;
+            if self.is_index_file(&path) {
+                continue;
+            }
+            let stamp = FileStamp::of(&path)?;
+            if self.entries.get(&path).map(|(old, _)| old) != Some(&stamp) {
+                changed.push(path.clone());
+            }
+            seen.insert(path);
+        }
+        // update resets stats and postings after removing files
+        self.entries.retain(|path, _| seen.contains(path));
+        self.update(changed)
+    }
+
+    /// Sets options for walking the tree on refresh.
+    pub fn set_options(&mut self, options: &WalkOptions) {
+        self.options = options.clone();
+    }
+
+    /// Indexed tags of file, None if not indexed.
+    pub fn tags(&self, path: &Path) -> Option<&XTags> {
+        self.entries.get(path).map(|(_, tags)| tags)
+    }
+
+    /// Iterates over indexed files and their tags, sorted by path.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &XTags)> {
+        self.entries
+            .iter()
+            .map(|(path, (_, tags))| (path.as_path(), tags))
+    }
+
+    // Entries with their change times, in order of paths
+    #[cfg(feature = "arrow")]
+    pub(crate) fn stamped(&self) -> impl Iterator<Item = (&Path, FileStamp, &XTags)> {
+        self.entries
+            .iter()
+            .map(|(path, (stamp, tags))| (path.as_path(), *stamp, tags))
+    }
+
+    /// Number of indexed files.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no file is indexed.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn is_index_file(&self, path: &Path) -> bool {
+        is_index_file(&self.root, path)
+    }
+}
+
+// Change time and tags of file, None if it is gone or has no tags
+#[cfg(feature = "parallel")]
+fn read_entry(tree: &TreeTags, path: &Path) -> Result<Option<(FileStamp, XTags)>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let stamp = FileStamp::of(path)?;
+    Ok(tree.get(path)?.map(|tags| (stamp, tags)))
+}
+
+type Entries = Vec<(PathBuf, Result<Option<(FileStamp, XTags)>>)>;
+
+// Entries of paths in their order, read in the thread pool of options
+#[cfg(feature = "parallel")]
+fn read_entries(tree: &TreeTags, paths: Vec<PathBuf>, options: &WalkOptions) -> Result<Entries> {
+    use rayon::prelude::*;
+
+    crate::parallel::install(options, || {
+        paths
+            .into_par_iter()
+            .map(|path| {
+                let entry = read_entry(tree, &path);
+                (path, entry)
+            })
+            .collect()
+    })
+}
+
+// Entries of paths, tags are read in batches by TagReader
+#[cfg(not(feature = "parallel"))]
+fn read_entries(tree: &TreeTags, paths: Vec<PathBuf>, options: &WalkOptions) -> Result<Entries> {
+    let mut entries = Vec::with_capacity(paths.len());
+    let mut stamps = Vec::new();
+    let mut existing = Vec::new();
+    for path in paths {
+        if path.exists() {
+            stamps.push(FileStamp::of(&path));
+            existing.push(path);
+        } else {
+            entries.push((path, Ok(None)));
+        }
+    }
+    let reader = TagReader::new(existing.into_iter(), options.store.clone());
+    for ((path, tags), stamp) in reader.zip(stamps) {
+        let entry = stamp.and_then(|stamp| Ok(tree.recover(&path, tags)?.map(|tags| (stamp, tags))));
+        entries.push((path, entry));
+    }
+    Ok(entries)
+}
+
+// Returns true for the files of an index in root
+pub(crate) fn is_index_file(root: &Path, path: &Path) -> bool {
+    path.parent() == Some(root)
+        && path.file_name().is_some_and(|name| {
+            [INDEX_FILE_NAME, INDEX_LOCK_FILE_NAME, INDEX_TEMP_FILE_NAME]
+                .iter()
+                .any(|index_name| name == *index_name)
+        })
+}
+
+// Takes advisory lock of index in root, retrying while other processes hold it
+fn lock(root: &Path, exclusive: bool) -> Result<File> {
+    let path = root.join(INDEX_LOCK_FILE_NAME);
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+    let start = Instant::now();
+    loop {
+        let result = match exclusive {
+            true => file.try_lock(),
+            false => file.try_lock_shared(),
+        };
+        match result {
+            Ok(()) => return Ok(file),
+            Err(TryLockError::WouldBlock) if start.elapsed() < LOCK_TIMEOUT => {
+                thread::sleep(LOCK_BACKOFF)
+            }
+            Err(TryLockError::WouldBlock) => return Err(XTagError::IndexLocked(path)),
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Index;
+    use crate::{compile_search, csl_to_map, set_tags, Fixture, WalkOptions};
+
+    #[test]
+    fn index_answers_queries_and_detects_changes() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("photo")
+            .file("sub/b")
+            .tags("photo,rating=5")
+            .file("c");
+        let index = Index::build(fixture.path(), &WalkOptions::default()).unwrap();
+        let searcher = compile_search("photo").unwrap();
+        assert_eq!(index.query(&searcher).len(), 2);
+        index.save().unwrap();
+
+        set_tags(&fixture.join("c"), &csl_to_map("photo").unwrap()).unwrap();
+        std::fs::remove_file(fixture.join("a")).unwrap();
+        let mut index = Index::load(fixture.path()).unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.stale().len(), 2);
+        index.refresh().unwrap();
+        assert!(index.stale().is_empty());
+        let result = index.query(&searcher);
+        assert_eq!(result.paths(), [fixture.join("c"), fixture.join("sub/b")]);
+    }
+
+    #[test]
+    fn transactions_of_concurrent_writers_keep_all_changes() {
+        let fixture = Fixture::new().dir("empty");
+        let root = fixture.path();
+        let options = WalkOptions::default();
+        Index::build(root, &options).unwrap().save().unwrap();
+        let paths: Vec<_> = (0..4).map(|i| fixture.join(format!("{i}"))).collect();
+        for path in &paths {
+            std::fs::write(path, "").unwrap();
+            set_tags(path, &csl_to_map("new").unwrap()).unwrap();
+        }
+
+        std::thread::scope(|scope| {
+            for path in &paths {
+                let options = &options;
+                scope.spawn(move || {
+                    Index::transaction(root, options, |index| {
+                        std::thread::sleep(std::time::Duration::from_millis(20));
+                        index.update([path])
+                    })
+                    .unwrap()
+                });
+            }
+        });
+        let index = Index::load(root).unwrap();
+        assert_eq!(index.len(), 4);
+        assert!(index.stale().is_empty());
+    }
+
+    #[test]
+    fn manifests_update_index_without_files() {
+        let fixture = Fixture::new();
+        let root = fixture.join("remote");
+        let export = "a\tset\tphoto,rating#0.5=3\nsub/b\tset\tdraft\n";
+        let mut index = Index::from_manifest(&root, export.as_bytes()).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.stale().len(), 2);
+
+        let report = index
+            .apply_manifest("a\tset\tphoto\nc\tbogus\n".as_bytes())
+            .unwrap();
+        assert_eq!((report.applied, report.errors[0].line), (0, 2));
+        let patch = "a\tremove\trating\nsub/b\tclear\nc\tadd\tphoto\n";
+        assert_eq!(index.apply_manifest(patch.as_bytes()).unwrap().applied, 3);
+        assert_eq!(
+            index.tags(&root.join("a")),
+            Some(&csl_to_map("photo").unwrap())
+        );
+        assert!(index.tags(&root.join("sub/b")).unwrap().is_empty());
+        assert_eq!(index.query(&compile_search("photo").unwrap()).len(), 2);
+        assert!(!root.exists());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn index_is_built_in_pool_of_options() {
+        let mut fixture = Fixture::new();
+        for index in 0..20 {
+            fixture = fixture
+                .file(&format!("{index:02}"))
+                .tags(&format!("n={index}"));
+        }
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let options = WalkOptions {
+            pool: Some(std::sync::Arc::new(pool)),
+            ..Default::default()
+        };
+        let index = Index::build(fixture.path(), &options).unwrap();
+        assert_eq!(index.len(), 20);
+        let tags = index.tags(&fixture.join("07")).unwrap();
+        assert_eq!(tags, &csl_to_map("n=7").unwrap());
+    }
+}
@@ -0,0 +1,281 @@
+use std::io::Cursor;
+use std::path::{Component, Path, PathBuf};
+
+use plist::Value;
+use rusqlite::{Connection, OpenFlags};
+
+use crate::read_only::ensure_writable;
+use crate::{delete_tags_from, keyword_to_tag, DumpEntry, Result, TagDump, XTagError, XTags};
+
+/// Attribute holding the Finder tags of macOS.
+///
+/// Other systems only allow attributes in namespaces, file servers like Samba store it in the
+/// user namespace.
+#[cfg(target_os = "macos")]
+pub const FINDER_TAGS_ATTRIBUTE: &str = "com.apple.metadata:_kMDItemUserTags";
+
+/// Attribute holding the Finder tags of macOS.
+///
+/// Other systems only allow attributes in namespaces, file servers like Samba store it in the
+/// user namespace.
+#[cfg(not(target_os = "macos"))]
+pub const FINDER_TAGS_ATTRIBUTE: &str = "user.com.apple.metadata:_kMDItemUserTags";
+
+/// Tag of the macOS Finder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinderTag {
+    /// Name as shown by Finder.
+    pub name: String,
+
+    /// Label color from 1 to 7, None for no color.
+    pub color: Option<u8>,
+}
+
+impl FinderTag {
+    // Finder stores `name\ncolor` with color 0 for none
+    fn from_plist_string(string: &str) -> Self {
+        match string.rsplit_once('\n') {
+            Some((name, color)) => FinderTag {
+                name: name.to_string(),
+                color: color.parse().ok().filter(|&color| color != 0),
+            },
+            None => FinderTag {
+                name: string.to_string(),
+                color: None,
+            },
+        }
+    }
+
+    fn to_plist_string(&self) -> String {
+        format!("{}\n{}", self.name, self.color.unwrap_or(0))
+    }
+}
+
+fn interop_error(err: impl std::fmt::Display) -> XTagError {
+    XTagError::Interop(err.to_string())
+}
+
+/// Finder tags of file, empty if it has none.
+///
+/// # Errors
+///
+/// - XTagError::File if the attribute can't be read
+/// - XTagError::Interop if the attribute is no binary plist array of strings
+pub fn get_finder_tags(path: &Path) -> Result<Vec<FinderTag>> {
+    let Some(bytes) = xattr::get(path, FINDER_TAGS_ATTRIBUTE).map_err(XTagError::File)? else {
+        return Ok(Vec::new());
+    };
+    let array = Value::from_reader(Cursor::new(bytes))
+        .map_err(interop_error)?
+        .into_array()
+        .ok_or_else(|| interop_error("finder tags are no array"))?;
+    array
+        .iter()
+        .map(|value| {
+            value
+                .as_string()
+                .map(FinderTag::from_plist_string)
+                .ok_or_else(|| interop_error("finder tag is no string"))
+        })
+        .collect()
+}
+
+/// Replaces Finder tags of file as binary plist, removes the attribute if tags is empty.
+///
+/// # Errors
+///
+/// - XTagError::File if the attribute can't be written
+pub fn set_finder_tags(path: &Path, tags: &[FinderTag]) -> Result<()> {
+    if tags.is_empty() {
+        return delete_tags_from(path, FINDER_TAGS_ATTRIBUTE);
+    }
+    ensure_writable(path)?;
+    let array = tags
+        .iter()
+        .map(|tag| Value::String(tag.to_plist_string()))
+        .collect();
+    let mut bytes = Vec::new();
+    Value::Array(array)
+        .to_writer_binary(&mut bytes)
+        .map_err(interop_error)?;
+    xattr::set(path, FINDER_TAGS_ATTRIBUTE, &bytes).map_err(XTagError::File)
+}
+
+/// Converts Finder tags to tags.
+///
+/// Finder tags are plain names, names like `rating=4` become tag and value. Names are turned
+/// into valid tag names with keyword_to_tag, values are kept. Colors are dropped.
+///
+/// # Example
+///
+/// ```
+/// use xtag::FinderTag;
+///
+/// let finder = [
+///     FinderTag { name: "Red Car".to_string(), color: Some(6) },
+///     FinderTag { name: "rating=4".to_string(), color: None },
+/// ];
+/// let tags = xtag::finder_to_xtags(&finder);
+/// assert_eq!(tags, xtag::csl_to_map("Red_Car,rating=4").unwrap());
+/// ```
+pub fn finder_to_xtags(tags: &[FinderTag]) -> XTags {
+    tags.iter()
+        .filter_map(|tag| {
+            let (name, value) = match tag.name.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (tag.name.as_str(), None),
+            };
+            Some((keyword_to_tag(name)?, value))
+        })
+        .collect()
+}
+
+/// Converts tags to Finder tags `tag` or `tag=value` without color, sorted by name.
+pub fn xtags_to_finder(tags: &XTags) -> Vec<FinderTag> {
+    let mut finder: Vec<FinderTag> = tags
+        .iter()
+        .map(|(tag, value)| FinderTag {
+            name: match value {
+                Some(value) => format!("{tag}={value}"),
+                None => tag.clone(),
+            },
+            color: None,
+        })
+        .collect();
+    finder.sort_by(|a, b| a.name.cmp(&b.name));
+    finder
+}
+
+/// Reads all tagged files of a TMSU database, usually `.tmsu/db`.
+///
+/// TMSU already knows tag=value pairs. Tag names are turned into valid tag names with
+/// keyword_to_tag, values are kept. xtag has one value per tag, of several values of a tag the
+/// lexicographically last is kept. TMSU stores paths below the directory containing `.tmsu`
+/// relative to it, so that directory is the root for import_tree. Paths outside of it are
+/// absolute, see TagDump::make_relative.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let dump = xtag::import_tmsu(Path::new("/photos/.tmsu/db")).unwrap();
+/// xtag::import_tree(Path::new("/photos"), &dump, xtag::ImportPolicy::Merge);
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Interop if the database can't be opened or read
+pub fn import_tmsu(database: &Path) -> Result<TagDump> {
+    let connection = Connection::open_with_flags(database, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(interop_error)?;
+    let mut statement = connection
+        .prepare(
+            "SELECT file.directory, file.name, tag.name, value.name FROM file_tag \
+             JOIN file ON file.id = file_tag.file_id \
+             JOIN tag ON tag.id = file_tag.tag_id \
+             LEFT JOIN value ON value.id = file_tag.value_id \
+             ORDER BY file.directory, file.name, tag.name, value.name",
+        )
+        .map_err(interop_error)?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })
+        .map_err(interop_error)?;
+    let mut dump = TagDump::default();
+    for row in rows {
+        let (directory, name, tag, value) = row.map_err(interop_error)?;
+        let Some(tag) = keyword_to_tag(&tag) else {
+            continue;
+        };
+        // Files directly below the root are in directory `.`
+        let path: PathBuf = Path::new(&directory)
+            .join(name)
+            .components()
+            .filter(|component| *component != Component::CurDir)
+            .collect();
+        match dump.entries.last_mut() {
+            Some(entry) if entry.path == path => {
+                entry.tags.insert(tag, value);
+            }
+            _ => dump.entries.push(DumpEntry {
+                path,
+                tags: XTags::from([(tag, value)]),
+                hash: None,
+            }),
+        }
+    }
+    Ok(dump)
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::{get_finder_tags, import_tmsu, set_finder_tags, xtags_to_finder, FinderTag};
+    use crate::{csl_to_map, finder_to_xtags, Fixture};
+
+    #[test]
+    fn finder_tags_round_trip() {
+        let fixture = Fixture::new().file("a");
+        let path = fixture.join("a");
+        assert!(get_finder_tags(&path).unwrap().is_empty());
+
+        let tags = csl_to_map("photo,rating=4").unwrap();
+        let mut finder = xtags_to_finder(&tags);
+        finder[0].color = Some(2);
+        set_finder_tags(&path, &finder).unwrap();
+        let read = get_finder_tags(&path).unwrap();
+        assert_eq!(
+            read[0],
+            FinderTag {
+                name: "photo".to_string(),
+                color: Some(2)
+            }
+        );
+        assert_eq!(finder_to_xtags(&read), tags);
+
+        set_finder_tags(&path, &[]).unwrap();
+        set_finder_tags(&path, &[]).unwrap();
+        assert!(get_finder_tags(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn tmsu_databases_are_imported() {
+        let fixture = Fixture::new();
+        let database = fixture.join("db");
+        let connection = Connection::open(&database).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE tag (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+                 CREATE TABLE value (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+                 CREATE TABLE file (id INTEGER PRIMARY KEY, directory TEXT NOT NULL,
+                     name TEXT NOT NULL);
+                 CREATE TABLE file_tag (file_id INTEGER, tag_id INTEGER, value_id INTEGER);
+                 INSERT INTO tag VALUES (1, 'holiday'), (2, 'year'), (3, 'new york');
+                 INSERT INTO value VALUES (1, '2019'), (2, '2020');
+                 INSERT INTO file VALUES (1, 'photos', 'a.jpg'), (2, '/music', 'b.mp3'),
+                     (3, '.', 'c');
+                 INSERT INTO file_tag VALUES (1, 1, 0), (1, 2, 2), (1, 2, 1), (2, 3, 0),
+                     (3, 1, 0);",
+            )
+            .unwrap();
+        drop(connection);
+
+        let dump = import_tmsu(&database).unwrap();
+        assert_eq!(dump.entries.len(), 3);
+        assert_eq!(dump.entries[0].path.to_str(), Some("c"));
+        assert_eq!(dump.entries[1].path.to_str(), Some("/music/b.mp3"));
+        assert_eq!(dump.entries[1].tags, csl_to_map("new_york").unwrap());
+        assert_eq!(dump.entries[2].path.to_str(), Some("photos/a.jpg"));
+        assert_eq!(
+            dump.entries[2].tags,
+            csl_to_map("holiday,year=2020").unwrap()
+        );
+    }
+}
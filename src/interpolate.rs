@@ -0,0 +1,228 @@
+use std::fmt::Write;
+use std::fs::{self, Metadata};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Date, Result, XTagError, XTags};
+
+// Format of times without explicit format
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d";
+
+// Metadata of the file, read on first use, and tags of the file if `{tag:name}` is supported
+struct Placeholders<'a> {
+    path: &'a Path,
+    metadata: Option<Metadata>,
+    tags: Option<&'a XTags>,
+}
+
+fn invalid(template: &str, reason: &str) -> XTagError {
+    XTagError::Template(format!("{reason} in {template:?}"))
+}
+
+impl Placeholders<'_> {
+    fn metadata(&mut self) -> Result<&Metadata> {
+        if self.metadata.is_none() {
+            self.metadata = Some(fs::metadata(self.path)?);
+        }
+        Ok(self.metadata.as_ref().expect("metadata was just read"))
+    }
+
+    fn expand(&mut self, template: &str) -> Result<String> {
+        let mut expanded = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            expanded.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            if rest[start..].starts_with("{{") || rest[start..].starts_with("}}") {
+                expanded.push_str(&rest[start..=start]);
+                rest = &after[1..];
+                continue;
+            }
+            if rest[start..].starts_with('}') {
+                return Err(invalid(template, "unmatched }"));
+            }
+            let end = after
+                .find('}')
+                .ok_or_else(|| invalid(template, "unclosed {"))?;
+            let (name, format) = match after[..end].split_once(':') {
+                Some((name, format)) => (name, Some(format)),
+                None => (&after[..end], None),
+            };
+            let value = self
+                .value(name, format)?
+                .ok_or_else(|| invalid(template, &format!("unknown placeholder {name:?}")))?;
+            expanded.push_str(&value);
+            rest = &after[end + 1..];
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    // None for unknown names
+    fn value(&mut self, name: &str, format: Option<&str>) -> Result<Option<String>> {
+        let os_str = |part: Option<&std::ffi::OsStr>| {
+            part.map(|part| part.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        let time = match name {
+            "name" => return Ok(Some(os_str(self.path.file_name()))),
+            "stem" => return Ok(Some(os_str(self.path.file_stem()))),
+            "ext" => return Ok(Some(os_str(self.path.extension()))),
+            "parent" => {
+                let parent = self.path.parent().and_then(Path::file_name);
+                return Ok(Some(os_str(parent)));
+            }
+            "size" => return Ok(Some(self.metadata()?.len().to_string())),
+            "tag" if self.tags.is_some() => return self.tag_value(format.unwrap_or_default()),
+            "mtime" => self.metadata()?.modified()?,
+            "atime" => self.metadata()?.accessed()?,
+            "now" => SystemTime::now(),
+            _ => return Ok(None),
+        };
+        format_time(time, format.unwrap_or(DEFAULT_TIME_FORMAT)).map(Some)
+    }
+
+    // Value of tag as part of a path, which must not leave the directory it is used in
+    fn tag_value(&self, tag: &str) -> Result<Option<String>> {
+        let value = self
+            .tags
+            .and_then(|tags| tags.get(tag))
+            .cloned()
+            .flatten()
+            .ok_or_else(|| XTagError::Template(format!("{tag:?} has no value")))?;
+        if value
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+        {
+            return Err(XTagError::Template(format!(
+                "value {value:?} of {tag:?} is no relative path"
+            )));
+        }
+        Ok(Some(value))
+    }
+}
+
+// Expands placeholders of interpolate_tags and `{tag:name}` for values of tags in template
+pub(crate) fn expand_path_template(path: &Path, tags: &XTags, template: &str) -> Result<String> {
+    Placeholders {
+        path,
+        metadata: None,
+        tags: Some(tags),
+    }
+    .expand(template)
+}
+
+// Formats time in UTC with a subset of strftime
+fn format_time(time: SystemTime, format: &str) -> Result<String> {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    };
+    let date = Date::from_days(seconds.div_euclid(86_400));
+    let second_of_day = seconds.rem_euclid(86_400);
+    let mut formatted = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+        // Writing to a String can't fail
+        let _ = match chars.next() {
+            Some('Y') => write!(formatted, "{:04}", date.year),
+            Some('m') => write!(formatted, "{:02}", date.month),
+            Some('d') => write!(formatted, "{:02}", date.day),
+            Some('H') => write!(formatted, "{:02}", second_of_day / 3600),
+            Some('M') => write!(formatted, "{:02}", second_of_day / 60 % 60),
+            Some('S') => write!(formatted, "{:02}", second_of_day % 60),
+            Some('s') => write!(formatted, "{seconds}"),
+            Some('%') => write!(formatted, "%"),
+            _ => return Err(invalid(format, "unknown time format")),
+        };
+    }
+    Ok(formatted)
+}
+
+/// Expands placeholders in the values of tags with metadata of file.
+///
+/// Placeholders in braces are `{name}`, `{stem}` and `{ext}` of the file name, `{parent}` for
+/// the name of its directory, `{size}` in bytes, and the times `{mtime}`, `{atime}` and `{now}`.
+/// Times are formatted in UTC as `%Y-%m-%d` or with a format like `{mtime:%Y-%m-%d %H:%M}`,
+/// supporting `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`, `%s` for seconds since 1970 and `%%`. `{{` and
+/// `}}` stand for braces. Tag names are left unchanged, and metadata is only read if a value
+/// needs it.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let tags = xtag::csl_to_map("added={now},modified={mtime:%Y-%m},type={ext}").unwrap();
+/// let tags = xtag::interpolate_tags(Path::new("a.jpg"), &tags).unwrap();
+/// assert_eq!(tags["type"].as_deref(), Some("jpg"));
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Template if a placeholder is unknown or a brace isn't closed
+/// - XTagError::File if the metadata of file can't be read
+pub fn interpolate_tags(path: &Path, tags: &XTags) -> Result<XTags> {
+    let mut placeholders = Placeholders {
+        path,
+        metadata: None,
+        tags: None,
+    };
+    tags.iter()
+        .map(|(tag, value)| {
+            let value = match value {
+                Some(value) if value.contains(['{', '}']) => Some(placeholders.expand(value)?),
+                value => value.clone(),
+            };
+            Ok((tag.clone(), value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{File, FileTimes};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::interpolate_tags;
+    use crate::{csl_to_map, Fixture, XTagError};
+
+    #[test]
+    fn values_are_expanded_per_file() {
+        let fixture = Fixture::new().file("photos/a.jpg");
+        let path = fixture.join("photos/a.jpg");
+        std::fs::write(&path, "1234").unwrap();
+        let modified = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(modified))
+            .unwrap();
+
+        let tags = csl_to_map(
+            "type={ext},name={stem}/{name},dir={parent},size={size},\
+             shot={mtime},at={mtime:%H:%M:%S %s%%},braces={{x}},flag,plain=x",
+        )
+        .unwrap();
+        assert_eq!(
+            interpolate_tags(&path, &tags).unwrap(),
+            csl_to_map(
+                "type=jpg,name=a/a.jpg,dir=photos,size=4,shot=2024-02-29,\
+                 at=12:34:56 1709210096%,braces={x},flag,plain=x"
+            )
+            .unwrap()
+        );
+
+        for value in ["{nope}", "{mtime", "x}", "{mtime:%q}"] {
+            let tags = [("a".to_string(), Some(value.to_string()))].into();
+            let result = interpolate_tags(&path, &tags);
+            assert!(matches!(result, Err(XTagError::Template(_))), "{value}");
+        }
+        let tags = csl_to_map("size={size}").unwrap();
+        assert!(interpolate_tags(&fixture.join("missing"), &tags).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{self, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    csl_to_map, delete_tags, get_tags, map_to_csl, rename, set_tags, Result, XTagError, XTags,
+};
+#[cfg(feature = "json")]
+use crate::{tag_changes, EventLog, LogEvent};
+
+/// Tags of a file before a journaled change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// Absolute path of the file.
+    pub path: PathBuf,
+
+    /// Tags before the change, empty if the file had none.
+    pub tags: XTags,
+
+    /// Time of the change, in whole seconds.
+    pub time: SystemTime,
+}
+
+impl JournalEntry {
+    // Seconds, tags and path separated by tabs, escaped tags contain no tabs or line breaks
+    fn to_line(&self) -> Result<String> {
+        let path = self
+            .path
+            .to_str()
+            .ok_or_else(|| XTagError::NonUnicodePath(self.path.clone()))?;
+        if path.contains(['\n', '\r']) {
+            return Err(XTagError::Journal(format!("line break in path {path:?}")));
+        }
+        let seconds = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Ok(format!(
+            "{}\t{}\t{path}\n",
+            seconds.as_secs(),
+            map_to_csl(&self.tags)
+        ))
+    }
+
+    fn from_line(line: &str) -> Result<Self> {
+        let invalid = || XTagError::Journal(format!("invalid line {line:?}"));
+        let mut fields = line.splitn(3, '\t');
+        let (Some(seconds), Some(tags), Some(path)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid());
+        };
+        Ok(JournalEntry {
+            path: PathBuf::from(path),
+            tags: csl_to_map(tags)?,
+            time: UNIX_EPOCH + Duration::from_secs(seconds.parse().map_err(|_| invalid())?),
+        })
+    }
+
+    // Puts the recorded tags back
+    fn restore(&self) -> Result<()> {
+        if self.tags.is_empty() {
+            delete_tags(&self.path)
+        } else {
+            set_tags(&self.path, &self.tags)
+        }
+    }
+}
+
+/// Tag changes recording the previous tags in a journal file.
+///
+/// Extended attributes have no history, so an accidental mass change can't be undone. Every
+/// change made through Journaled first appends the previous tags of the file to the journal,
+/// undo_last restores them. The journal only grows until entries are undone.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let journal = xtag::Journaled::new(Path::new("/var/lib/xtag/journal"));
+/// journal.rename(Path::new("a.jpg"), "holiday", "vacation").unwrap();
+/// journal.undo_last(1).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Journaled {
+    journal: PathBuf,
+    #[cfg(feature = "json")]
+    event_log: Option<EventLog>,
+}
+
+impl Journaled {
+    /// Returns Journaled appending to journal, which is created on the first change.
+    pub fn new(journal: &Path) -> Self {
+        Journaled {
+            journal: journal.to_path_buf(),
+            #[cfg(feature = "json")]
+            event_log: None,
+        }
+    }
+
+    /// Also appends every change to log, one event per changed tag.
+    #[cfg(feature = "json")]
+    pub fn with_event_log(mut self, log: EventLog) -> Self {
+        self.event_log = Some(log);
+        self
+    }
+
+    /// Path of the journal file.
+    pub fn journal(&self) -> &Path {
+        &self.journal
+    }
+
+    /// Set tags for file from map, see set_tags.
+    pub fn set_tags(&self, path: &Path, tags: &XTags) -> Result<()> {
+        let entry = self.record(path)?;
+        set_tags(path, tags)?;
+        self.log(entry, tags)
+    }
+
+    /// Delete all tags for file, see delete_tags.
+    pub fn delete_tags(&self, path: &Path) -> Result<()> {
+        let entry = self.record(path)?;
+        delete_tags(path)?;
+        self.log(entry, &XTags::new())
+    }
+
+    /// Rename tags of file matching find, see rename.
+    pub fn rename(&self, path: &Path, find: &str, replace: &str) -> Result<()> {
+        let tags = rename(find, replace, get_tags(path)?)?;
+        self.set_tags(path, &tags)
+    }
+
+    /// Undo the last n changes of the journal, see undo_last.
+    pub fn undo_last(&self, n: usize) -> Result<usize> {
+        undo_last(&self.journal, n)
+    }
+
+    #[cfg(feature = "json")]
+    fn log(&self, entry: JournalEntry, tags: &XTags) -> Result<()> {
+        let Some(log) = &self.event_log else {
+            return Ok(());
+        };
+        let events: Vec<LogEvent> = tag_changes(&entry.tags, tags)
+            .into_iter()
+            .map(|change| LogEvent {
+                time: entry.time,
+                path: entry.path.clone(),
+                change,
+            })
+            .collect();
+        log.append(&events)
+    }
+
+    #[cfg(not(feature = "json"))]
+    fn log(&self, _entry: JournalEntry, _tags: &XTags) -> Result<()> {
+        Ok(())
+    }
+
+    // Returns the recorded entry
+    fn record(&self, path: &Path) -> Result<JournalEntry> {
+        let entry = JournalEntry {
+            path: path::absolute(path)?,
+            tags: get_tags(path)?,
+            time: SystemTime::now(),
+        };
+        let line = entry.to_line()?;
+        let _lock = lock_journal(&self.journal, false)?;
+        // One write per line, so appends of several processes don't interleave
+        let mut file = File::options()
+            .create(true)
+            .append(true)
+            .open(&self.journal)?;
+        file.write_all(line.as_bytes())?;
+        Ok(entry)
+    }
+}
+
+// Advisory lock of journal, shared by appending writers and exclusive while undo_last rewrites
+// it. The journal itself is replaced on rewrites, so its sibling `<journal>.lock` is locked.
+fn lock_journal(journal: &Path, exclusive: bool) -> Result<File> {
+    let mut name = journal.as_os_str().to_os_string();
+    name.push(".lock");
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(PathBuf::from(name))?;
+    match exclusive {
+        true => file.lock()?,
+        false => file.lock_shared()?,
+    }
+    Ok(file)
+}
+
+/// Entries of journal, oldest first
+///
+/// # Errors
+///
+/// - XTagError::Journal if a line is no valid entry
+pub fn read_journal(journal: &Path) -> Result<Vec<JournalEntry>> {
+    let file = match File::open(journal) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        entries.push(JournalEntry::from_line(&line?)?);
+    }
+    Ok(entries)
+}
+
+/// Undo the last n changes recorded in journal, newest first
+///
+/// Undone entries are removed from the journal, so calling it again goes further back. Returns
+/// the number of undone changes, less than n if the journal is shorter. If restoring a file
+/// fails, the entries undone so far are removed and the error is returned. Changes journaled
+/// meanwhile by other threads or processes wait until the journal is rewritten.
+///
+/// # Errors
+///
+/// - XTagError::Journal if a line is no valid entry
+/// - Same as set_tags and delete_tags
+pub fn undo_last(journal: &Path, n: usize) -> Result<usize> {
+    let _lock = lock_journal(journal, true)?;
+    let mut entries = read_journal(journal)?;
+    let mut undone = 0;
+    let mut result = Ok(());
+    while undone < n {
+        let Some(entry) = entries.last() else {
+            break;
+        };
+        result = entry.restore();
+        if result.is_err() {
+            break;
+        }
+        entries.pop();
+        undone += 1;
+    }
+    if undone > 0 {
+        let lines = entries
+            .iter()
+            .map(JournalEntry::to_line)
+            .collect::<Result<String>>()?;
+        let temp = journal.with_extension("tmp");
+        fs::write(&temp, lines)?;
+        fs::rename(temp, journal)?;
+    }
+    result.map(|()| undone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_journal, Journaled};
+    use crate::{csl_to_map, get_tags, Fixture};
+
+    #[test]
+    fn journaled_changes_can_be_undone() {
+        let fixture = Fixture::new().file("a").tags("holiday,rating=3").file("b");
+        let journal = Journaled::new(&fixture.join("journal"));
+        let a = fixture.join("a");
+        let b = fixture.join("b");
+
+        journal.rename(&a, "holiday", "vacation").unwrap();
+        journal.set_tags(&b, &csl_to_map("new").unwrap()).unwrap();
+        journal.delete_tags(&a).unwrap();
+        assert_eq!(read_journal(journal.journal()).unwrap().len(), 3);
+        assert!(get_tags(&a).unwrap().is_empty());
+
+        assert_eq!(journal.undo_last(2).unwrap(), 2);
+        assert_eq!(
+            get_tags(&a).unwrap(),
+            csl_to_map("vacation,rating=3").unwrap()
+        );
+        assert!(get_tags(&b).unwrap().is_empty());
+
+        assert_eq!(journal.undo_last(5).unwrap(), 1);
+        assert_eq!(
+            get_tags(&a).unwrap(),
+            csl_to_map("holiday,rating=3").unwrap()
+        );
+        assert!(read_journal(journal.journal()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn undo_keeps_concurrent_entries() {
+        let fixture = Fixture::new().file("a").file("b").file("c");
+        let journal = Journaled::new(&fixture.join("journal"));
+        journal
+            .set_tags(&fixture.join("a"), &csl_to_map("x").unwrap())
+            .unwrap();
+        let undone: usize = std::thread::scope(|scope| {
+            for name in ["b", "c"] {
+                let (journal, path) = (&journal, fixture.join(name));
+                scope.spawn(move || {
+                    for n in 0..50 {
+                        let tags = csl_to_map(&format!("n={n}")).unwrap();
+                        journal.set_tags(&path, &tags).unwrap();
+                    }
+                });
+            }
+            (0..20).map(|_| journal.undo_last(1).unwrap()).sum()
+        });
+        let entries = read_journal(journal.journal()).unwrap();
+        assert_eq!(entries.len() + undone, 101);
+    }
+}
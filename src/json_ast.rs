@@ -0,0 +1,314 @@
+use regex::Regex;
+use serde_json::{json, Map, Value};
+
+use crate::searcher::unexpand_regex;
+use crate::{AliasMap, Comparand, Result, Searcher, XTagError};
+
+// Removes the anchors added by expand_regex, so the AST holds what users wrote
+fn unexpand(regex: &Regex) -> &str {
+    unexpand_regex(regex.as_str())
+}
+
+impl Searcher {
+    /// Returns canonical JSON representation of Searcher.
+    ///
+    /// The format is meant for exchange with programs not written in Rust and stays stable
+    /// between versions. Every node is an object with a `type`:
+    ///
+    /// - `{"type":"and","terms":[...]}` and `{"type":"or","terms":[...]}` with at least two
+    ///   terms, nested operations of the same type are flattened
+    /// - `{"type":"not","term":{...}}`
+    /// - `{"type":"tag","tag":"photo"}`
+    /// - `{"type":"compare","tag":"rating","op":">=","value":4}` with op one of `==`, `<`,
+    ///   `<=`, `>` and `>=`, value is a string for `==` and an integer, a float or an ISO-8601
+    ///   date string otherwise
+    /// - `{"type":"anyvalue","value":"invoice"}` for values of any tag
+    /// - `{"type":"istrue","tag":"done"}` and `{"type":"isfalse","tag":"done"}`
+    /// - `{"type":"under","tag":"place/europe","separator":"/"}` for tags and their descendants
+    /// - `{"type":"hasvalue","tag":"rating"}` and `{"type":"valueless","tag":"draft"}`
+    /// - `{"type":"tagcount","min":2,"max":5}` for the number of tags, max is null for no limit
+    /// - `{"type":"fold","term":{...}}` for accent- and case-insensitive matching
+    /// - `{"type":"canonical","aliases":{"b&w":"bw"},"term":{...}}` for matching tags
+    ///   canonicalized with an AliasMap
+    /// - `{"type":"bookmark","name":"recent","term":{...}}` for the expansion of `{recent}`
+    ///
+    /// Tags and values are regular expressions, anchored to match whole tags and values. The
+    /// output has no whitespace and keys are sorted, so equal searches give equal strings.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let search = xtag::compile_search("photo and rating >= 4").unwrap();
+    /// assert_eq!(
+    ///     search.to_json_ast(),
+    ///     r#"{"terms":[{"tag":"photo","type":"tag"},{"op":">=","tag":"rating","type":"compare","value":4}],"type":"and"}"#
+    /// );
+    /// ```
+    pub fn to_json_ast(&self) -> String {
+        self.to_json_value().to_string()
+    }
+
+    /// Returns Searcher from canonical JSON representation of to_json_ast.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::JsonAst if json isn't valid JSON or no valid representation
+    /// - XTagError::Regex if a tag or value isn't a valid regular expression
+    pub fn from_json_ast(json: &str) -> Result<Self> {
+        let value: Value =
+            serde_json::from_str(json).map_err(|err| XTagError::JsonAst(err.to_string()))?;
+        from_json_value(&value)
+    }
+
+    fn to_json_value(&self) -> Value {
+        match self {
+            Searcher::And { .. } => json!({"type": "and", "terms": self.flatten_terms()}),
+            Searcher::Or { .. } => json!({"type": "or", "terms": self.flatten_terms()}),
+            Searcher::Not { lhs } => json!({"type": "not", "term": lhs.to_json_value()}),
+            Searcher::Tag { regex } => json!({"type": "tag", "tag": unexpand(regex)}),
+            Searcher::Equal {
+                tag_regex,
+                value_regex,
+            } => compare(tag_regex, "==", json!(unexpand(value_regex))),
+            Searcher::EqualCaptures {
+                tag_regex,
+                value_template,
+            } => compare(
+                tag_regex,
+                "==",
+                json!(unexpand_regex(value_template.as_str())),
+            ),
+            Searcher::AnyValue { value_regex } => {
+                json!({"type": "anyvalue", "value": unexpand(value_regex)})
+            }
+            Searcher::Less { tag_regex, value } => compare(tag_regex, "<", comparand(value)),
+            Searcher::LessEqual { tag_regex, value } => compare(tag_regex, "<=", comparand(value)),
+            Searcher::Greater { tag_regex, value } => compare(tag_regex, ">", comparand(value)),
+            Searcher::GreaterEqual { tag_regex, value } => {
+                compare(tag_regex, ">=", comparand(value))
+            }
+            Searcher::IsTrue { tag_regex } => json!({"type": "istrue", "tag": unexpand(tag_regex)}),
+            Searcher::IsFalse { tag_regex } => {
+                json!({"type": "isfalse", "tag": unexpand(tag_regex)})
+            }
+            Searcher::Folded { lhs } => json!({"type": "fold", "term": lhs.to_json_value()}),
+            Searcher::Canonical { lhs, aliases } => {
+                let aliases: Map<String, Value> = aliases
+                    .iter()
+                    .map(|(alias, canonical)| (alias.to_string(), json!(canonical)))
+                    .collect();
+                json!({"type": "canonical", "aliases": aliases, "term": lhs.to_json_value()})
+            }
+            Searcher::Descendant { regex, separator } => {
+                json!({"type": "under", "tag": unexpand(regex), "separator": separator})
+            }
+            Searcher::HasValue { tag_regex } => {
+                json!({"type": "hasvalue", "tag": unexpand(tag_regex)})
+            }
+            Searcher::ValuelessTag { tag_regex } => {
+                json!({"type": "valueless", "tag": unexpand(tag_regex)})
+            }
+            Searcher::TagCount { min, max } => json!({"type": "tagcount", "min": min, "max": max}),
+            Searcher::Bookmark { name, lhs } => {
+                json!({"type": "bookmark", "name": name, "term": lhs.to_json_value()})
+            }
+        }
+    }
+
+    // Operands of nested ands or nested ors as one list
+    fn flatten_terms(&self) -> Vec<Value> {
+        let mut terms = Vec::new();
+        let mut pending = vec![self];
+        while let Some(searcher) = pending.pop() {
+            match (self, searcher) {
+                (Searcher::And { .. }, Searcher::And { lhs, rhs })
+                | (Searcher::Or { .. }, Searcher::Or { lhs, rhs }) => {
+                    pending.push(rhs);
+                    pending.push(lhs);
+                }
+                _ => terms.push(searcher.to_json_value()),
+            }
+        }
+        terms
+    }
+}
+
+fn compare(tag_regex: &Regex, op: &str, value: Value) -> Value {
+    json!({"type": "compare", "tag": unexpand(tag_regex), "op": op, "value": value})
+}
+
+fn comparand(value: &Comparand) -> Value {
+    match value {
+        Comparand::Int(value) => json!(value),
+        Comparand::Float(value) => json!(value),
+        Comparand::Date(value) => json!(value.to_string()),
+    }
+}
+
+fn invalid(message: &str, value: &Value) -> XTagError {
+    XTagError::JsonAst(format!("{message} in {value}"))
+}
+
+fn field<'a>(object: &'a Map<String, Value>, key: &str, value: &Value) -> Result<&'a Value> {
+    object
+        .get(key)
+        .ok_or_else(|| invalid(&format!("missing {key:?}"), value))
+}
+
+fn string_field<'a>(object: &'a Map<String, Value>, key: &str, value: &Value) -> Result<&'a str> {
+    field(object, key, value)?
+        .as_str()
+        .ok_or_else(|| invalid(&format!("{key:?} is no string"), value))
+}
+
+fn from_json_value(value: &Value) -> Result<Searcher> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| invalid("node is no object", value))?;
+    match string_field(object, "type", value)? {
+        kind @ ("and" | "or") => {
+            let terms = field(object, "terms", value)?
+                .as_array()
+                .filter(|terms| terms.len() >= 2)
+                .ok_or_else(|| invalid("\"terms\" needs at least two nodes", value))?;
+            let mut terms = terms.iter().map(from_json_value);
+            let first = terms.next().unwrap()?;
+            terms.try_fold(first, |lhs, rhs| {
+                Ok(match kind {
+                    "and" => Searcher::new_and(lhs, rhs?),
+                    _ => Searcher::new_or(lhs, rhs?),
+                })
+            })
+        }
+        "not" => Ok(Searcher::new_not(from_json_value(field(
+            object, "term", value,
+        )?)?)),
+        "fold" => Ok(Searcher::new_folded(from_json_value(field(
+            object, "term", value,
+        )?)?)),
+        "bookmark" => {
+            let term = from_json_value(field(object, "term", value)?)?;
+            Ok(Searcher::new_bookmark(
+                string_field(object, "name", value)?,
+                term,
+            ))
+        }
+        "canonical" => {
+            let mut aliases = AliasMap::new();
+            let entries = field(object, "aliases", value)?
+                .as_object()
+                .ok_or_else(|| invalid("\"aliases\" is no object", value))?;
+            for (alias, canonical) in entries {
+                let canonical = canonical
+                    .as_str()
+                    .ok_or_else(|| invalid("alias of no string", value))?;
+                aliases.insert(alias, canonical)?;
+            }
+            let term = from_json_value(field(object, "term", value)?)?;
+            Ok(Searcher::new_canonical(term, aliases))
+        }
+        "tag" => Searcher::new_tag(string_field(object, "tag", value)?),
+        "anyvalue" => Searcher::new_any_value(string_field(object, "value", value)?),
+        "istrue" => Searcher::new_is_true(string_field(object, "tag", value)?),
+        "under" => {
+            let mut separator = string_field(object, "separator", value)?.chars();
+            match (separator.next(), separator.next()) {
+                (Some(separator), None) => {
+                    Searcher::new_descendant(string_field(object, "tag", value)?, separator)
+                }
+                _ => Err(invalid("\"separator\" is no single character", value)),
+            }
+        }
+        "isfalse" => Searcher::new_is_false(string_field(object, "tag", value)?),
+        "hasvalue" => Searcher::new_has_value(string_field(object, "tag", value)?),
+        "valueless" => Searcher::new_valueless_tag(string_field(object, "tag", value)?),
+        "tagcount" => {
+            let count = |key| match field(object, key, value)? {
+                Value::Null if key == "max" => Ok(None),
+                count => count
+                    .as_u64()
+                    .and_then(|count| usize::try_from(count).ok())
+                    .map(Some)
+                    .ok_or_else(|| invalid(&format!("{key:?} is no count"), value)),
+            };
+            let min = count("min")?.unwrap_or_default();
+            Ok(Searcher::TagCount {
+                min,
+                max: count("max")?,
+            })
+        }
+        "compare" => {
+            let tag = string_field(object, "tag", value)?;
+            let op = string_field(object, "op", value)?;
+            if op == "==" {
+                return Searcher::new_equal(tag, string_field(object, "value", value)?);
+            }
+            let rhs = field(object, "value", value)?;
+            let rhs = match rhs {
+                Value::Number(number) => match number.as_i64() {
+                    Some(number) => i32::try_from(number).ok().map(Comparand::Int),
+                    None => number.as_f64().map(Comparand::Float),
+                },
+                Value::String(date) => date.parse().ok().map(Comparand::Date),
+                _ => None,
+            }
+            .ok_or_else(|| invalid("\"value\" is no integer, float or date", value))?
+            .to_string();
+            match op {
+                "<" => Searcher::new_less(tag, &rhs),
+                "<=" => Searcher::new_less_equal(tag, &rhs),
+                ">" => Searcher::new_greater(tag, &rhs),
+                ">=" => Searcher::new_greater_equal(tag, &rhs),
+                _ => Err(invalid("unknown \"op\"", value)),
+            }
+        }
+        _ => Err(invalid("unknown \"type\"", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compile_search, compile_search_with, csl_to_map, SearchOptions, Searcher};
+
+    #[test]
+    fn json_ast_round_trips() {
+        let options = SearchOptions {
+            fold: true,
+            ..Default::default()
+        };
+        for term in [
+            "a or b or c and d",
+            "not (x == y+) and istrue(t) and isfalse(f)",
+            "a != b and n < -3 and n <= 5 and n > 1 and n >= 2",
+            "r < 4.5 and r > 1.0 and d <= 2023-06-01",
+            "(a|b) and ^c$",
+            "under(place/europe) or x",
+            "* == inv.* and := x",
+            "a? and b! and #tags != 2 and #tags > 1 and #tags < 9",
+        ] {
+            let searcher = compile_search(term).unwrap();
+            let json = searcher.to_json_ast();
+            let restored = Searcher::from_json_ast(&json).unwrap();
+            assert_eq!(restored.to_json_ast(), json);
+            assert_eq!(restored.to_string(), searcher.to_string());
+        }
+        let folded = compile_search_with("Malmo", &options).unwrap();
+        let restored = Searcher::from_json_ast(&folded.to_json_ast()).unwrap();
+        assert!(restored.is_match(&csl_to_map("malmö").unwrap()));
+    }
+
+    #[test]
+    fn invalid_json_ast_is_rejected() {
+        for json in [
+            "[]",
+            r#"{"type":"and","terms":[{"type":"tag","tag":"a"}]}"#,
+            r#"{"type":"compare","tag":"a","op":"<","value":"1"}"#,
+            r#"{"type":"compare","tag":"a","op":"<","value":true}"#,
+            r#"{"type":"compare","tag":"a","op":"~","value":1}"#,
+            r#"{"type":"xor"}"#,
+            "{",
+        ] {
+            assert!(Searcher::from_json_ast(json).is_err(), "{json}");
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::encoding::decode_base64;
+use crate::{DumpEntry, Result, TagDump, XTagError};
+
+/// Turns free text keyword into tag name.
+///
+/// Tag names only consist of letters, numbers, `:`, `_`, `-` and `/`, so runs of other
+/// characters are replaced by `_`. Returns None if nothing is left.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(xtag::keyword_to_tag(" New York ").unwrap(), "New_York");
+/// assert_eq!(xtag::keyword_to_tag("b&w").unwrap(), "b_w");
+/// assert!(xtag::keyword_to_tag("!?").is_none());
+/// ```
+pub fn keyword_to_tag(keyword: &str) -> Option<String> {
+    let mut tag = String::with_capacity(keyword.len());
+    for c in keyword.trim().chars() {
+        if c.is_alphanumeric() || matches!(c, ':' | '_' | '-' | '/') {
+            tag.push(c);
+        } else if !tag.ends_with('_') {
+            tag.push('_');
+        }
+    }
+    let tag = tag.trim_matches('_');
+    (!tag.is_empty()).then(|| tag.to_string())
+}
+
+// Keywords separated by commas or semicolons, by whitespace if there are none
+fn split_keywords(keywords: &str) -> Vec<&str> {
+    if keywords.contains([',', ';']) {
+        keywords.split([',', ';']).collect()
+    } else {
+        keywords.split_whitespace().collect()
+    }
+}
+
+// Collects keywords per path, keywords of repeated paths are merged
+#[derive(Default)]
+struct KeywordCollector {
+    dump: TagDump,
+    index: HashMap<PathBuf, usize>,
+}
+
+impl KeywordCollector {
+    fn add(&mut self, path: PathBuf, keywords: &str) {
+        let index = *self.index.entry(path.clone()).or_insert_with(|| {
+            self.dump.entries.push(DumpEntry {
+                path,
+                tags: Default::default(),
+                hash: None,
+            });
+            self.dump.entries.len() - 1
+        });
+        let tags = &mut self.dump.entries[index].tags;
+        for tag in split_keywords(keywords)
+            .into_iter()
+            .filter_map(keyword_to_tag)
+        {
+            tags.insert(tag, None);
+        }
+    }
+}
+
+impl TagDump {
+    /// Parses lines `<file><TAB><keywords>` of plain text keyword lists.
+    ///
+    /// Keywords are separated by commas or semicolons, or by whitespace if a line has neither,
+    /// and become tags without value, see keyword_to_tag. Keywords of files listed more than
+    /// once are merged. Empty lines and lines starting with `#` are skipped. Relative paths are
+    /// resolved against the root of import_tree, absolute ones have to be made relative to it
+    /// with make_relative.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// let list = std::fs::read_to_string("/photos/keywords.txt").unwrap();
+    /// let dump = xtag::TagDump::from_keyword_list(&list).unwrap();
+    /// xtag::import_tree(Path::new("/photos"), &dump, xtag::ImportPolicy::Merge);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Dump if a line has no tab
+    pub fn from_keyword_list(text: &str) -> Result<Self> {
+        let mut collector = KeywordCollector::default();
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (path, keywords) = line
+                .split_once('\t')
+                .filter(|(path, _)| !path.is_empty())
+                .ok_or_else(|| XTagError::Dump(format!("invalid line {}", index + 1)))?;
+            collector.add(PathBuf::from(path), keywords);
+        }
+        Ok(collector.dump)
+    }
+
+    /// Parses output of `recollq -F "url keywords"`.
+    ///
+    /// Every result line holds the base64 encoded url and keywords separated by a space. Lines
+    /// whose first field isn't a base64 encoded `file://` url, like the header lines of recollq,
+    /// are skipped. Keywords are split like from_keyword_list does. Paths are absolute, see
+    /// make_relative.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Dump if a field of a result line isn't valid base64 of UTF-8 text
+    pub fn from_recoll(text: &str) -> Result<Self> {
+        let mut collector = KeywordCollector::default();
+        for (index, line) in text.lines().enumerate() {
+            let mut fields = line.split(' ');
+            let Some(url) = fields.next().and_then(|url| decode_base64(url.as_bytes())) else {
+                continue;
+            };
+            let Some(path) = url.strip_prefix(b"file://") else {
+                continue;
+            };
+            let invalid = || XTagError::Dump(format!("invalid line {}", index + 1));
+            let path = String::from_utf8(path.to_vec()).map_err(|_| invalid())?;
+            let keywords = match fields.next().unwrap_or_default() {
+                "" => Vec::new(),
+                keywords => decode_base64(keywords.as_bytes()).ok_or_else(invalid)?,
+            };
+            let keywords = String::from_utf8(keywords).map_err(|_| invalid())?;
+            collector.add(PathBuf::from(path), &keywords);
+        }
+        Ok(collector.dump)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{csl_to_map, get_tags, import_tree, Fixture, ImportPolicy, TagDump};
+
+    #[test]
+    fn keyword_lists_are_imported() {
+        let fixture = Fixture::new().file("a.jpg").tags("old").file("b.jpg");
+        let list = "# exported keywords\na.jpg\tbeach; New York\nb.jpg\tb&w  film\n\na.jpg\tsea\n";
+        let dump = TagDump::from_keyword_list(list).unwrap();
+        assert_eq!(dump.entries.len(), 2);
+        let report = import_tree(fixture.path(), &dump, ImportPolicy::Merge);
+        assert_eq!(report.imported, 2);
+        assert_eq!(
+            get_tags(&fixture.join("a.jpg")).unwrap(),
+            csl_to_map("old,beach,New_York,sea").unwrap()
+        );
+        assert_eq!(
+            get_tags(&fixture.join("b.jpg")).unwrap(),
+            csl_to_map("b_w,film").unwrap()
+        );
+        assert!(TagDump::from_keyword_list("a.jpg beach").is_err());
+
+        let recoll = "Xapian query: [beach]\n2 results\n\
+            ZmlsZTovLy9waG90b3MvYS5qcGc= YmVhY2gsIE5ldyBZb3Jr\n\
+            ZmlsZTovLy9waG90b3MvYi5qcGc= YiZ3\n\
+            ZmlsZTovLy9waG90b3MvYi5qcGc=\n";
+        let dump = TagDump::from_recoll(recoll).unwrap();
+        assert_eq!(dump.entries.len(), 2);
+        assert_eq!(dump.entries[0].path.to_str(), Some("/photos/a.jpg"));
+        assert_eq!(dump.entries[0].tags, csl_to_map("beach,New_York").unwrap());
+        assert_eq!(dump.entries[1].tags, csl_to_map("b_w").unwrap());
+        assert!(TagDump::from_recoll("ZmlsZTovLy9h !").is_err());
+
+        let mut dump = TagDump::from_keyword_list("/photos/a.jpg\tx\n/videos/b\ty\n").unwrap();
+        dump.make_relative(std::path::Path::new("/photos"));
+        assert_eq!(dump.entries[0].path.to_str(), Some("a.jpg"));
+        assert_eq!(dump.entries[1].path.to_str(), Some("/videos/b"));
+    }
+}
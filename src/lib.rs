@@ -1,105 +1,654 @@
-mod bookmarks;
+// Items of feature full, without it only the literal search is left
+macro_rules! full {
+    ($($item:item)*) => {
+        $(
+            #[cfg(feature = "full")]
+            $item
+        )*
+    };
+}
+
+full! {
+    mod aliases;
+    #[cfg(feature = "arrow")]
+    mod analytics;
+    #[cfg(feature = "zip")]
+    mod archive;
+    #[cfg(feature = "async")]
+    mod async_api;
+    mod autotag;
+    mod batch;
+    mod bench;
+    mod bookmarks;
+    mod bulk;
+    mod cache;
+    mod captures;
+    #[cfg(feature = "checksum")]
+    mod checksum;
+    mod classify;
+    mod client;
+    mod collation;
+    mod collision;
+    mod comparand;
+    mod comparison;
+    mod completion;
+    mod content_type;
+    mod daemon;
+    mod desktop;
+    mod details;
+    mod dump;
+    mod editor;
+    mod encoding;
+    #[cfg(feature = "json")]
+    mod event_log;
+    mod explain;
+    mod fd;
+    mod federated;
+    #[cfg(any(test, feature = "fixture"))]
+    mod fixture;
+    mod fold;
+    mod gc;
+    mod glob;
+    mod groups;
+    mod hierarchy;
+    mod history;
+    mod index;
+    #[cfg(feature = "interop")]
+    mod interop;
+    mod interpolate;
+    mod journal;
+    #[cfg(feature = "json")]
+    mod json_ast;
+    mod keywords;
+    mod limits;
+    mod manifest;
+    mod match_stats;
+    mod migrate;
+    mod multi;
+    mod namespace;
+    mod normalize;
+    mod optimize;
+    mod options;
+    #[cfg(feature = "parallel")]
+    mod parallel;
+    mod params;
+    mod parse_error;
+    mod parse_search;
+    mod parse_tags;
+    mod parser;
+    mod plan;
+    mod playlist;
+    mod query;
+    mod query_plan;
+    mod query_set;
+    mod read_only;
+    mod reader;
+    mod rename_dir;
+    mod result_set;
+    mod results;
+    mod retry;
+    mod rewrite;
+    mod schema;
+    mod script;
+    mod searcher;
+    #[cfg(feature = "serde")]
+    mod serialize;
+    mod session;
+    mod similar;
+    mod stats;
+    mod store;
+    mod stores;
+    mod summary;
+    mod tags_ref;
+    mod temp_tag;
+    mod timeout;
+    mod tombstone;
+    mod transfer;
+    mod untagged;
+    mod views;
+    mod virtual_tags;
+    mod visibility;
+    mod visit;
+    mod vocabulary;
+    mod walk;
+    #[cfg(feature = "watch")]
+    mod watch;
+    mod weight;
+}
 mod error;
-mod parse_search;
-mod parse_tags;
-mod parser;
-mod searcher;
+#[cfg(feature = "literal")]
+mod literal;
 
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::str;
+use std::collections::{BTreeSet, HashMap};
 
-use itertools::Itertools;
-use regex::Regex;
+full! {
+    use std::borrow::Cow;
+    use std::path::Path;
+    use std::str;
 
-pub use crate::bookmarks::get_bookmark;
+    use regex::Regex;
+
+    pub use crate::aliases::{
+        get_tags_canonical, get_tags_canonical_with_store, normalize_tags, normalize_tags_with_store,
+        AliasMap,
+    };
+    #[cfg(feature = "zip")]
+    pub use crate::archive::{pack_zip, read_zip_tags, unpack_zip, ZIP_TAGS_NAME};
+    #[cfg(feature = "async")]
+    pub use crate::async_api::{delete_tags_async, get_tags_async, search_dir_async, set_tags_async};
+    pub use crate::autotag::{autotag, AutotagRule};
+    pub use crate::batch::{Batch, BatchFailure, BatchReport};
+    pub use crate::bench::{bench_query, NodeProfile, QueryProfile};
+    pub use crate::bookmarks::{
+        evaluate_bookmarks, get_bookmark, get_bookmark_description, save_bookmark, BookmarkRegistry,
+        BOOKMARK_EXTENSION,
+    };
+    pub use crate::bulk::{bulk_apply, BulkFailure, BulkOptions, BulkReport};
+    pub use crate::cache::{SearchCache, TagCache};
+    pub use crate::captures::ValueTemplate;
+    #[cfg(feature = "checksum")]
+    pub use crate::checksum::{tag_checksum, verify_checksums, ChecksumAlgorithm, ChecksumReport};
+    pub use crate::classify::{classify, Classifier};
+    pub use crate::client::{Client, XTagConfig};
+    pub use crate::collation::Collation;
+    use crate::collision::collisions_with;
+    pub use crate::collision::{rename_collisions, Collision};
+    pub use crate::comparand::{Comparand, Date};
+    pub use crate::comparison::{ComparisonPolicy, SkippedValue};
+    pub use crate::completion::SuggestMode;
+    pub use crate::content_type::{
+        autotag_content, autotag_content_file, detect_mime_type, mime_tags, MimeRule,
+    };
+    pub use crate::daemon::{query_daemon, Daemon};
+    pub use crate::desktop::{desktop_entry, write_symlink_tree};
+    pub use crate::details::{MatchDetails, TermMatch};
+    pub use crate::dump::{
+        export_tree, import_tree, import_tree_with_store, DumpEntry, ImportPolicy, ImportReport,
+        TagDump,
+    };
+    pub use crate::editor::XTagsEditor;
+    #[cfg(feature = "json")]
+    pub use crate::event_log::{tag_changes, EventLog, LogChange, LogEvent, EVENT_LOG_VERSION};
+    pub use crate::explain::{explain_dir, MatchTrace};
+    pub use crate::fd::{delete_tags_fd, get_tags_fd, set_tags_fd};
+    pub use crate::federated::{search_roots, FederatedMatch, FederatedResult, RootReport};
+    #[cfg(any(test, feature = "fixture"))]
+    pub use crate::fixture::Fixture;
+    pub use crate::fold::fold;
+    pub use crate::gc::{gc, GcChange, GcPolicy, GcReport};
+    pub use crate::glob::{compile_search_glob, glob_to_regex};
+    pub use crate::groups::{
+        delete_group_tags, get_all_tags, get_group_tags, list_groups, set_group_tags,
+    };
+    pub use crate::hierarchy::{ancestors, ancestors_with, HIERARCHY_SEPARATOR};
+    pub use crate::history::{history, restore_version, set_tags_with_history};
+    pub use crate::index::{Index, INDEX_FILE_NAME, INDEX_LOCK_FILE_NAME};
+    #[cfg(feature = "interop")]
+    pub use crate::interop::{
+        finder_to_xtags, get_finder_tags, import_tmsu, set_finder_tags, xtags_to_finder, FinderTag,
+        FINDER_TAGS_ATTRIBUTE,
+    };
+    pub use crate::interpolate::interpolate_tags;
+    pub use crate::journal::{read_journal, undo_last, JournalEntry, Journaled};
+    pub use crate::keywords::keyword_to_tag;
+    pub use crate::limits::{LimitAction, LimitViolation, LimitedStore, TagLimits};
+    pub use crate::manifest::{
+        apply_manifest, export_manifest, verify_roundtrip, ManifestError, ManifestOperation,
+        ManifestOptions, ManifestReport, RoundtripReport, TagDrift,
+    };
+    pub use crate::match_stats::{match_stats, search_dir_with_stats, MatchStats, TermStats};
+    pub use crate::migrate::{migrate_vocabulary, MigrationReport, VocabularyMap};
+    pub use crate::multi::{
+        csl_to_multi_map, get_multi_tags, multi_map_to_csl, set_multi_tags, xtags_to_multi,
+    };
+    pub use crate::namespace::{delete_tags_in, get_tags_in, set_tags_in, Namespace};
+    pub use crate::normalize::{
+        set_tags_normalized, set_tags_normalized_with_store, NormalizationPolicy,
+    };
+    pub use crate::options::{Anchoring, Keyword, SearchOptions};
+    #[cfg(feature = "parallel")]
+    pub use crate::parallel::search_dir_par;
+    pub use crate::params::Param;
+    pub use crate::parse_error::SearchParseError;
+    pub use crate::parse_search::{compile_search, compile_search_with, compile_search_with_params};
+    pub use crate::parse_tags::csl_to_map;
+    use crate::parser::Rule;
+    pub use crate::plan::{plan, Action, Change, Plan, PlanReport, PlanStep};
+    pub use crate::playlist::{export_list, ListFormat, PathStyle};
+    pub use crate::query::{Query, ValueQuery};
+    pub use crate::query_plan::{IndexAccess, QueryPlan};
+    pub use crate::query_set::QuerySet;
+    use crate::read_only::ensure_writable;
+    pub use crate::read_only::{is_read_only, set_read_only, ReadOnlyStore};
+    pub use crate::reader::{read_tags, read_tags_with_store, TagReader};
+    pub use crate::rename_dir::{rename_in_dir, RenameReport};
+    pub use crate::result_set::{InodeGroup, ResultSet};
+    pub use crate::results::{group_by_tag, sort_by_tag, Order, ResultGroup};
+    pub use crate::retry::RetryingStore;
+    pub use crate::rewrite::{rewrite_bookmark, rewrite_term, rewrite_views};
+    pub use crate::schema::{
+        set_tags_checked, set_tags_checked_with_store, TagRule, TagSchema, Violation,
+    };
+    pub use crate::script::export_setfattr_script;
+    pub use crate::searcher::{Searcher, TagPairs};
+    pub use crate::session::Session;
+    pub use crate::similar::{similar_to, similarity};
+    pub use crate::stats::{
+        collect_stats, collect_stats_dir, cooccurrence, histogram, suggest_for, Bucket, Bucketing,
+        Cooccurrence, Histogram, NumericSummary, RelatedTag, TagCount, TagStats, NAMESPACE_SEPARATOR,
+    };
+    pub use crate::store::{
+        add_tags_with_store, merge_tags_with_store, remove_tags_with_store, swap_tags_with_store,
+        SidecarLayout, SidecarStore, Store, XattrStore, DIRECTORY_SIDECAR_NAME, XDG_TAGS_ATTRIBUTE,
+    };
+    pub use crate::stores::{
+        get_sidecar_tags, set_sidecar_tags, sidecar_path, Conflict, StoredTags, Stores, TagStore,
+        SIDECAR_EXTENSION,
+    };
+    pub use crate::summary::{summarize_dir, Aggregate, SummaryOptions};
+    pub use crate::tags_ref::XTagsRef;
+    pub use crate::temp_tag::TempTag;
+    pub use crate::timeout::TimeoutStore;
+    pub use crate::tombstone::{
+        purge_tombstones, restore_tombstone, soft_delete_tags, tombstones, Tombstone,
+    };
+    pub use crate::transfer::{copy_tags, copy_with_tags, move_with_tags};
+    pub use crate::untagged::{find_untagged, UntaggedFilter};
+    pub use crate::views::{delete_view, list_views, run_view, set_view};
+    pub use crate::virtual_tags::{
+        get_tags_with, ExtensionProvider, ImageDimensionsProvider, SizeProvider, VirtualTagProvider,
+    };
+    pub use crate::visibility::VisibilityFilter;
+    pub use crate::visit::SearcherVisitor;
+    pub use crate::vocabulary::{vocabulary, ValueType, Vocabulary, VocabularyEntry};
+    pub use crate::walk::{
+        search_dir, walk, DegradationPolicy, Throttle, WalkOptions, IGNORE_FILE_NAME,
+    };
+    #[cfg(feature = "watch")]
+    pub use crate::watch::{TagEvent, TagWatcher};
+    pub use crate::weight::{rank, split_weight, with_weight, DEFAULT_WEIGHT};
+}
 pub use crate::error::{Result, XTagError};
-pub use crate::parse_search::compile_search;
-pub use crate::parse_tags::csl_to_map;
-use crate::parser::Rule;
-pub use crate::searcher::Searcher;
+#[cfg(feature = "literal")]
+pub use crate::literal::{compile_literal, LiteralSearcher};
 
 pub type XTags = HashMap<String, Option<String>>;
 
-static XATTR_NAME: &'static str = "user.xtag";
+/// Tags with any number of values each, an empty set for tags without value.
+pub type MultiXTags = HashMap<String, BTreeSet<String>>;
 
-/// Convert map to comma separated list of tag=value pairs
-pub fn map_to_csl(set: &XTags) -> String {
-    set.iter()
-        .map(|(tag, value)| match value {
-            Some(value) => tag.to_string() + "=" + value,
-            None => tag.to_string(),
-        })
-        .join(",")
-}
+full! {
+    static XATTR_NAME: &str = "user.xtag";
+
+    /// Convert map to comma separated list of tag=value pairs
+    ///
+    /// Values are escaped as expected by csl_to_map, so they may contain any character. Tags are
+    /// sorted, so equal maps give equal lists.
+    pub fn map_to_csl(set: &XTags) -> String {
+        parse_tags::write_csl(
+            set.iter()
+                .map(|(tag, value)| (tag.as_str(), value.as_deref())),
+        )
+    }
 
-/// Get tags for file as map
-pub fn get_tags(path: &PathBuf) -> Result<XTags> {
-    let xattrs = xattr::get(path, XATTR_NAME).map_err(|err| XTagError::File(err))?;
-    match xattrs {
-        Some(value) => {
-            let string = str::from_utf8(&value).map_err(|err| XTagError::Charset(err))?;
-            csl_to_map(string)
+    /// Get tags for file as map
+    ///
+    /// Trailing NUL and newline characters are ignored. Values in the hex (`0x`) or base64 (`0s`)
+    /// notation of setfattr are decoded.
+    pub fn get_tags(path: &Path) -> Result<XTags> {
+        get_tags_from(path, XATTR_NAME)
+    }
+
+    // Reads tags from attribute name
+    fn get_tags_from(path: &Path, name: &str) -> Result<XTags> {
+        let xattrs = xattr::get(path, name).map_err(XTagError::File)?;
+        match xattrs {
+            Some(value) => tags_from_bytes(&value),
+            None => csl_to_map(""),
         }
-        None => csl_to_map(""),
     }
-}
 
-/// Set tags for file from map
-///
-/// The used utf-8 string format is architecture independent.
-pub fn set_tags(path: &PathBuf, tags: &XTags) -> Result<()> {
-    let string = map_to_csl(tags);
-    xattr::set(path, XATTR_NAME, &string.as_bytes()).map_err(|err| XTagError::File(err))
-}
+    // Reads comma separated list from attribute name, empty if there is none
+    fn get_csl_from(path: &Path, name: &str) -> Result<String> {
+        let xattrs = xattr::get(path, name).map_err(XTagError::File)?;
+        match xattrs {
+            Some(value) => csl_from_bytes(&value),
+            None => Ok(String::new()),
+        }
+    }
 
-/// Delete all tags for file
-pub fn delete_tags(path: &PathBuf) -> Result<()> {
-    match xattr::remove(path, XATTR_NAME) {
-        Ok(()) => Ok(()),
-        Err(err) if err.to_string().starts_with("No data available") => Ok(()),
-        Err(err) => Err(XTagError::File(err)),
+    // Parses raw attribute value
+    fn tags_from_bytes(value: &[u8]) -> Result<XTags> {
+        csl_to_map(&csl_from_bytes(value)?)
     }
-}
 
-pub fn rename(find: &str, replace: &str, tags: XTags) -> Result<XTags> {
-    let mut result: XTags = HashMap::with_capacity(tags.len());
-    let re = Regex::new(&searcher::expand_regex(find)).map_err(|err| XTagError::Regex(err))?;
-    for (key, value) in tags {
-        let new_key = re.replace_all(&key, replace).into_owned();
-        result.insert(new_key, value);
+    // Calls f with tags of file borrowed from its attribute, like get_tags without copying them
+    pub(crate) fn with_tags_ref<R, F: FnOnce(&XTagsRef) -> R>(path: &Path, f: F) -> Result<R> {
+        let raw = xattr::get(path, XATTR_NAME)
+            .map_err(XTagError::File)?
+            .unwrap_or_default();
+        let value = encoding::decode_attribute(&raw);
+        let csl = str::from_utf8(&value).map_err(XTagError::Charset)?;
+        Ok(f(&XTagsRef::parse(csl)?))
+    }
+
+    // Decodes raw attribute value, tolerating encodings of other tools
+    fn csl_from_bytes(value: &[u8]) -> Result<String> {
+        let value = encoding::decode_attribute(value);
+        let string = str::from_utf8(&value).map_err(XTagError::Charset)?;
+        Ok(string.to_string())
     }
-    Ok(result)
-}
 
-#[cfg(test)]
-mod tests {
-    use super::rename;
-    use super::XTags;
-    use std::collections::HashMap;
+    /// Set tags for file from map
+    ///
+    /// The used utf-8 string format is architecture independent.
+    pub fn set_tags(path: &Path, tags: &XTags) -> Result<()> {
+        set_tags_to(path, XATTR_NAME, tags)
+    }
 
-    fn test(key: &str, value: &str, from: &str, to: &str, end_key: &str) {
-        let mut map: XTags = HashMap::new();
-        map.insert(key.to_string(), Some(value.to_string()));
-        let map = rename(from, to, map).unwrap();
-        assert_eq!(map.len(), 1);
-        let element = map.iter().next().unwrap();
-        assert_eq!(element.0, end_key);
-        assert_eq!(element.1, &Some(value.to_string()));
+    fn set_tags_to(path: &Path, name: &str, tags: &XTags) -> Result<()> {
+        ensure_writable(path)?;
+        let string = map_to_csl(tags);
+        xattr::set(path, name, string.as_bytes()).map_err(XTagError::File)
     }
 
-    #[test]
-    fn rename_supports_plain_text() {
-        test("from", "value", "from", "to", "to");
+    /// Delete all tags for file
+    pub fn delete_tags(path: &Path) -> Result<()> {
+        delete_tags_from(path, XATTR_NAME)
     }
 
-    #[test]
-    fn rename_supports_regex() {
-        test("from", "value", "f(.)om", "to$1", "tor");
+    fn delete_tags_from(path: &Path, name: &str) -> Result<()> {
+        ensure_writable(path)?;
+        match xattr::remove(path, name) {
+            Ok(()) => Ok(()),
+            Err(err) if err.to_string().starts_with("No data available") => Ok(()),
+            Err(err) => Err(XTagError::File(err)),
+        }
     }
 
-    #[test]
-    fn rename_supports_named_capture_groups() {
-        test("from", "value", "f(?P<a>.)om", "to$a", "tor");
+    /// How merge_tags treats tags the file already has
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum MergePolicy {
+        /// Existing values win, only new tags are added.
+        KeepExisting,
+
+        /// Given values replace existing ones.
+        Overwrite,
+    }
+
+    /// Merge tags into the tags of file
+    ///
+    /// Reads, modifies and writes the tags in one call and only writes if something changed, which
+    /// keeps the window for concurrent writers small. Extended attributes offer no locking, so
+    /// writers changing the same file at the same time may still lose updates. Tags are compared
+    /// without weight, so `classic#2` replaces or keeps `classic`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// let tags = xtag::csl_to_map("rating=3").unwrap();
+    /// xtag::merge_tags(Path::new("a.jpg"), &tags, xtag::MergePolicy::KeepExisting).unwrap();
+    /// ```
+    pub fn merge_tags(path: &Path, tags: &XTags, policy: MergePolicy) -> Result<()> {
+        merge_tags_with_store(path, tags, policy, &XattrStore::default())
+    }
+
+    /// Add tags to file, replacing values of tags it already has
+    ///
+    /// Same as merge_tags with MergePolicy::Overwrite.
+    pub fn add_tags(path: &Path, tags: &XTags) -> Result<()> {
+        merge_tags(path, tags, MergePolicy::Overwrite)
+    }
+
+    /// Remove tags from file
+    ///
+    /// Tags the file doesn't have are ignored, weighted tags are removed by their plain name. The
+    /// attribute is deleted when no tag is left.
+    pub fn remove_tags(path: &Path, tags: &[&str]) -> Result<()> {
+        remove_tags_with_store(path, tags, &XattrStore::default())
+    }
+
+    /// Exchange the tags of two files
+    ///
+    /// Like fixing mixed-up files, or letting a new version of a file inherit the tags of the old
+    /// one. Extended attributes can't be written together, so a is written first. If writing b
+    /// fails, a gets its tags back. If that fails too, XTagError::RollbackFailed holds the lost tags
+    /// of a.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// xtag::swap_tags(Path::new("a.jpg"), Path::new("b.jpg")).unwrap();
+    /// ```
+    pub fn swap_tags(a: &Path, b: &Path) -> Result<()> {
+        swap_tags_with_store(a, b, &XattrStore::default())
+    }
+
+    /// Rename tags matching find
+    ///
+    /// find is a regular expression expanded with anchors, replace may reference its capture groups.
+    pub fn rename(find: &str, replace: &str, tags: XTags) -> Result<XTags> {
+        rename_cow(find, replace, Cow::Owned(tags)).map(Cow::into_owned)
+    }
+
+    /// Rename tags matching find without copying unchanged maps
+    ///
+    /// Like rename, but tags is only cloned if borrowed and at least one tag changes. Only changed
+    /// entries are touched, so chaining transformations over many maps stays cheap.
+    pub fn rename_cow<'a>(find: &str, replace: &str, tags: Cow<'a, XTags>) -> Result<Cow<'a, XTags>> {
+        let re = Regex::new(&searcher::expand_regex(find)).map_err(XTagError::Regex)?;
+        Ok(rename_with(&re, replace, tags).0)
+    }
+
+    /// Rename tags matching find, failing instead of merging tags
+    ///
+    /// Like rename, but if several tags would get the same name, or a tag would replace one that
+    /// isn't renamed, XTagError::RenameCollision lists them all and tags stay unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let tags = xtag::csl_to_map("holiday=rome,vacation=paris").unwrap();
+    /// assert!(xtag::rename_checked("holiday", "vacation", tags).is_err());
+    /// ```
+    pub fn rename_checked(find: &str, replace: &str, tags: XTags) -> Result<XTags> {
+        let re = Regex::new(&searcher::expand_regex(find)).map_err(XTagError::Regex)?;
+        let collisions = collisions_with(&re, replace, &tags);
+        if !collisions.is_empty() {
+            return Err(XTagError::RenameCollision(collisions));
+        }
+        Ok(rename_with(&re, replace, Cow::Owned(tags)).0.into_owned())
+    }
+
+    // Old and new names of tags matching compiled re that change
+    pub(crate) fn renamed_keys(re: &Regex, replace: &str, tags: &XTags) -> Vec<(String, String)> {
+        tags.keys()
+            .filter_map(|key| match re.replace_all(key, replace) {
+                Cow::Owned(new_key) if &new_key != key => Some((key.clone(), new_key)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Renames tags matching compiled re, returns tags and number of renamed tags
+    pub(crate) fn rename_with<'a>(
+        re: &Regex,
+        replace: &str,
+        tags: Cow<'a, XTags>,
+    ) -> (Cow<'a, XTags>, usize) {
+        let renames = renamed_keys(re, replace, &tags);
+        if renames.is_empty() {
+            return (tags, 0);
+        }
+
+        let count = renames.len();
+        let mut tags = tags.into_owned();
+        let values: Vec<(String, Option<String>)> = renames
+            .into_iter()
+            .map(|(key, new_key)| {
+                let value = tags.remove(&key).flatten();
+                (new_key, value)
+            })
+            .collect();
+        tags.extend(values);
+        (Cow::Owned(tags), count)
+    }
+
+    /// Replace values matching find of tags matching tag_filter
+    ///
+    /// tag_filter and find are regular expressions expanded with anchors, replace may reference the
+    /// capture groups of find. Tags without value are left alone.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let tags = xtag::csl_to_map("camera=5dmk3,lens=5dmk3").unwrap();
+    /// let tags = xtag::rename_values("camera", "5dmk(.)", "canon-5d-mark-$1", tags).unwrap();
+    /// assert_eq!(tags["camera"].as_deref(), Some("canon-5d-mark-3"));
+    /// assert_eq!(tags["lens"].as_deref(), Some("5dmk3"));
+    /// ```
+    pub fn rename_values(tag_filter: &str, find: &str, replace: &str, tags: XTags) -> Result<XTags> {
+        let tag_re = Regex::new(&searcher::expand_regex(tag_filter)).map_err(XTagError::Regex)?;
+        let value_re = Regex::new(&searcher::expand_regex(find)).map_err(XTagError::Regex)?;
+        Ok(tags
+            .into_iter()
+            .map(|(tag, value)| match value {
+                Some(value) if tag_re.is_match(&tag) => {
+                    let value = value_re.replace_all(&value, replace).into_owned();
+                    (tag, Some(value))
+                }
+                value => (tag, value),
+            })
+            .collect())
+    }
+
+    /// Rewrite tags and values together
+    ///
+    /// find is a regular expression expanded with anchors, matched against `tag=value`, or just
+    /// `tag` for tags without value. The replacement is split at its first `=` into the new tag and
+    /// value, without `=` the tag loses its value. So replace may move captures between tag and
+    /// value, like `(year)=(\d+)` to `$1/$2`. Like rename, a new tag replaces an existing one of the
+    /// same name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let tags = xtag::csl_to_map("camera=5dmk3").unwrap();
+    /// let tags = xtag::transform("camera=(.*)", "camera/$1", tags).unwrap();
+    /// assert_eq!(tags, xtag::csl_to_map("camera/5dmk3").unwrap());
+    /// ```
+    pub fn transform(find: &str, replace: &str, tags: XTags) -> Result<XTags> {
+        let re = Regex::new(&searcher::expand_regex(find)).map_err(XTagError::Regex)?;
+        let mut unchanged = XTags::with_capacity(tags.len());
+        let mut changed = Vec::new();
+        for (tag, value) in tags {
+            let entry = match &value {
+                Some(value) => format!("{tag}={value}"),
+                None => tag.clone(),
+            };
+            match re.replace_all(&entry, replace) {
+                Cow::Owned(new) if new != entry => changed.push(match new.split_once('=') {
+                    Some((tag, value)) => (tag.to_string(), Some(value.to_string())),
+                    None => (new, None),
+                }),
+                _ => {
+                    unchanged.insert(tag, value);
+                }
+            }
+        }
+        unchanged.extend(changed);
+        Ok(unchanged)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::rename;
+        use super::rename_cow;
+        use super::XTags;
+        use super::{add_tags, csl_to_map, get_tags, merge_tags, remove_tags, Fixture, MergePolicy};
+        use super::{rename_values, transform};
+        use std::borrow::Cow;
+        use std::collections::HashMap;
+
+        fn test(key: &str, value: &str, from: &str, to: &str, end_key: &str) {
+            let mut map: XTags = HashMap::new();
+            map.insert(key.to_string(), Some(value.to_string()));
+            let map = rename(from, to, map).unwrap();
+            assert_eq!(map.len(), 1);
+            let element = map.iter().next().unwrap();
+            assert_eq!(element.0, end_key);
+            assert_eq!(element.1, &Some(value.to_string()));
+        }
+
+        #[test]
+        fn rename_supports_plain_text() {
+            test("from", "value", "from", "to", "to");
+        }
+
+        #[test]
+        fn rename_supports_regex() {
+            test("from", "value", "f(.)om", "to$1", "tor");
+        }
+
+        #[test]
+        fn rename_supports_named_capture_groups() {
+            test("from", "value", "f(?P<a>.)om", "to$a", "tor");
+        }
+
+        #[test]
+        fn rename_cow_borrows_unchanged_maps() {
+            let mut map: XTags = HashMap::new();
+            map.insert("from".to_string(), None);
+            let result = rename_cow("other", "to", Cow::Borrowed(&map)).unwrap();
+            assert!(matches!(result, Cow::Borrowed(_)));
+            let result = rename_cow("from", "from", Cow::Borrowed(&map)).unwrap();
+            assert!(matches!(result, Cow::Borrowed(_)));
+            let result = rename_cow("fr(.*)", "to$1", Cow::Borrowed(&map)).unwrap();
+            assert!(result.contains_key("toom"));
+        }
+
+        #[test]
+        fn values_are_renamed_and_transformed() {
+            let tags = csl_to_map("camera=5dmk3,old=5dmk3,flag").unwrap();
+            let tags = rename_values("cam.*|flag", "5d(.*)", "canon-5d-$1", tags).unwrap();
+            assert_eq!(
+                tags,
+                csl_to_map("camera=canon-5d-mk3,old=5dmk3,flag").unwrap()
+            );
+
+            let tags = csl_to_map("year=2024,flag,camera=x").unwrap();
+            let tags = transform("(year)=(.*)", "$1/$2", tags).unwrap();
+            let tags = transform("flag", "state=$0", tags).unwrap();
+            let tags = transform("(?<tag>.*)=x", "$tag=x=y", tags).unwrap();
+            assert_eq!(
+                tags,
+                csl_to_map("year/2024,state=flag,camera=x\\=y").unwrap()
+            );
+        }
+
+        #[test]
+        fn tags_are_edited_incrementally() {
+            let fixture = Fixture::new().file("a").tags("photo,rating=3");
+            let path = fixture.join("a");
+
+            merge_tags(
+                &path,
+                &csl_to_map("rating=5,city=Rome").unwrap(),
+                MergePolicy::KeepExisting,
+            )
+            .unwrap();
+            let expected = csl_to_map("photo,rating=3,city=Rome").unwrap();
+            assert_eq!(get_tags(&path).unwrap(), expected);
+
+            add_tags(&path, &csl_to_map("rating=5").unwrap()).unwrap();
+            assert_eq!(get_tags(&path).unwrap()["rating"].as_deref(), Some("5"));
+
+            remove_tags(&path, &["rating", "missing"]).unwrap();
+            assert_eq!(get_tags(&path).unwrap().len(), 2);
+            remove_tags(&path, &["photo", "city"]).unwrap();
+            assert!(xattr::get(&path, super::XATTR_NAME).unwrap().is_none());
+        }
     }
 }
@@ -0,0 +1,291 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::{Result, Store, XTagError, XTags};
+
+/// Limits for the tags of a file, None for no limit.
+///
+/// Lengths are counted in bytes of UTF-8, like the size limits of extended attributes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TagLimits {
+    /// Most tags a file may have.
+    pub max_tags: Option<usize>,
+
+    /// Longest tag name.
+    pub max_tag_len: Option<usize>,
+
+    /// Longest value.
+    pub max_value_len: Option<usize>,
+}
+
+/// Limit of TagLimits exceeded by tags about to be written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitViolation {
+    /// More tags than max_tags.
+    TooManyTags { count: usize, max: usize },
+
+    /// Tag name longer than max_tag_len.
+    TagTooLong { tag: String, max: usize },
+
+    /// Value of tag longer than max_value_len.
+    ValueTooLong { tag: String, max: usize },
+}
+
+impl fmt::Display for LimitViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitViolation::TooManyTags { count, max } => {
+                write!(f, "{count} tags, at most {max} allowed")
+            }
+            LimitViolation::TagTooLong { tag, max } => {
+                write!(f, "tag {tag:?} longer than {max} bytes")
+            }
+            LimitViolation::ValueTooLong { tag, max } => {
+                write!(f, "value of tag {tag:?} longer than {max} bytes")
+            }
+        }
+    }
+}
+
+/// What LimitedStore does about a LimitViolation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitAction {
+    /// Shorten the tag or value, or drop the tags beyond max_tags.
+    Truncate,
+
+    /// Write nothing and return XTagError::TagLimit.
+    Reject,
+
+    /// Write the tags unchanged, the policy has already reported it.
+    Warn,
+}
+
+impl TagLimits {
+    /// Violations of tags, per tag in ascending order followed by the count of tags.
+    pub fn check(&self, tags: &XTags) -> Vec<LimitViolation> {
+        let mut names: Vec<&String> = tags.keys().collect();
+        names.sort();
+        let mut violations = Vec::new();
+        for tag in names {
+            if let Some(max) = self.max_tag_len.filter(|&max| tag.len() > max) {
+                violations.push(LimitViolation::TagTooLong {
+                    tag: tag.clone(),
+                    max,
+                });
+            }
+            if let (Some(Some(value)), Some(max)) = (tags.get(tag), self.max_value_len) {
+                if value.len() > max {
+                    violations.push(LimitViolation::ValueTooLong {
+                        tag: tag.clone(),
+                        max,
+                    });
+                }
+            }
+        }
+        if let Some(max) = self.max_tags.filter(|&max| tags.len() > max) {
+            violations.push(LimitViolation::TooManyTags {
+                count: tags.len(),
+                max,
+            });
+        }
+        violations
+    }
+}
+
+// Longest prefix of string with at most max bytes, not splitting characters
+fn truncate(string: &str, max: usize) -> &str {
+    let mut end = max.min(string.len());
+    while !string.is_char_boundary(end) {
+        end -= 1;
+    }
+    &string[..end]
+}
+
+/// Store enforcing TagLimits when tags are set.
+///
+/// Protects consumers and the size limits of extended attributes from runaway automated
+/// taggers. Every violation is passed to policy with the path, its LimitAction decides what
+/// happens. Reading and deleting tags is left to the inner store.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// use xtag::{LimitAction, LimitedStore, Store, TagLimits, XattrStore};
+///
+/// let limits = TagLimits {
+///     max_tags: Some(50),
+///     max_value_len: Some(256),
+///     ..Default::default()
+/// };
+/// let store = LimitedStore::new(XattrStore::default(), limits, |path, violation| {
+///     eprintln!("{}: {violation}", path.display());
+///     LimitAction::Truncate
+/// });
+/// let tags = xtag::csl_to_map("photo").unwrap();
+/// store.set_tags(Path::new("a.jpg"), &tags).unwrap();
+/// ```
+pub struct LimitedStore<S, F> {
+    store: S,
+    limits: TagLimits,
+    policy: F,
+}
+
+impl<S, F> LimitedStore<S, F>
+where
+    S: Store,
+    F: Fn(&Path, &LimitViolation) -> LimitAction,
+{
+    /// Returns store writing to store within limits.
+    pub fn new(store: S, limits: TagLimits, policy: F) -> Self {
+        LimitedStore {
+            store,
+            limits,
+            policy,
+        }
+    }
+
+    /// Limits enforced by the store.
+    pub fn limits(&self) -> &TagLimits {
+        &self.limits
+    }
+
+    /// Tags as they would be written to path, after the policy decided on every violation.
+    ///
+    /// Truncated tags becoming equal are merged, the value of the first in ascending order
+    /// wins. Beyond max_tags the tags in ascending order are kept.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::TagLimit if the policy rejects a violation
+    pub fn limit(&self, path: &Path, tags: &XTags) -> Result<XTags> {
+        let violations = self.limits.check(tags);
+        if violations.is_empty() {
+            return Ok(tags.clone());
+        }
+        let mut names: Vec<&String> = tags.keys().collect();
+        names.sort();
+        let mut limited: Vec<(String, Option<String>)> = Vec::with_capacity(names.len());
+        let mut actions = violations
+            .into_iter()
+            .map(|violation| match (self.policy)(path, &violation) {
+                LimitAction::Reject => Err(XTagError::TagLimit(violation.to_string())),
+                action => Ok((violation, action)),
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .peekable();
+        for tag in names {
+            let mut name = tag.as_str();
+            let mut value = tags[tag].as_deref();
+            while let Some((violation, action)) = actions.next_if(|(violation, _)| {
+                matches!(violation, LimitViolation::TagTooLong { tag: other, .. }
+                    | LimitViolation::ValueTooLong { tag: other, .. } if other == tag)
+            }) {
+                match (violation, action) {
+                    (LimitViolation::TagTooLong { max, .. }, LimitAction::Truncate) => {
+                        name = truncate(name, max)
+                    }
+                    (LimitViolation::ValueTooLong { max, .. }, LimitAction::Truncate) => {
+                        value = value.map(|value| truncate(value, max))
+                    }
+                    _ => (),
+                }
+            }
+            if !name.is_empty() && limited.iter().all(|(other, _)| other != name) {
+                limited.push((name.to_string(), value.map(str::to_string)));
+            }
+        }
+        // Only the count is left
+        if let Some((LimitViolation::TooManyTags { max, .. }, LimitAction::Truncate)) =
+            actions.next()
+        {
+            limited.truncate(max);
+        }
+        Ok(limited.into_iter().collect())
+    }
+}
+
+impl<S, F> Store for LimitedStore<S, F>
+where
+    S: Store,
+    F: Fn(&Path, &LimitViolation) -> LimitAction,
+{
+    fn get_tags(&self, path: &Path) -> Result<XTags> {
+        self.store.get_tags(path)
+    }
+
+    /// Replace all tags of file, enforcing the limits first.
+    fn set_tags(&self, path: &Path, tags: &XTags) -> Result<()> {
+        self.store.set_tags(path, &self.limit(path, tags)?)
+    }
+
+    fn delete_tags(&self, path: &Path) -> Result<()> {
+        self.store.delete_tags(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::{LimitAction, LimitViolation, LimitedStore, TagLimits};
+    use crate::{csl_to_map, Fixture, Store, XTagError, XattrStore};
+
+    #[test]
+    fn limits_are_enforced_by_policy() {
+        let fixture = Fixture::new().file("a");
+        let path = fixture.join("a");
+        let limits = TagLimits {
+            max_tags: Some(2),
+            max_tag_len: Some(4),
+            max_value_len: Some(3),
+        };
+        let tags = csl_to_map("photo=1,photos=2,b=äää,c,d").unwrap();
+        assert_eq!(
+            limits.check(&tags),
+            [
+                LimitViolation::ValueTooLong {
+                    tag: "b".to_string(),
+                    max: 3
+                },
+                LimitViolation::TagTooLong {
+                    tag: "photo".to_string(),
+                    max: 4
+                },
+                LimitViolation::TagTooLong {
+                    tag: "photos".to_string(),
+                    max: 4
+                },
+                LimitViolation::TooManyTags { count: 5, max: 2 },
+            ]
+        );
+
+        let reported = RefCell::new(Vec::new());
+        let store = LimitedStore::new(XattrStore::default(), limits, |_, violation| {
+            reported.borrow_mut().push(violation.to_string());
+            LimitAction::Truncate
+        });
+        store.set_tags(&path, &tags).unwrap();
+        assert_eq!(store.get_tags(&path).unwrap(), csl_to_map("b=ä,c").unwrap());
+        assert_eq!(reported.borrow().len(), 4);
+        let limited = store
+            .limit(&path, &csl_to_map("photo=1,photos=2").unwrap())
+            .unwrap();
+        assert_eq!(limited, csl_to_map("phot=1").unwrap());
+
+        let store = LimitedStore::new(
+            XattrStore::default(),
+            limits,
+            |_, violation| match violation {
+                LimitViolation::TooManyTags { .. } => LimitAction::Warn,
+                _ => LimitAction::Reject,
+            },
+        );
+        let result = store.set_tags(&path, &tags);
+        assert!(matches!(result, Err(XTagError::TagLimit(_))));
+        let many = csl_to_map("a,b,c").unwrap();
+        store.set_tags(&path, &many).unwrap();
+        assert_eq!(store.get_tags(&path).unwrap(), many);
+    }
+}
@@ -0,0 +1,303 @@
+//! Lightweight search supporting literal tags only.
+//!
+//! Understands the logical operators of the full grammar (`AND`/`&&`, `OR`/`||`, `NOT`/`!`) and
+//! parentheses, but no regular expressions, values or bookmarks. Uses neither pest nor regex.
+
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::error::{Result, XTagError};
+use crate::XTags;
+
+/// Searcher for literal tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LiteralSearcher {
+    /// Logical and.
+    And(Box<LiteralSearcher>, Box<LiteralSearcher>),
+
+    /// Logical or.
+    Or(Box<LiteralSearcher>, Box<LiteralSearcher>),
+
+    /// Logical not.
+    Not(Box<LiteralSearcher>),
+
+    /// Matches tag exactly.
+    Tag(String),
+}
+
+impl LiteralSearcher {
+    /// Evaluates LiteralSearcher against tags.
+    ///
+    /// Like the full search, weights of tags like `classic#2` are left out.
+    pub fn is_match(&self, tags: &XTags) -> bool {
+        self.is_match_with(&|tag| {
+            tags.contains_key(tag) || tags.keys().any(|key| plain_tag(key) == tag)
+        })
+    }
+
+    /// Evaluates LiteralSearcher against comma separated list of tag=value pairs.
+    ///
+    /// Splits the list without full parsing, which is enough for matching tags. Escaped commas
+    /// and equals signs in values are skipped like in csl_to_map, weights of tags are left out.
+    pub fn is_match_csl(&self, csl: &str) -> bool {
+        let names = csl_tags(csl);
+        self.is_match_with(&|tag| names.contains(&tag))
+    }
+
+    fn is_match_with(&self, has_tag: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            LiteralSearcher::And(lhs, rhs) => {
+                lhs.is_match_with(has_tag) && rhs.is_match_with(has_tag)
+            }
+            LiteralSearcher::Or(lhs, rhs) => {
+                lhs.is_match_with(has_tag) || rhs.is_match_with(has_tag)
+            }
+            LiteralSearcher::Not(lhs) => !lhs.is_match_with(has_tag),
+            LiteralSearcher::Tag(tag) => has_tag(tag),
+        }
+    }
+}
+
+// Plain names of the tags of comma separated list, skipping values with their escapes
+fn csl_tags(csl: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    // Start of the current name, None inside of values
+    let mut start = Some(0);
+    let mut chars = csl.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '=' => {
+                if let Some(start) = start.take() {
+                    names.push(&csl[start..index]);
+                }
+            }
+            ',' => {
+                if let Some(start) = start {
+                    names.push(&csl[start..index]);
+                }
+                start = Some(index + 1);
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = start {
+        names.push(&csl[start..]);
+    }
+    names
+        .into_iter()
+        .map(|name| plain_tag(name.trim()))
+        .collect()
+}
+
+// Tag without weight like `#0.8`, following the weight rule of the grammar
+fn plain_tag(tag: &str) -> &str {
+    let is_number = |digits: &str| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+    match tag.rsplit_once('#') {
+        Some((plain, weight)) => {
+            let (integer, fraction) = weight.split_once('.').unwrap_or((weight, "0"));
+            if is_number(integer) && is_number(fraction) {
+                plain
+            } else {
+                tag
+            }
+        }
+        None => tag,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Tag(&'a str),
+}
+
+struct Lexer<'a> {
+    term: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(term: &'a str) -> Self {
+        Lexer {
+            term,
+            chars: term.char_indices().peekable(),
+        }
+    }
+
+    fn tokens(mut self) -> Result<Vec<Token<'a>>> {
+        let mut tokens = Vec::new();
+        while let Some((start, c)) = self.chars.next() {
+            let token = match c {
+                ' ' => continue,
+                '(' => Token::Open,
+                ')' => Token::Close,
+                '!' => Token::Not,
+                '&' => self.expect_second('&', Token::And)?,
+                '|' => self.expect_second('|', Token::Or)?,
+                _ => self.word(start),
+            };
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    fn expect_second(&mut self, c: char, token: Token<'a>) -> Result<Token<'a>> {
+        match self.chars.next() {
+            Some((_, next)) if next == c => Ok(token),
+            _ => Err(XTagError::LiteralSyntax(format!("expected {c}{c}"))),
+        }
+    }
+
+    fn word(&mut self, start: usize) -> Token<'a> {
+        let mut end = self.term.len();
+        while let Some(&(index, c)) = self.chars.peek() {
+            if matches!(c, ' ' | '(' | ')' | '!' | '&' | '|') {
+                end = index;
+                break;
+            }
+            self.chars.next();
+        }
+        let word = &self.term[start..end];
+        if word.eq_ignore_ascii_case("and") {
+            Token::And
+        } else if word.eq_ignore_ascii_case("or") {
+            Token::Or
+        } else if word.eq_ignore_ascii_case("not") {
+            Token::Not
+        } else {
+            Token::Tag(word)
+        }
+    }
+}
+
+// Recursive descent parser with same precedence as the full grammar
+struct Parser<'a> {
+    tokens: Peekable<std::vec::IntoIter<Token<'a>>>,
+}
+
+impl<'a> Parser<'a> {
+    fn or_expr(&mut self) -> Result<LiteralSearcher> {
+        let mut lhs = self.and_expr()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            let rhs = self.and_expr()?;
+            lhs = LiteralSearcher::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn and_expr(&mut self) -> Result<LiteralSearcher> {
+        let mut lhs = self.not_expr()?;
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            let rhs = self.not_expr()?;
+            lhs = LiteralSearcher::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn not_expr(&mut self) -> Result<LiteralSearcher> {
+        if self.tokens.next_if_eq(&Token::Not).is_some() {
+            Ok(LiteralSearcher::Not(Box::new(self.not_expr()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<LiteralSearcher> {
+        match self.tokens.next() {
+            Some(Token::Tag(tag)) => Ok(LiteralSearcher::Tag(tag.to_string())),
+            Some(Token::Open) => {
+                let expression = self.or_expr()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(expression),
+                    _ => Err(XTagError::LiteralSyntax("expected )".to_string())),
+                }
+            }
+            Some(token) => Err(XTagError::LiteralSyntax(format!("unexpected {token:?}"))),
+            None => Err(XTagError::LiteralSyntax("unexpected end".to_string())),
+        }
+    }
+}
+
+/// Compiles term into LiteralSearcher.
+///
+/// # Example
+///
+/// ```
+/// let search = xtag::compile_literal("foo and not bar").unwrap();
+/// assert!(search.is_match_csl("foo,baz=1") == true);
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::LiteralSyntax if term is no valid expression
+pub fn compile_literal(term: &str) -> Result<LiteralSearcher> {
+    let mut parser = Parser {
+        tokens: Lexer::new(term).tokens()?.into_iter().peekable(),
+    };
+    let searcher = parser.or_expr()?;
+    match parser.tokens.next() {
+        None => Ok(searcher),
+        Some(token) => Err(XTagError::LiteralSyntax(format!("unexpected {token:?}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compile_literal;
+    use crate::XTags;
+
+    fn find_in_string(term: &str, string: &str) -> bool {
+        compile_literal(term).unwrap().is_match_csl(string)
+    }
+
+    #[test]
+    fn literal_has_same_precedence_as_grammar() {
+        assert!(find_in_string("a AND b OR c AND d", "c,d"));
+        assert!(!find_in_string("a AND (b OR c) AND d", "c,d"));
+        assert!(find_in_string("NOT a AND b", "b"));
+        assert!(!find_in_string("!(a&&b)||c", "a,b"));
+        assert!(find_in_string("a and b", "a=1, b"));
+    }
+
+    #[test]
+    fn literal_skips_escaped_values() {
+        assert!(find_in_string("place", "place=Rome\\, Italy"));
+        assert!(!find_in_string("Italy", "place=Rome\\, Italy"));
+        assert!(!find_in_string("b", "a=x\\=b\\,b"));
+        assert!(find_in_string("c", "a=x\\\\,c"));
+        assert!(!find_in_string("x", "a=\\ x"));
+    }
+
+    #[test]
+    fn literal_leaves_out_weights() {
+        assert!(find_in_string("classic", "classic#2,theme=noir#0.8"));
+        assert!(find_in_string("theme", "classic#2,theme=noir#0.8"));
+        assert!(find_in_string("a and b", "a#0.5, b#1"));
+        assert!(!find_in_string("noir", "classic#2,theme=noir#0.8"));
+        assert!(!find_in_string("bug", "bug#x"));
+        assert!(find_in_string("bug#x", "bug#x"));
+    }
+
+    #[test]
+    fn literal_matches_weighted_xtags() {
+        let tags = XTags::from([("classic#2".to_string(), None), ("a".to_string(), None)]);
+        let search = compile_literal("classic and a").unwrap();
+        assert!(search.is_match(&tags));
+        assert!(!compile_literal("classic#3").unwrap().is_match(&tags));
+    }
+
+    #[test]
+    fn literal_rejects_invalid_expression() {
+        assert!(compile_literal("a b").is_err());
+        assert!(compile_literal("(a").is_err());
+        assert!(compile_literal("a &| b").is_err());
+        assert!(compile_literal("").is_err());
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::store::store_or_xattr;
+use crate::walk::TreeTags;
+use crate::{
+    csl_to_map, get_tags, interpolate_tags, map_to_csl, split_weight, walk, NormalizationPolicy,
+    Result, Store, WalkOptions, XTagError, XTags,
+};
+
+/// Tag operation of one manifest line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestOperation {
+    /// Adds tags, replacing values of existing tags.
+    Add(XTags),
+
+    /// Removes tags by name, values and weights in the manifest are ignored.
+    Remove(XTags),
+
+    /// Replaces all tags.
+    Set(XTags),
+
+    /// Deletes all tags.
+    Clear,
+}
+
+/// Options for apply_manifest.
+#[derive(Debug, Clone, Default)]
+pub struct ManifestOptions {
+    /// Validate and read everything but don't write any tags.
+    pub dry_run: bool,
+
+    /// Normalization of the resulting tags before they are written.
+    pub normalization: Option<NormalizationPolicy>,
+
+    /// Expand placeholders like `{ext}` in values of add and set per file, see interpolate_tags.
+    pub interpolate: bool,
+
+    /// Backend of the changed tags, None for the `user.xtag` attribute like set_tags.
+    pub store: Option<Arc<dyn Store + Send + Sync>>,
+}
+
+/// Error of one manifest line.
+#[derive(Debug)]
+pub struct ManifestError {
+    /// Line number starting with 1.
+    pub line: usize,
+
+    /// What went wrong.
+    pub error: XTagError,
+}
+
+/// Outcome of apply_manifest.
+#[derive(Debug, Default)]
+pub struct ManifestReport {
+    /// Number of lines applied successfully, or that would have been in a dry run.
+    pub applied: usize,
+
+    /// Lines that failed.
+    pub errors: Vec<ManifestError>,
+}
+
+/// Applies tag operations listed in a manifest.
+///
+/// The manifest consists of tab separated lines `path<TAB>operation<TAB>tags` with operation one
+/// of `add`, `remove`, `set` or `clear` and tags as comma separated list of tag=value pairs,
+/// optional for `clear`. Relative paths are resolved against root. Empty lines and lines
+/// starting with `#` are skipped.
+///
+/// The whole manifest is validated first. If any line is invalid nothing is applied and the
+/// report lists all invalid lines. Otherwise failures of single files are reported without
+/// stopping the other lines.
+///
+/// # Errors
+///
+/// - XTagError::File if the manifest can't be read
+pub fn apply_manifest<R: BufRead>(
+    reader: R,
+    root: &Path,
+    options: &ManifestOptions,
+) -> Result<ManifestReport> {
+    let mut report = ManifestReport::default();
+    let mut entries: Vec<(usize, PathBuf, ManifestOperation)> = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        match parse_line(&line) {
+            Ok(Some((path, operation))) => entries.push((index + 1, root.join(path), operation)),
+            Ok(None) => (),
+            Err(error) => report.errors.push(ManifestError {
+                line: index + 1,
+                error,
+            }),
+        }
+    }
+    if !report.errors.is_empty() {
+        return Ok(report);
+    }
+
+    for (line, path, operation) in entries {
+        match apply_operation(&path, &operation, options) {
+            Ok(()) => report.applied += 1,
+            Err(error) => report.errors.push(ManifestError { line, error }),
+        }
+    }
+    Ok(report)
+}
+
+/// Writes tags of all files below root as manifest of `set` lines.
+///
+/// Paths are relative to root and sorted, so exports of unchanged trees are equal. Applying the
+/// manifest with apply_manifest restores the tags, verify_roundtrip compares them. Returns
+/// number of files with tags.
+///
+/// # Errors
+///
+/// - XTagError::Manifest if a path contains a tab or line break
+/// - XTagError::NonUnicodePath
+/// - Same as walk and get_tags, files without attribute support are handled by options
+pub fn export_manifest<W: Write>(
+    root: &Path,
+    options: &WalkOptions,
+    mut writer: W,
+) -> Result<usize> {
+    let mut lines = Vec::new();
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        let path = path?;
+        let Some(tags) = tree.get(&path)? else {
+            continue;
+        };
+        if tags.is_empty() {
+            continue;
+        }
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let relative = relative
+            .to_str()
+            .ok_or_else(|| XTagError::NonUnicodePath(path.clone()))?;
+        if relative.contains(['\t', '\n', '\r']) {
+            return Err(XTagError::Manifest(format!(
+                "tab or line break in path {relative:?}"
+            )));
+        }
+        lines.push(format!("{relative}\tset\t{}\n", map_to_csl(&tags)));
+    }
+    lines.sort();
+    for line in &lines {
+        writer.write_all(line.as_bytes())?;
+    }
+    writer.flush()?;
+    Ok(lines.len())
+}
+
+/// File whose tags differ from the manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagDrift {
+    /// Path of the file, joined to root.
+    pub path: PathBuf,
+
+    /// Tags according to the manifest.
+    pub expected: XTags,
+
+    /// Tags of the file.
+    pub actual: XTags,
+}
+
+/// Outcome of verify_roundtrip.
+#[derive(Debug, Default)]
+pub struct RoundtripReport {
+    /// Number of files whose tags match the manifest.
+    pub verified: usize,
+
+    /// Files of the manifest that don't exist anymore.
+    pub missing: Vec<PathBuf>,
+
+    /// Files with other tags than in the manifest.
+    pub changed: Vec<TagDrift>,
+
+    /// Invalid lines and files that couldn't be read.
+    pub errors: Vec<ManifestError>,
+}
+
+impl RoundtripReport {
+    /// Returns true if all files of the manifest match.
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.changed.is_empty() && self.errors.is_empty()
+    }
+}
+
+/// Compares tags of files below root against a manifest, like one of export_manifest.
+///
+/// The operations of all lines for a path are applied to an empty map in order, the result is
+/// expected on the file. Files below root that aren't listed are not looked at. Like
+/// apply_manifest, nothing is compared if any line is invalid. Files and tags are reported in
+/// manifest order.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::fs::File;
+/// # use std::io::BufReader;
+/// # use std::path::Path;
+/// let manifest = BufReader::new(File::open("/backup/photos.tags").unwrap());
+/// let report = xtag::verify_roundtrip(Path::new("/photos"), manifest).unwrap();
+/// for drift in &report.changed {
+///     println!("{}: {:?} != {:?}", drift.path.display(), drift.actual, drift.expected);
+/// }
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::File if the manifest can't be read
+pub fn verify_roundtrip<R: BufRead>(root: &Path, manifest: R) -> Result<RoundtripReport> {
+    let mut report = RoundtripReport::default();
+    let mut expected: Vec<(usize, PathBuf, XTags)> = Vec::new();
+    let mut positions: HashMap<PathBuf, usize> = HashMap::new();
+    for (index, line) in manifest.lines().enumerate() {
+        let line = line?;
+        let (path, operation) = match parse_line(&line) {
+            Ok(Some(entry)) => entry,
+            Ok(None) => continue,
+            Err(error) => {
+                report.errors.push(ManifestError {
+                    line: index + 1,
+                    error,
+                });
+                continue;
+            }
+        };
+        let path = root.join(path);
+        let position = *positions.entry(path.clone()).or_insert_with(|| {
+            expected.push((index + 1, path, XTags::new()));
+            expected.len() - 1
+        });
+        let tags = &mut expected[position].2;
+        match operation {
+            ManifestOperation::Add(add) => tags.extend(add),
+            ManifestOperation::Remove(remove) => tags.retain(|tag, _| !remove.contains_key(tag)),
+            ManifestOperation::Set(set) => *tags = set,
+            ManifestOperation::Clear => tags.clear(),
+        }
+    }
+    if !report.errors.is_empty() {
+        return Ok(report);
+    }
+
+    for (line, path, expected) in expected {
+        match get_tags(&path) {
+            Ok(actual) if actual == expected => report.verified += 1,
+            Ok(actual) => report.changed.push(TagDrift {
+                path,
+                expected,
+                actual,
+            }),
+            Err(XTagError::File(err)) if err.kind() == io::ErrorKind::NotFound => {
+                report.missing.push(path)
+            }
+            Err(error) => report.errors.push(ManifestError { line, error }),
+        }
+    }
+    Ok(report)
+}
+
+// Returns None for lines without content
+pub(crate) fn parse_line(line: &str) -> Result<Option<(&str, ManifestOperation)>> {
+    if line.trim().is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let mut fields = line.split('\t');
+    let path = fields.next().unwrap_or_default();
+    let operation = fields.next();
+    let tags = csl_to_map(fields.next().unwrap_or_default())?;
+    if fields.next().is_some() {
+        return Err(XTagError::Manifest("too many fields".to_string()));
+    }
+    if path.is_empty() {
+        return Err(XTagError::Manifest("missing path".to_string()));
+    }
+    let operation = match operation {
+        Some("add") => ManifestOperation::Add(tags),
+        Some("remove") => ManifestOperation::Remove(tags),
+        Some("set") => ManifestOperation::Set(tags),
+        Some("clear") if tags.is_empty() => ManifestOperation::Clear,
+        Some("clear") => return Err(XTagError::Manifest("clear takes no tags".to_string())),
+        Some(other) => {
+            return Err(XTagError::Manifest(format!("unknown operation {other:?}")));
+        }
+        None => return Err(XTagError::Manifest("missing operation".to_string())),
+    };
+    Ok(Some((path, operation)))
+}
+
+// Removes tags by plain name, so `rating` removes `rating#0.5` too
+pub(crate) fn remove_by_name(tags: &mut XTags, remove: &XTags) {
+    let names: Vec<&str> = remove.keys().map(|tag| split_weight(tag).0).collect();
+    tags.retain(|tag, _| !names.contains(&split_weight(tag).0));
+}
+
+pub(crate) fn apply_operation(
+    path: &Path,
+    operation: &ManifestOperation,
+    options: &ManifestOptions,
+) -> Result<()> {
+    let expand = |tags: &XTags| match options.interpolate {
+        true => interpolate_tags(path, tags),
+        false => Ok(tags.clone()),
+    };
+    let store = store_or_xattr(&options.store);
+    let mut tags = store.get_tags(path)?;
+    match operation {
+        ManifestOperation::Add(add) => tags.extend(expand(add)?),
+        ManifestOperation::Remove(remove) => remove_by_name(&mut tags, remove),
+        ManifestOperation::Set(set) => tags = expand(set)?,
+        ManifestOperation::Clear if options.dry_run => (),
+        ManifestOperation::Clear => return store.delete_tags(path),
+    }
+    if let Some(policy) = &options.normalization {
+        tags = policy.normalize(&tags);
+    }
+    if options.dry_run {
+        return Ok(());
+    }
+    store.set_tags(path, &tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::{
+        apply_manifest, export_manifest, parse_line, verify_roundtrip, ManifestOperation,
+        ManifestOptions,
+    };
+    use crate::{csl_to_map, set_tags, Fixture, WalkOptions};
+
+    #[test]
+    fn manifest_lines_are_parsed() {
+        assert!(parse_line("# comment").unwrap().is_none());
+        assert!(parse_line("").unwrap().is_none());
+        let (path, operation) = parse_line("a b.jpg\tadd\tx,y=1").unwrap().unwrap();
+        assert_eq!(path, "a b.jpg");
+        assert!(matches!(operation, ManifestOperation::Add(tags) if tags.len() == 2));
+        assert!(parse_line("a\tclear").unwrap().is_some());
+        assert!(parse_line("a\tclear\tx").is_err());
+        assert!(parse_line("a\tmove\tx").is_err());
+        assert!(parse_line("a").is_err());
+        assert!(parse_line("a\tadd\tx\ty").is_err());
+    }
+
+    #[test]
+    fn invalid_manifest_applies_nothing() {
+        let manifest = "missing1\tadd\tx\nmissing2\tfoo\tx\n\nmissing3\tadd\t,\n";
+        let options = ManifestOptions::default();
+        let report = apply_manifest(manifest.as_bytes(), Path::new("."), &options).unwrap();
+        assert_eq!(report.applied, 0);
+        let lines: Vec<usize> = report.errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, vec![2, 4]);
+    }
+
+    #[test]
+    fn exported_manifest_verifies_until_tags_drift() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("photo,rating=3")
+            .file("sub/b")
+            .tags("x=a\\,b")
+            .file("c");
+        let mut manifest = Vec::new();
+        let files = export_manifest(fixture.path(), &WalkOptions::default(), &mut manifest);
+        assert_eq!(files.unwrap(), 2);
+        let report = verify_roundtrip(fixture.path(), manifest.as_slice()).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.verified, 2);
+
+        set_tags(&fixture.join("a"), &csl_to_map("photo,rating=4").unwrap()).unwrap();
+        fs::remove_file(fixture.join("sub/b")).unwrap();
+        let report = verify_roundtrip(fixture.path(), manifest.as_slice()).unwrap();
+        assert_eq!(report.verified, 0);
+        assert_eq!(report.missing, vec![fixture.join("sub/b")]);
+        assert_eq!(report.changed.len(), 1);
+        assert_eq!(report.changed[0].path, fixture.join("a"));
+        assert_eq!(report.changed[0].expected["rating"].as_deref(), Some("3"));
+
+        let manifest = "c\tadd\tx\nc\tremove\tx\n";
+        let report = verify_roundtrip(fixture.path(), manifest.as_bytes()).unwrap();
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn weighted_tags_are_removed_by_name() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("photo,rating#0.5=3,classic#2");
+        let manifest = "a\tremove\trating,classic#3\n";
+        let options = ManifestOptions::default();
+        let report = apply_manifest(manifest.as_bytes(), fixture.path(), &options).unwrap();
+        assert_eq!(report.applied, 1);
+        assert_eq!(
+            crate::get_tags(&fixture.join("a")).unwrap(),
+            csl_to_map("photo").unwrap()
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::visit::Evaluator;
+use crate::walk::TreeTags;
+use crate::{walk, Result, ResultSet, Searcher, SearcherVisitor, WalkOptions, XTags};
+
+/// Files satisfying one term of a search, see MatchStats.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TermStats {
+    /// Term as displayed by Searcher, or the operator like `and` for terms with operands.
+    pub term: String,
+
+    /// Level below the root, 0 for the root.
+    pub depth: usize,
+
+    /// Files satisfying the term on its own.
+    pub matches: usize,
+}
+
+/// Number of files satisfying every term of a search.
+///
+/// Unlike with bench_query, all terms are evaluated for every file, so the counts tell how
+/// many files each term would let through, like that `year < 2000` rules out nearly all.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchStats {
+    /// Number of evaluated files.
+    pub files: usize,
+
+    /// All terms in pre-order, the root first and operands after their operator.
+    pub terms: Vec<TermStats>,
+}
+
+impl MatchStats {
+    fn new(searcher: &Searcher) -> Self {
+        let mut terms = Terms::default();
+        searcher.visit(&mut terms);
+        MatchStats {
+            files: 0,
+            terms: terms.terms,
+        }
+    }
+
+    // Evaluates all terms of searcher, returns whether the file matched
+    fn record(&mut self, searcher: &Searcher, tags: &XTags) -> bool {
+        self.files += 1;
+        searcher.evaluate_with(tags, self)
+    }
+
+    /// Files matching the whole search.
+    pub fn matches(&self) -> usize {
+        self.terms.first().map_or(0, |root| root.matches)
+    }
+}
+
+// Without short-circuits
+impl Evaluator for MatchStats {
+    fn short_circuit(&self) -> bool {
+        false
+    }
+
+    fn leave(&mut self, id: usize, _: &Searcher, matched: bool) {
+        self.terms[id].matches += usize::from(matched);
+    }
+}
+
+// Terms in pre-order with their depth
+#[derive(Default)]
+struct Terms {
+    terms: Vec<TermStats>,
+    depth: usize,
+}
+
+impl SearcherVisitor<'_> for Terms {
+    fn enter(&mut self, searcher: &Searcher) -> bool {
+        self.terms.push(TermStats {
+            term: searcher.label(),
+            depth: self.depth,
+            matches: 0,
+        });
+        self.depth += 1;
+        true
+    }
+
+    fn leave(&mut self, _: &Searcher) {
+        self.depth -= 1;
+    }
+}
+
+impl fmt::Display for MatchStats {
+    /// One line per term with its number of matching files, operands indented.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.files.to_string().len();
+        for stats in &self.terms {
+            writeln!(
+                f,
+                "{:>width$} {:indent$}{}",
+                stats.matches,
+                "",
+                stats.term,
+                indent = stats.depth * 2
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts the tag maps satisfying every term of searcher.
+///
+/// # Example
+///
+/// ```
+/// let search = xtag::compile_search("photo and year < 2000").unwrap();
+/// let tags: Vec<xtag::XTags> = ["photo,year=2021", "photo,year=1999", "video,year=1990"]
+///     .into_iter()
+///     .map(|tags| xtag::csl_to_map(tags).unwrap())
+///     .collect();
+/// let stats = xtag::match_stats(&search, &tags);
+/// assert_eq!(stats.matches(), 1);
+/// assert_eq!(stats.to_string(), "1 and\n2   ^(?:photo)$\n2   ^(?:year)$ < 2000\n");
+/// ```
+pub fn match_stats<'a, I>(searcher: &Searcher, tags: I) -> MatchStats
+where
+    I: IntoIterator<Item = &'a XTags>,
+{
+    let mut stats = MatchStats::new(searcher);
+    for tags in tags {
+        stats.record(searcher, tags);
+    }
+    stats
+}
+
+/// Like search_dir, counting the files satisfying every term of searcher, see MatchStats.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let search = xtag::compile_search("photo and (beach or sea) and not draft").unwrap();
+/// let (found, stats) =
+///     xtag::search_dir_with_stats(Path::new("photos"), &search, &Default::default()).unwrap();
+/// print!("{} of {} files\n{stats}", found.len(), stats.files);
+/// ```
+///
+/// # Errors
+///
+/// Same as walk and get_tags, files without attribute support are handled by options
+pub fn search_dir_with_stats(
+    root: &Path,
+    searcher: &Searcher,
+    options: &WalkOptions,
+) -> Result<(ResultSet, MatchStats)> {
+    let mut found = ResultSet::new();
+    let mut stats = MatchStats::new(searcher);
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        let path = path?;
+        if let Some(tags) = tree.get(&path)? {
+            if stats.record(searcher, &tags) {
+                found.push(path);
+            }
+        }
+    }
+    Ok((found, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::search_dir_with_stats;
+    use crate::{compile_search, Fixture};
+
+    #[test]
+    fn all_terms_are_counted() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("photo,beach")
+            .file("b")
+            .tags("photo")
+            .file("c")
+            .tags("video,beach")
+            .file("d");
+        let search = compile_search("photo and beach or not (beach or photo)").unwrap();
+        let (found, stats) =
+            search_dir_with_stats(fixture.path(), &search, &Default::default()).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(stats.files, 4);
+        let counts: Vec<_> = stats
+            .terms
+            .iter()
+            .map(|term| (term.depth, term.matches))
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                (0, 2),
+                (1, 1),
+                (2, 2),
+                (2, 2),
+                (1, 1),
+                (2, 3),
+                (3, 2),
+                (3, 2)
+            ]
+        );
+        assert_eq!(stats.terms[4].term, "not");
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::walk::TreeTags;
+use crate::{walk, BatchFailure, Result, WalkOptions, XTags};
+
+/// Mapping of old tags and values to new ones, applied by migrate_vocabulary.
+///
+/// Renames are applied first, value rewrites and implications refer to tags after renaming.
+/// Chains like `a` to `b` and `b` to `c` are followed to their end, so applying a map twice
+/// changes nothing the second time, unless renames or rewrites form a cycle.
+///
+/// # Example
+///
+/// ```
+/// let map = xtag::VocabularyMap::new()
+///     .rename("holiday", "vacation")
+///     .rename("trip", "vacation")
+///     .rewrite_value("rating", "five", "5")
+///     .imply("vacation", "photo", None);
+/// let tags = map.apply(&xtag::csl_to_map("trip=rome,rating=five").unwrap());
+/// assert_eq!(tags, xtag::csl_to_map("vacation=rome,rating=5,photo").unwrap());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VocabularyMap {
+    renames: BTreeMap<String, String>,
+    values: BTreeMap<String, BTreeMap<String, String>>,
+    implications: BTreeMap<String, Vec<(String, Option<String>)>>,
+}
+
+// End of the chain starting at key, stops before revisiting a key of a cycle
+fn follow<'a>(map: &'a BTreeMap<String, String>, key: &'a str) -> &'a str {
+    let mut seen = HashSet::from([key]);
+    let mut current = key;
+    while let Some(next) = map.get(current) {
+        if !seen.insert(next) {
+            break;
+        }
+        current = next;
+    }
+    current
+}
+
+impl VocabularyMap {
+    /// Returns empty map, which changes no tags.
+    pub fn new() -> Self {
+        VocabularyMap::default()
+    }
+
+    /// Renames tag old to new, keeping its value.
+    ///
+    /// Renaming several tags to the same one merges them: a tag already named new keeps its
+    /// value, otherwise the first old tag in alphabetical order wins.
+    pub fn rename(mut self, old: &str, new: &str) -> Self {
+        self.renames.insert(old.to_string(), new.to_string());
+        self
+    }
+
+    /// Replaces value old of tag by new.
+    pub fn rewrite_value(mut self, tag: &str, old: &str, new: &str) -> Self {
+        self.values
+            .entry(tag.to_string())
+            .or_default()
+            .insert(old.to_string(), new.to_string());
+        self
+    }
+
+    /// Adds implied tag with value to files with tag, unless they already have it.
+    ///
+    /// Implied tags are renamed as well and may imply further tags.
+    pub fn imply(mut self, tag: &str, implied: &str, value: Option<&str>) -> Self {
+        self.implications
+            .entry(tag.to_string())
+            .or_default()
+            .push((implied.to_string(), value.map(str::to_string)));
+        self
+    }
+
+    /// Returns whether the map changes no tags.
+    pub fn is_empty(&self) -> bool {
+        self.renames.is_empty() && self.values.is_empty() && self.implications.is_empty()
+    }
+
+    /// Name of tag after renaming.
+    pub fn resolve<'a>(&'a self, tag: &'a str) -> &'a str {
+        follow(&self.renames, tag)
+    }
+
+    /// Returns tags with the map applied.
+    pub fn apply(&self, tags: &XTags) -> XTags {
+        self.apply_counted(tags, &mut MigrationReport::default())
+    }
+
+    fn apply_counted(&self, tags: &XTags, report: &mut MigrationReport) -> XTags {
+        let mut result = XTags::new();
+        let mut renamed: Vec<(&str, &str, &Option<String>)> = Vec::new();
+        for (tag, value) in tags {
+            match self.resolve(tag) {
+                new if new == tag => {
+                    result.insert(tag.clone(), value.clone());
+                }
+                new => renamed.push((tag, new, value)),
+            }
+        }
+        renamed.sort_unstable_by_key(|(old, _, _)| *old);
+        for (_, new, value) in renamed {
+            report.renamed += 1;
+            if result.contains_key(new) {
+                report.merged += 1;
+            } else {
+                result.insert(new.to_string(), value.clone());
+            }
+        }
+
+        for (tag, value) in result.iter_mut() {
+            let (Some(rewrites), Some(old)) = (self.values.get(tag), value.as_deref()) else {
+                continue;
+            };
+            let new = follow(rewrites, old);
+            if new != old {
+                *value = Some(new.to_string());
+                report.rewritten += 1;
+            }
+        }
+
+        let mut pending: Vec<String> = result.keys().cloned().collect();
+        while let Some(tag) = pending.pop() {
+            for (implied, value) in self.implications.get(&tag).into_iter().flatten() {
+                let implied = self.resolve(implied);
+                if !result.contains_key(implied) {
+                    result.insert(implied.to_string(), value.clone());
+                    report.implied += 1;
+                    pending.push(implied.to_string());
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Outcome of migrate_vocabulary.
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    /// Number of files looked at.
+    pub scanned: usize,
+
+    /// Number of files skipped as done by an interrupted run, see migrate_vocabulary.
+    pub skipped: usize,
+
+    /// Number of files written.
+    pub files: usize,
+
+    /// Number of renamed tags over all files, including merged ones.
+    pub renamed: usize,
+
+    /// Number of renamed tags dropped because the file already had the new tag.
+    pub merged: usize,
+
+    /// Number of rewritten values over all files.
+    pub rewritten: usize,
+
+    /// Number of tags added by implications over all files.
+    pub implied: usize,
+
+    /// Files whose tags couldn't be read or written, they are retried when resuming.
+    pub failed: Vec<BatchFailure>,
+}
+
+fn read_progress(progress: &Path) -> Result<HashSet<PathBuf>> {
+    match fs::read(progress) {
+        Ok(content) => Ok(content
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| PathBuf::from(OsStr::from_bytes(line)))
+            .collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Applies map to the tags of all files below root in one pass.
+///
+/// Only files with changed tags are written. Failing files are reported and don't stop the
+/// migration. With a progress file, every finished file is appended to it, and a migration
+/// interrupted by a crash or signal continues with the files not yet finished when run again
+/// with the same file. The progress file is removed once all files are migrated.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let map = xtag::VocabularyMap::new()
+///     .rename("holiday", "vacation")
+///     .imply("vacation", "photo", None);
+/// let report = xtag::migrate_vocabulary(
+///     Path::new("/photos"),
+///     &map,
+///     &Default::default(),
+///     Some(Path::new("/photos.migration")),
+/// )
+/// .unwrap();
+/// println!("{} renamed, {} implied in {} files", report.renamed, report.implied, report.files);
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::File if the progress file can't be read or written
+/// - Same as walk, files without attribute support are handled by options
+pub fn migrate_vocabulary(
+    root: &Path,
+    map: &VocabularyMap,
+    options: &WalkOptions,
+    progress: Option<&Path>,
+) -> Result<MigrationReport> {
+    let done = match progress {
+        Some(progress) => read_progress(progress)?,
+        None => HashSet::new(),
+    };
+    let mut log = match progress {
+        Some(progress) => Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(progress)?,
+        ),
+        None => None,
+    };
+    let mut report = MigrationReport::default();
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        let path = path?;
+        report.scanned += 1;
+        if done.contains(&path) {
+            report.skipped += 1;
+            continue;
+        }
+        let migrated = tree.get(&path).and_then(|tags| {
+            let Some(tags) = tags else {
+                return Ok(false);
+            };
+            let migrated = map.apply_counted(&tags, &mut report);
+            if migrated == tags {
+                return Ok(false);
+            }
+            tree.set(&path, &migrated)
+        });
+        match migrated {
+            Ok(written) => report.files += usize::from(written),
+            Err(error) => {
+                report.failed.push(BatchFailure { path, error });
+                continue;
+            }
+        }
+        // Paths with line breaks aren't recorded, migrating them again changes nothing
+        let bytes = path.as_os_str().as_bytes();
+        if let (Some(log), false) = (&mut log, bytes.contains(&b'\n')) {
+            log.write_all(&[bytes, b"\n"].concat())?;
+        }
+    }
+    if let (Some(progress), true) = (progress, report.failed.is_empty()) {
+        fs::remove_file(progress)?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{migrate_vocabulary, VocabularyMap};
+    use crate::{csl_to_map, get_tags, Fixture};
+
+    #[test]
+    fn migrations_resume_and_converge() {
+        let fixture = Fixture::new()
+            .file("tree/a")
+            .tags("holiday=rome,trip=paris,rating=five")
+            .file("tree/b")
+            .tags("trip,photo")
+            .file("tree/c")
+            .tags("video")
+            .file("tree/d")
+            .tags("holiday");
+        let map = VocabularyMap::new()
+            .rename("holiday", "trip")
+            .rename("trip", "vacation")
+            .rewrite_value("rating", "five", "5")
+            .imply("vacation", "photo", None)
+            .imply("photo", "media", Some("image"));
+        let (root, progress) = (fixture.join("tree"), fixture.join("progress"));
+        // d was finished by an interrupted run
+        fs::write(&progress, format!("{}\n", fixture.join("tree/d").display())).unwrap();
+
+        let report = migrate_vocabulary(&root, &map, &Default::default(), Some(&progress)).unwrap();
+        assert_eq!(
+            (report.scanned, report.skipped, report.files),
+            (4, 1, 2),
+            "{report:?}"
+        );
+        assert_eq!((report.renamed, report.merged), (3, 1));
+        assert_eq!((report.rewritten, report.implied), (1, 3));
+        assert!(report.failed.is_empty());
+        assert!(!progress.exists());
+        assert_eq!(
+            get_tags(&fixture.join("tree/a")).unwrap(),
+            csl_to_map("vacation=rome,rating=5,photo,media=image").unwrap()
+        );
+        assert_eq!(
+            get_tags(&fixture.join("tree/b")).unwrap(),
+            csl_to_map("vacation,photo,media=image").unwrap()
+        );
+        assert_eq!(
+            get_tags(&fixture.join("tree/d")).unwrap(),
+            csl_to_map("holiday").unwrap()
+        );
+
+        let report = migrate_vocabulary(&root, &map, &Default::default(), None).unwrap();
+        assert_eq!((report.skipped, report.files), (0, 1));
+        let report = migrate_vocabulary(&root, &map, &Default::default(), None).unwrap();
+        assert_eq!(report.files, 0);
+    }
+}
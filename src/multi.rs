@@ -0,0 +1,183 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use itertools::Itertools;
+
+use crate::fold::fold;
+use crate::parse_tags::{escape_value, parse_csl};
+use crate::read_only::ensure_writable;
+use crate::searcher::TagPairs;
+use crate::weight::split_weight;
+use crate::{get_csl_from, AliasMap, MultiXTags, Result, XTagError, XTags, XATTR_NAME};
+
+impl TagPairs for MultiXTags {
+    fn pairs(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.iter().flat_map(|(tag, values)| {
+            let valueless = values.is_empty().then_some((tag.as_str(), None));
+            let values = values
+                .iter()
+                .map(|value| (tag.as_str(), Some(value.as_str())));
+            valueless.into_iter().chain(values)
+        })
+    }
+
+    fn tag_count(&self) -> usize {
+        self.len()
+    }
+
+    fn folded(&self) -> Self {
+        let mut folded = MultiXTags::with_capacity(self.len());
+        for (tag, values) in self {
+            let entry: &mut BTreeSet<String> = folded.entry(fold(tag)).or_default();
+            entry.extend(values.iter().map(|value| fold(value)));
+        }
+        folded
+    }
+
+    // Unlike XTags, values of aliases and canonical tags are merged
+    fn canonicalized(&self, aliases: &AliasMap) -> Self {
+        let mut canonicalized = MultiXTags::with_capacity(self.len());
+        for (tag, values) in self {
+            let (plain, _) = split_weight(tag);
+            let canonical = aliases.canonical(plain).to_string() + &tag[plain.len()..];
+            let entry: &mut BTreeSet<String> = canonicalized.entry(canonical).or_default();
+            entry.extend(values.iter().cloned());
+        }
+        canonicalized
+    }
+}
+
+/// Convert comma separated list of tag=value pairs to map with several values per tag
+///
+/// Values of repeated tags are collected, so `person=alice,person=bob` gives both values. Tags
+/// without value have an empty set, unless the tag also occurs with a value. Escaping is the
+/// same as for csl_to_map.
+///
+/// # Example
+///
+/// ```
+/// let tags = xtag::csl_to_multi_map("person=alice,person=bob,draft").unwrap();
+/// assert_eq!(tags["person"].len(), 2);
+/// assert!(tags["draft"].is_empty());
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Parser if string is no valid list
+pub fn csl_to_multi_map(string: &str) -> Result<MultiXTags> {
+    let mut result = MultiXTags::new();
+    parse_csl(string, |tag, value| {
+        let values = result.entry(tag).or_default();
+        values.extend(value);
+    })?;
+    Ok(result)
+}
+
+/// Convert map with several values per tag to comma separated list
+///
+/// Tags with several values are repeated once per value, values in sorted order. csl_to_map
+/// reads such lists too, keeping one value per tag.
+pub fn multi_map_to_csl(tags: &MultiXTags) -> String {
+    tags.iter()
+        .flat_map(|(tag, values)| match values.is_empty() {
+            true => vec![tag.to_string()],
+            false => values
+                .iter()
+                .map(|value| format!("{tag}={}", escape_value(value)))
+                .collect(),
+        })
+        .join(",")
+}
+
+/// Converts map with one value per tag to map with several values per tag.
+pub fn xtags_to_multi(tags: &XTags) -> MultiXTags {
+    tags.iter()
+        .map(|(tag, value)| (tag.clone(), value.iter().cloned().collect()))
+        .collect()
+}
+
+/// Get tags for file with all values of repeated tags
+///
+/// Like get_tags, but tags stored several times with different values keep all values.
+///
+/// # Errors
+///
+/// Same as get_tags
+pub fn get_multi_tags(path: &Path) -> Result<MultiXTags> {
+    csl_to_multi_map(&get_csl_from(path, XATTR_NAME)?)
+}
+
+/// Set tags with several values per tag for file
+///
+/// Tags with several values are stored repeated, see multi_map_to_csl.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let mut tags = xtag::get_multi_tags(Path::new("a.jpg")).unwrap();
+/// tags.entry("person".to_string()).or_default().insert("carol".to_string());
+/// xtag::set_multi_tags(Path::new("a.jpg"), &tags).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Same as set_tags
+pub fn set_multi_tags(path: &Path, tags: &MultiXTags) -> Result<()> {
+    ensure_writable(path)?;
+    let string = multi_map_to_csl(tags);
+    xattr::set(path, XATTR_NAME, string.as_bytes()).map_err(XTagError::File)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csl_to_multi_map, get_multi_tags, multi_map_to_csl, set_multi_tags};
+    use crate::{compile_search_with, csl_to_map, get_tags, AliasMap, Fixture, SearchOptions};
+
+    #[test]
+    fn tags_keep_several_values() {
+        let tags = csl_to_multi_map("person=bob,person=alice,note=x\\,y\\=z,draft").unwrap();
+        assert_eq!(
+            multi_map_to_csl(&[("person".to_string(), tags["person"].clone())].into()),
+            "person=alice,person=bob"
+        );
+        assert_eq!(csl_to_multi_map(&multi_map_to_csl(&tags)).unwrap(), tags);
+
+        let fixture = Fixture::new().file("a");
+        set_multi_tags(&fixture.join("a"), &tags).unwrap();
+        assert_eq!(get_multi_tags(&fixture.join("a")).unwrap(), tags);
+        assert_eq!(
+            get_tags(&fixture.join("a")).unwrap()["person"].as_deref(),
+            Some("bob")
+        );
+        assert!(csl_to_map(&multi_map_to_csl(&tags)).is_ok());
+    }
+
+    #[test]
+    fn searches_test_all_values() {
+        let tags = csl_to_multi_map("person=alice,person=Bob,year=2001,year=2023,draft").unwrap();
+        let mut aliases = AliasMap::new();
+        aliases.insert("who", "person").unwrap();
+        let options = SearchOptions {
+            fold: true,
+            tag_aliases: Some(aliases),
+            ..Default::default()
+        };
+        for (term, expected) in [
+            ("person == alice and person == Bob", true),
+            ("person != alice", false),
+            ("person == carol", false),
+            ("year < 2000", false),
+            ("year > 2020 and year < 2002", true),
+            ("draft! and person? and #tags == 3", true),
+            ("* == 2023", true),
+            ("person == bob", true),
+        ] {
+            let search = compile_search_with(term, &options).unwrap();
+            assert_eq!(search.is_match_multi(&tags), expected, "{term}");
+        }
+        let tags = csl_to_multi_map("who=carol,person=alice").unwrap();
+        let search = compile_search_with("person == carol", &options).unwrap();
+        assert!(search.is_match_multi(&tags));
+    }
+}
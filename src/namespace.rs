@@ -0,0 +1,140 @@
+use std::io;
+use std::path::Path;
+
+use crate::{delete_tags_from, get_tags_from, set_tags_to, Result, XTagError, XTags};
+
+// Capability needed for the trusted namespace and for writing the security namespace
+const CAP_SYS_ADMIN: u32 = 21;
+
+/// Extended attribute namespaces tags can be stored in.
+///
+/// User is the default of get_tags and set_tags. Attributes of the trusted namespace can only be
+/// read and written with CAP_SYS_ADMIN. Attributes of the security namespace can be read by
+/// everyone but only written with CAP_SYS_ADMIN, which makes it suitable for admin managed tags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Namespace {
+    User,
+    Trusted,
+    Security,
+}
+
+impl Namespace {
+    /// Namespace prefix without separator, like `trusted`
+    pub fn name(self) -> &'static str {
+        match self {
+            Namespace::User => "user",
+            Namespace::Trusted => "trusted",
+            Namespace::Security => "security",
+        }
+    }
+
+    /// Attribute holding the tags in this namespace, like `trusted.xtag`
+    pub fn attribute(self) -> String {
+        format!("{}.xtag", self.name())
+    }
+
+    /// Returns true if the process is permitted to read attributes of this namespace.
+    ///
+    /// Only the namespace is checked, permissions of individual files still apply.
+    pub fn can_read(self) -> bool {
+        match self {
+            Namespace::User | Namespace::Security => true,
+            Namespace::Trusted => has_capability(CAP_SYS_ADMIN),
+        }
+    }
+
+    /// Returns true if the process is permitted to write attributes of this namespace.
+    ///
+    /// Only the namespace is checked, permissions of individual files still apply.
+    pub fn can_write(self) -> bool {
+        match self {
+            Namespace::User => true,
+            Namespace::Trusted | Namespace::Security => has_capability(CAP_SYS_ADMIN),
+        }
+    }
+}
+
+/// Get tags of namespace for file as map
+///
+/// # Errors
+///
+/// - XTagError::NamespacePermission if the process lacks the permission for namespace
+pub fn get_tags_in(path: &Path, namespace: Namespace) -> Result<XTags> {
+    get_tags_from(path, &namespace.attribute()).map_err(|err| denied(namespace, err))
+}
+
+/// Set tags of namespace for file from map
+///
+/// # Errors
+///
+/// - XTagError::NamespacePermission if the process lacks the permission for namespace
+pub fn set_tags_in(path: &Path, namespace: Namespace, tags: &XTags) -> Result<()> {
+    set_tags_to(path, &namespace.attribute(), tags).map_err(|err| denied(namespace, err))
+}
+
+/// Delete all tags of namespace for file
+///
+/// # Errors
+///
+/// - XTagError::NamespacePermission if the process lacks the permission for namespace
+pub fn delete_tags_in(path: &Path, namespace: Namespace) -> Result<()> {
+    delete_tags_from(path, &namespace.attribute()).map_err(|err| denied(namespace, err))
+}
+
+// Permission errors outside of user namespace are caused by the namespace, not the file
+fn denied(namespace: Namespace, err: XTagError) -> XTagError {
+    match err {
+        XTagError::File(err)
+            if namespace != Namespace::User && err.kind() == io::ErrorKind::PermissionDenied =>
+        {
+            XTagError::NamespacePermission(namespace.name().to_string())
+        }
+        err => err,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn has_capability(capability: u32) -> bool {
+    std::fs::read_to_string("/proc/self/status")
+        .map(|status| effective_capabilities(&status) & (1 << capability) != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn has_capability(_capability: u32) -> bool {
+    false
+}
+
+// Bit set of CapEff line in /proc/self/status, empty if missing
+#[cfg(target_os = "linux")]
+fn effective_capabilities(status: &str) -> u64 {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|value| u64::from_str_radix(value.trim(), 16).ok())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes_are_prefixed_by_namespace() {
+        assert_eq!(Namespace::User.attribute(), crate::XATTR_NAME);
+        assert_eq!(Namespace::Trusted.attribute(), "trusted.xtag");
+        assert_eq!(Namespace::Security.attribute(), "security.xtag");
+        assert!(Namespace::User.can_read() && Namespace::User.can_write());
+        assert!(Namespace::Security.can_read());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn capabilities_are_parsed_from_status() {
+        let status = "Name:\txtag\nCapEff:\t000001ffffffffff\n";
+        assert_ne!(effective_capabilities(status) & (1 << CAP_SYS_ADMIN), 0);
+        let status = "Name:\txtag\nCapEff:\t0000000000000000\n";
+        assert_eq!(effective_capabilities(status), 0);
+        assert_eq!(effective_capabilities("Name:\txtag\n"), 0);
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::store::xattr_store;
+use crate::{Result, Searcher, Store, XTags};
+
+/// Rules to keep tags consistent.
+///
+/// Applied when writing tags with set_tags_normalized or the importers, and to the tags under
+/// test with Searcher::is_match_normalized. All steps are disabled by default.
+#[derive(Debug, Clone, Default)]
+pub struct NormalizationPolicy {
+    /// Remove leading and trailing whitespace from tags and values.
+    pub trim: bool,
+
+    /// Convert tags to lowercase.
+    pub lowercase_tags: bool,
+
+    /// Convert values to lowercase.
+    pub lowercase_values: bool,
+
+    /// Convert dates like `2024/06/24`, `20240624` or `24.06.2024` to ISO 8601 `2024-06-24`.
+    pub unify_dates: bool,
+
+    /// Replace values by their canonical form. Looked up after the other steps.
+    pub synonyms: HashMap<String, String>,
+}
+
+impl NormalizationPolicy {
+    /// Returns normalized tag name.
+    pub fn normalize_tag(&self, tag: &str) -> String {
+        let tag = if self.trim { tag.trim() } else { tag };
+        if self.lowercase_tags {
+            tag.to_lowercase()
+        } else {
+            tag.to_string()
+        }
+    }
+
+    /// Returns normalized value.
+    pub fn normalize_value(&self, value: &str) -> String {
+        let value = if self.trim { value.trim() } else { value };
+        let mut value = if self.lowercase_values {
+            value.to_lowercase()
+        } else {
+            value.to_string()
+        };
+        if self.unify_dates {
+            if let Some(date) = unify_date(&value) {
+                value = date;
+            }
+        }
+        match self.synonyms.get(&value) {
+            Some(canonical) => canonical.clone(),
+            None => value,
+        }
+    }
+
+    /// Returns normalized tags.
+    ///
+    /// Tags becoming equal by normalization are merged, which one's value survives is undefined.
+    pub fn normalize(&self, tags: &XTags) -> XTags {
+        tags.iter()
+            .map(|(tag, value)| {
+                (
+                    self.normalize_tag(tag),
+                    value.as_deref().map(|value| self.normalize_value(value)),
+                )
+            })
+            .collect()
+    }
+}
+
+// Returns ISO 8601 form of supported date formats
+fn unify_date(value: &str) -> Option<String> {
+    let parts: Vec<&str> = value.split(['-', '/', '.']).collect();
+    let (year, month, day) = match parts.as_slice() {
+        [year, month, day] if year.len() == 4 => (*year, *month, *day),
+        [day, month, year] if year.len() == 4 && value.contains('.') => (*year, *month, *day),
+        [date] if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) => {
+            (&date[0..4], &date[4..6], &date[6..8])
+        }
+        _ => return None,
+    };
+    let is_digits =
+        |s: &str| !s.is_empty() && s.len() <= 2 && s.bytes().all(|b| b.is_ascii_digit());
+    if !year.bytes().all(|b| b.is_ascii_digit()) || !is_digits(month) || !is_digits(day) {
+        return None;
+    }
+    let month: u32 = month.parse().ok()?;
+    let day: u32 = day.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(format!("{year}-{month:02}-{day:02}"))
+}
+
+/// Set tags for file from map after normalizing them
+pub fn set_tags_normalized(path: &Path, tags: &XTags, policy: &NormalizationPolicy) -> Result<()> {
+    set_tags_normalized_with_store(path, tags, policy, xattr_store())
+}
+
+/// Set tags for file in store after normalizing them, see set_tags_normalized.
+pub fn set_tags_normalized_with_store(
+    path: &Path,
+    tags: &XTags,
+    policy: &NormalizationPolicy,
+    store: &dyn Store,
+) -> Result<()> {
+    store.set_tags(path, &policy.normalize(tags))
+}
+
+impl Searcher {
+    /// Evaluates Searcher against normalized tags.
+    ///
+    /// Useful for collections that weren't normalized when written. The search term itself is
+    /// not normalized.
+    pub fn is_match_normalized(&self, tags: &XTags, policy: &NormalizationPolicy) -> bool {
+        self.is_match(&policy.normalize(tags))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unify_date, NormalizationPolicy};
+
+    #[test]
+    fn dates_are_unified() {
+        assert_eq!(unify_date("2024/06/24").unwrap(), "2024-06-24");
+        assert_eq!(unify_date("2024.6.4").unwrap(), "2024-06-04");
+        assert_eq!(unify_date("24.06.2024").unwrap(), "2024-06-24");
+        assert_eq!(unify_date("20240624").unwrap(), "2024-06-24");
+        assert!(unify_date("24/06/2024").is_none());
+        assert!(unify_date("2024-13-01").is_none());
+        assert!(unify_date("12345678a").is_none());
+        assert!(unify_date("1.2.3").is_none());
+        assert!(unify_date("2024ä06").is_none());
+        assert!(unify_date("ä240624").is_none());
+    }
+
+    #[test]
+    fn values_are_normalized_in_order() {
+        let mut policy = NormalizationPolicy {
+            trim: true,
+            lowercase_values: true,
+            ..Default::default()
+        };
+        policy
+            .synonyms
+            .insert("b&w".to_string(), "monochrome".to_string());
+        assert_eq!(policy.normalize_value(" B&W "), "monochrome");
+        assert_eq!(policy.normalize_tag(" Style "), "Style");
+    }
+}
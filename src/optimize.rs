@@ -0,0 +1,178 @@
+use std::mem::discriminant;
+
+use regex::Regex;
+
+use crate::searcher::expand_regex;
+use crate::Searcher;
+
+// Text identifying equal searchers, None for searchers with aliases, which aren't compared
+fn key(searcher: &Searcher) -> Option<String> {
+    searcher.fold_nodes(|node, operands: Vec<Option<String>>| {
+        let operands: Vec<String> = operands.into_iter().collect::<Option<_>>()?;
+        Some(match node {
+            Searcher::And { .. } => format!("({}) AND ({})", operands[0], operands[1]),
+            Searcher::Or { .. } => format!("({}) OR ({})", operands[0], operands[1]),
+            Searcher::Not { .. } => format!("NOT ({})", operands[0]),
+            Searcher::Folded { .. } => format!("FOLDED({})", operands[0]),
+            Searcher::Canonical { .. } => return None,
+            // Equal to its expansion, the name is only provenance
+            Searcher::Bookmark { .. } => operands[0].clone(),
+            Searcher::Descendant { regex, separator } => format!("UNDER({regex}{separator})"),
+            term => format!("{:?} {term}", discriminant(term)),
+        })
+    })
+}
+
+// Relative cost of evaluating searcher once, matching tags is cheaper than matching values
+fn cost(searcher: &Searcher) -> usize {
+    searcher.fold_nodes(|node, operands: Vec<usize>| {
+        let own = match node {
+            Searcher::And { .. }
+            | Searcher::Or { .. }
+            | Searcher::Not { .. }
+            | Searcher::Bookmark { .. } => 0,
+            // Copy all tags before evaluating lhs
+            Searcher::Folded { .. } | Searcher::Canonical { .. } => 4,
+            Searcher::Tag { .. }
+            | Searcher::Descendant { .. }
+            | Searcher::HasValue { .. }
+            | Searcher::ValuelessTag { .. } => 1,
+            // Number of tags is known without looking at them
+            Searcher::TagCount { .. } => 0,
+            Searcher::Equal { .. }
+            | Searcher::Less { .. }
+            | Searcher::LessEqual { .. }
+            | Searcher::Greater { .. }
+            | Searcher::GreaterEqual { .. }
+            | Searcher::IsTrue { .. }
+            | Searcher::IsFalse { .. } => 2,
+            // Values of all tags
+            Searcher::AnyValue { .. } => 3,
+            // Compiles a regular expression per matching tag
+            Searcher::EqualCaptures { .. } => 8,
+        };
+        own + operands.iter().sum::<usize>()
+    })
+}
+
+// Operands of nested operations of the same kind as searcher, from left to right
+fn flatten(searcher: Searcher, is_and: bool, operands: &mut Vec<Searcher>) {
+    match searcher {
+        Searcher::And { lhs, rhs } if is_and => {
+            flatten(*lhs, is_and, operands);
+            flatten(*rhs, is_and, operands);
+        }
+        Searcher::Or { lhs, rhs } if !is_and => {
+            flatten(*lhs, is_and, operands);
+            flatten(*rhs, is_and, operands);
+        }
+        operand => operands.push(operand),
+    }
+}
+
+// Optimized node from its optimized operands
+fn optimize_node(node: &Searcher, optimized: Vec<Searcher>) -> Searcher {
+    match node {
+        Searcher::Not { .. } => match optimized.into_iter().next() {
+            Some(Searcher::Not { lhs }) => *lhs,
+            lhs => Searcher::new_not(lhs.expect("not has an operand")),
+        },
+        Searcher::And { .. } | Searcher::Or { .. } => {
+            let is_and = matches!(node, Searcher::And { .. });
+            let mut operands = Vec::new();
+            for operand in optimized {
+                flatten(operand, is_and, &mut operands);
+            }
+            let mut keys = Vec::with_capacity(operands.len());
+            operands.retain(|operand| match key(operand) {
+                Some(key) if keys.contains(&key) => false,
+                Some(key) => {
+                    keys.push(key);
+                    true
+                }
+                None => true,
+            });
+            operands.sort_by_key(cost);
+            let new = if is_and {
+                Searcher::new_and
+            } else {
+                Searcher::new_or
+            };
+            operands
+                .into_iter()
+                .reduce(new)
+                .expect("operations have operands")
+        }
+        Searcher::Equal {
+            tag_regex,
+            value_regex,
+        } if tag_regex.as_str() == expand_regex(".*") => Searcher::AnyValue {
+            value_regex: value_regex.clone(),
+        },
+        node => node.map_node(optimized, &mut Regex::clone),
+    }
+}
+
+impl Searcher {
+    /// Returns equivalent Searcher that is cheaper to evaluate.
+    ///
+    /// Double negations are removed, repeated operands of and and or are evaluated once, and
+    /// operands are ordered by cost, so tests of tag names run before regular expressions on
+    /// values. Evaluation short-circuits, so the expensive operands are often skipped. Terms like
+    /// `.* == value` become `== value`, which doesn't match tags at all. Searches
+    /// with aliases are never considered equal to others. Compare formulations with bench_query.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let term = "title == .*draft.* and not not photo and photo";
+    /// let optimized = xtag::compile_search(term).unwrap().optimize();
+    /// assert_eq!(optimized.depth(), 2);
+    /// let tags = xtag::csl_to_map("photo,title=draft2").unwrap();
+    /// assert!(optimized.is_match(&tags));
+    /// ```
+    pub fn optimize(self) -> Searcher {
+        self.fold_nodes(optimize_node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::key;
+    use crate::{compile_search, compile_search_with, csl_to_map, SearchOptions, Searcher};
+
+    #[test]
+    fn searchers_are_simplified_and_reordered() {
+        let search = compile_search("x == y.* and not not a and (b or a) and a and not (not b)")
+            .unwrap()
+            .optimize();
+        let expected = compile_search("a and b and x == y.* and (b or a)").unwrap();
+        assert_eq!(key(&search), key(&expected));
+
+        let term = "c == 1 or (not not (a and a)) or under(b/c) or not a";
+        let (search, optimized) = (
+            compile_search(term).unwrap(),
+            compile_search(term).unwrap().optimize(),
+        );
+        assert!(optimized.depth() < search.depth());
+        for tags in ["a", "b/c/d", "c=1", "c=2,a", ""] {
+            let tags = csl_to_map(tags).unwrap();
+            assert_eq!(optimized.is_match(&tags), search.is_match(&tags));
+        }
+
+        // Folding changes what a term matches
+        let folded = compile_search_with(
+            "a",
+            &SearchOptions {
+                fold: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let both = Searcher::new_or(compile_search("a").unwrap(), folded).optimize();
+        assert!(both.is_match(&csl_to_map("A").unwrap()));
+
+        let any = compile_search("a and .* == draft.*").unwrap().optimize();
+        assert_eq!(key(&any), key(&compile_search("a and == draft.*").unwrap()));
+    }
+}
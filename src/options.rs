@@ -0,0 +1,236 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::fold::strip_marks;
+use regex::RegexBuilder;
+
+use crate::glob::glob_to_regex;
+use crate::params::{bind_regex, bind_value};
+use crate::searcher::expand_regex;
+use crate::{AliasMap, BookmarkRegistry, Param, Result, Searcher, XTagError};
+
+/// Logical operator keywords of the search grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keyword {
+    /// Logical and, like `AND` or `&&`.
+    And,
+
+    /// Logical or, like `OR` or `||`.
+    Or,
+
+    /// Logical not, like `NOT` or `!`.
+    Not,
+}
+
+impl Keyword {
+    fn as_str(self) -> &'static str {
+        match self {
+            Keyword::And => "AND",
+            Keyword::Or => "OR",
+            Keyword::Not => "NOT",
+        }
+    }
+}
+
+/// How regular expressions of search terms are matched against tags and values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Anchoring {
+    /// Expression has to match the whole tag or value.
+    #[default]
+    Whole,
+
+    /// Expression may match any part of the tag or value, so `day` finds `holiday`.
+    Substring,
+}
+
+/// Options for compiling search terms.
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Match tags and values accent- and case-insensitive, so `Malmo` finds `Malmö`.
+    pub fold: bool,
+
+    /// Additional words for logical operators, like `und` or `|`.
+    ///
+    /// Aliases are compared case-insensitive and only recognized as words of their own, separated
+    /// by whitespace or parentheses and outside of bookmarks.
+    pub aliases: HashMap<String, Keyword>,
+
+    /// Matching of tag and value expressions, whole tags and values by default.
+    pub anchoring: Anchoring,
+
+    /// Match tags and values case-insensitive, so `holiday` finds `Holiday`.
+    pub case_insensitive: bool,
+
+    /// Interpret tag and value expressions as shell globs instead of regular expressions, see
+    /// glob_to_regex.
+    pub glob: bool,
+
+    /// Separator of levels in hierarchical tags for `under(...)`, HIERARCHY_SEPARATOR if None.
+    pub hierarchy_separator: Option<char>,
+
+    /// Canonicalize tags before matching, so searching a canonical tag finds all its aliases.
+    pub tag_aliases: Option<AliasMap>,
+
+    /// Longest accepted search term in bytes, for terms from untrusted sources.
+    pub max_term_length: Option<usize>,
+
+    /// Largest accepted size of a compiled regular expression in bytes, see
+    /// regex::RegexBuilder::size_limit.
+    pub max_regex_size: Option<usize>,
+
+    /// Deepest accepted nesting of parentheses and operations.
+    pub max_depth: Option<usize>,
+
+    /// Values of placeholders like `$min` in tags and values, see compile_search_with_params.
+    pub params: HashMap<String, Param>,
+
+    /// Bookmarks referenced by name like `{recent}`, other references are paths of bookmarks.
+    pub bookmarks: BookmarkRegistry,
+
+    /// Reject references to bookmark files by path, so only bookmarks of the registry are
+    /// resolved, for terms from untrusted sources.
+    pub registered_bookmarks_only: bool,
+}
+
+impl SearchOptions {
+    /// Adds alias for keyword.
+    pub fn alias(mut self, alias: &str, keyword: Keyword) -> Self {
+        self.aliases.insert(alias.to_lowercase(), keyword);
+        self
+    }
+
+    // Returns regex of term adjusted to options
+    pub(crate) fn regex<'a>(&self, regex: &'a str) -> Cow<'a, str> {
+        let regex = bind_regex(regex, &self.params, |part| {
+            if self.glob {
+                Cow::Owned(glob_to_regex(part))
+            } else {
+                Cow::Borrowed(part)
+            }
+        });
+        let regex = if self.fold {
+            Cow::Owned(format!("(?i){}", strip_marks(&regex)))
+        } else if self.case_insensitive {
+            Cow::Owned(format!("(?i){regex}"))
+        } else {
+            regex
+        };
+        match self.anchoring {
+            Anchoring::Whole => regex,
+            // Already anchored, so Searcher leaves it unchanged
+            Anchoring::Substring => Cow::Owned(format!("^.*(?:{regex}).*$")),
+        }
+    }
+
+    // Returns right hand side of comparison with placeholders replaced
+    pub(crate) fn value<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        bind_value(value, &self.params)
+    }
+
+    // Rejects terms too long or too deeply nested before parsing them
+    pub(crate) fn check_term(&self, term: &str) -> Result<()> {
+        if let Some(max) = self.max_term_length {
+            if term.len() > max {
+                return Err(XTagError::QueryTooComplex(format!(
+                    "term is longer than {max} bytes"
+                )));
+            }
+        }
+        if let Some(max) = self.max_depth {
+            let mut depth = 0usize;
+            for c in term.chars() {
+                match c {
+                    '(' | '{' => depth += 1,
+                    ')' | '}' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                if depth > max {
+                    return Err(too_deep(max));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Rejects regular expressions compiling to more than max_regex_size
+    pub(crate) fn check_regex(&self, regex: &str) -> Result<()> {
+        let Some(max) = self.max_regex_size else {
+            return Ok(());
+        };
+        match RegexBuilder::new(&expand_regex(regex))
+            .size_limit(max)
+            .build()
+        {
+            Ok(_) => Ok(()),
+            Err(regex::Error::CompiledTooBig(_)) => Err(XTagError::QueryTooComplex(format!(
+                "regular expression {regex:?} exceeds {max} bytes"
+            ))),
+            Err(err) => Err(XTagError::Regex(err)),
+        }
+    }
+
+    // Rejects searchers nested deeper than max_depth
+    pub(crate) fn check_depth(&self, searcher: &Searcher) -> Result<()> {
+        match self.max_depth {
+            Some(max) if searcher.depth() > max => Err(too_deep(max)),
+            _ => Ok(()),
+        }
+    }
+
+    // Returns term with aliases replaced by their keywords
+    pub(crate) fn replace_aliases<'a>(&self, term: &'a str) -> Cow<'a, str> {
+        if self.aliases.is_empty() {
+            return Cow::Borrowed(term);
+        }
+
+        let mut result = String::with_capacity(term.len());
+        let mut word = String::new();
+        let mut bookmark_depth = 0;
+        for c in term.chars() {
+            let is_separator = bookmark_depth == 0 && (c.is_whitespace() || c == '(' || c == ')');
+            if is_separator {
+                self.push_word(&mut result, &word);
+                word.clear();
+                result.push(c);
+                continue;
+            }
+            match c {
+                '{' => bookmark_depth += 1,
+                '}' if bookmark_depth > 0 => bookmark_depth -= 1,
+                _ => {}
+            }
+            word.push(c);
+        }
+        self.push_word(&mut result, &word);
+        Cow::Owned(result)
+    }
+
+    fn push_word(&self, result: &mut String, word: &str) {
+        match self.aliases.get(&word.to_lowercase()) {
+            Some(keyword) => result.push_str(keyword.as_str()),
+            None => result.push_str(word),
+        }
+    }
+}
+
+fn too_deep(max: usize) -> XTagError {
+    XTagError::QueryTooComplex(format!("term is nested deeper than {max}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Keyword, SearchOptions};
+
+    #[test]
+    fn aliases_are_replaced_as_whole_words() {
+        let options = SearchOptions::default()
+            .alias("und", Keyword::And)
+            .alias("|", Keyword::Or)
+            .alias("Nicht", Keyword::Not);
+        assert_eq!(
+            options.replace_aliases("(a UND nicht b) | c|d"),
+            "(a AND NOT b) OR c|d"
+        );
+        assert_eq!(options.replace_aliases("{und | x} und"), "{und | x} AND");
+    }
+}
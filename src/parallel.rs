@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::walk::TreeTags;
+use crate::{walk, Result, Searcher, WalkOptions};
+
+// Pools built for WalkOptions::threads, shared by all calls with the same number of threads
+static POOLS: LazyLock<Mutex<HashMap<usize, Arc<ThreadPool>>>> = LazyLock::new(Default::default);
+
+/// Searches files below root on all cores.
+///
+/// Like search_dir, but the tags are read and evaluated by options.pool, a pool of
+/// options.threads threads or the global rayon pool. Errors of the walk come first, followed by the matching
+/// files sorted by path, so the result doesn't depend on the scheduling of threads.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let searcher = xtag::compile_search("photo").unwrap();
+/// let results = xtag::search_dir_par(Path::new("."), &searcher, &xtag::WalkOptions::default());
+/// println!("{} results", results.len());
+/// ```
+pub fn search_dir_par(
+    root: &Path,
+    searcher: &Searcher,
+    options: &WalkOptions,
+) -> Vec<Result<PathBuf>> {
+    let mut results = Vec::new();
+    let mut paths = Vec::new();
+    for path in walk(root, options) {
+        match path {
+            Ok(path) => paths.push(path),
+            Err(err) => results.push(Err(err)),
+        }
+    }
+    paths.sort();
+
+    let tree = TreeTags::new(root, options);
+    let matches = install(options, || {
+        paths
+            .into_par_iter()
+            .filter_map(|path| match tree.is_match(searcher, &path) {
+                Ok(Some(true)) => Some(Ok(path)),
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            })
+            .collect::<Vec<_>>()
+    });
+    match matches {
+        Ok(matches) => results.extend(matches),
+        Err(err) => results.push(Err(err)),
+    }
+    results
+}
+
+// Runs f in options.pool, a pool of options.threads threads or the global pool
+pub(crate) fn install<T, F>(options: &WalkOptions, f: F) -> Result<T>
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    match pool_of(options)? {
+        Some(pool) => Ok(pool.install(f)),
+        None => Ok(f()),
+    }
+}
+
+// Pool of options, None for the global pool
+fn pool_of(options: &WalkOptions) -> Result<Option<Arc<ThreadPool>>> {
+    if let Some(pool) = &options.pool {
+        return Ok(Some(pool.clone()));
+    }
+    let Some(threads) = options.threads else {
+        return Ok(None);
+    };
+    let mut pools = POOLS.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(pool) = pools.get(&threads) {
+        return Ok(Some(pool.clone()));
+    }
+    let pool = Arc::new(ThreadPoolBuilder::new().num_threads(threads).build()?);
+    pools.insert(threads, pool.clone());
+    Ok(Some(pool))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rayon::ThreadPoolBuilder;
+
+    use super::{install, pool_of, search_dir_par};
+    use crate::{compile_search, Fixture, WalkOptions};
+
+    #[test]
+    fn parallel_results_are_sorted() {
+        let mut fixture = Fixture::new();
+        for index in 0..50 {
+            fixture = fixture.file(&format!("{index:02}")).tags("a");
+        }
+        let fixture = fixture.file("other").tags("b");
+        let searcher = compile_search("a").unwrap();
+        let paths: Vec<_> = search_dir_par(fixture.path(), &searcher, &WalkOptions::default())
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(paths.len(), 50);
+        assert!(paths.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn threads_are_limited_by_options() {
+        let options = WalkOptions {
+            threads: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(install(&options, rayon::current_num_threads).unwrap(), 3);
+
+        let fixture = Fixture::new().file("a").tags("x").file("b").tags("y");
+        let searcher = compile_search("x").unwrap();
+        let paths: Vec<_> = search_dir_par(fixture.path(), &searcher, &options)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(paths, vec![fixture.join("a")]);
+    }
+
+    #[test]
+    fn pools_of_callers_are_used_and_others_reused() {
+        let pool = Arc::new(ThreadPoolBuilder::new().num_threads(2).build().unwrap());
+        let options = WalkOptions {
+            threads: Some(5),
+            pool: Some(pool.clone()),
+            ..Default::default()
+        };
+        assert_eq!(install(&options, rayon::current_num_threads).unwrap(), 2);
+        let used = pool_of(&options).unwrap().unwrap();
+        assert!(Arc::ptr_eq(&used, &pool));
+
+        let options = WalkOptions {
+            threads: Some(5),
+            ..Default::default()
+        };
+        let first = pool_of(&options).unwrap().unwrap();
+        let second = pool_of(&options).unwrap().unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+}
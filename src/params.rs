@@ -0,0 +1,166 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::captures::references;
+use crate::Date;
+
+/// Value bound to a placeholder `$name` or `${name}` of a search term.
+///
+/// Placeholders are replaced after the term is parsed, so values may contain any character
+/// without breaking the grammar. See compile_search_with_params.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Param {
+    /// Text matched literally, characters special in regular expressions are escaped.
+    Text(String),
+
+    /// Regular expression inserted as it is.
+    Regex(String),
+
+    /// Integer, usually for comparisons like `rating >= $min`.
+    Int(i32),
+
+    /// Float, kept with decimal point so it is compared as float.
+    Float(f64),
+
+    /// Date in ISO-8601 format.
+    Date(Date),
+}
+
+impl Param {
+    // Text for the position of tag and value expressions
+    fn to_regex(&self) -> String {
+        match self {
+            Param::Regex(regex) => regex.clone(),
+            param => regex::escape(&param.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Param::Text(text) | Param::Regex(text) => f.write_str(text),
+            Param::Int(value) => write!(f, "{value}"),
+            Param::Float(value) => write!(f, "{value:?}"),
+            Param::Date(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl From<&str> for Param {
+    fn from(text: &str) -> Self {
+        Param::Text(text.to_string())
+    }
+}
+
+impl From<String> for Param {
+    fn from(text: String) -> Self {
+        Param::Text(text)
+    }
+}
+
+impl From<i32> for Param {
+    fn from(value: i32) -> Self {
+        Param::Int(value)
+    }
+}
+
+impl From<f64> for Param {
+    fn from(value: f64) -> Self {
+        Param::Float(value)
+    }
+}
+
+impl From<Date> for Param {
+    fn from(value: Date) -> Self {
+        Param::Date(value)
+    }
+}
+
+// Replaces placeholders of params in expression, other parts are passed through translate.
+// References to unknown names are kept, they may be anchors or captures of tag expressions.
+pub(crate) fn bind_regex<'a, F>(
+    expression: &'a str,
+    params: &HashMap<String, Param>,
+    translate: F,
+) -> Cow<'a, str>
+where
+    F: Fn(&str) -> Cow<'_, str>,
+{
+    bind(expression, params, translate, Param::to_regex)
+}
+
+// Replaces placeholders of params in right hand side of comparisons with their plain text
+pub(crate) fn bind_value<'a>(value: &'a str, params: &HashMap<String, Param>) -> Cow<'a, str> {
+    bind(value, params, |part| Cow::Borrowed(part), Param::to_string)
+}
+
+fn bind<'a, F, P>(
+    text: &'a str,
+    params: &HashMap<String, Param>,
+    translate: F,
+    param_text: P,
+) -> Cow<'a, str>
+where
+    F: Fn(&str) -> Cow<'_, str>,
+    P: Fn(&Param) -> String,
+{
+    let found: Vec<_> = match params.is_empty() {
+        true => Vec::new(),
+        false => references(text)
+            .into_iter()
+            .filter_map(|(range, name)| Some((range, params.get(name)?)))
+            .collect(),
+    };
+    if found.is_empty() {
+        return translate(text);
+    }
+    let mut bound = String::with_capacity(text.len());
+    let mut last = 0;
+    for (range, param) in found {
+        bound.push_str(&translate(&text[last..range.start]));
+        bound.push_str(&param_text(param));
+        last = range.end;
+    }
+    bound.push_str(&translate(&text[last..]));
+    Cow::Owned(bound)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::Param;
+    use crate::{compile_search_with, compile_search_with_params, csl_to_map, SearchOptions};
+
+    #[test]
+    fn params_are_bound_after_parsing() {
+        let params = HashMap::from([
+            ("min".to_string(), Param::from(4)),
+            ("tag".to_string(), Param::from("photo")),
+            ("title".to_string(), Param::from("R&D (draft), v1.0")),
+            ("year".to_string(), Param::Regex("20[0-9]{2}".to_string())),
+        ]);
+        let search =
+            compile_search_with_params("rating >= $min AND $tag AND title == ${title}", &params)
+                .unwrap();
+        assert!(search.is_match(&csl_to_map("photo,rating=5,title=R&D (draft)\\, v1.0").unwrap()));
+        assert!(!search.is_match(&csl_to_map("photo,rating=3,title=R&D (draft)\\, v1.0").unwrap()));
+        assert!(!search.is_match(&csl_to_map("photo,rating=5,title=R&D (draft)\\, v1x0").unwrap()));
+
+        let search = compile_search_with_params("y == $year and x == a$", &params).unwrap();
+        assert!(search.is_match(&csl_to_map("y=2024,x=a").unwrap()));
+        let search = compile_search_with_params("$unknown or $", &params).unwrap();
+        assert!(!search.is_match(&csl_to_map("unknown").unwrap()));
+
+        let options = SearchOptions {
+            glob: true,
+            params: HashMap::from([("t".to_string(), Param::from("a*"))]),
+            ..Default::default()
+        };
+        let search = compile_search_with("b* == $t", &options).unwrap();
+        assert!(search.is_match(&csl_to_map("bc=a*").unwrap()));
+        assert!(!search.is_match(&csl_to_map("bc=ab").unwrap()));
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::ops::Range;
+
+use pest::error::{Error, ErrorVariant, InputLocation};
+
+use crate::Rule;
+
+/// Syntax error of a search term or list of tags.
+///
+/// Tells where the input is wrong and what would have been valid there, so frontends can
+/// underline the offending part.
+///
+/// # Example
+///
+/// ```
+/// let Err(xtag::XTagError::Parser(err)) = xtag::compile_search("photo beach") else {
+///     panic!("invalid term compiled");
+/// };
+/// assert_eq!(err.span(), 6..7);
+/// assert_eq!(err.hint(), "two terms need AND or OR between them");
+/// assert!(err.expected().contains(&"and".to_string()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchParseError {
+    input: String,
+    span: Range<usize>,
+    expected: Vec<String>,
+    hint: String,
+}
+
+impl SearchParseError {
+    pub(crate) fn new(err: Error<Rule>, input: &str) -> Self {
+        let span = match err.location {
+            InputLocation::Pos(pos) => {
+                let len = input[pos..].chars().next().map_or(0, char::len_utf8);
+                pos..pos + len
+            }
+            InputLocation::Span((start, end)) => start..end,
+        };
+        let positives = match &err.variant {
+            ErrorVariant::ParsingError { positives, .. } => positives.clone(),
+            ErrorVariant::CustomError { .. } => Vec::new(),
+        };
+        SearchParseError {
+            hint: hint(input, span.start, &positives),
+            expected: positives.iter().map(|rule| rule_name(*rule)).collect(),
+            input: input.to_string(),
+            span,
+        }
+    }
+
+    /// Parsed input. Keywords of search terms are already replaced by their aliases.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Byte range of input at which parsing failed, empty at the end of input.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// What would have been valid at span, like `and`, `==`, `term` or `end`.
+    pub fn expected(&self) -> &[String] {
+        &self.expected
+    }
+
+    /// Explanation of the error for humans.
+    pub fn hint(&self) -> &str {
+        &self.hint
+    }
+}
+
+impl fmt::Display for SearchParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.hint, self.span.start)?;
+        if !self.expected.is_empty() {
+            write!(f, ", expected {}", self.expected.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SearchParseError {}
+
+fn rule_name(rule: Rule) -> String {
+    let name = match rule {
+        Rule::EOI => "end",
+        Rule::less => "<",
+        Rule::less_equal => "<=",
+        Rule::greater => ">",
+        Rule::greater_equal => ">=",
+        Rule::equal => "==",
+        Rule::inequal => "!=",
+        Rule::assign => "=",
+        Rule::comparison_expr => "term",
+        Rule::tag_with_regex => "tag",
+        Rule::value_with_regex => "value",
+        rule => return format!("{rule:?}").replace('_', " "),
+    };
+    name.to_string()
+}
+
+// Guesses the mistake from the position and what was expected there
+fn hint(input: &str, pos: usize, positives: &[Rule]) -> String {
+    let expects = |rule| positives.contains(&rule);
+    let before = &input[..pos];
+    let open = before
+        .matches('(')
+        .count()
+        .saturating_sub(before.matches(')').count());
+    let hint = match input[pos..].chars().next() {
+        None if open > 0 && expects(Rule::or) => "parenthesis isn't closed",
+        None if expects(Rule::value_with_regex) => "comparison needs a value",
+        None if expects(Rule::comparison_expr) => "search ends where a term is expected",
+        Some(')') if open == 0 => "parenthesis closes nothing",
+        Some(_) if expects(Rule::value_with_regex) => "comparison needs a value",
+        Some(_) if expects(Rule::and) => "two terms need AND or OR between them",
+        Some(_) if expects(Rule::comparison_expr) => "term expected",
+        Some(',') if expects(Rule::tag) => "empty tag between commas",
+        Some(_) if expects(Rule::assign) && before.ends_with(' ') => "tags are separated by commas",
+        Some('=') => "equals signs in values have to be escaped with a backslash",
+        Some(c) => return format!("unexpected {c:?}"),
+        None => "unexpected end",
+    };
+    hint.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compile_search, csl_to_map, XTagError};
+
+    fn parse_error(result: Result<impl Sized, XTagError>) -> (std::ops::Range<usize>, String) {
+        match result {
+            Err(XTagError::Parser(err)) => (err.span(), err.hint().to_string()),
+            _ => panic!("no parse error"),
+        }
+    }
+
+    #[test]
+    fn parse_errors_carry_spans_and_hints() {
+        for (term, span, hint) in [
+            ("a b", 2..3, "two terms need AND or OR between them"),
+            ("(a or b", 7..7, "parenthesis isn't closed"),
+            ("a ==", 4..4, "comparison needs a value"),
+            ("a and", 5..5, "search ends where a term is expected"),
+            ("a )", 2..3, "parenthesis closes nothing"),
+            ("a && && b", 5..6, "term expected"),
+        ] {
+            assert_eq!(parse_error(compile_search(term)), (span, hint.to_string()));
+        }
+        for (tags, span, hint) in [
+            ("a,,b", 2..3, "empty tag between commas"),
+            ("a b", 2..3, "tags are separated by commas"),
+            (
+                "a=b=c",
+                3..4,
+                "equals signs in values have to be escaped with a backslash",
+            ),
+        ] {
+            assert_eq!(parse_error(csl_to_map(tags)), (span, hint.to_string()));
+        }
+
+        let Err(XTagError::Parser(err)) = compile_search("ä ö") else {
+            panic!("no parse error");
+        };
+        assert_eq!(&err.input()[err.span()], "ö");
+        assert_eq!(err.expected().last().map(String::as_str), Some("or"));
+        assert!(err
+            .to_string()
+            .starts_with("two terms need AND or OR between them at 3"));
+    }
+}
@@ -1,47 +1,56 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::Path;
 
 use pest::iterators::Pair;
 use pest::Parser;
 
+use crate::bookmarks::read_bookmark;
 use crate::parser::Rule;
 use crate::parser::SearchParser;
-use crate::Searcher;
-use crate::{Result, XTagError};
+use crate::{Param, SearchOptions, Searcher, HIERARCHY_SEPARATOR};
+use crate::{Result, SearchParseError, XTagError};
 
-fn eval_or_expr(pair: Pair<Rule>) -> Result<Searcher> {
+// State of one compilation, bookmarks being compiled are tracked to detect cycles
+struct Context<'a> {
+    options: &'a SearchOptions,
+    bookmarks: Vec<String>,
+}
+
+fn eval_or_expr(pair: Pair<Rule>, context: &mut Context) -> Result<Searcher> {
     let mut pairs = pair.into_inner();
-    let mut lhs = eval_expression(pairs.next().unwrap())?;
+    let mut lhs = eval_expression(pairs.next().unwrap(), context)?;
     while pairs.peek().is_some() {
         let _operation = pairs.next().unwrap();
-        let rhs = eval_expression(pairs.next().unwrap())?;
+        let rhs = eval_expression(pairs.next().unwrap(), context)?;
         lhs = Searcher::new_or(lhs, rhs);
     }
     Ok(lhs)
 }
 
-fn eval_and_expr(pair: Pair<Rule>) -> Result<Searcher> {
+fn eval_and_expr(pair: Pair<Rule>, context: &mut Context) -> Result<Searcher> {
     let mut pairs = pair.into_inner();
-    let mut lhs = eval_expression(pairs.next().unwrap())?;
+    let mut lhs = eval_expression(pairs.next().unwrap(), context)?;
     while pairs.peek().is_some() {
         let _operation = pairs.next().unwrap();
-        let rhs = eval_expression(pairs.next().unwrap())?;
+        let rhs = eval_expression(pairs.next().unwrap(), context)?;
         lhs = Searcher::new_and(lhs, rhs);
     }
     Ok(lhs)
 }
 
-fn eval_tag(pair: Pair<Rule>) -> Result<Searcher> {
-    let tag_regex = pair.as_str();
-    Searcher::new_tag(tag_regex)
+fn eval_tag(pair: Pair<Rule>, context: &mut Context) -> Result<Searcher> {
+    let tag_regex = context.options.regex(pair.as_str());
+    context.options.check_regex(&tag_regex)?;
+    Searcher::new_tag(&tag_regex)
 }
 
-fn eval_not_expr(pair: Pair<Rule>) -> Result<Searcher> {
+fn eval_not_expr(pair: Pair<Rule>, context: &mut Context) -> Result<Searcher> {
     let mut pairs = pair.into_inner();
     let first = pairs.next().unwrap();
     if pairs.peek().is_some() {
         // unary_op ~ unary_expr
         let operation = first;
-        let rhs = eval_expression(pairs.next().unwrap())?;
+        let rhs = eval_expression(pairs.next().unwrap(), context)?;
         match operation.as_rule() {
             Rule::not => Ok(Searcher::new_not(rhs)),
             op => Err(XTagError::ParserImplementation(format!(
@@ -50,51 +59,152 @@ fn eval_not_expr(pair: Pair<Rule>) -> Result<Searcher> {
         }
     } else {
         // comparison
-        eval_expression(first)
+        eval_expression(first, context)
     }
 }
 
 // Equality is tested as regex, inequality operators are done after conversion
 // to int
-fn eval_comparison(pair: Pair<Rule>) -> Result<Searcher> {
+fn eval_comparison(pair: Pair<Rule>, context: &mut Context) -> Result<Searcher> {
     let mut pairs = pair.into_inner();
     let lhs = pairs.next().unwrap();
     if pairs.peek().is_some() {
         // tag ~ comparison_op ~ value
-        let tag_regex = lhs.as_str();
+        let tag_regex = &context.options.regex(lhs.as_str());
+        context.options.check_regex(tag_regex)?;
         let operation = pairs.next().unwrap();
         let value = pairs.next().unwrap().as_str();
+        let value_regex = || -> Result<_> {
+            let value_regex = context.options.regex(value);
+            context.options.check_regex(&value_regex)?;
+            Ok(value_regex)
+        };
 
         match operation.as_rule() {
-            Rule::equal => Searcher::new_equal(tag_regex, value),
-            Rule::inequal => Searcher::new_inequal(tag_regex, value),
-            Rule::less => Searcher::new_less(tag_regex, value),
-            Rule::less_equal => Searcher::new_less_equal(tag_regex, value),
-            Rule::greater => Searcher::new_greater(tag_regex, value),
-            Rule::greater_equal => Searcher::new_greater_equal(tag_regex, value),
+            Rule::equal => Searcher::new_equal(tag_regex, &value_regex()?),
+            Rule::inequal => Searcher::new_inequal(tag_regex, &value_regex()?),
+            Rule::less => Searcher::new_less(tag_regex, &context.options.value(value)),
+            Rule::less_equal => Searcher::new_less_equal(tag_regex, &context.options.value(value)),
+            Rule::greater => Searcher::new_greater(tag_regex, &context.options.value(value)),
+            Rule::greater_equal => {
+                Searcher::new_greater_equal(tag_regex, &context.options.value(value))
+            }
             op => Err(XTagError::ParserImplementation(format!(
                 "unsupported comparison operation {op:?}"
             ))),
         }
     } else {
         // primary
-        eval_expression(lhs)
+        eval_expression(lhs, context)
+    }
+}
+
+fn eval_any_value(pair: Pair<Rule>, context: &mut Context) -> Result<Searcher> {
+    let value_regex = context
+        .options
+        .regex(pair.into_inner().next().unwrap().as_str());
+    context.options.check_regex(&value_regex)?;
+    Searcher::new_any_value(&value_regex)
+}
+
+fn eval_truth(pair: Pair<Rule>, context: &mut Context) -> Result<Searcher> {
+    let mut pairs = pair.into_inner();
+    let operation = pairs.next().unwrap();
+    let tag_regex = &context.options.regex(pairs.next().unwrap().as_str());
+    context.options.check_regex(tag_regex)?;
+    match operation.as_rule() {
+        Rule::istrue => Searcher::new_is_true(tag_regex),
+        Rule::isfalse => Searcher::new_is_false(tag_regex),
+        op => Err(XTagError::ParserImplementation(format!(
+            "unsupported truth operation {op:?}"
+        ))),
+    }
+}
+
+fn eval_under(pair: Pair<Rule>, context: &mut Context) -> Result<Searcher> {
+    let mut pairs = pair.into_inner();
+    let _operation = pairs.next().unwrap();
+    let regex = context.options.regex(pairs.next().unwrap().as_str());
+    context.options.check_regex(&regex)?;
+    let separator = match pairs.next() {
+        Some(separator) => separator.as_str().chars().nth(1).unwrap(),
+        None => context
+            .options
+            .hierarchy_separator
+            .unwrap_or(HIERARCHY_SEPARATOR),
+    };
+    Searcher::new_descendant(&regex, separator)
+}
+
+fn eval_value_presence(pair: Pair<Rule>, context: &mut Context) -> Result<Searcher> {
+    let mut pairs = pair.into_inner();
+    let tag_regex = &context.options.regex(pairs.next().unwrap().as_str());
+    context.options.check_regex(tag_regex)?;
+    match pairs.next().unwrap().as_rule() {
+        Rule::has_value => Searcher::new_has_value(tag_regex),
+        Rule::valueless => Searcher::new_valueless_tag(tag_regex),
+        op => Err(XTagError::ParserImplementation(format!(
+            "unsupported value operation {op:?}"
+        ))),
     }
 }
 
-fn eval_bookmark(pair: Pair<Rule>) -> Result<Searcher> {
-    let path = PathBuf::from(pair.as_str());
-    crate::get_bookmark(&path)
+fn eval_tag_count(pair: Pair<Rule>) -> Result<Searcher> {
+    let mut pairs = pair.into_inner();
+    let operation = pairs.next().unwrap();
+    let count = pairs.next().unwrap().as_str();
+    let count: usize = count
+        .parse()
+        .map_err(|_| XTagError::ComparisonValue(count.to_string()))?;
+    Ok(match operation.as_rule() {
+        Rule::equal => Searcher::new_tag_count(count..=count),
+        Rule::inequal => Searcher::new_not(Searcher::new_tag_count(count..=count)),
+        Rule::less => Searcher::new_tag_count(..count),
+        Rule::less_equal => Searcher::new_tag_count(..=count),
+        Rule::greater => Searcher::new_tag_count(count.saturating_add(1)..),
+        Rule::greater_equal => Searcher::new_tag_count(count..),
+        op => {
+            return Err(XTagError::ParserImplementation(format!(
+                "unsupported comparison operation {op:?}"
+            )))
+        }
+    })
 }
 
-fn eval_expression(pair: Pair<Rule>) -> Result<Searcher> {
+// Bookmarks of the registry are referenced by name, others by path unless options forbid it
+fn eval_bookmark(pair: Pair<Rule>, context: &mut Context) -> Result<Searcher> {
+    let reference = pair.as_str();
+    if let Some(start) = context.bookmarks.iter().position(|name| name == reference) {
+        let mut cycle = context.bookmarks[start..].to_vec();
+        cycle.push(reference.to_string());
+        return Err(XTagError::BookmarkCycle(cycle));
+    }
+    let term = match context.options.bookmarks.term(reference) {
+        Some(term) => term.to_string(),
+        None if context.options.registered_bookmarks_only => {
+            return Err(XTagError::Bookmark(reference.into()));
+        }
+        None => read_bookmark(Path::new(reference))?,
+    };
+    context.bookmarks.push(reference.to_string());
+    let searcher = compile(&term, context);
+    context.bookmarks.pop();
+    Ok(Searcher::new_bookmark(reference, searcher?))
+}
+
+fn eval_expression(pair: Pair<Rule>, context: &mut Context) -> Result<Searcher> {
     match pair.as_rule() {
-        Rule::tag_with_regex => eval_tag(pair),
-        Rule::or_expr => eval_or_expr(pair),
-        Rule::and_expr => eval_and_expr(pair),
-        Rule::not_expr => eval_not_expr(pair),
-        Rule::comparison_expr => eval_comparison(pair),
-        Rule::bookmark => eval_bookmark(pair),
+        Rule::tag_with_regex => eval_tag(pair, context),
+        Rule::or_expr => eval_or_expr(pair, context),
+        Rule::and_expr => eval_and_expr(pair, context),
+        Rule::not_expr => eval_not_expr(pair, context),
+        Rule::comparison_expr => eval_comparison(pair, context),
+        Rule::any_value_expr => eval_any_value(pair, context),
+        Rule::truth_expr => eval_truth(pair, context),
+        Rule::under_expr => eval_under(pair, context),
+        Rule::has_value_expr | Rule::valueless_expr => eval_value_presence(pair, context),
+        Rule::tag_count_expr => eval_tag_count(pair),
+        Rule::bookmark => eval_bookmark(pair, context),
         rule => Err(XTagError::ParserImplementation(format!(
             "unexpected grammar rule {rule:?}"
         ))),
@@ -119,18 +229,101 @@ fn eval_expression(pair: Pair<Rule>) -> Result<Searcher> {
 /// # Errors
 /// - XTagError::Parser
 pub fn compile_search(term: &str) -> Result<Searcher> {
+    compile_search_with(term, &SearchOptions::default())
+}
+
+/// Compiles search term like compile_search, adjusted by options.
+///
+/// Terms from untrusted sources should be compiled with limits set in options, so pathological
+/// terms are rejected instead of exhausting memory or stack.
+///
+/// # Example
+///
+/// ```
+/// # use std::collections::HashMap;
+/// # use xtag::XTags;
+/// let mut tags: XTags = HashMap::new();
+/// tags.insert("city".to_string(), Some("Malmö".to_string()));
+/// let options = xtag::SearchOptions {
+///     fold: true,
+///     ..Default::default()
+/// };
+/// let search = xtag::compile_search_with("City == malmo", &options).unwrap();
+/// assert!(search.is_match(&tags) == true);
+/// ```
+/// # Errors
+/// - XTagError::Parser
+/// - XTagError::QueryTooComplex if a limit of options is exceeded
+pub fn compile_search_with(term: &str, options: &SearchOptions) -> Result<Searcher> {
+    let mut context = Context {
+        options,
+        bookmarks: Vec::new(),
+    };
+    let mut searcher = compile(term, &mut context)?;
+    options.check_depth(&searcher)?;
+    if options.fold {
+        searcher = Searcher::new_folded(searcher);
+    }
+    // Aliases are spelled like stored, so tags are canonicalized before folding
+    match &options.tag_aliases {
+        Some(aliases) => Ok(Searcher::new_canonical(searcher, aliases.clone())),
+        None => Ok(searcher),
+    }
+}
+
+/// Compiles search term like compile_search, with placeholders bound to params.
+///
+/// Placeholders `$name` or `${name}` may stand for tags, values and right hand sides of
+/// comparisons. They are replaced after parsing, so user input can be bound without breaking the
+/// term. Param::Text matches literally, Param::Regex is inserted as regular expression.
+/// Placeholders without param are kept, like `$` anchors and references to captures of tag
+/// expressions.
+///
+/// # Example
+///
+/// ```
+/// # use std::collections::HashMap;
+/// use xtag::Param;
+///
+/// let params = HashMap::from([
+///     ("min".to_string(), Param::from(4)),
+///     ("city".to_string(), Param::from("St. Gallen")),
+/// ]);
+/// let search = xtag::compile_search_with_params("rating >= $min and city == $city", &params);
+/// let tags = xtag::csl_to_map("rating=5,city=St. Gallen").unwrap();
+/// assert!(search.unwrap().is_match(&tags));
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Parser
+/// - XTagError::ComparisonValue if a bound comparison value is neither integer, float nor date
+pub fn compile_search_with_params(term: &str, params: &HashMap<String, Param>) -> Result<Searcher> {
+    let options = SearchOptions {
+        params: params.clone(),
+        ..Default::default()
+    };
+    compile_search_with(term, &options)
+}
+
+// Bookmarks are compiled with the options of the including term, but folded only once
+fn compile(term: &str, context: &mut Context) -> Result<Searcher> {
+    context.options.check_term(term)?;
     // parse returns array of one rule + EOI. Start with first element here
-    let pair = SearchParser::parse(Rule::search, term)
-        .map_err(|err| XTagError::Parser(err))?
+    let term = context.options.replace_aliases(term);
+    let pair = SearchParser::parse(Rule::search, &term)
+        .map_err(|err| SearchParseError::new(err, &term))?
         .next()
         .unwrap();
-    eval_expression(pair)
+    eval_expression(pair, context)
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_comparison)]
 mod tests {
-    use super::compile_search;
+    use super::{compile_search, compile_search_with};
     use crate::parse_tags::csl_to_map;
+    use crate::{Anchoring, Keyword, SearchOptions, XTagError};
 
     fn find_in_string(term: &str, string: &str) -> bool {
         let tags = csl_to_map(string).unwrap();
@@ -215,6 +408,18 @@ mod tests {
         assert!(find_in_string(".* != b", "a=b,b=d") == false);
     }
 
+    #[test]
+    fn grammar_supports_any_tag_equality() {
+        assert!(find_in_string("* == invoice", "type=invoice,paid"));
+        assert!(find_in_string(":= inv.*", "a,b=x,c=invoice#0.5"));
+        assert!(find_in_string("*==invoice and paid", "b=invoice,paid"));
+        assert!(!find_in_string(":=invoice", "invoice,a=b"));
+        assert!(!find_in_string("* == invoice or x", "a=invoices"));
+        assert!(find_in_string("== draft.*", "title=drafts"));
+        assert!(find_in_string("photo and ==draft", "photo,state=draft"));
+        assert!(!find_in_string("not == draft", "draft=x,b=draft"));
+    }
+
     #[test]
     fn grammar_supports_int_value_relations() {
         assert!(find_in_string("a > 1 AND a < 3", "a=2") == true);
@@ -223,6 +428,138 @@ mod tests {
         assert!(find_in_string("a > 1 AND a < 3", "a") == false);
     }
 
+    #[test]
+    fn grammar_supports_float_and_date_relations() {
+        assert!(find_in_string("rating > 4.5", "rating=4.75"));
+        assert!(!find_in_string("rating > 4.5", "rating=4"));
+        assert!(find_in_string("rating >= 4", "rating=4.5"));
+        assert!(find_in_string("shot < 2023-06-01", "shot=2023-05-31"));
+        assert!(!find_in_string(
+            "shot < 2023-06-01",
+            "shot=2023-06-01T08:00"
+        ));
+        assert!(!find_in_string("shot < 2023-06-01", "shot=yesterday"));
+        assert!(compile_search("shot < 2023-06").is_err());
+    }
+
+    #[test]
+    fn grammar_supports_truth_predicates() {
+        assert!(find_in_string("istrue(a)", "a=1"));
+        assert!(find_in_string("istrue(a)", "a=Yes"));
+        assert!(find_in_string("ISTRUE(a) and isfalse(b)", "a=on,b=off"));
+        assert!(!find_in_string("istrue(a)", "a=0"));
+        assert!(!find_in_string("istrue(a)", "a"));
+        assert!(!find_in_string("istrue(a)", "a=maybe"));
+        assert!(find_in_string("isfalse(a)", "a=FALSE"));
+        assert!(!find_in_string("isfalse(a)", "a=true"));
+        assert!(!find_in_string("isfalse(a)", "b=no"));
+        assert!(find_in_string("!istrue(a)", "a=no"));
+    }
+
+    #[test]
+    fn grammar_supports_value_presence_and_tag_count() {
+        assert!(find_in_string("a?", "a=1"));
+        assert!(!find_in_string("a?", "a"));
+        assert!(find_in_string("a! and b?", "a,b=x"));
+        assert!(!find_in_string("a!", "a=1"));
+        assert!(find_in_string("!a!", "a=1"));
+        assert!(find_in_string("(x|a)?||b", "b"));
+        assert!(find_in_string("a!=1", "a=2"));
+        // Question marks inside the expression keep their meaning
+        assert!(find_in_string("colou?r?", "color=red"));
+        assert!(find_in_string("(a(b|))", "a"));
+        assert!(find_in_string("#tags >= 2 and #TAGS < 3", "a,b=1"));
+        assert!(!find_in_string("#tags > 2", "a,b"));
+        assert!(find_in_string("#tags == 0", ""));
+        assert!(find_in_string("#tags != 1", "a,b"));
+        assert!(!find_in_string("#tags < 0", ""));
+    }
+
+    #[test]
+    fn folding_ignores_diacritics_and_case() {
+        let options = SearchOptions {
+            fold: true,
+            ..Default::default()
+        };
+        let tags = csl_to_map("Stadt=Malmö,Größe=10").unwrap();
+        let search = compile_search_with("stadt == MALMO and große > 5", &options).unwrap();
+        assert!(search.is_match(&tags));
+        assert!(!compile_search("stadt == malmo").unwrap().is_match(&tags));
+    }
+
+    #[test]
+    fn aliases_extend_keywords() {
+        let options = SearchOptions::default()
+            .alias("&", Keyword::And)
+            .alias("oder", Keyword::Or);
+        let search = compile_search_with("a & (b oder c)", &options).unwrap();
+        assert!(search.is_match(&csl_to_map("a,c").unwrap()));
+        assert!(!search.is_match(&csl_to_map("b,c").unwrap()));
+    }
+
+    #[test]
+    fn case_insensitive_option_applies_to_tags_and_values() {
+        let options = SearchOptions {
+            case_insensitive: true,
+            ..Default::default()
+        };
+        let tags = csl_to_map("Holiday,City=Rome,Rating=3").unwrap();
+        for term in [
+            "holiday",
+            "city == rome",
+            "rating > 2",
+            "HOLIDAY and not (city != ROME)",
+        ] {
+            assert!(
+                compile_search_with(term, &options).unwrap().is_match(&tags),
+                "{term}"
+            );
+            assert!(!compile_search(term).unwrap().is_match(&tags), "{term}");
+        }
+    }
+
+    #[test]
+    fn limits_reject_complex_terms() {
+        let options = SearchOptions {
+            max_term_length: Some(64),
+            max_regex_size: Some(10_000),
+            max_depth: Some(4),
+            ..Default::default()
+        };
+        assert!(compile_search_with("a and (b or not c)", &options).is_ok());
+        for term in [
+            "a and b and c and d and e",
+            "((((((a))))))",
+            "x == a{1000}{1000}",
+            &"a or ".repeat(20),
+        ] {
+            let result = compile_search_with(term, &options);
+            assert!(
+                matches!(result, Err(XTagError::QueryTooComplex(_))),
+                "{term}"
+            );
+        }
+    }
+
+    #[test]
+    fn substring_anchoring_matches_parts() {
+        let options = SearchOptions {
+            anchoring: Anchoring::Substring,
+            ..Default::default()
+        };
+        let tags = csl_to_map("holiday=summer-2023").unwrap();
+        assert!(compile_search_with("day == 2023", &options)
+            .unwrap()
+            .is_match(&tags));
+        assert!(compile_search_with("^holi", &options)
+            .unwrap()
+            .is_match(&tags));
+        assert!(!compile_search_with("day$ == ^2023", &options)
+            .unwrap()
+            .is_match(&tags));
+        assert!(!compile_search("day").unwrap().is_match(&tags));
+    }
+
     #[test]
     fn grammar_panics_on_invalid_expression() {
         let result = std::panic::catch_unwind(|| find_in_string("a b c", "a,b,c"));
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 use pest::iterators::Pair;
@@ -7,20 +8,106 @@ use pest::Parser;
 use crate::error::{Result, XTagError};
 use crate::parser::Rule;
 use crate::parser::SearchParser;
-use crate::XTags;
+use crate::{SearchParseError, XTags};
 
-fn eval_tag_with_value(pair: Pair<Rule>, container: &mut XTags) {
+fn eval_tag_with_value<'a, F>(pair: Pair<'a, Rule>, insert: &mut F)
+where
+    F: FnMut(&'a str, Option<Cow<'a, str>>),
+{
     let mut pairs = pair.into_inner();
-    let tag = pairs.next().unwrap().as_str().to_string();
-    let value = pairs.next().map(|v| v.as_str().to_string());
-    container.insert(tag, value);
+    let tag = pairs.next().unwrap().as_str();
+    // tag ~ assign ~ value?, a missing value after assign is the empty value
+    let value = pairs.next().map(|_| {
+        pairs
+            .next()
+            .map_or(Cow::Borrowed(""), |v| unescape_value(v.as_str()))
+    });
+    insert(tag, value);
 }
 
-fn eval_tags(pairs: &mut Pairs<Rule>, container: &mut XTags) -> Result<()> {
+// Copies value only if it has escaped characters
+fn unescape_value(value: &str) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('0') => result.push('\0'),
+            Some(c) => result.push(c),
+            None => {}
+        }
+    }
+    Cow::Owned(result)
+}
+
+// Escapes value, so it survives storage as part of a comma separated list
+pub(crate) fn escape_value(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    push_escaped(&mut result, value);
+    result
+}
+
+fn push_escaped(result: &mut String, value: &str) {
+    let last = value.chars().count().saturating_sub(1);
+    for (index, c) in value.chars().enumerate() {
+        match c {
+            '\\' | ',' | '=' => {
+                result.push('\\');
+                result.push(c);
+            }
+            ' ' if index == 0 || index == last => result.push_str("\\ "),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            '\0' => result.push_str("\\0"),
+            c => result.push(c),
+        }
+    }
+}
+
+// Comma separated list of pairs sorted by tag and value, so equal tags give equal lists
+pub(crate) fn write_csl<'a, I>(pairs: I) -> String
+where
+    I: IntoIterator<Item = (&'a str, Option<&'a str>)>,
+{
+    let mut pairs: Vec<_> = pairs.into_iter().collect();
+    pairs.sort_unstable();
+    // Enough unless values need escaping
+    let capacity = pairs
+        .iter()
+        .map(|(tag, value)| tag.len() + value.map_or(0, |value| value.len() + 1) + 1)
+        .sum();
+    let mut csl = String::with_capacity(capacity);
+    for (index, (tag, value)) in pairs.into_iter().enumerate() {
+        if index > 0 {
+            csl.push(',');
+        }
+        csl.push_str(tag);
+        if let Some(value) = value {
+            csl.push('=');
+            push_escaped(&mut csl, value);
+        }
+    }
+    csl
+}
+
+fn eval_tags<'a, F>(pairs: &mut Pairs<'a, Rule>, insert: &mut F) -> Result<()>
+where
+    F: FnMut(&'a str, Option<Cow<'a, str>>),
+{
     while pairs.peek().is_some() {
         let thing = pairs.next().unwrap();
         match thing.as_rule() {
-            Rule::tag_with_value => eval_tag_with_value(thing, container),
+            Rule::tag_with_value => eval_tag_with_value(thing, insert),
             Rule::EOI => (),
             other => {
                 return Err(XTagError::ParserImplementation(format!(
@@ -34,23 +121,77 @@ fn eval_tags(pairs: &mut Pairs<Rule>, container: &mut XTags) -> Result<()> {
 }
 
 /// Convert comma separated list of tag=value pairs to map
+///
+/// Backslashes in values escape commas, equals signs, leading and trailing spaces and
+/// backslashes, `\n`, `\r`, `\t` and `\0` stand for control characters. Values written by
+/// map_to_csl are escaped accordingly. Of repeated tags the last value is kept, csl_to_multi_map
+/// keeps all.
 pub fn csl_to_map(string: &str) -> Result<XTags> {
     let mut result: XTags = HashMap::new();
+    parse_csl(string, |tag, value| {
+        result.insert(tag, value);
+    })?;
+    Ok(result)
+}
 
+// Calls insert for every tag of comma separated list in order, repeated tags included
+pub(crate) fn parse_csl<F>(string: &str, mut insert: F) -> Result<()>
+where
+    F: FnMut(String, Option<String>),
+{
+    parse_csl_ref(string, |tag, value| {
+        insert(tag.to_string(), value.map(Cow::into_owned))
+    })
+}
+
+// Like parse_csl, with tags and values borrowed from string where possible
+pub(crate) fn parse_csl_ref<'a, F>(string: &'a str, mut insert: F) -> Result<()>
+where
+    F: FnMut(&'a str, Option<Cow<'a, str>>),
+{
     // pairs = Array of tag_with_value with final EOI
     let mut pairs = SearchParser::parse(Rule::comma_separated_tags_with_values, string)
-        .map_err(|err| XTagError::Parser(err))?;
-    eval_tags(&mut pairs, &mut result)?;
-    Ok(result)
+        .map_err(|err| SearchParseError::new(err, string))?;
+    eval_tags(&mut pairs, &mut insert)
+}
+
+// Checks that tags survive storage unchanged
+pub(crate) fn validate_tags(tags: &XTags) -> Result<()> {
+    let csl = crate::map_to_csl(tags);
+    if &csl_to_map(&csl)? != tags {
+        return Err(XTagError::InvalidTags(csl));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::csl_to_map;
+    use crate::{map_to_csl, XTags};
 
     #[test]
     fn grammar_tags_support_spaces() {
         let result = std::panic::catch_unwind(|| csl_to_map("a , b = c"));
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn values_with_special_characters_round_trip() {
+        let mut tags = XTags::new();
+        for (index, value) in [" a, b=c ", "\\", "x\ny\t\0", "", "summer 2023", "Ärger"]
+            .iter()
+            .enumerate()
+        {
+            tags.insert(format!("t{index}"), Some(value.to_string()));
+        }
+        tags.insert("plain".to_string(), None);
+        let csl = map_to_csl(&tags);
+        assert!(!csl.contains(['\n', '\t', '\0']));
+        assert_eq!(csl_to_map(&csl).unwrap(), tags);
+
+        let tags = csl_to_map("a=b\\,c ,d = e f").unwrap();
+        assert_eq!(tags["a"].as_deref(), Some("b,c"));
+        assert_eq!(tags["d"].as_deref(), Some("e f"));
+        assert!(csl_to_map("a=b=c").is_err());
+    }
 }
@@ -0,0 +1,461 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, Permissions};
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::interpolate::expand_path_template;
+use crate::read_only::ensure_writable;
+use crate::store::store_or_xattr;
+use crate::transfer::remove_sidecar;
+use crate::{
+    move_with_tags, search_dir, BatchFailure, Result, Searcher, Store, WalkOptions, XTagError,
+};
+
+/// What to do with files matching a search, see plan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Action {
+    /// Moves files with their tags into the directory given by a template, relative to the
+    /// searched root. Templates take the placeholders of interpolate_tags and `{tag:name}` for
+    /// the value of tag name, like `photos/{tag:year}`. Values with `/` give nested directories.
+    /// Files whose directory would be absolute or contain `..` are skipped, so tag values can't
+    /// move files out of the root.
+    MoveTo(String),
+
+    /// Sets the permission bits of files, like `0o640`.
+    Chmod(u32),
+
+    /// Deletes files with their tags, which can't be rolled back. Moving files to a trash
+    /// directory can.
+    Delete,
+}
+
+/// Change of one file in a Plan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Change {
+    /// Move to path.
+    Move { to: PathBuf },
+
+    /// Change of permission bits.
+    Chmod { from: u32, to: u32 },
+
+    /// Deletion.
+    Delete,
+}
+
+/// File and its planned change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlanStep {
+    /// Path of the file.
+    pub path: PathBuf,
+
+    /// What happens to it.
+    pub change: Change,
+}
+
+impl fmt::Display for PlanStep {
+    /// Like `move a.jpg -> 2023/a.jpg`, `chmod 644 -> 600 a.jpg` or `delete a.jpg`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = self.path.display();
+        match &self.change {
+            Change::Move { to } => write!(f, "move {path} -> {}", to.display()),
+            Change::Chmod { from, to } => write!(f, "chmod {from:o} -> {to:o} {path}"),
+            Change::Delete => write!(f, "delete {path}"),
+        }
+    }
+}
+
+/// Changes of files matching a search, computed by plan and applied by Plan::apply.
+///
+/// Nothing is changed until the plan is applied, so it can be reviewed and steps can be
+/// removed before.
+#[derive(Debug, Default)]
+pub struct Plan {
+    steps: Vec<PlanStep>,
+    skipped: Vec<BatchFailure>,
+}
+
+/// Plans action for all files below root matching searcher.
+///
+/// Files that need no change, like files already in their directory, get no step. Files the
+/// action can't be planned for, like files without the tag a template needs or files whose
+/// target exists, are skipped and listed by Plan::skipped. Tags are read from options.store.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// use xtag::{compile_search, plan, Action};
+///
+/// let search = compile_search("photo and year?").unwrap();
+/// let action = Action::MoveTo("by-year/{tag:year}".to_string());
+/// let plan = plan(Path::new("inbox"), &search, &action, &Default::default()).unwrap();
+/// print!("{plan}");
+/// let report = plan.apply();
+/// if !report.failed.is_empty() {
+///     report.rollback();
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Same as search_dir
+pub fn plan(
+    root: &Path,
+    searcher: &Searcher,
+    action: &Action,
+    options: &WalkOptions,
+) -> Result<Plan> {
+    let mut plan = Plan::default();
+    let mut targets = HashSet::new();
+    for path in search_dir(root, searcher, options) {
+        let path = path?;
+        match plan_change(root, &path, action, store_or_xattr(&options.store), &mut targets) {
+            Ok(Some(change)) => plan.steps.push(PlanStep { path, change }),
+            Ok(None) => (),
+            Err(error) => plan.skipped.push(BatchFailure { path, error }),
+        }
+    }
+    plan.steps.sort_by(|a, b| a.path.cmp(&b.path));
+    plan.skipped.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(plan)
+}
+
+fn already_exists(target: &Path) -> XTagError {
+    XTagError::File(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists", target.display()),
+    ))
+}
+
+// None if file needs no change, targets are those of earlier moves
+fn plan_change(
+    root: &Path,
+    path: &Path,
+    action: &Action,
+    store: &dyn Store,
+    targets: &mut HashSet<PathBuf>,
+) -> Result<Option<Change>> {
+    match action {
+        Action::MoveTo(template) => {
+            let dir = expand_path_template(path, &store.get_tags(path)?, template)?;
+            let is_plain = |component| matches!(component, Component::Normal(_));
+            if !Path::new(&dir).components().all(is_plain) {
+                return Err(XTagError::Template(format!(
+                    "directory {dir:?} of {template:?} leaves the root"
+                )));
+            }
+            let to = root.join(dir).join(path.file_name().unwrap_or_default());
+            if to == path {
+                return Ok(None);
+            }
+            if to.exists() || !targets.insert(to.clone()) {
+                return Err(already_exists(&to));
+            }
+            Ok(Some(Change::Move { to }))
+        }
+        Action::Chmod(mode) => {
+            let from = fs::metadata(path)?.permissions().mode() & 0o7777;
+            let to = mode & 0o7777;
+            Ok((from != to).then_some(Change::Chmod { from, to }))
+        }
+        Action::Delete => Ok(Some(Change::Delete)),
+    }
+}
+
+impl Plan {
+    /// Planned steps, sorted by path.
+    pub fn steps(&self) -> &[PlanStep] {
+        &self.steps
+    }
+
+    /// Files that matched but couldn't be planned for.
+    pub fn skipped(&self) -> &[BatchFailure] {
+        &self.skipped
+    }
+
+    /// Number of steps.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Returns true if there are no steps.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Keeps only the steps for which keep returns true.
+    pub fn retain<F: FnMut(&PlanStep) -> bool>(&mut self, keep: F) {
+        self.steps.retain(keep);
+    }
+
+    /// Applies all steps in order.
+    ///
+    /// Failing steps don't stop the plan, their errors are collected in the PlanReport.
+    /// Directories needed by moves are created.
+    pub fn apply(self) -> PlanReport {
+        let mut report = PlanReport::default();
+        for step in self.steps {
+            match apply_step(&step, &mut report.created) {
+                Ok(()) => report.applied.push(step),
+                Err(error) => report.failed.push(BatchFailure {
+                    path: step.path,
+                    error,
+                }),
+            }
+        }
+        report
+    }
+}
+
+impl fmt::Display for Plan {
+    /// One line per step.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.steps.iter().try_for_each(|step| writeln!(f, "{step}"))
+    }
+}
+
+// Created directories are added to created, outermost first
+fn apply_step(step: &PlanStep, created: &mut Vec<PathBuf>) -> Result<()> {
+    match &step.change {
+        Change::Move { to } => {
+            // Renaming would silently replace files created since planning
+            if to.exists() {
+                return Err(already_exists(to));
+            }
+            if let Some(dir) = to.parent() {
+                let mut missing: Vec<&Path> =
+                    dir.ancestors().take_while(|dir| !dir.exists()).collect();
+                fs::create_dir_all(dir)?;
+                missing.reverse();
+                created.extend(missing.into_iter().map(Path::to_path_buf));
+            }
+            move_with_tags(&step.path, to)
+        }
+        Change::Chmod { to, .. } => {
+            ensure_writable(&step.path)?;
+            fs::set_permissions(&step.path, Permissions::from_mode(*to))?;
+            Ok(())
+        }
+        Change::Delete => {
+            ensure_writable(&step.path)?;
+            fs::remove_file(&step.path)?;
+            remove_sidecar(&step.path)
+        }
+    }
+}
+
+/// Outcome of Plan::apply or PlanReport::rollback.
+#[derive(Debug, Default)]
+pub struct PlanReport {
+    /// Steps applied successfully, in order.
+    pub applied: Vec<PlanStep>,
+
+    /// Steps that failed, in order.
+    pub failed: Vec<BatchFailure>,
+
+    created: Vec<PathBuf>,
+}
+
+impl PlanReport {
+    /// Returns true if all applied steps can be undone, which is false after deletions.
+    pub fn can_rollback(&self) -> bool {
+        !self
+            .applied
+            .iter()
+            .any(|step| step.change == Change::Delete)
+    }
+
+    /// Undoes all applied steps, the last one first, and removes directories created for
+    /// moves if they are empty again.
+    ///
+    /// Deleted files stay deleted. Steps that fail to undo are reported, the others are undone
+    /// anyway.
+    pub fn rollback(&self) -> PlanReport {
+        let mut report = PlanReport::default();
+        for step in self.applied.iter().rev() {
+            let undo = match &step.change {
+                Change::Move { to } => PlanStep {
+                    path: to.clone(),
+                    change: Change::Move {
+                        to: step.path.clone(),
+                    },
+                },
+                Change::Chmod { from, to } => PlanStep {
+                    path: step.path.clone(),
+                    change: Change::Chmod {
+                        from: *to,
+                        to: *from,
+                    },
+                },
+                Change::Delete => continue,
+            };
+            match apply_step(&undo, &mut report.created) {
+                Ok(()) => report.applied.push(undo),
+                Err(error) => report.failed.push(BatchFailure {
+                    path: undo.path,
+                    error,
+                }),
+            }
+        }
+        for dir in self.created.iter().rev() {
+            // Fails for directories with other files, which are kept
+            let _ = fs::remove_dir(dir);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use std::sync::Arc;
+
+    use super::{plan, Action, Change};
+    use crate::{
+        compile_search, csl_to_map, get_tags, Fixture, SidecarLayout, SidecarStore, Store,
+        WalkOptions, XTagError,
+    };
+
+    #[test]
+    fn files_are_moved_by_tag_values_and_moved_back() {
+        let fixture = Fixture::new()
+            .file("a.jpg")
+            .tags("photo,year=2023")
+            .file("b.jpg")
+            .tags("photo,year=2021")
+            .file("c.jpg")
+            .tags("photo")
+            .file("d.jpg")
+            .tags("photo,year=..")
+            .file("2021/e.jpg")
+            .tags("photo,year=2021");
+        let search = compile_search("photo").unwrap();
+        let action = Action::MoveTo("{tag:year}".to_string());
+        let options = WalkOptions::default();
+        let mut found = plan(fixture.path(), &search, &action, &options).unwrap();
+        assert_eq!(
+            found.to_string(),
+            format!(
+                "move {0}/a.jpg -> {0}/2023/a.jpg\nmove {0}/b.jpg -> {0}/2021/b.jpg\n",
+                fixture.path().display()
+            )
+        );
+        let skipped: Vec<_> = found
+            .skipped()
+            .iter()
+            .map(|failure| &failure.path)
+            .collect();
+        assert_eq!(skipped, [&fixture.join("c.jpg"), &fixture.join("d.jpg")]);
+
+        found.retain(|step| !step.path.ends_with("b.jpg"));
+        let report = found.apply();
+        assert_eq!(report.applied.len(), 1);
+        assert!(report.can_rollback());
+        assert_eq!(
+            get_tags(&fixture.join("2023/a.jpg")).unwrap()["year"].as_deref(),
+            Some("2023")
+        );
+
+        let undone = report.rollback();
+        assert!(undone.failed.is_empty());
+        assert!(fixture.join("a.jpg").exists());
+        assert!(!fixture.join("2023").exists());
+    }
+
+    #[test]
+    fn tag_values_cant_move_files_out_of_root() {
+        let fixture = Fixture::new().file("outside/x").file("root/sub/y");
+        let outside = fixture.join("outside");
+        let values = [
+            format!("{}", outside.display()),
+            "../outside".to_string(),
+            "../../..".to_string(),
+            "a/../../outside".to_string(),
+        ];
+        let root = fixture.join("root");
+        for value in values {
+            let tags = csl_to_map(&format!("photo,dir={value}")).unwrap();
+            crate::set_tags(&root.join("sub/y"), &tags).unwrap();
+            let search = compile_search("photo").unwrap();
+            let action = Action::MoveTo("{tag:dir}".to_string());
+            let found = plan(&root, &search, &action, &WalkOptions::default()).unwrap();
+            assert!(found.is_empty(), "{value}");
+            assert!(
+                matches!(found.skipped()[0].error, XTagError::Template(_)),
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn tags_are_read_from_store_of_options() {
+        let fixture = Fixture::new().file("a.jpg");
+        let store = SidecarStore::new(SidecarLayout::PerFile);
+        let tags = csl_to_map("photo,year=2023").unwrap();
+        store.set_tags(&fixture.join("a.jpg"), &tags).unwrap();
+        let options = WalkOptions {
+            store: Some(Arc::new(store)),
+            ..Default::default()
+        };
+        let search = compile_search("photo").unwrap();
+        let action = Action::MoveTo("{tag:year}".to_string());
+        let found = plan(fixture.path(), &search, &action, &options).unwrap();
+        assert_eq!(
+            found.steps()[0].change,
+            Change::Move {
+                to: fixture.join("2023/a.jpg")
+            }
+        );
+    }
+
+    #[test]
+    fn permissions_and_deletions_are_planned() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("private")
+            .file("b")
+            .tags("junk");
+        fs::set_permissions(fixture.join("a"), fs::Permissions::from_mode(0o644)).unwrap();
+        let options = WalkOptions::default();
+
+        let private = compile_search("private").unwrap();
+        let chmod = plan(fixture.path(), &private, &Action::Chmod(0o600), &options).unwrap();
+        assert_eq!(
+            chmod.steps()[0].change,
+            Change::Chmod {
+                from: 0o644,
+                to: 0o600
+            }
+        );
+        let report = chmod.apply();
+        let mode = || {
+            fs::metadata(fixture.join("a"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(mode(), 0o600);
+        assert!(
+            plan(fixture.path(), &private, &Action::Chmod(0o600), &options)
+                .unwrap()
+                .is_empty()
+        );
+        report.rollback();
+        assert_eq!(mode(), 0o644);
+
+        let junk = compile_search("junk").unwrap();
+        let report = plan(fixture.path(), &junk, &Action::Delete, &options)
+            .unwrap()
+            .apply();
+        assert!(!report.can_rollback());
+        assert!(!fixture.join("b").exists());
+    }
+}
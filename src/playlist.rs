@@ -0,0 +1,124 @@
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{self, Component, Path, PathBuf};
+
+use crate::{Result, ResultSet};
+
+/// Formats of exported file lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFormat {
+    /// One path per line.
+    Plain,
+
+    /// Extended M3U playlist, paths are written as raw bytes.
+    M3u,
+
+    /// Extended M3U playlist in UTF-8, paths that aren't valid unicode are converted lossy.
+    M3u8,
+}
+
+/// How paths are written to exported file lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathStyle<'a> {
+    /// Paths as they are in the ResultSet.
+    AsIs,
+
+    /// Absolute paths.
+    Absolute,
+
+    /// Paths relative to a directory, like the one the playlist is stored in.
+    RelativeTo(&'a Path),
+}
+
+// Relative path from base to path, both absolute
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    relative.extend(&path[common..]);
+    relative
+}
+
+fn styled(path: &Path, style: PathStyle) -> Result<PathBuf> {
+    Ok(match style {
+        PathStyle::AsIs => path.to_path_buf(),
+        PathStyle::Absolute => path::absolute(path)?,
+        PathStyle::RelativeTo(base) => {
+            relative_path(&path::absolute(path)?, &path::absolute(base)?)
+        }
+    })
+}
+
+/// Writes paths of result as file list or playlist for media players.
+///
+/// Playlist entries are titled with the file name without extension.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use std::fs::File;
+/// let searcher = xtag::compile_search("genre == jazz").unwrap();
+/// let music = Path::new("/home/me/Music");
+/// let mut cache = xtag::SearchCache::new();
+/// let result = cache.search(&searcher, music, &xtag::WalkOptions::default()).unwrap();
+/// let playlist = File::create(music.join("jazz.m3u8")).unwrap();
+/// xtag::export_list(&result, xtag::ListFormat::M3u8, xtag::PathStyle::RelativeTo(music), playlist)
+///     .unwrap();
+/// ```
+pub fn export_list<W: Write>(
+    result: &ResultSet,
+    format: ListFormat,
+    style: PathStyle,
+    mut writer: W,
+) -> Result<()> {
+    if format != ListFormat::Plain {
+        writeln!(writer, "#EXTM3U")?;
+    }
+    for path in result {
+        let path = styled(path, style)?;
+        if format != ListFormat::Plain {
+            let title = path.file_stem().unwrap_or(path.as_os_str());
+            writeln!(writer, "#EXTINF:-1,{}", title.to_string_lossy())?;
+        }
+        match format {
+            ListFormat::M3u8 => writer.write_all(path.to_string_lossy().as_bytes())?,
+            _ => writer.write_all(path.as_os_str().as_bytes())?,
+        }
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{export_list, ListFormat, PathStyle};
+    use crate::ResultSet;
+
+    #[test]
+    fn playlists_use_relative_paths() {
+        let result: ResultSet = [PathBuf::from("/music/jazz/a.mp3"), PathBuf::from("/b.mp3")]
+            .into_iter()
+            .collect();
+        let mut out = Vec::new();
+        let style = PathStyle::RelativeTo(Path::new("/music/lists"));
+        export_list(&result, ListFormat::M3u, style, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "#EXTM3U\n#EXTINF:-1,a\n../jazz/a.mp3\n#EXTINF:-1,b\n../../b.mp3\n"
+        );
+        let mut out = Vec::new();
+        export_list(&result, ListFormat::Plain, PathStyle::AsIs, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "/music/jazz/a.mp3\n/b.mp3\n"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use std::ops;
+
+use regex::escape;
+
+use crate::{Comparand, Result, Searcher};
+
+#[derive(Debug, Clone)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Tag(String),
+    Equal(String, String),
+    Inequal(String, String),
+    Less(String, Comparand),
+    LessEqual(String, Comparand),
+    Greater(String, Comparand),
+    GreaterEqual(String, Comparand),
+    IsTrue(String),
+    IsFalse(String),
+}
+
+/// Typed builder for Searcher.
+///
+/// Tags and values given to the builder are taken literally, regular expression characters in
+/// them have no special meaning. Use the `_regex` variants for regular expressions.
+///
+/// # Example
+///
+/// ```
+/// # use xtag::Query;
+/// let searcher = Query::tag("photo")
+///     .and(Query::value("rating").ge(4))
+///     .not(Query::tag("private"))
+///     .build()
+///     .unwrap();
+/// let tags = xtag::csl_to_map("photo,rating=5").unwrap();
+/// assert!(searcher.is_match(&tags) == true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    node: Node,
+}
+
+/// Builder for comparisons of the values of a tag.
+#[derive(Debug, Clone)]
+pub struct ValueQuery {
+    tag_regex: String,
+}
+
+impl Query {
+    /// Matches files with tag.
+    pub fn tag(tag: &str) -> Self {
+        Query::tag_regex(&escape(tag))
+    }
+
+    /// Matches files with a tag matching regular expression.
+    ///
+    /// The expression is expanded with anchors to match the whole tag.
+    pub fn tag_regex(tag_regex: &str) -> Self {
+        Query {
+            node: Node::Tag(tag_regex.to_string()),
+        }
+    }
+
+    /// Starts comparison of the values of tag.
+    pub fn value(tag: &str) -> ValueQuery {
+        Query::value_regex(&escape(tag))
+    }
+
+    /// Starts comparison of the values of all tags matching regular expression.
+    pub fn value_regex(tag_regex: &str) -> ValueQuery {
+        ValueQuery {
+            tag_regex: tag_regex.to_string(),
+        }
+    }
+
+    /// Matches when both match.
+    pub fn and(self, rhs: Query) -> Self {
+        Query {
+            node: Node::And(Box::new(self.node), Box::new(rhs.node)),
+        }
+    }
+
+    /// Matches when at least one matches.
+    pub fn or(self, rhs: Query) -> Self {
+        Query {
+            node: Node::Or(Box::new(self.node), Box::new(rhs.node)),
+        }
+    }
+
+    /// Matches when self matches and rhs doesn't.
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self, rhs: Query) -> Self {
+        self.and(!rhs)
+    }
+
+    /// Compiles the query.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if a regular expression given to a `_regex` method is invalid
+    pub fn build(self) -> Result<Searcher> {
+        build(self.node)
+    }
+}
+
+impl ops::Not for Query {
+    type Output = Query;
+
+    /// Matches when the query doesn't match.
+    fn not(self) -> Query {
+        Query {
+            node: Node::Not(Box::new(self.node)),
+        }
+    }
+}
+
+impl ValueQuery {
+    fn query(self, node: impl FnOnce(String) -> Node) -> Query {
+        Query {
+            node: node(self.tag_regex),
+        }
+    }
+
+    /// Matches when a value equals value.
+    pub fn eq(self, value: &str) -> Query {
+        self.eq_regex(&escape(value))
+    }
+
+    /// Matches when a value matches regular expression.
+    pub fn eq_regex(self, value_regex: &str) -> Query {
+        self.query(|tag| Node::Equal(tag, value_regex.to_string()))
+    }
+
+    /// Matches when no value equals value.
+    pub fn ne(self, value: &str) -> Query {
+        let value_regex = escape(value);
+        self.query(|tag| Node::Inequal(tag, value_regex))
+    }
+
+    /// Matches when a value is less than value.
+    ///
+    /// Values are compared as integer, float or date like value, see Comparand.
+    pub fn lt(self, value: impl Into<Comparand>) -> Query {
+        self.query(|tag| Node::Less(tag, value.into()))
+    }
+
+    /// Matches when a value is less than or equal to value, see lt.
+    pub fn le(self, value: impl Into<Comparand>) -> Query {
+        self.query(|tag| Node::LessEqual(tag, value.into()))
+    }
+
+    /// Matches when a value is greater than value, see lt.
+    pub fn gt(self, value: impl Into<Comparand>) -> Query {
+        self.query(|tag| Node::Greater(tag, value.into()))
+    }
+
+    /// Matches when a value is greater than or equal to value, see lt.
+    pub fn ge(self, value: impl Into<Comparand>) -> Query {
+        self.query(|tag| Node::GreaterEqual(tag, value.into()))
+    }
+
+    /// Matches when a value is one of the true spellings.
+    pub fn is_true(self) -> Query {
+        self.query(Node::IsTrue)
+    }
+
+    /// Matches when a value is one of the false spellings.
+    pub fn is_false(self) -> Query {
+        self.query(Node::IsFalse)
+    }
+}
+
+fn build(node: Node) -> Result<Searcher> {
+    Ok(match node {
+        Node::And(lhs, rhs) => Searcher::new_and(build(*lhs)?, build(*rhs)?),
+        Node::Or(lhs, rhs) => Searcher::new_or(build(*lhs)?, build(*rhs)?),
+        Node::Not(lhs) => Searcher::new_not(build(*lhs)?),
+        Node::Tag(tag) => Searcher::new_tag(&tag)?,
+        Node::Equal(tag, value) => Searcher::new_equal(&tag, &value)?,
+        Node::Inequal(tag, value) => Searcher::new_inequal(&tag, &value)?,
+        Node::Less(tag, value) => Searcher::new_less(&tag, &comparand(value)?)?,
+        Node::LessEqual(tag, value) => Searcher::new_less_equal(&tag, &comparand(value)?)?,
+        Node::Greater(tag, value) => Searcher::new_greater(&tag, &comparand(value)?)?,
+        Node::GreaterEqual(tag, value) => Searcher::new_greater_equal(&tag, &comparand(value)?)?,
+        Node::IsTrue(tag) => Searcher::new_is_true(&tag)?,
+        Node::IsFalse(tag) => Searcher::new_is_false(&tag)?,
+    })
+}
+
+// Comparand as parsed by the Searcher constructors, floats like NaN are rejected
+fn comparand(value: Comparand) -> Result<String> {
+    if let Comparand::Float(float) = value {
+        Comparand::float(float)?;
+    }
+    Ok(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Query;
+    use crate::{Date, XTagError, XTags};
+
+    // Builds map directly, the storage format doesn't allow all characters
+    fn find_in_string(query: Query, string: &str) -> bool {
+        let tags: XTags = string
+            .split(',')
+            .map(|pair| match pair.split_once('=') {
+                Some((tag, value)) => (tag.to_string(), Some(value.to_string())),
+                None => (pair.to_string(), None),
+            })
+            .collect();
+        query.build().unwrap().is_match(&tags)
+    }
+
+    #[test]
+    fn query_takes_tags_literally() {
+        assert!(find_in_string(Query::tag("a.b"), "a.b"));
+        assert!(!find_in_string(Query::tag("a.b"), "aXb"));
+        assert!(find_in_string(Query::tag_regex("a.b"), "aXb"));
+        assert!(find_in_string(Query::value("a").eq("1+"), "a=1+"));
+        assert!(!find_in_string(Query::value("a").eq("1+"), "a=11"));
+    }
+
+    #[test]
+    fn query_combines_terms() {
+        let query = || {
+            Query::tag("a")
+                .or(Query::value("b").lt(3))
+                .not(Query::value("c").is_true())
+        };
+        assert!(find_in_string(query(), "a"));
+        assert!(find_in_string(query(), "b=2,c=no"));
+        assert!(!find_in_string(query(), "b=2,c=yes"));
+        assert!(find_in_string(!Query::tag("a"), "b"));
+        assert!(Query::tag_regex("(").build().is_err());
+    }
+
+    #[test]
+    fn query_compares_floats_and_dates() {
+        assert!(find_in_string(Query::value("a").gt(1.5), "a=2"));
+        assert!(!find_in_string(Query::value("a").le(1.5), "a=1.75"));
+        assert!(find_in_string(Query::value("a").ge(2.0), "a=2"));
+        let date = Date::new(2023, 6, 1).unwrap();
+        assert!(find_in_string(Query::value("shot").lt(date), "shot=2023-05-31"));
+        assert!(!find_in_string(Query::value("shot").gt(date), "shot=2023-06-01"));
+        assert!(!find_in_string(Query::value("shot").gt(date), "shot=high"));
+        assert!(matches!(
+            Query::value("a").lt(f64::NAN).build(),
+            Err(XTagError::ComparisonValue(_))
+        ));
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::{Index, ResultSet, Searcher, XTags};
+
+/// How a term of a search is answered by Index::query, see Index::explain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IndexAccess {
+    /// Files looked up by the distinct tags and values of the index, or combined from lookups
+    /// of the operands, without evaluating the tags of single files.
+    Lookup,
+
+    /// Tags of the candidate files are evaluated one by one. Candidates are narrowed by lookups
+    /// of operands where possible, otherwise all indexed files are evaluated.
+    Fallback,
+}
+
+impl fmt::Display for IndexAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IndexAccess::Lookup => write!(f, "lookup"),
+            IndexAccess::Fallback => write!(f, "fallback"),
+        }
+    }
+}
+
+/// Plan of a search over an Index, returned by Index::explain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QueryPlan {
+    /// Term as displayed by Searcher, or the operator like `and` for terms with operands.
+    pub term: String,
+
+    /// How the term is answered.
+    pub access: IndexAccess,
+
+    /// Files matching the term, exact for lookups. For fallbacks the number of candidates,
+    /// whose tags are evaluated if the term is the whole search.
+    pub estimated_files: usize,
+
+    /// Plans of the operands, in term order.
+    pub operands: Vec<QueryPlan>,
+}
+
+impl QueryPlan {
+    fn write(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let approximate = match self.access {
+            IndexAccess::Lookup => "",
+            IndexAccess::Fallback => "~",
+        };
+        writeln!(
+            f,
+            "{:indent$}{} ({}, {approximate}{} files)",
+            "",
+            self.term,
+            self.access,
+            self.estimated_files,
+            indent = depth * 2
+        )?;
+        self.operands
+            .iter()
+            .try_for_each(|operand| operand.write(f, depth + 1))
+    }
+}
+
+impl fmt::Display for QueryPlan {
+    /// One line per term with access and estimated files, operands indented.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+// Indexed files by their distinct tag and value pairs, built on first query
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Postings {
+    paths: Vec<PathBuf>,
+    pairs: Vec<(XTags, BTreeSet<usize>)>,
+}
+
+impl Postings {
+    pub(crate) fn new<'a>(entries: impl Iterator<Item = (&'a Path, &'a XTags)>) -> Self {
+        let mut paths = Vec::new();
+        let mut files: HashMap<(&str, Option<&str>), BTreeSet<usize>> = HashMap::new();
+        for (file, (path, tags)) in entries.enumerate() {
+            paths.push(path.to_path_buf());
+            for (tag, value) in tags {
+                files
+                    .entry((tag, value.as_deref()))
+                    .or_default()
+                    .insert(file);
+            }
+        }
+        let pairs = files
+            .into_iter()
+            .map(|((tag, value), files)| {
+                let pair = XTags::from([(tag.to_string(), value.map(str::to_string))]);
+                (pair, files)
+            })
+            .collect();
+        Postings { paths, pairs }
+    }
+
+    fn all(&self) -> BTreeSet<usize> {
+        (0..self.paths.len()).collect()
+    }
+}
+
+// Files of a term, only candidates if not exact
+struct Resolved {
+    files: BTreeSet<usize>,
+    exact: bool,
+}
+
+// Returns true if searcher matches a file as soon as it matches one of its tags alone, so it
+// can be answered from the postings. Terms without operands test single tags, except for the
+// number of tags.
+fn is_pairwise(searcher: &Searcher) -> bool {
+    searcher.operands().is_empty() && !matches!(searcher, Searcher::TagCount { .. })
+}
+
+// Returns true if the operands of searcher are resolved on their own and combined, others are
+// evaluated as a whole
+fn is_combined(searcher: &Searcher) -> bool {
+    matches!(
+        searcher,
+        Searcher::And { .. }
+            | Searcher::Or { .. }
+            | Searcher::Not { .. }
+            | Searcher::Bookmark { .. }
+    )
+}
+
+// Resolves searcher by lookups, recording the plan of every term
+fn resolve(searcher: &Searcher, postings: &Postings) -> (Resolved, QueryPlan) {
+    searcher.fold_nodes_with(is_combined, |node, operands| {
+        let (resolved, plans): (Vec<Resolved>, Vec<QueryPlan>) = operands.into_iter().unzip();
+        let exact = resolved.iter().all(|operand| operand.exact);
+        let files = match node {
+            Searcher::And { .. } => &resolved[0].files & &resolved[1].files,
+            Searcher::Or { .. } => &resolved[0].files | &resolved[1].files,
+            Searcher::Not { .. } if exact => &postings.all() - &resolved[0].files,
+            Searcher::Bookmark { .. } => resolved[0].files.clone(),
+            _ if is_pairwise(node) => postings
+                .pairs
+                .iter()
+                .filter(|(pair, _)| node.is_match(pair))
+                .flat_map(|(_, files)| files)
+                .copied()
+                .collect(),
+            _ => postings.all(),
+        };
+        let exact = match node {
+            _ if is_combined(node) => exact,
+            _ => is_pairwise(node),
+        };
+        let plan = QueryPlan {
+            term: node.label(),
+            access: match exact {
+                true => IndexAccess::Lookup,
+                false => IndexAccess::Fallback,
+            },
+            estimated_files: files.len(),
+            operands: plans,
+        };
+        (Resolved { files, exact }, plan)
+    })
+}
+
+impl Index {
+    fn postings(&self) -> &Postings {
+        self.postings.get_or_init(|| Postings::new(self.iter()))
+    }
+
+    /// Files below root matching searcher, sorted by path.
+    ///
+    /// Answered from the index alone, call refresh first to see changes to the tree. Terms
+    /// testing single tags are looked up by the distinct tags and values of the index, only
+    /// the remaining candidates are evaluated file by file, see explain.
+    pub fn query(&self, searcher: &Searcher) -> ResultSet {
+        let postings = self.postings();
+        let (resolved, _) = resolve(searcher, postings);
+        resolved
+            .files
+            .into_iter()
+            .map(|file| &postings.paths[file])
+            .filter(|path| {
+                resolved.exact || self.tags(path).is_some_and(|tags| searcher.is_match(tags))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns how query answers searcher, with the access and estimated number of files of
+    /// every term.
+    ///
+    /// Terms testing single tags, like `photo` or `rating >= 4`, and operators over them are
+    /// lookups. Others, like `#tags >= 5` or folded terms, fall back to evaluating the tags of
+    /// every candidate file, so their operators do as well. Candidates of `and` are narrowed by
+    /// its lookups, so put selective lookups next to fallbacks to speed them up.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// let index = xtag::Index::from_manifest(
+    ///     Path::new("/photos"),
+    ///     "a.jpg\tset\tphoto,rating=5\nb.jpg\tset\tphoto\nc.txt\tset\tnote\n".as_bytes(),
+    /// )
+    /// .unwrap();
+    /// let search = xtag::compile_search("photo and #tags >= 2").unwrap();
+    /// assert_eq!(
+    ///     index.explain(&search).to_string(),
+    ///     "and (fallback, ~2 files)\n  ^(?:photo)$ (lookup, 2 files)\n  \
+    ///      #tags >= 2 (fallback, ~3 files)\n"
+    /// );
+    /// ```
+    pub fn explain(&self, searcher: &Searcher) -> QueryPlan {
+        resolve(searcher, self.postings()).1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::IndexAccess;
+    use crate::{compile_search, Index};
+
+    fn index() -> Index {
+        let manifest = "a\tset\tphoto,rating=5#0.5\n\
+                        b\tset\tphoto,rating=3\n\
+                        c\tset\tnote,x,y\n";
+        Index::from_manifest(Path::new("/r"), manifest.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn lookups_match_like_scans() {
+        let index = index();
+        for term in [
+            "photo",
+            "rating >= 4",
+            "rating == 5",
+            "not photo",
+            "photo or note",
+            "photo and not (rating < 4)",
+            "#tags >= 3",
+            "not #tags >= 1",
+            "photo and #tags >= 2",
+            "rating? or x!",
+        ] {
+            let searcher = compile_search(term).unwrap();
+            let scanned: Vec<_> = index
+                .iter()
+                .filter(|(_, tags)| searcher.is_match(*tags))
+                .map(|(path, _)| path.to_path_buf())
+                .collect();
+            let found: Vec<_> = index.query(&searcher).into_iter().collect();
+            assert_eq!(found, scanned, "{term}");
+        }
+    }
+
+    #[test]
+    fn plans_report_access_and_files() {
+        let index = index();
+        let plan = index.explain(&compile_search("photo and not (rating < 4)").unwrap());
+        assert_eq!(plan.access, IndexAccess::Lookup);
+        assert_eq!(plan.estimated_files, 1);
+        assert_eq!(plan.operands[1].term, "not");
+        assert_eq!(plan.operands[1].estimated_files, 2);
+
+        let plan = index.explain(&compile_search("#tags >= 3 or photo").unwrap());
+        assert_eq!(plan.access, IndexAccess::Fallback);
+        assert_eq!(plan.estimated_files, 3);
+        assert_eq!(plan.operands[1].access, IndexAccess::Lookup);
+    }
+}
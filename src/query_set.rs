@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::aliases::parse_toml_line;
+use crate::{compile_search_with, BookmarkRegistry, Result, SearchOptions, Searcher, XTagError};
+
+/// Named searches compiled once, like the predefined smart views of an application.
+///
+/// All searches are compiled when the set is built, so invalid terms fail at startup instead of
+/// on first use. Searches may reference each other like bookmarks, `{name}` is the search name
+/// of the set.
+///
+/// # Example
+///
+/// ```
+/// # use xtag::{QuerySet, SearchOptions};
+/// let toml = r#"
+/// photos = "photo or image"
+/// "recent photos" = "{photos} and year > 2020"
+/// "#;
+/// let queries = QuerySet::from_toml(toml, &SearchOptions::default()).unwrap();
+/// let tags = xtag::csl_to_map("image,year=2023").unwrap();
+/// assert!(queries.get("recent photos").unwrap().is_match(&tags));
+/// ```
+#[derive(Default)]
+pub struct QuerySet {
+    terms: BTreeMap<String, String>,
+    searchers: BTreeMap<String, Searcher>,
+}
+
+impl QuerySet {
+    /// Compiles searches from name and term pairs with options.
+    ///
+    /// References to names of terms are resolved before the bookmarks of options.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::QuerySet if a name occurs twice
+    /// - XTagError::InvalidQuery with the name of the first search that doesn't compile
+    pub fn new<I, N, T>(terms: I, options: &SearchOptions) -> Result<Self>
+    where
+        I: IntoIterator<Item = (N, T)>,
+        N: Into<String>,
+        T: Into<String>,
+    {
+        let mut set = QuerySet::default();
+        for (name, term) in terms {
+            let name = name.into();
+            if set.terms.contains_key(&name) {
+                return Err(XTagError::QuerySet(format!("duplicate name {name:?}")));
+            }
+            set.terms.insert(name, term.into());
+        }
+        let mut options = options.clone();
+        for (name, term) in &set.terms {
+            options.bookmarks.insert(name, term);
+        }
+        for name in set.terms.keys() {
+            let searcher =
+                compile_search_with(&format!("{{{name}}}"), &options).map_err(|err| {
+                    XTagError::InvalidQuery {
+                        name: name.clone(),
+                        source: Box::new(err),
+                    }
+                })?;
+            set.searchers.insert(name.clone(), searcher);
+        }
+        Ok(set)
+    }
+
+    /// Parses TOML table of names and terms, like `photos = "photo or image"`.
+    ///
+    /// Like AliasMap::from_toml, keys are bare or quoted and assigned a string on one line.
+    /// Comments and table headers are skipped.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::QuerySet if a line is invalid
+    /// - Same as new
+    pub fn from_toml(toml: &str, options: &SearchOptions) -> Result<Self> {
+        let mut terms = Vec::new();
+        for (index, line) in toml.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+                continue;
+            }
+            let invalid = || XTagError::QuerySet(format!("invalid line {}", index + 1));
+            let (name, mut values) = parse_toml_line(line).ok_or_else(invalid)?;
+            if values.len() != 1 {
+                return Err(invalid());
+            }
+            terms.push((name, values.pop().ok_or_else(invalid)?));
+        }
+        QuerySet::new(terms, options)
+    }
+
+    /// Parses JSON object of names and terms, like `{"photos": "photo or image"}`.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::QuerySet if json is no object of strings
+    /// - Same as new
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str, options: &SearchOptions) -> Result<Self> {
+        let invalid = |err: &dyn std::fmt::Display| XTagError::QuerySet(err.to_string());
+        let value: serde_json::Value = serde_json::from_str(json).map_err(|err| invalid(&err))?;
+        let object = value
+            .as_object()
+            .ok_or_else(|| invalid(&"no object of names"))?;
+        let terms = object
+            .iter()
+            .map(|(name, term)| match term.as_str() {
+                Some(term) => Ok((name.as_str(), term)),
+                None => Err(invalid(&format!("term of {name:?} is no string"))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        QuerySet::new(terms, options)
+    }
+
+    /// Reads searches from file, JSON if its extension is `json`, TOML otherwise.
+    ///
+    /// JSON needs the feature json.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if the file can't be read
+    /// - XTagError::QuerySet for JSON files without the feature json
+    /// - Same as from_toml and from_json
+    pub fn load(path: &Path, options: &SearchOptions) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        match path.extension() {
+            #[cfg(feature = "json")]
+            Some(extension) if extension == "json" => QuerySet::from_json(&content, options),
+            #[cfg(not(feature = "json"))]
+            Some(extension) if extension == "json" => Err(XTagError::QuerySet(format!(
+                "{path:?} needs the json feature, which is disabled"
+            ))),
+            _ => QuerySet::from_toml(&content, options),
+        }
+    }
+
+    /// Compiled search name, None if there is no such search.
+    pub fn get(&self, name: &str) -> Option<&Searcher> {
+        self.searchers.get(name)
+    }
+
+    /// Term of search name as written.
+    pub fn term(&self, name: &str) -> Option<&str> {
+        self.terms.get(name).map(String::as_str)
+    }
+
+    /// Names of all searches in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.searchers.keys().map(String::as_str)
+    }
+
+    /// Names and searches in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Searcher)> {
+        self.searchers
+            .iter()
+            .map(|(name, searcher)| (name.as_str(), searcher))
+    }
+
+    /// Number of searches.
+    pub fn len(&self) -> usize {
+        self.searchers.len()
+    }
+
+    /// Returns true if there are no searches.
+    pub fn is_empty(&self) -> bool {
+        self.searchers.is_empty()
+    }
+
+    /// Registry of the terms, to resolve names of the set in other searches.
+    pub fn bookmarks(&self) -> BookmarkRegistry {
+        let mut registry = BookmarkRegistry::new();
+        for (name, term) in &self.terms {
+            registry.insert(name, term);
+        }
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QuerySet;
+    use crate::{csl_to_map, Fixture, SearchOptions, XTagError};
+
+    #[test]
+    fn query_sets_compile_on_load() {
+        let options = SearchOptions::default();
+        let toml = "[queries]\na = \"x or y\"\n\"b c\" = \"{a} and not z\"\n";
+        let queries = QuerySet::from_toml(toml, &options).unwrap();
+        assert_eq!(queries.names().collect::<Vec<_>>(), ["a", "b c"]);
+        assert_eq!(queries.term("b c"), Some("{a} and not z"));
+        let search = queries.get("b c").unwrap();
+        assert!(search.is_match(&csl_to_map("y").unwrap()));
+        assert!(!search.is_match(&csl_to_map("y,z").unwrap()));
+        assert!(queries.get("d").is_none());
+
+        match QuerySet::from_toml("a = \"x\"\nb = \"x y\"", &options) {
+            Err(XTagError::InvalidQuery { name, .. }) => assert_eq!(name, "b"),
+            _ => panic!("invalid query accepted"),
+        }
+        let result = QuerySet::from_toml("a = \"{b}\"\nb = \"{a}\"", &options);
+        assert!(matches!(result, Err(XTagError::InvalidQuery { .. })));
+        let result = QuerySet::from_toml("a = x", &options);
+        assert!(matches!(result, Err(XTagError::QuerySet(_))));
+        let result = QuerySet::new([("a", "x"), ("a", "y")], &options);
+        assert!(matches!(result, Err(XTagError::QuerySet(_))));
+
+        let fixture = Fixture::new();
+        let path = fixture.join("queries.toml");
+        std::fs::write(&path, toml).unwrap();
+        assert_eq!(QuerySet::load(&path, &options).unwrap().len(), 2);
+
+        let path = fixture.join("queries.json");
+        std::fs::write(&path, r#"{"a": "x"}"#).unwrap();
+        let result = QuerySet::load(&path, &options);
+        if cfg!(feature = "json") {
+            assert_eq!(result.unwrap().len(), 1);
+        } else {
+            assert!(
+                matches!(result, Err(XTagError::QuerySet(message)) if message.contains("json"))
+            );
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn query_sets_load_json() {
+        let options = SearchOptions::default();
+        let queries = QuerySet::from_json(r#"{"a": "x", "b": "{a} or y"}"#, &options).unwrap();
+        assert_eq!(queries.len(), 2);
+        assert!(queries
+            .get("b")
+            .unwrap()
+            .is_match(&csl_to_map("x").unwrap()));
+        assert!(QuerySet::from_json(r#"{"a": 1}"#, &options).is_err());
+        assert!(QuerySet::from_json("[]", &options).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{Result, Store, XTagError, XTags};
+
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Turns all functions changing tags of files into errors, for the whole process.
+///
+/// While enabled, set_tags, delete_tags, stores and every other function writing tags, sidecar
+/// files, views, history, bookmarks, symlink trees or extracted archives return
+/// XTagError::ReadOnly before touching the file system. Viewers and indexers can enable it at
+/// start to be sure they never change user data. Index, cache and journal files of the library
+/// itself are still written.
+///
+/// # Example
+///
+/// ```
+/// # use std::path::Path;
+/// xtag::set_read_only(true);
+/// let result = xtag::set_tags(Path::new("a.jpg"), &xtag::csl_to_map("photo").unwrap());
+/// assert!(matches!(result, Err(xtag::XTagError::ReadOnly(_))));
+/// let search = xtag::compile_search("photo").unwrap();
+/// let result = xtag::save_bookmark(Path::new("photos.bookmark"), &search);
+/// assert!(matches!(result, Err(xtag::XTagError::ReadOnly(_))));
+/// # xtag::set_read_only(false);
+/// ```
+pub fn set_read_only(read_only: bool) {
+    READ_ONLY.store(read_only, Ordering::SeqCst);
+}
+
+/// Returns true if set_read_only is enabled.
+pub fn is_read_only() -> bool {
+    READ_ONLY.load(Ordering::SeqCst)
+}
+
+// Called before every change of tags of path
+pub(crate) fn ensure_writable(path: &Path) -> Result<()> {
+    match is_read_only() {
+        true => Err(XTagError::ReadOnly(path.to_path_buf())),
+        false => Ok(()),
+    }
+}
+
+/// Store refusing all changes, reading from the wrapped store.
+///
+/// Like set_read_only, but only for code given this store, so other parts of a program can
+/// still write.
+///
+/// # Example
+///
+/// ```
+/// # use std::path::Path;
+/// use xtag::{ReadOnlyStore, Store, XattrStore};
+///
+/// let store = ReadOnlyStore::new(XattrStore::default());
+/// assert!(store.delete_tags(Path::new("a.jpg")).is_err());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadOnlyStore<S> {
+    store: S,
+}
+
+impl<S: Store> ReadOnlyStore<S> {
+    /// Returns store reading from store.
+    pub fn new(store: S) -> Self {
+        ReadOnlyStore { store }
+    }
+
+    /// Returns the wrapped store, allowing changes again.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+}
+
+impl<S: Store> Store for ReadOnlyStore<S> {
+    fn get_tags(&self, path: &Path) -> Result<XTags> {
+        self.store.get_tags(path)
+    }
+
+    fn set_tags(&self, path: &Path, _tags: &XTags) -> Result<()> {
+        Err(XTagError::ReadOnly(path.to_path_buf()))
+    }
+
+    fn delete_tags(&self, path: &Path) -> Result<()> {
+        Err(XTagError::ReadOnly(path.to_path_buf()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReadOnlyStore;
+    use crate::{
+        add_tags_with_store, csl_to_map, get_tags, swap_tags_with_store, Fixture, Store, XTagError,
+        XattrStore,
+    };
+
+    // The process-wide switch is only tested in its doc test, it would break parallel tests
+    #[test]
+    fn read_only_stores_refuse_changes() {
+        let fixture = Fixture::new().file("a").tags("photo").file("b");
+        let store = ReadOnlyStore::new(XattrStore::default());
+        assert_eq!(
+            store.get_tags(&fixture.join("a")).unwrap(),
+            csl_to_map("photo").unwrap()
+        );
+        let tags = csl_to_map("video").unwrap();
+        let result = add_tags_with_store(&fixture.join("a"), &tags, &store);
+        assert!(matches!(result, Err(XTagError::ReadOnly(path)) if path == fixture.join("a")));
+        let result = swap_tags_with_store(&fixture.join("a"), &fixture.join("b"), &store);
+        assert!(matches!(result, Err(XTagError::ReadOnly(_))));
+        assert_eq!(
+            get_tags(&fixture.join("a")).unwrap(),
+            csl_to_map("photo").unwrap()
+        );
+
+        let store = store.into_inner();
+        store.delete_tags(&fixture.join("a")).unwrap();
+        assert!(get_tags(&fixture.join("a")).unwrap().is_empty());
+    }
+}
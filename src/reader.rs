@@ -0,0 +1,329 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::store::store_or_xattr;
+use crate::{Result, Store, XTags};
+
+// True if TagReader reads attributes in batches
+pub(crate) const BATCHED: bool = cfg!(all(target_os = "linux", feature = "uring"));
+
+/// Iterator reading the tags of many files.
+///
+/// Yields every path together with its tags in input order. Without the `uring` feature the
+/// attributes are read one after another. With it, on Linux, the reads are submitted to io_uring in
+/// batches so the kernel can overlap them. Kernels without io_uring xattr support transparently fall
+/// back to serial reads, as do readers of other stores than the `user.xtag` attribute.
+///
+/// search_dir, search_dir_async and Index::update read their tags through it.
+pub struct TagReader<I> {
+    paths: I,
+    ready: VecDeque<(PathBuf, Result<XTags>)>,
+    store: Option<Arc<dyn Store + Send + Sync>>,
+    #[cfg(all(target_os = "linux", feature = "uring"))]
+    ring: Option<uring::Ring>,
+}
+
+/// Returns TagReader over paths.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::PathBuf;
+/// let paths = vec![PathBuf::from("a.jpg"), PathBuf::from("b.jpg")];
+/// for (path, tags) in xtag::read_tags(paths) {
+///     println!("{}: {}", path.display(), xtag::map_to_csl(&tags.unwrap()));
+/// }
+/// ```
+pub fn read_tags<I>(paths: I) -> TagReader<I::IntoIter>
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    TagReader::new(paths.into_iter(), None)
+}
+
+/// Like read_tags, with the tags of store instead of the `user.xtag` attribute.
+///
+/// The tags are read one after another, io_uring only reads attributes.
+pub fn read_tags_with_store<I>(paths: I, store: Arc<dyn Store + Send + Sync>) -> TagReader<I::IntoIter>
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    TagReader::new(paths.into_iter(), Some(store))
+}
+
+impl<I> TagReader<I>
+where
+    I: Iterator<Item = PathBuf>,
+{
+    // Reader of the tags of store, of the `user.xtag` attribute if None
+    pub(crate) fn new(paths: I, store: Option<Arc<dyn Store + Send + Sync>>) -> Self {
+        TagReader {
+            paths,
+            ready: VecDeque::new(),
+            #[cfg(all(target_os = "linux", feature = "uring"))]
+            ring: match store {
+                None => uring::Ring::new().ok(),
+                Some(_) => None,
+            },
+            store,
+        }
+    }
+
+    #[cfg(all(target_os = "linux", feature = "uring"))]
+    fn fill(&mut self) {
+        let Some(ring) = self.ring.as_mut() else {
+            return self.fill_serial();
+        };
+        let batch: Vec<PathBuf> = self.paths.by_ref().take(uring::BATCH_SIZE).collect();
+        match ring.get_all(&batch) {
+            Ok(results) => {
+                for (path, result) in batch.into_iter().zip(results) {
+                    let tags = match result {
+                        Some(result) => result.and_then(|value| crate::tags_from_bytes(&value)),
+                        None => self.get(&path),
+                    };
+                    self.ready.push_back((path, tags));
+                }
+            }
+            Err(_) => {
+                // io_uring unusable, continue without
+                self.ring = None;
+                for path in batch {
+                    let tags = self.get(&path);
+                    self.ready.push_back((path, tags));
+                }
+            }
+        }
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "uring")))]
+    fn fill(&mut self) {
+        self.fill_serial();
+    }
+
+    fn fill_serial(&mut self) {
+        if let Some(path) = self.paths.next() {
+            let tags = self.get(&path);
+            self.ready.push_back((path, tags));
+        }
+    }
+
+    fn get(&self, path: &std::path::Path) -> Result<XTags> {
+        store_or_xattr(&self.store).get_tags(path)
+    }
+}
+
+impl<I> Iterator for TagReader<I>
+where
+    I: Iterator<Item = PathBuf>,
+{
+    type Item = (PathBuf, Result<XTags>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ready.is_empty() {
+            self.fill();
+        }
+        self.ready.pop_front()
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "uring"))]
+mod uring {
+    use std::ffi::CString;
+    use std::io;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+    use std::sync::LazyLock;
+
+    use io_uring::{opcode, IoUring};
+
+    use crate::{Result, XTagError, XATTR_NAME};
+
+    pub(super) const BATCH_SIZE: usize = 64;
+
+    // Large enough for most tag lists, longer ones are read again without io_uring
+    const BUFFER_SIZE: usize = 4096;
+
+    // Lives as long as the process, so reads still in flight when a ring is dropped can't
+    // outlive it
+    static NAME: LazyLock<CString> =
+        LazyLock::new(|| CString::new(XATTR_NAME).expect("attribute name contains no NUL"));
+
+    pub(super) struct Ring {
+        ring: IoUring,
+    }
+
+    impl Ring {
+        pub(super) fn new() -> io::Result<Self> {
+            Ok(Ring {
+                ring: IoUring::new(BATCH_SIZE as u32)?,
+            })
+        }
+
+        // Returns raw attribute value per path. None means the read has to be repeated without
+        // io_uring. Err is returned if io_uring can't read attributes at all, the ring must not
+        // be used anymore then.
+        pub(super) fn get_all(
+            &mut self,
+            paths: &[PathBuf],
+        ) -> io::Result<Vec<Option<Result<Vec<u8>>>>> {
+            let mut results: Vec<Option<Result<Vec<u8>>>> = paths.iter().map(|_| None).collect();
+            let c_paths: Vec<Option<CString>> = paths
+                .iter()
+                .map(|path| CString::new(path.as_os_str().as_bytes()).ok())
+                .collect();
+            let mut buffers: Vec<Vec<u8>> = paths.iter().map(|_| vec![0; BUFFER_SIZE]).collect();
+
+            let mut pending = 0;
+            for (index, (c_path, buffer)) in c_paths.iter().zip(buffers.iter_mut()).enumerate() {
+                let Some(c_path) = c_path else {
+                    continue;
+                };
+                let entry = opcode::GetXattr::new(
+                    NAME.as_ptr(),
+                    buffer.as_mut_ptr().cast(),
+                    c_path.as_ptr(),
+                    BUFFER_SIZE as u32,
+                )
+                .build()
+                .user_data(index as u64);
+                // Safety: path and buffer are only dropped after the completions of all queued
+                // entries are reaped below, or leaked if that fails. The name is static.
+                if unsafe { self.ring.submission().push(&entry) }.is_err() {
+                    // Queue full, the remaining paths are read without io_uring
+                    break;
+                }
+                pending += 1;
+            }
+
+            let mut unsupported = None;
+            while pending > 0 {
+                match self.ring.submit_and_wait(1) {
+                    Ok(_) => (),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => {
+                        // Completions can't be awaited, the kernel may still write into the
+                        // buffers of the queued entries
+                        mem::forget(buffers);
+                        mem::forget(c_paths);
+                        return Err(err);
+                    }
+                }
+                for cqe in self.ring.completion() {
+                    pending -= 1;
+                    let index = cqe.user_data() as usize;
+                    let result = cqe.result();
+                    results[index] = match -result {
+                        libc::EINVAL | libc::ENOSYS | libc::EOPNOTSUPP if result < 0 => {
+                            unsupported = Some(-result);
+                            None
+                        }
+                        libc::ENODATA => Some(Ok(Vec::new())),
+                        libc::ERANGE => None,
+                        _ if result < 0 => {
+                            Some(Err(XTagError::File(io::Error::from_raw_os_error(-result))))
+                        }
+                        _ => {
+                            let mut value = mem::take(&mut buffers[index]);
+                            value.truncate(result as usize);
+                            Some(Ok(value))
+                        }
+                    };
+                }
+            }
+            match unsupported {
+                Some(errno) => Err(io::Error::from_raw_os_error(errno)),
+                None => Ok(results),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use super::{read_tags, read_tags_with_store};
+    use crate::{csl_to_map, Fixture, SidecarLayout, SidecarStore, Store};
+
+    #[test]
+    fn reader_keeps_order_and_reports_errors() {
+        let paths = vec![
+            PathBuf::from("does/not/exist/1"),
+            PathBuf::from("Cargo.toml"),
+            PathBuf::from("does/not/exist/2"),
+        ];
+        let results: Vec<_> = read_tags(paths.clone()).collect();
+        assert_eq!(results.len(), 3);
+        for ((path, result), expected) in results.iter().zip(&paths) {
+            assert_eq!(path, expected);
+            assert_eq!(result.is_ok(), path.exists());
+        }
+    }
+
+    #[test]
+    fn reader_reads_tags_set_before() {
+        // More files than one batch
+        let mut fixture = Fixture::new().file("untagged");
+        for index in 0..100 {
+            fixture = fixture
+                .file(&format!("{index:03}"))
+                .tags(&format!("n={index}"));
+        }
+        let mut paths: Vec<PathBuf> = (0..100)
+            .map(|index| fixture.join(format!("{index:03}")))
+            .collect();
+        paths.insert(70, fixture.join("untagged"));
+
+        let results: Vec<_> = read_tags(paths.clone()).collect();
+        assert_eq!(results.len(), paths.len());
+        for ((path, tags), expected) in results.into_iter().zip(paths) {
+            assert_eq!(path, expected);
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            let expected = match name.as_str() {
+                "untagged" => csl_to_map("").unwrap(),
+                index => csl_to_map(&format!("n={}", index.parse::<u32>().unwrap())).unwrap(),
+            };
+            assert_eq!(tags.unwrap(), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn reader_reads_tags_of_store() {
+        let fixture = Fixture::new().file("a").file("b");
+        let store = SidecarStore::new(SidecarLayout::PerFile);
+        store
+            .set_tags(&fixture.join("a"), &csl_to_map("photo").unwrap())
+            .unwrap();
+        let paths = vec![fixture.join("a"), fixture.join("b")];
+        let results: Vec<_> = read_tags_with_store(paths, Arc::new(store))
+            .map(|(_, tags)| tags.unwrap())
+            .collect();
+        assert_eq!(results, [csl_to_map("photo").unwrap(), csl_to_map("").unwrap()]);
+    }
+
+    #[cfg(all(target_os = "linux", feature = "uring"))]
+    #[test]
+    fn ring_reads_attributes() {
+        let Ok(mut ring) = super::uring::Ring::new() else {
+            // io_uring not available, e.g. forbidden by seccomp
+            return;
+        };
+        let fixture = Fixture::new().file("a").tags("photo,rating=5").file("b");
+        let paths = [fixture.join("a"), fixture.join("b"), fixture.join("c")];
+        let Ok(results) = ring.get_all(&paths) else {
+            // Kernel without xattr operations
+            return;
+        };
+        let values: Vec<_> = results
+            .into_iter()
+            .map(|result| result.unwrap().map(|value| crate::tags_from_bytes(&value).unwrap()))
+            .collect();
+        assert_eq!(values[0].as_ref().unwrap(), &csl_to_map("photo,rating=5").unwrap());
+        assert_eq!(values[1].as_ref().unwrap(), &csl_to_map("").unwrap());
+        assert!(values[2].is_err());
+    }
+}
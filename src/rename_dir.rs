@@ -0,0 +1,105 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::searcher::expand_regex;
+use crate::walk::TreeTags;
+use crate::{rename_with, walk, Result, WalkOptions, XTagError};
+
+/// Outcome of rename_in_dir.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenameReport {
+    /// Number of files looked at.
+    pub scanned: usize,
+
+    /// Number of files written.
+    pub files: usize,
+
+    /// Number of renamed tags over all files.
+    pub keys: usize,
+}
+
+/// Renames tags matching find in all files below root, see rename.
+///
+/// Only files with at least one renamed tag are written.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let options = xtag::WalkOptions::default();
+/// let report = xtag::rename_in_dir(Path::new("/photos"), "holiday", "vacation", &options).unwrap();
+/// println!("renamed {} tags in {} files", report.keys, report.files);
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Regex if find is not a valid regular expression
+/// - Same as walk, get_tags and set_tags, files without attribute support are handled by
+///   options
+pub fn rename_in_dir(
+    root: &Path,
+    find: &str,
+    replace: &str,
+    options: &WalkOptions,
+) -> Result<RenameReport> {
+    let re = Regex::new(&expand_regex(find)).map_err(XTagError::Regex)?;
+    let mut report = RenameReport::default();
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        let path = path?;
+        report.scanned += 1;
+        let Some(tags) = tree.get(&path)? else {
+            continue;
+        };
+        if let (Cow::Owned(renamed), keys) = rename_with(&re, replace, Cow::Borrowed(&tags)) {
+            if !tree.set(&path, &renamed)? {
+                continue;
+            }
+            report.files += 1;
+            report.keys += keys;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rename_in_dir, RenameReport};
+    use crate::{csl_to_map, get_tags, Fixture, WalkOptions};
+
+    #[test]
+    fn only_changed_files_are_written() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("holiday=2023,photo")
+            .file("sub/b")
+            .tags("holiday-rome,holiday")
+            .file("c")
+            .tags("photo");
+        let report = rename_in_dir(
+            fixture.path(),
+            "holiday(.*)",
+            "vacation$1",
+            &WalkOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            report,
+            RenameReport {
+                scanned: 3,
+                files: 2,
+                keys: 3
+            }
+        );
+        assert_eq!(
+            get_tags(&fixture.join("a")).unwrap(),
+            csl_to_map("vacation=2023,photo").unwrap()
+        );
+        assert_eq!(
+            get_tags(&fixture.join("sub/b")).unwrap(),
+            csl_to_map("vacation-rome,vacation").unwrap()
+        );
+    }
+}
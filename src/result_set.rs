@@ -0,0 +1,319 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::vec;
+
+use crate::{get_tags, group_by_tag, sort_by_tag, Collation, Order, Result, ResultGroup, XTags};
+
+/// Paths referring to the same file, like hard links or files seen through bind mounts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InodeGroup {
+    /// First path of the file in the set.
+    pub path: PathBuf,
+
+    /// Other paths of the same file, in order of the set.
+    pub aliases: Vec<PathBuf>,
+}
+
+// Identifies a file independent of its paths
+pub(crate) fn file_id(path: &Path) -> Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+// Key of paths in set operations, paths that can't be examined are compared as they are
+#[derive(PartialEq, Eq, Hash)]
+enum FileKey {
+    Inode(u64, u64),
+    Path(PathBuf),
+}
+
+impl FileKey {
+    fn new(path: &Path) -> Self {
+        match file_id(path) {
+            Ok((dev, ino)) => FileKey::Inode(dev, ino),
+            Err(_) => FileKey::Path(path.to_path_buf()),
+        }
+    }
+}
+
+/// Paths of files that matched a search, in the order they were found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct ResultSet {
+    paths: Vec<PathBuf>,
+}
+
+impl ResultSet {
+    /// Returns new empty ResultSet.
+    pub fn new() -> Self {
+        ResultSet { paths: Vec::new() }
+    }
+
+    /// Adds path to the end of the set.
+    pub fn push(&mut self, path: PathBuf) {
+        self.paths.push(path);
+    }
+
+    /// Number of paths in the set.
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Returns true if the set holds no paths.
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// Iterates over the paths in the set.
+    pub fn iter(&self) -> slice::Iter<'_, PathBuf> {
+        self.paths.iter()
+    }
+
+    /// Returns the paths as slice.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Groups paths referring to the same file, in order of their first path.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if a path can't be examined
+    pub fn group_by_inode(&self) -> Result<Vec<InodeGroup>> {
+        let mut groups: Vec<InodeGroup> = Vec::new();
+        let mut indices: HashMap<(u64, u64), usize> = HashMap::new();
+        for path in &self.paths {
+            let id = file_id(path)?;
+            match indices.get(&id) {
+                Some(&index) => groups[index].aliases.push(path.clone()),
+                None => {
+                    indices.insert(id, groups.len());
+                    groups.push(InodeGroup {
+                        path: path.clone(),
+                        aliases: Vec::new(),
+                    });
+                }
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Removes all but the first path of every file.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if a path can't be examined, the set is unchanged then
+    pub fn dedup_inodes(&mut self) -> Result<()> {
+        self.paths = self
+            .group_by_inode()?
+            .into_iter()
+            .map(|group| group.path)
+            .collect();
+        Ok(())
+    }
+
+    /// Sorts paths by the value of tag in their files, see sort_by_tag.
+    ///
+    /// Files without tag or value come last. Files with equal values keep their order.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// let photos = xtag::Index::load(std::path::Path::new("/photos")).unwrap();
+    /// let mut found = photos.query(&xtag::compile_search("person").unwrap());
+    /// found.sort_by_value("person", xtag::Collation::Folded).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - Same as get_tags, the set is unchanged then
+    pub fn sort_by_value(&mut self, tag: &str, collation: Collation) -> Result<()> {
+        self.paths = self
+            .sorted_results(tag, collation)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        Ok(())
+    }
+
+    /// Groups paths by the value of tag in their files, in order of values, see group_by_tag.
+    ///
+    /// Values that collation doesn't tell apart, like `Ärger` and `ärger` with Collation::Folded,
+    /// form one group. Files without tag or value form the last group.
+    ///
+    /// # Errors
+    ///
+    /// - Same as get_tags
+    pub fn group_by_value(&self, tag: &str, collation: Collation) -> Result<Vec<ResultGroup>> {
+        let results = self.sorted_results(tag, collation)?;
+        Ok(group_by_tag(results, tag, Order::Lexicographic(collation)))
+    }
+
+    // Paths with their tags, sorted by values of tag
+    fn sorted_results(&self, tag: &str, collation: Collation) -> Result<Vec<(PathBuf, XTags)>> {
+        let mut results = self
+            .paths
+            .iter()
+            .map(|path| Ok((path.clone(), get_tags(path)?)))
+            .collect::<Result<Vec<_>>>()?;
+        sort_by_tag(&mut results, tag, Order::Lexicographic(collation));
+        Ok(results)
+    }
+
+    /// Returns paths of self followed by paths of other whose files aren't in self.
+    ///
+    /// Files are compared by inode, so hard links and different spellings of a path are the same
+    /// file. Paths that can't be examined, like of deleted files, are compared as they are.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// let photos = xtag::Index::load(std::path::Path::new("/photos")).unwrap();
+    /// let rome = photos.query(&xtag::compile_search("city == Rome").unwrap());
+    /// let paris = photos.query(&xtag::compile_search("city == Paris").unwrap());
+    /// let trips = rome.union(&paris);
+    /// ```
+    pub fn union(&self, other: &ResultSet) -> ResultSet {
+        let keys = self.keys();
+        let mut paths = self.paths.clone();
+        paths.extend(
+            other
+                .paths
+                .iter()
+                .filter(|path| !keys.contains(&FileKey::new(path)))
+                .cloned(),
+        );
+        ResultSet { paths }
+    }
+
+    /// Returns paths of self whose files are in other too, see union.
+    pub fn intersection(&self, other: &ResultSet) -> ResultSet {
+        let keys = other.keys();
+        self.filter(|key| keys.contains(key))
+    }
+
+    /// Returns paths of self whose files aren't in other, see union.
+    pub fn difference(&self, other: &ResultSet) -> ResultSet {
+        let keys = other.keys();
+        self.filter(|key| !keys.contains(key))
+    }
+
+    fn keys(&self) -> HashSet<FileKey> {
+        self.paths.iter().map(|path| FileKey::new(path)).collect()
+    }
+
+    fn filter<F: Fn(&FileKey) -> bool>(&self, keep: F) -> ResultSet {
+        self.paths
+            .iter()
+            .filter(|path| keep(&FileKey::new(path)))
+            .cloned()
+            .collect()
+    }
+}
+
+impl FromIterator<PathBuf> for ResultSet {
+    fn from_iter<I: IntoIterator<Item = PathBuf>>(iter: I) -> Self {
+        ResultSet {
+            paths: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl IntoIterator for ResultSet {
+    type Item = PathBuf;
+    type IntoIter = vec::IntoIter<PathBuf>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.paths.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ResultSet {
+    type Item = &'a PathBuf;
+    type IntoIter = slice::Iter<'a, PathBuf>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.paths.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResultSet;
+    use crate::{Collation, Fixture};
+
+    #[test]
+    fn hard_links_are_grouped() {
+        let fixture = Fixture::new().file("a").file("b");
+        let dir = fixture.path();
+        std::fs::hard_link(dir.join("a"), dir.join("c")).unwrap();
+
+        let mut set: ResultSet = ["a", "b", "c"].iter().map(|name| dir.join(name)).collect();
+        let groups = set.group_by_inode().unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].aliases, vec![dir.join("c")]);
+        set.dedup_inodes().unwrap();
+        assert_eq!(set.paths(), &[dir.join("a"), dir.join("b")]);
+    }
+
+    #[test]
+    fn set_operations_compare_files() {
+        let fixture = Fixture::new().file("a").file("b").file("c");
+        let dir = fixture.path();
+        std::fs::hard_link(dir.join("a"), dir.join("a2")).unwrap();
+        let set =
+            |names: &[&str]| -> ResultSet { names.iter().map(|name| dir.join(name)).collect() };
+
+        let before = set(&["a", "b", "gone"]);
+        let after = set(&["b", "./c", "a2"]);
+        assert_eq!(before.union(&after), set(&["a", "b", "gone", "./c"]));
+        assert_eq!(before.intersection(&after), set(&["a", "b"]));
+        assert_eq!(before.difference(&after), set(&["gone"]));
+        assert_eq!(after.difference(&before), set(&["./c"]));
+    }
+
+    #[test]
+    fn values_are_sorted_and_grouped_by_collation() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("name=Zorn")
+            .file("b")
+            .tags("name=ärger")
+            .file("c")
+            .tags("other")
+            .file("d")
+            .tags("name=Apfel")
+            .file("e")
+            .tags("name=Ärger");
+        let set: ResultSet = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|name| fixture.join(name))
+            .collect();
+        let sorted = |collation| {
+            let mut set = set.clone();
+            set.sort_by_value("name", collation).unwrap();
+            set.iter()
+                .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sorted(Collation::Binary), ["d", "a", "e", "b", "c"]);
+        assert_eq!(sorted(Collation::Folded), ["d", "e", "b", "a", "c"]);
+        #[cfg(feature = "collation")]
+        assert_eq!(sorted(Collation::Unicode), ["d", "b", "e", "a", "c"]);
+
+        let groups = set.group_by_value("name", Collation::Folded).unwrap();
+        assert_eq!(groups.len(), 4);
+        assert_eq!(groups[1].value.as_deref(), Some("Ärger"));
+        assert_eq!(groups[1].paths(), [fixture.join("e"), fixture.join("b")]);
+        assert_eq!(groups[3].value, None);
+        assert_eq!(
+            set.group_by_value("name", Collation::Binary).unwrap().len(),
+            5
+        );
+    }
+}
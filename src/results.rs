@@ -0,0 +1,286 @@
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+use crate::collation::Comparer;
+use crate::comparand::parse_float;
+use crate::{Collation, Date, XTags};
+
+/// How sort_by_tag compares values of a tag.
+///
+/// Values that aren't of the compared type, like `good` when sorting numerically, come last in
+/// both directions, after them files without the tag or value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Order {
+    /// Values as strings ordered by collation.
+    Lexicographic(Collation),
+
+    /// Lexicographic, last first.
+    LexicographicDesc(Collation),
+
+    /// Values as numbers, integers and floats compare with each other.
+    Numeric,
+
+    /// Numeric, largest first.
+    NumericDesc,
+
+    /// Values as dates like `2023-06-01`, a time after the date is ignored.
+    Date,
+
+    /// Date, latest first.
+    DateDesc,
+}
+
+impl Default for Order {
+    fn default() -> Self {
+        Order::Lexicographic(Collation::Binary)
+    }
+}
+
+// Value as compared by an order, all keys of one order have the same variant
+enum Key<'a> {
+    Text(&'a str),
+    Number(f64),
+    Date(Date),
+}
+
+// Compares values of a tag by order, None for missing values. Without tiebreak values order
+// doesn't tell apart compare equal, see Collation::comparer.
+struct Comparison {
+    order: Order,
+    compare: Comparer,
+}
+
+impl Comparison {
+    fn new(order: Order, tiebreak: bool) -> Self {
+        let collation = match order {
+            Order::Lexicographic(collation) | Order::LexicographicDesc(collation) => collation,
+            _ => Collation::Binary,
+        };
+        Comparison {
+            order,
+            compare: collation.comparer(tiebreak),
+        }
+    }
+
+    fn key<'a>(&self, value: &'a str) -> Option<Key<'a>> {
+        match self.order {
+            Order::Lexicographic(_) | Order::LexicographicDesc(_) => Some(Key::Text(value)),
+            Order::Numeric | Order::NumericDesc => parse_float(value).map(Key::Number),
+            Order::Date | Order::DateDesc => value.parse().ok().map(Key::Date),
+        }
+    }
+
+    fn is_descending(&self) -> bool {
+        matches!(
+            self.order,
+            Order::LexicographicDesc(_) | Order::NumericDesc | Order::DateDesc
+        )
+    }
+
+    fn compare(&mut self, a: Option<&str>, b: Option<&str>) -> Ordering {
+        let (a_key, b_key) = (a.and_then(|a| self.key(a)), b.and_then(|b| self.key(b)));
+        let ordering = match (a_key, b_key) {
+            (Some(Key::Text(a)), Some(Key::Text(b))) => (self.compare)(a, b),
+            (Some(Key::Number(a)), Some(Key::Number(b))) => {
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            }
+            (Some(Key::Date(a)), Some(Key::Date(b))) => a.cmp(&b),
+            // Values of the compared type first, then other values, then missing values
+            (a_key, b_key) => {
+                let rank = |is_key: bool, value: Option<&str>| match value {
+                    _ if is_key => 0,
+                    Some(_) => 1,
+                    None => 2,
+                };
+                return rank(a_key.is_some(), a).cmp(&rank(b_key.is_some(), b));
+            }
+        };
+        match self.is_descending() {
+            true => ordering.reverse(),
+            false => ordering,
+        }
+    }
+
+    // Returns true if a and b belong in the same group, values not of the compared type only
+    // if they are the same
+    fn is_same(&mut self, a: Option<&str>, b: Option<&str>) -> bool {
+        match (a.and_then(|a| self.key(a)), b.and_then(|b| self.key(b))) {
+            (Some(_), Some(_)) => self.compare(a, b) == Ordering::Equal,
+            _ => a == b,
+        }
+    }
+}
+
+// Value of tag, None for files without tag or value
+fn value_of<'a>(tags: &'a XTags, tag: &str) -> Option<&'a str> {
+    tags.get(tag)?.as_deref()
+}
+
+/// Files with equal values of a tag, see group_by_tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResultGroup {
+    /// Value of the first file of the group, None for files without tag or value.
+    pub value: Option<String>,
+
+    /// Paths and tags of the group, in order of results.
+    pub results: Vec<(PathBuf, XTags)>,
+}
+
+impl ResultGroup {
+    /// Paths of the group, in order of results.
+    pub fn paths(&self) -> Vec<&Path> {
+        self.results
+            .iter()
+            .map(|(path, _)| path.as_path())
+            .collect()
+    }
+}
+
+/// Sorts search results by the value of tag.
+///
+/// Files with equal values keep their order, so sorting by several tags works by sorting by the
+/// least significant one first.
+///
+/// # Example
+///
+/// ```
+/// use std::path::PathBuf;
+/// use xtag::{csl_to_map, sort_by_tag, Order};
+///
+/// let mut results = vec![
+///     (PathBuf::from("a"), csl_to_map("rating=4").unwrap()),
+///     (PathBuf::from("b"), csl_to_map("photo").unwrap()),
+///     (PathBuf::from("c"), csl_to_map("rating=10").unwrap()),
+/// ];
+/// sort_by_tag(&mut results, "rating", Order::NumericDesc);
+/// let paths: Vec<_> = results.iter().map(|(path, _)| path.to_str().unwrap()).collect();
+/// assert_eq!(paths, ["c", "a", "b"]);
+/// ```
+pub fn sort_by_tag(results: &mut [(PathBuf, XTags)], tag: &str, order: Order) {
+    let mut comparison = Comparison::new(order, true);
+    results.sort_by(|(_, a), (_, b)| comparison.compare(value_of(a, tag), value_of(b, tag)));
+}
+
+/// Groups search results by the value of tag.
+///
+/// Values order doesn't tell apart form one group, like `Ärger` and `ärger` with
+/// Collation::Folded or `5` and `5.0` with Order::Numeric. Groups are in order of their first
+/// file, so results sorted with sort_by_tag give sorted groups. Files without tag or value form
+/// the last group.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let photos = xtag::Index::load(Path::new("photos")).unwrap();
+/// let mut results: Vec<_> = photos
+///     .iter()
+///     .map(|(path, tags)| (path.to_path_buf(), tags.clone()))
+///     .collect();
+/// xtag::sort_by_tag(&mut results, "year", xtag::Order::Numeric);
+/// for group in xtag::group_by_tag(results, "year", xtag::Order::Numeric) {
+///     println!("{:?}: {} files", group.value, group.results.len());
+/// }
+/// ```
+pub fn group_by_tag<I>(results: I, tag: &str, order: Order) -> Vec<ResultGroup>
+where
+    I: IntoIterator<Item = (PathBuf, XTags)>,
+{
+    let mut comparison = Comparison::new(order, false);
+    let mut groups: Vec<ResultGroup> = Vec::new();
+    for (path, tags) in results {
+        let value = value_of(&tags, tag);
+        // Sorted results find their group at the end
+        let index = groups
+            .iter()
+            .rposition(|group| comparison.is_same(group.value.as_deref(), value));
+        let index = index.unwrap_or_else(|| {
+            groups.push(ResultGroup {
+                value: value.map(str::to_string),
+                results: Vec::new(),
+            });
+            groups.len() - 1
+        });
+        groups[index].results.push((path, tags));
+    }
+    if let Some(index) = groups.iter().position(|group| group.value.is_none()) {
+        let missing = groups.remove(index);
+        groups.push(missing);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{group_by_tag, sort_by_tag, Order};
+    use crate::{csl_to_map, XTags};
+
+    fn results(tags: &[&str]) -> Vec<(PathBuf, XTags)> {
+        tags.iter()
+            .enumerate()
+            .map(|(index, tags)| (PathBuf::from(index.to_string()), csl_to_map(tags).unwrap()))
+            .collect()
+    }
+
+    fn sorted(tags: &[&str], tag: &str, order: Order) -> Vec<String> {
+        let mut results = results(tags);
+        sort_by_tag(&mut results, tag, order);
+        results
+            .into_iter()
+            .map(|(path, _)| path.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn results_are_sorted_by_strategy() {
+        let values = [
+            "v=10",
+            "v",
+            "v=9.5",
+            "v=abc",
+            "other",
+            "v=2023-01-31",
+            "v=9",
+        ];
+        assert_eq!(
+            sorted(&values, "v", Order::default()),
+            ["0", "5", "6", "2", "3", "1", "4"]
+        );
+        assert_eq!(
+            sorted(&values, "v", Order::Numeric),
+            ["6", "2", "0", "3", "5", "1", "4"]
+        );
+        assert_eq!(
+            sorted(&values, "v", Order::NumericDesc),
+            ["0", "2", "6", "3", "5", "1", "4"]
+        );
+        let dates = [
+            "d=2023-02-01",
+            "d=2022-12-24T18:00",
+            "d=soon",
+            "d=2023-02-01",
+        ];
+        assert_eq!(sorted(&dates, "d", Order::Date), ["1", "0", "3", "2"]);
+        assert_eq!(sorted(&dates, "d", Order::DateDesc), ["0", "3", "1", "2"]);
+    }
+
+    #[test]
+    fn results_are_grouped_in_order() {
+        let numbers = results(&["n=5", "n=x", "n=5.0", "n=x"]);
+        let mut results = results(&["year=2021", "photo", "year=2019", "year=2021,x"]);
+        sort_by_tag(&mut results, "year", Order::Numeric);
+        let groups = group_by_tag(results, "year", Order::Numeric);
+        let values: Vec<_> = groups.iter().map(|group| group.value.as_deref()).collect();
+        assert_eq!(values, [Some("2019"), Some("2021"), None]);
+        assert_eq!(groups[1].results.len(), 2);
+        assert_eq!(groups[1].results[1].0, PathBuf::from("3"));
+
+        let groups = group_by_tag(numbers, "n", Order::Numeric);
+        let sizes: Vec<_> = groups.iter().map(|group| group.results.len()).collect();
+        assert_eq!(sizes, [2, 2]);
+    }
+}
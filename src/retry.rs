@@ -0,0 +1,207 @@
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::{Result, Store, XTagError, XTags};
+
+// Input/output error, the same on all unix systems
+const EIO: i32 = 5;
+
+/// Store retrying transient errors of network file systems, accessing the wrapped store.
+///
+/// NFS and SMB mounts report short outages as stale file handles or input/output errors. Such
+/// errors are retried with a delay doubling from backoff up to max_backoff. Once all retries
+/// failed, XTagError::RetriesExhausted tells the path and the number of attempts.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// use xtag::{RetryingStore, Store, XattrStore};
+///
+/// let store = RetryingStore::new(XattrStore::default());
+/// let tags = store.get_tags(Path::new("/mnt/nas/a.jpg")).unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RetryingStore<S> {
+    /// How often an operation is tried again after a transient error.
+    pub retries: u32,
+
+    /// Wait before the first retry.
+    pub backoff: Duration,
+
+    /// Longest wait between two attempts.
+    pub max_backoff: Duration,
+
+    store: S,
+}
+
+impl<S: Store> RetryingStore<S> {
+    /// Returns store accessing store with 5 retries, waiting 200ms up to 5s in between.
+    pub fn new(store: S) -> Self {
+        RetryingStore {
+            retries: 5,
+            backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            store,
+        }
+    }
+
+    /// Returns the wrapped store.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// Runs operation on path, retrying transient errors.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::RetriesExhausted if the error persisted through all retries
+    /// - Errors that aren't transient are returned unchanged after the first attempt
+    pub fn retry<T, F>(&self, path: &Path, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Result<T>,
+    {
+        let mut attempts = 0;
+        let mut backoff = self.backoff;
+        loop {
+            attempts += 1;
+            match operation() {
+                Err(err) if is_transient(&err) && attempts <= self.retries => {
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                }
+                Err(err) if is_transient(&err) => {
+                    return Err(XTagError::RetriesExhausted {
+                        path: path.to_path_buf(),
+                        attempts,
+                        source: Box::new(err),
+                    });
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<S: Store> Store for RetryingStore<S> {
+    fn get_tags(&self, path: &Path) -> Result<XTags> {
+        self.retry(path, || self.store.get_tags(path))
+    }
+
+    fn set_tags(&self, path: &Path, tags: &XTags) -> Result<()> {
+        self.retry(path, || self.store.set_tags(path, tags))
+    }
+
+    fn delete_tags(&self, path: &Path) -> Result<()> {
+        self.retry(path, || self.store.delete_tags(path))
+    }
+}
+
+// Errors of network file systems that may go away by waiting
+fn is_transient(err: &XTagError) -> bool {
+    match err {
+        XTagError::File(err) => {
+            matches!(
+                err.kind(),
+                io::ErrorKind::StaleNetworkFileHandle
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::Interrupted
+            ) || err.raw_os_error() == Some(EIO)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::io;
+    use std::path::Path;
+    use std::time::Duration;
+
+    use super::RetryingStore;
+    use crate::{csl_to_map, Result, Store, XTagError, XTags, XattrStore};
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let store = RetryingStore {
+            retries: 2,
+            backoff: Duration::ZERO,
+            ..RetryingStore::new(XattrStore::default())
+        };
+        let path = Path::new("a");
+
+        let mut calls = 0;
+        let result = store.retry(path, || {
+            calls += 1;
+            match calls {
+                1 => Err(XTagError::File(io::Error::from_raw_os_error(5))),
+                _ => Ok(calls),
+            }
+        });
+        assert_eq!(result.unwrap(), 2);
+
+        let result: crate::Result<()> = store.retry(path, || {
+            Err(XTagError::File(
+                io::ErrorKind::StaleNetworkFileHandle.into(),
+            ))
+        });
+        match result {
+            Err(XTagError::RetriesExhausted { attempts, .. }) => assert_eq!(attempts, 3),
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let mut calls = 0;
+        let result: crate::Result<()> = store.retry(path, || {
+            calls += 1;
+            Err(XTagError::File(io::ErrorKind::NotFound.into()))
+        });
+        assert!(matches!(result, Err(XTagError::File(_))));
+        assert_eq!(calls, 1);
+    }
+
+    // Fails reading with input/output errors until failures are used up
+    #[derive(Default)]
+    struct FlakyStore {
+        failures: Cell<u32>,
+    }
+
+    impl Store for FlakyStore {
+        fn get_tags(&self, _path: &Path) -> Result<XTags> {
+            match self.failures.get() {
+                0 => csl_to_map("photo"),
+                n => {
+                    self.failures.set(n - 1);
+                    Err(XTagError::File(io::Error::from_raw_os_error(5)))
+                }
+            }
+        }
+
+        fn set_tags(&self, _path: &Path, _tags: &XTags) -> Result<()> {
+            Ok(())
+        }
+
+        fn delete_tags(&self, _path: &Path) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn wrapped_stores_are_retried() {
+        let store = RetryingStore {
+            retries: 2,
+            backoff: Duration::ZERO,
+            ..RetryingStore::new(FlakyStore::default())
+        };
+        store.store.failures.set(2);
+        assert_eq!(store.get_tags(Path::new("a")).unwrap(), csl_to_map("photo").unwrap());
+        store.store.failures.set(3);
+        assert!(matches!(
+            store.get_tags(Path::new("a")),
+            Err(XTagError::RetriesExhausted { attempts: 3, .. })
+        ));
+        assert_eq!(store.into_inner().failures.get(), 0);
+    }
+}
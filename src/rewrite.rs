@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use pest::iterators::Pair;
+use pest::Parser;
+use regex::Regex;
+
+use crate::bookmarks::{read_bookmark, write_bookmark};
+use crate::parser::{Rule, SearchParser};
+use crate::searcher::expand_regex;
+use crate::views::{read_views, write_views};
+use crate::{Result, SearchParseError, Searcher};
+
+/// Returns term with tags renamed by mapping from old to new tag.
+///
+/// Only tag terms naming exactly an old tag are renamed, regular expressions, values and
+/// bookmarks are left unchanged. Spacing and operator spelling of term are kept.
+///
+/// # Example
+///
+/// ```
+/// # use std::collections::HashMap;
+/// let mapping = HashMap::from([("holiday".to_string(), "vacation".to_string())]);
+/// let term = xtag::rewrite_term("holiday and year > 2020", &mapping).unwrap();
+/// assert_eq!(term, "vacation and year > 2020");
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Parser if term is no valid search
+pub fn rewrite_term(term: &str, mapping: &HashMap<String, String>) -> Result<String> {
+    let pair = SearchParser::parse(Rule::search, term)
+        .map_err(|err| SearchParseError::new(err, term))?
+        .next()
+        .unwrap();
+    let mut spans = Vec::new();
+    collect_tags(pair, &mut spans);
+
+    let mut result = String::with_capacity(term.len());
+    let mut end = 0;
+    for span in spans {
+        if let Some(new) = mapping.get(&term[span.clone()]) {
+            result.push_str(&term[end..span.start]);
+            result.push_str(new);
+            end = span.end;
+        }
+    }
+    result.push_str(&term[end..]);
+    Ok(result)
+}
+
+// Collects byte ranges of tags in term order, values contain tag rules too and are skipped
+fn collect_tags(pair: Pair<Rule>, spans: &mut Vec<Range<usize>>) {
+    match pair.as_rule() {
+        Rule::tag_with_regex => {
+            let span = pair.as_span();
+            spans.push(span.start()..span.end());
+        }
+        Rule::value_with_regex | Rule::bookmark => {}
+        _ => {
+            for inner in pair.into_inner() {
+                collect_tags(inner, spans);
+            }
+        }
+    }
+}
+
+/// Renames tags in all views of directory, returns number of changed views.
+///
+/// # Errors
+///
+/// - XTagError::Parser if a stored view is no valid search
+pub fn rewrite_views(dir: &Path, mapping: &HashMap<String, String>) -> Result<usize> {
+    let mut views = read_views(dir)?;
+    let mut changed = 0;
+    for term in views.values_mut() {
+        let new_term = rewrite_term(term, mapping)?;
+        if new_term != *term {
+            *term = new_term;
+            changed += 1;
+        }
+    }
+    if changed > 0 {
+        write_views(dir, &views)?;
+    }
+    Ok(changed)
+}
+
+/// Renames tags in bookmark, returns true if it changed.
+///
+/// Symbolic links are replaced by one with the new term, in bookmark files only the term is
+/// replaced.
+///
+/// # Errors
+///
+/// - XTagError::Bookmark if path is no valid bookmark
+/// - XTagError::Parser if the bookmark is no valid search
+pub fn rewrite_bookmark(path: &Path, mapping: &HashMap<String, String>) -> Result<bool> {
+    let term = read_bookmark(path)?;
+    let new_term = rewrite_term(&term, mapping)?;
+    if new_term == term {
+        return Ok(false);
+    }
+    write_bookmark(path, &new_term)?;
+    Ok(true)
+}
+
+impl Searcher {
+    /// Returns copy of Searcher with tags renamed by mapping from old to new tag.
+    ///
+    /// Like rewrite_term, only tag expressions naming exactly an old tag are renamed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// let mapping = HashMap::from([("holiday".to_string(), "vacation".to_string())]);
+    /// let search = xtag::compile_search("holiday == summer").unwrap();
+    /// let tags = xtag::csl_to_map("vacation=summer").unwrap();
+    /// assert!(search.rewrite_tags(&mapping).is_match(&tags));
+    /// ```
+    pub fn rewrite_tags(&self, mapping: &HashMap<String, String>) -> Searcher {
+        let regexes: HashMap<String, String> = mapping
+            .iter()
+            .map(|(old, new)| (expand_regex(old), expand_regex(&regex::escape(new))))
+            .collect();
+        self.map_tags(|regex| match regexes.get(regex.as_str()) {
+            Some(new) => Regex::new(new).expect("escaped tag is a valid regex"),
+            None => regex.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{rewrite_bookmark, rewrite_term, rewrite_views};
+    use crate::{get_bookmark, list_views, set_view, Fixture};
+
+    fn mapping() -> HashMap<String, String> {
+        HashMap::from([
+            ("a".to_string(), "x".to_string()),
+            ("b".to_string(), "y".to_string()),
+        ])
+    }
+
+    #[test]
+    fn only_exact_tags_are_rewritten() {
+        let term = "a and (b == a or a.* > 1) && !istrue(b) || {tests/a_or_b}";
+        assert_eq!(
+            rewrite_term(term, &mapping()).unwrap(),
+            "x and (y == a or a.* > 1) && !istrue(y) || {tests/a_or_b}"
+        );
+        assert!(rewrite_term("a b", &mapping()).is_err());
+    }
+
+    #[test]
+    fn views_and_bookmarks_are_rewritten() {
+        let fixture = Fixture::new();
+        set_view(fixture.path(), "first", "a or c").unwrap();
+        set_view(fixture.path(), "second", "c").unwrap();
+        assert_eq!(rewrite_views(fixture.path(), &mapping()).unwrap(), 1);
+        assert_eq!(list_views(fixture.path()).unwrap()[0].1, "x or c");
+
+        let bookmark = fixture.join("bookmark");
+        std::os::unix::fs::symlink("a and b", &bookmark).unwrap();
+        assert!(rewrite_bookmark(&bookmark, &mapping()).unwrap());
+        assert!(!rewrite_bookmark(&bookmark, &mapping()).unwrap());
+        let tags = crate::csl_to_map("x,y").unwrap();
+        assert!(get_bookmark(&bookmark).unwrap().is_match(&tags));
+
+        // Bookmarks are replaced at once, failures leave them as they were
+        let file = fixture.join("file.bookmark");
+        std::fs::write(&file, "# comment\nquery = \"a\"\n").unwrap();
+        std::fs::create_dir_all(fixture.join(".file.bookmark.tmp/blocked")).unwrap();
+        assert!(rewrite_bookmark(&file, &mapping()).is_err());
+        assert_eq!(crate::bookmarks::read_bookmark(&file).unwrap(), "a");
+        std::fs::remove_dir_all(fixture.join(".file.bookmark.tmp")).unwrap();
+        assert!(rewrite_bookmark(&file, &mapping()).unwrap());
+        let content = std::fs::read_to_string(&file).unwrap();
+        assert_eq!(content, "# comment\nquery = \"x\"\n");
+        let names: Vec<_> = std::fs::read_dir(fixture.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 2, "{names:?}");
+    }
+}
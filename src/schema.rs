@@ -0,0 +1,478 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::aliases::parse_toml_line;
+use crate::searcher::{expand_regex, is_one_of, FALSE_VALUES, TRUE_VALUES};
+use crate::store::xattr_store;
+use crate::{Comparand, Date, Result, Store, ValueType, XTagError, XTags};
+
+/// Rules for the values of one tag in a TagSchema.
+///
+/// Unset rules allow everything.
+#[derive(Debug, Clone, Default)]
+pub struct TagRule {
+    /// Type all values must have, Flag for tags without value.
+    pub value_type: Option<ValueType>,
+
+    /// Allowed values, any value if empty.
+    pub values: Vec<String>,
+
+    /// Regular expression values must match, from_toml anchors it like searches.
+    pub pattern: Option<Regex>,
+
+    /// Smallest allowed value, compared like in searches.
+    pub min: Option<Comparand>,
+
+    /// Largest allowed value, compared like in searches.
+    pub max: Option<Comparand>,
+}
+
+/// Broken rule of a TagSchema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// Tag name doesn't match the names pattern of the schema.
+    InvalidName { tag: String },
+
+    /// Required tag is missing.
+    Missing { tag: String },
+
+    /// Value doesn't have the type of the rule, None if the value is missing.
+    WrongType {
+        tag: String,
+        value: Option<String>,
+        expected: ValueType,
+    },
+
+    /// Value isn't one of the allowed values.
+    NotAllowed { tag: String, value: String },
+
+    /// Value doesn't match the pattern of the rule.
+    PatternMismatch { tag: String, value: String },
+
+    /// Value is below min or above max, or can't be compared with them.
+    OutOfRange { tag: String, value: String },
+}
+
+impl Violation {
+    /// Tag breaking the rule.
+    pub fn tag(&self) -> &str {
+        match self {
+            Violation::InvalidName { tag }
+            | Violation::Missing { tag }
+            | Violation::WrongType { tag, .. }
+            | Violation::NotAllowed { tag, .. }
+            | Violation::PatternMismatch { tag, .. }
+            | Violation::OutOfRange { tag, .. } => tag,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::InvalidName { tag } => write!(f, "invalid tag name {tag:?}"),
+            Violation::Missing { tag } => write!(f, "missing tag {tag:?}"),
+            Violation::WrongType {
+                tag,
+                value: None,
+                expected,
+            } => write!(f, "tag {tag:?} needs a value of type {expected:?}"),
+            Violation::WrongType {
+                tag,
+                value: Some(value),
+                expected,
+            } => write!(f, "value {value:?} of {tag:?} is no {expected:?}"),
+            Violation::NotAllowed { tag, value } => {
+                write!(f, "value {value:?} of {tag:?} isn't allowed")
+            }
+            Violation::PatternMismatch { tag, value } => {
+                write!(f, "value {value:?} of {tag:?} doesn't match the pattern")
+            }
+            Violation::OutOfRange { tag, value } => {
+                write!(f, "value {value:?} of {tag:?} is out of range")
+            }
+        }
+    }
+}
+
+/// Rules tags have to follow, like agreed on by a team.
+///
+/// Tags without rule are allowed as long as their name matches the names pattern.
+///
+/// # Example
+///
+/// ```
+/// use xtag::{TagSchema, Violation};
+///
+/// let toml = r#"
+/// names = "[a-z0-9_/]+"
+/// required = ["status"]
+///
+/// [rating]
+/// type = "integer"
+/// min = "0"
+/// max = "5"
+///
+/// [status]
+/// values = ["todo", "doing", "done"]
+/// "#;
+/// let schema = TagSchema::from_toml(toml).unwrap();
+/// let tags = xtag::csl_to_map("rating=7,status=done,Camera=x").unwrap();
+/// let violations = schema.validate(&tags);
+/// assert_eq!(violations.len(), 2);
+/// assert_eq!(violations[0], Violation::InvalidName { tag: "Camera".to_string() });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TagSchema {
+    names: Option<Regex>,
+    required: Vec<String>,
+    rules: BTreeMap<String, TagRule>,
+}
+
+fn invalid_schema(message: String) -> XTagError {
+    XTagError::InvalidSchema(message)
+}
+
+fn compile(pattern: &str) -> Result<Regex> {
+    Regex::new(&expand_regex(pattern)).map_err(XTagError::Regex)
+}
+
+fn parse_type(name: &str) -> Option<ValueType> {
+    match name {
+        "flag" => Some(ValueType::Flag),
+        "integer" => Some(ValueType::Integer),
+        "float" => Some(ValueType::Float),
+        "date" => Some(ValueType::Date),
+        "boolean" => Some(ValueType::Boolean),
+        "text" => Some(ValueType::Text),
+        _ => None,
+    }
+}
+
+fn has_type(value: Option<&str>, expected: ValueType) -> bool {
+    match (expected, value) {
+        (ValueType::Flag, value) => value.is_none(),
+        (_, None) => false,
+        (ValueType::Integer, Some(value)) => value.parse::<i64>().is_ok(),
+        (ValueType::Float, Some(value)) => value.parse::<f64>().is_ok_and(f64::is_finite),
+        (ValueType::Date, Some(value)) => value.parse::<Date>().is_ok(),
+        (ValueType::Boolean, Some(value)) => {
+            is_one_of(value, &TRUE_VALUES) || is_one_of(value, &FALSE_VALUES)
+        }
+        (ValueType::Text, Some(_)) => true,
+    }
+}
+
+impl TagRule {
+    // Violations of tag with value, empty if it follows the rule
+    fn check(&self, tag: &str, value: Option<&str>, violations: &mut Vec<Violation>) {
+        if let Some(expected) = self.value_type {
+            if !has_type(value, expected) {
+                violations.push(Violation::WrongType {
+                    tag: tag.to_string(),
+                    value: value.map(str::to_string),
+                    expected,
+                });
+                return;
+            }
+        }
+        let Some(value) = value else {
+            return;
+        };
+        let violation = |make: fn(String, String) -> Violation| make(tag.into(), value.into());
+        if !self.values.is_empty() && !self.values.iter().any(|allowed| allowed == value) {
+            violations.push(violation(|tag, value| Violation::NotAllowed { tag, value }));
+        }
+        if self.pattern.as_ref().is_some_and(|re| !re.is_match(value)) {
+            violations.push(violation(|tag, value| Violation::PatternMismatch {
+                tag,
+                value,
+            }));
+        }
+        // compare gives the order of value, None if it has another type
+        let within = |bound: &Option<Comparand>, outside: Ordering| {
+            bound.as_ref().is_none_or(|bound| {
+                bound
+                    .compare(value)
+                    .is_some_and(|ordering| ordering != outside)
+            })
+        };
+        if !within(&self.min, Ordering::Less) || !within(&self.max, Ordering::Greater) {
+            violations.push(violation(|tag, value| Violation::OutOfRange { tag, value }));
+        }
+    }
+}
+
+impl TagSchema {
+    /// Returns schema allowing everything.
+    pub fn new() -> Self {
+        TagSchema::default()
+    }
+
+    /// Requires all tag names to match pattern, a regular expression expanded with anchors.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if pattern is invalid
+    pub fn names(mut self, pattern: &str) -> Result<Self> {
+        self.names = Some(compile(pattern)?);
+        Ok(self)
+    }
+
+    /// Requires every file to have tag.
+    pub fn require(mut self, tag: &str) -> Self {
+        self.required.push(tag.to_string());
+        self
+    }
+
+    /// Sets rule for values of tag, replacing an earlier one.
+    pub fn rule(mut self, tag: &str, rule: TagRule) -> Self {
+        self.rules.insert(tag.to_string(), rule);
+        self
+    }
+
+    /// Parses schema from TOML.
+    ///
+    /// Top level keys are `names` with a pattern for all tag names and `required` with a list
+    /// of tags. Every other table is named after a tag and may have the keys `type` (flag,
+    /// integer, float, date, boolean or text), `values` with a list of allowed values,
+    /// `pattern`, `min` and `max`. Like QuerySet::from_toml, values are strings or lists of
+    /// strings on one line, so numbers are quoted.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::InvalidSchema if a line, key or value is invalid
+    /// - XTagError::Regex if a pattern is invalid
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        let mut schema = TagSchema::new();
+        let mut table: Option<String> = None;
+        for (index, line) in toml.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |what: &str| invalid_schema(format!("{what} in line {}", index + 1));
+            if let Some(header) = line.strip_prefix('[') {
+                let name = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| invalid("invalid table"))?
+                    .trim();
+                let name = match name.strip_prefix('"') {
+                    Some(quoted) => quoted
+                        .strip_suffix('"')
+                        .ok_or_else(|| invalid("invalid table"))?,
+                    None => name,
+                };
+                schema.rules.entry(name.to_string()).or_default();
+                table = Some(name.to_string());
+                continue;
+            }
+            let (key, values) = parse_toml_line(line).ok_or_else(|| invalid("invalid line"))?;
+            let single = || match values.as_slice() {
+                [value] => Ok(value.as_str()),
+                _ => Err(invalid("expected one string")),
+            };
+            let Some(tag) = &table else {
+                match key.as_str() {
+                    "names" => schema = schema.names(single()?)?,
+                    "required" => schema.required.extend(values),
+                    _ => return Err(invalid(&format!("unknown key {key:?}"))),
+                }
+                continue;
+            };
+            let rule = schema.rules.entry(tag.clone()).or_default();
+            match key.as_str() {
+                "type" => {
+                    let name = single()?;
+                    let value_type = parse_type(name)
+                        .ok_or_else(|| invalid(&format!("unknown type {name:?}")))?;
+                    rule.value_type = Some(value_type);
+                }
+                "values" => rule.values = values,
+                "pattern" => rule.pattern = Some(compile(single()?)?),
+                "min" | "max" => {
+                    let bound = single()?
+                        .parse()
+                        .map_err(|_| invalid(&format!("{key} is no number or date")))?;
+                    match key.as_str() {
+                        "min" => rule.min = Some(bound),
+                        _ => rule.max = Some(bound),
+                    }
+                }
+                _ => return Err(invalid(&format!("unknown key {key:?}"))),
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Reads schema from TOML file, see from_toml.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::File if the file can't be read
+    /// - Same as from_toml
+    pub fn load(path: &Path) -> Result<Self> {
+        TagSchema::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// Checks tags against all rules, returns violations sorted by tag, empty if tags are valid.
+    pub fn validate(&self, tags: &XTags) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for tag in &self.required {
+            if !tags.contains_key(tag) {
+                violations.push(Violation::Missing { tag: tag.clone() });
+            }
+        }
+        for (tag, value) in tags {
+            if self
+                .names
+                .as_ref()
+                .is_some_and(|names| !names.is_match(tag))
+            {
+                violations.push(Violation::InvalidName { tag: tag.clone() });
+            }
+            if let Some(rule) = self.rules.get(tag) {
+                rule.check(tag, value.as_deref(), &mut violations);
+            }
+        }
+        violations.sort_by(|a, b| a.tag().cmp(b.tag()));
+        violations
+    }
+}
+
+/// Set tags for file after checking them against schema.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let schema = xtag::TagSchema::load(Path::new("schema.toml")).unwrap();
+/// let tags = xtag::csl_to_map("rating=3,status=todo").unwrap();
+/// xtag::set_tags_checked(Path::new("a.jpg"), &tags, &schema).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::SchemaViolation with all violations, the file is unchanged then
+/// - Same as set_tags
+pub fn set_tags_checked(path: &Path, tags: &XTags, schema: &TagSchema) -> Result<()> {
+    set_tags_checked_with_store(path, tags, schema, xattr_store())
+}
+
+/// Set tags for file in store after checking them against schema, see set_tags_checked.
+pub fn set_tags_checked_with_store(
+    path: &Path,
+    tags: &XTags,
+    schema: &TagSchema,
+    store: &dyn Store,
+) -> Result<()> {
+    let violations = schema.validate(tags);
+    if !violations.is_empty() {
+        return Err(XTagError::SchemaViolation(violations));
+    }
+    store.set_tags(path, tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{set_tags_checked, TagRule, TagSchema, Violation};
+    use crate::{csl_to_map, get_tags, Fixture, ValueType, XTagError};
+
+    #[test]
+    fn tags_are_checked_against_rules() {
+        let schema = TagSchema::from_toml(
+            r#"
+            names = "[a-z0-9_/]+"
+            required = ["status", "title"]
+
+            [rating]
+            type = "integer"
+            min = "0"
+            max = "5"
+
+            [status]
+            values = ["todo", "doing", "done"] # workflow
+
+            ["shot"]
+            type = "date"
+
+            [draft]
+            type = "flag"
+            "#,
+        )
+        .unwrap();
+        assert!(schema
+            .validate(&csl_to_map("rating=5,status=done,title=a,draft,shot=2024-01-31").unwrap())
+            .is_empty());
+
+        let tags = csl_to_map("rating=x,status=late,Bad,draft=1,shot").unwrap();
+        let violation = |tag: &str, value: Option<&str>, expected| Violation::WrongType {
+            tag: tag.to_string(),
+            value: value.map(str::to_string),
+            expected,
+        };
+        assert_eq!(
+            schema.validate(&tags),
+            [
+                Violation::InvalidName {
+                    tag: "Bad".to_string()
+                },
+                violation("draft", Some("1"), ValueType::Flag),
+                violation("rating", Some("x"), ValueType::Integer),
+                violation("shot", None, ValueType::Date),
+                Violation::NotAllowed {
+                    tag: "status".to_string(),
+                    value: "late".to_string()
+                },
+                Violation::Missing {
+                    tag: "title".to_string()
+                },
+            ]
+        );
+        let tags = csl_to_map("rating=6,status=todo,title=a").unwrap();
+        assert_eq!(
+            schema.validate(&tags)[0].to_string(),
+            "value \"6\" of \"rating\" is out of range"
+        );
+
+        for toml in [
+            "[a]\ntype = \"number\"",
+            "[a]\nmin = \"x\"",
+            "other = \"x\"",
+            "[a",
+        ] {
+            let result = TagSchema::from_toml(toml);
+            assert!(matches!(result, Err(XTagError::InvalidSchema(_))), "{toml}");
+        }
+    }
+
+    #[test]
+    fn only_valid_tags_are_set() {
+        let fixture = Fixture::new().file("a").tags("old");
+        let rule = TagRule {
+            pattern: Some(regex::Regex::new("^[a-z0-9-]+$").unwrap()),
+            ..Default::default()
+        };
+        let schema = TagSchema::new().rule("camera", rule);
+        let result = set_tags_checked(
+            &fixture.join("a"),
+            &csl_to_map("camera=Canon 5D").unwrap(),
+            &schema,
+        );
+        assert!(
+            matches!(result, Err(XTagError::SchemaViolation(violations)) if violations.len() == 1)
+        );
+        assert_eq!(
+            get_tags(&fixture.join("a")).unwrap(),
+            csl_to_map("old").unwrap()
+        );
+        let tags = csl_to_map("camera=canon-5d").unwrap();
+        set_tags_checked(&fixture.join("a"), &tags, &schema).unwrap();
+        assert_eq!(get_tags(&fixture.join("a")).unwrap(), tags);
+    }
+}
@@ -0,0 +1,107 @@
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::{walk, Result, WalkOptions, XATTR_NAME};
+
+/// Writes tags of all files below root as shell script of `setfattr` calls.
+///
+/// Default tags and all tag groups are exported unchanged. Paths are relative to root, so the
+/// script restores the tags when run from root or from a copy of it. Returns number of files with
+/// tags.
+///
+/// # Errors
+///
+/// Stops at the first file whose attributes can't be read, unless options allow skipping it.
+pub fn export_setfattr_script<W: Write>(
+    root: &Path,
+    options: &WalkOptions,
+    mut writer: W,
+) -> Result<usize> {
+    writeln!(writer, "#!/bin/sh")?;
+    writeln!(
+        writer,
+        "# Restores xtag tags, run from the exported directory"
+    )?;
+    writeln!(writer, "set -e")?;
+
+    let group_prefix = format!("{XATTR_NAME}.");
+    let mut files = 0;
+    for path in walk(root, options) {
+        let path = path?;
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let Some(names) = options.unsupported.handle(list_attributes(&path))? else {
+            continue;
+        };
+        let mut names: Vec<String> = names
+            .into_iter()
+            .filter_map(|name| name.into_string().ok())
+            .filter(|name| name == XATTR_NAME || name.starts_with(&group_prefix))
+            .collect();
+        if names.is_empty() {
+            continue;
+        }
+        names.sort();
+        for name in names {
+            if let Some(value) = xattr::get(&path, &name)? {
+                let mut line = b"setfattr -n ".to_vec();
+                line.extend(shell_quote(name.as_bytes()));
+                line.extend(b" -v ");
+                line.extend(shell_quote(&setfattr_value(&value)));
+                line.extend(b" -- ");
+                line.extend(shell_quote(relative.as_os_str().as_bytes()));
+                line.push(b'\n');
+                writer.write_all(&line)?;
+            }
+        }
+        files += 1;
+    }
+    writer.flush()?;
+    Ok(files)
+}
+
+fn list_attributes(path: &Path) -> Result<Vec<std::ffi::OsString>> {
+    Ok(xattr::list(path)?.collect())
+}
+
+// setfattr interprets values starting with 0x, 0s or " as encoded
+fn setfattr_value(value: &[u8]) -> Vec<u8> {
+    let is_encoded = value.first() == Some(&b'"')
+        || (value.len() >= 2 && value[0] == b'0' && b"xXsS".contains(&value[1]));
+    if value.is_empty() || is_encoded || value.contains(&0) {
+        let mut hex = b"0x".to_vec();
+        for byte in value {
+            hex.extend(format!("{byte:02x}").bytes());
+        }
+        hex
+    } else {
+        value.to_vec()
+    }
+}
+
+// Single quotes everything, embedded single quotes become '\''
+fn shell_quote(value: &[u8]) -> Vec<u8> {
+    let mut quoted = vec![b'\''];
+    for byte in value {
+        match byte {
+            b'\'' => quoted.extend(br"'\''"),
+            byte => quoted.push(*byte),
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{setfattr_value, shell_quote};
+
+    #[test]
+    fn values_are_quoted_for_shell_and_setfattr() {
+        assert_eq!(shell_quote(b"it's"), br"'it'\''s'");
+        assert_eq!(setfattr_value(b"a,b=1"), b"a,b=1");
+        assert_eq!(setfattr_value(b"0x1"), b"0x307831");
+        assert_eq!(setfattr_value(b"\"a"), b"0x2261");
+        assert_eq!(setfattr_value(b""), b"0x");
+    }
+}
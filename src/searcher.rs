@@ -1,11 +1,22 @@
 use std::fmt;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
 
 use regex::Regex;
 
+use crate::captures::{equal_searcher, is_match_captures, ValueTemplate};
 use crate::error::{Result, XTagError};
-use crate::XTags;
+use crate::fold::fold_tags;
+use crate::hierarchy::is_under;
+use crate::weight::split_weight;
+use crate::{
+    with_tags_ref, AliasMap, Comparand, Date, MultiXTags, SearcherVisitor, TagCache, XTags,
+};
 
 /// Searcher variants.
+///
+/// Searcher is Send and Sync, so one compiled search can be shared by reference between worker
+/// threads without cloning or parsing it again.
 pub enum Searcher {
     /// Logical and.
     And {
@@ -31,19 +42,103 @@ pub enum Searcher {
         value_regex: Regex,
     },
 
-    /// Matches if integer value is less than value.    
-    Less { tag_regex: Regex, value: i32 },
+    /// Matches value against value_template with references like `$n` to groups of tag_regex
+    /// replaced by the text they captured from the tag.
+    EqualCaptures {
+        tag_regex: Regex,
+        value_template: ValueTemplate,
+    },
+
+    /// Matches value of any tag.
+    AnyValue { value_regex: Regex },
+
+    /// Matches if value is less than value.
+    Less { tag_regex: Regex, value: Comparand },
+
+    /// Matches if value is less or equal than rhs.
+    LessEqual { tag_regex: Regex, value: Comparand },
+
+    /// Matches if value is greater than rhs.
+    Greater { tag_regex: Regex, value: Comparand },
+
+    /// Matches if value is greater or equal than rhs.
+    GreaterEqual { tag_regex: Regex, value: Comparand },
+
+    /// Matches if value is one of the true spellings.
+    IsTrue { tag_regex: Regex },
+
+    /// Matches if value is one of the false spellings.
+    IsFalse { tag_regex: Regex },
+
+    /// Evaluates lhs against tags without diacritics and in lowercase.
+    Folded { lhs: Box<Searcher> },
+
+    /// Matches tag or one of its ancestors, levels divided by separator.
+    Descendant { regex: Regex, separator: char },
+
+    /// Evaluates lhs against tags with aliases replaced by their canonical tags.
+    Canonical {
+        lhs: Box<Searcher>,
+        aliases: AliasMap,
+    },
+
+    /// Matches if a matching tag has a value.
+    HasValue { tag_regex: Regex },
+
+    /// Matches if a matching tag has no value.
+    ValuelessTag { tag_regex: Regex },
+
+    /// Matches if the number of tags is at least min and at most max.
+    TagCount { min: usize, max: Option<usize> },
 
-    /// Matches if integer value is less or equal than rhs.
-    LessEqual { tag_regex: Regex, value: i32 },
+    /// Evaluates lhs compiled from bookmark reference name, kept to trace expansions.
+    Bookmark { name: String, lhs: Box<Searcher> },
+}
+
+// Sharing Searcher between threads is part of the API, new variants must not break it
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Searcher>();
+};
+
+/// Tags as seen by Searcher::is_match, implemented for XTags, MultiXTags and XTagsRef.
+pub trait TagPairs: Sized {
+    /// Tag and value pairs, tags with several values occur once per value.
+    fn pairs(&self) -> impl Iterator<Item = (&str, Option<&str>)>;
+
+    /// Number of distinct tags.
+    fn tag_count(&self) -> usize;
+
+    /// Tags and values folded with fold.
+    fn folded(&self) -> Self;
+
+    /// Tags with aliases replaced by their canonical tags, see AliasMap::canonicalize.
+    fn canonicalized(&self, aliases: &AliasMap) -> Self;
+}
+
+impl TagPairs for XTags {
+    fn pairs(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.iter()
+            .map(|(tag, value)| (tag.as_str(), value.as_deref()))
+    }
 
-    /// Matches if integer value is greater than rhs.
-    Greater { tag_regex: Regex, value: i32 },
+    fn tag_count(&self) -> usize {
+        self.len()
+    }
+
+    fn folded(&self) -> Self {
+        fold_tags(self)
+    }
 
-    /// Matches if integer value is greater or equal than rhs.
-    GreaterEqual { tag_regex: Regex, value: i32 },
+    fn canonicalized(&self, aliases: &AliasMap) -> Self {
+        aliases.canonicalize(self)
+    }
 }
 
+// Accepted spellings of boolean values, compared case-insensitive
+pub(crate) static TRUE_VALUES: [&str; 4] = ["1", "true", "yes", "on"];
+pub(crate) static FALSE_VALUES: [&str; 4] = ["0", "false", "no", "off"];
+
 impl Searcher {
     /// Returns new and Searcher.
     ///
@@ -132,7 +227,7 @@ impl Searcher {
     ///
     /// - XTagError::Regex if the regex argument is not a valid regular expression
     pub fn new_tag(regex: &str) -> Result<Self> {
-        let regex = Regex::new(&expand_regex(regex)).map_err(|err| XTagError::Regex(err))?;
+        let regex = Regex::new(&expand_regex(regex)).map_err(XTagError::Regex)?;
         Ok(Searcher::Tag { regex })
     }
 
@@ -155,18 +250,39 @@ impl Searcher {
     /// assert!(search.is_match(&tags) == true);
     /// ```
     ///
+    /// If value_regex references capture groups of tag_regex with `$name`, `${name}` or `$1`,
+    /// but not the whole match `$0`, an EqualCaptures Searcher is returned. It compares the value of each matching tag with
+    /// the text captured from that tag, so `(?P<n>disc\d) == $n` matches `disc1=disc1`.
+    ///
     /// # Errors
     ///
     /// - XTagError::Regex if tag_regex or value_regex are not a valid regular expression
     pub fn new_equal(tag_regex: &str, value_regex: &str) -> Result<Self> {
-        let tag_regex =
-            Regex::new(&expand_regex(tag_regex)).map_err(|err| XTagError::Regex(err))?;
-        let value_regex =
-            Regex::new(&expand_regex(value_regex)).map_err(|err| XTagError::Regex(err))?;
-        Ok(Searcher::Equal {
-            tag_regex,
-            value_regex,
-        })
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        equal_searcher(tag_regex, &expand_regex(value_regex))
+    }
+
+    /// Returns new Searcher matching the value of any tag.
+    ///
+    /// Like new_equal with tag_regex `.*`, but tags aren't matched against a regular expression
+    /// at all. value_regex is expanded with anchors to match the whole value. Written as
+    /// `== value`, `* == value` or `:= value` in search terms.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use xtag::Searcher;
+    /// let tags = xtag::csl_to_map("type=invoice,paid").unwrap();
+    /// let search = Searcher::new_any_value("invoice").unwrap();
+    /// assert!(search.is_match(&tags));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if value_regex is not a valid regular expression
+    pub fn new_any_value(value_regex: &str) -> Result<Self> {
+        let value_regex = Regex::new(&expand_regex(value_regex)).map_err(XTagError::Regex)?;
+        Ok(Searcher::AnyValue { value_regex })
     }
 
     /// Returns Searcher for inequality
@@ -183,10 +299,10 @@ impl Searcher {
 
     /// Returns new less Searcher.
     ///
-    /// tag_regex specifies which tags are checked and rhs is matched against the integer
-    /// representation of the associated values. Matches when one value of one matching tag matches.
-    /// tag_regex is expanded with anchors to match the whole tag. If the value cannot be converted
-    /// to integer that's no match.
+    /// tag_regex specifies which tags are checked and rhs is matched against the associated values.
+    /// rhs is parsed as integer, float or ISO-8601 date in this order and values are compared as
+    /// this type. Matches when one value of one matching tag matches. tag_regex is expanded with
+    /// anchors to match the whole tag. If the value cannot be converted that's no match.
     ///
     /// # Example
     ///
@@ -204,22 +320,19 @@ impl Searcher {
     /// # Errors
     ///
     /// - XTagError::Regex if tag_regex is not a valid regular expression
-    /// - XtagError::IntParse if rhs can't be parsed into an integer
+    /// - XTagError::ComparisonValue if rhs is neither integer, float nor date
     pub fn new_less(tag_regex: &str, value: &str) -> Result<Self> {
-        let tag_regex =
-            Regex::new(&expand_regex(tag_regex)).map_err(|err| XTagError::Regex(err))?;
-        let value = value
-            .parse::<i32>()
-            .map_err(|err| XTagError::IntParse(err))?;
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        let value = value.parse::<Comparand>()?;
         Ok(Searcher::Less { tag_regex, value })
     }
 
     /// Returns new less or equal Searcher.
     ///
-    /// tag_regex specifies which tags are checked and rhs is matched against the integer
-    /// representation of the associated values. Matches when one value of one matching tag matches.
-    /// tag_regex is expanded with anchors to match the whole tag. If the value cannot be converted
-    /// to integer that's no match.
+    /// tag_regex specifies which tags are checked and rhs is matched against the associated values.
+    /// rhs is parsed as integer, float or ISO-8601 date in this order and values are compared as
+    /// this type. Matches when one value of one matching tag matches. tag_regex is expanded with
+    /// anchors to match the whole tag. If the value cannot be converted that's no match.
     ///
     /// # Example
     ///
@@ -234,20 +347,17 @@ impl Searcher {
     /// assert!(search.is_match(&tags) == true);
     /// ```
     pub fn new_less_equal(tag_regex: &str, value: &str) -> Result<Self> {
-        let tag_regex =
-            Regex::new(&expand_regex(tag_regex)).map_err(|err| XTagError::Regex(err))?;
-        let value = value
-            .parse::<i32>()
-            .map_err(|err| XTagError::IntParse(err))?;
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        let value = value.parse::<Comparand>()?;
         Ok(Searcher::LessEqual { tag_regex, value })
     }
 
     /// Returns new greater Searcher.
     ///
-    /// tag_regex specifies which tags are checked and rhs is matched against the integer
-    /// representation of the associated values. Matches when one value of one matching tag matches.
-    /// tag_regex is expanded with anchors to match the whole tag. If the value cannot be converted
-    /// to integer that's no match.
+    /// tag_regex specifies which tags are checked and rhs is matched against the associated values.
+    /// rhs is parsed as integer, float or ISO-8601 date in this order and values are compared as
+    /// this type. Matches when one value of one matching tag matches. tag_regex is expanded with
+    /// anchors to match the whole tag. If the value cannot be converted that's no match.
     ///
     /// # Example
     ///
@@ -262,20 +372,17 @@ impl Searcher {
     /// assert!(search.is_match(&tags) == true);
     /// ```
     pub fn new_greater(tag_regex: &str, value: &str) -> Result<Self> {
-        let tag_regex =
-            Regex::new(&expand_regex(tag_regex)).map_err(|err| XTagError::Regex(err))?;
-        let value = value
-            .parse::<i32>()
-            .map_err(|err| XTagError::IntParse(err))?;
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        let value = value.parse::<Comparand>()?;
         Ok(Searcher::Greater { tag_regex, value })
     }
 
     /// Returns new greater or equal Searcher.
     ///
-    /// tag_regex specifies which tags are checked and rhs is matched against the integer
-    /// representation of the associated values. Matches when one value of one matching tag matches.
-    /// tag_regex is expanded with anchors to match the whole tag. If the value cannot be converted
-    /// to integer that's no match.
+    /// tag_regex specifies which tags are checked and rhs is matched against the associated values.
+    /// rhs is parsed as integer, float or ISO-8601 date in this order and values are compared as
+    /// this type. Matches when one value of one matching tag matches. tag_regex is expanded with
+    /// anchors to match the whole tag. If the value cannot be converted that's no match.
     ///
     /// # Example
     ///
@@ -290,142 +397,631 @@ impl Searcher {
     /// assert!(search.is_match(&tags) == true);
     /// ```
     pub fn new_greater_equal(tag_regex: &str, value: &str) -> Result<Self> {
-        let tag_regex =
-            Regex::new(&expand_regex(tag_regex)).map_err(|err| XTagError::Regex(err))?;
-        let value = value
-            .parse::<i32>()
-            .map_err(|err| XTagError::IntParse(err))?;
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        let value = value.parse::<Comparand>()?;
         Ok(Searcher::GreaterEqual { tag_regex, value })
     }
 
+    /// Returns new less Searcher comparing values as floats.
+    ///
+    /// Like new_less, but rhs is always a float.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use xtag::Searcher;
+    /// let tags = xtag::csl_to_map("rating=4.5").unwrap();
+    /// assert!(Searcher::new_less_f64("rating", 4.75).unwrap().is_match(&tags));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if tag_regex is not a valid regular expression
+    /// - XTagError::ComparisonValue if value is NaN or infinite
+    pub fn new_less_f64(tag_regex: &str, value: f64) -> Result<Self> {
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        let value = Comparand::float(value)?;
+        Ok(Searcher::Less { tag_regex, value })
+    }
+
+    /// Returns new less or equal Searcher comparing values as floats.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if tag_regex is not a valid regular expression
+    /// - XTagError::ComparisonValue if value is NaN or infinite
+    pub fn new_less_equal_f64(tag_regex: &str, value: f64) -> Result<Self> {
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        let value = Comparand::float(value)?;
+        Ok(Searcher::LessEqual { tag_regex, value })
+    }
+
+    /// Returns new greater Searcher comparing values as floats.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if tag_regex is not a valid regular expression
+    /// - XTagError::ComparisonValue if value is NaN or infinite
+    pub fn new_greater_f64(tag_regex: &str, value: f64) -> Result<Self> {
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        let value = Comparand::float(value)?;
+        Ok(Searcher::Greater { tag_regex, value })
+    }
+
+    /// Returns new greater or equal Searcher comparing values as floats.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if tag_regex is not a valid regular expression
+    /// - XTagError::ComparisonValue if value is NaN or infinite
+    pub fn new_greater_equal_f64(tag_regex: &str, value: f64) -> Result<Self> {
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        let value = Comparand::float(value)?;
+        Ok(Searcher::GreaterEqual { tag_regex, value })
+    }
+
+    /// Returns new Searcher matching dates before date.
+    ///
+    /// Values are parsed as ISO-8601 dates, times following the date are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use xtag::{Date, Searcher};
+    /// let tags = xtag::csl_to_map("shot=2023-05-20").unwrap();
+    /// let date = Date::new(2023, 6, 1).unwrap();
+    /// assert!(Searcher::new_before_date("shot", date).unwrap().is_match(&tags));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if tag_regex is not a valid regular expression
+    pub fn new_before_date(tag_regex: &str, date: Date) -> Result<Self> {
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        let value = Comparand::Date(date);
+        Ok(Searcher::Less { tag_regex, value })
+    }
+
+    /// Returns new Searcher matching dates on or before date.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if tag_regex is not a valid regular expression
+    pub fn new_on_or_before_date(tag_regex: &str, date: Date) -> Result<Self> {
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        let value = Comparand::Date(date);
+        Ok(Searcher::LessEqual { tag_regex, value })
+    }
+
+    /// Returns new Searcher matching dates after date.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if tag_regex is not a valid regular expression
+    pub fn new_after_date(tag_regex: &str, date: Date) -> Result<Self> {
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        let value = Comparand::Date(date);
+        Ok(Searcher::Greater { tag_regex, value })
+    }
+
+    /// Returns new Searcher matching dates on or after date.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if tag_regex is not a valid regular expression
+    pub fn new_on_or_after_date(tag_regex: &str, date: Date) -> Result<Self> {
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        let value = Comparand::Date(date);
+        Ok(Searcher::GreaterEqual { tag_regex, value })
+    }
+
+    /// Returns new is true Searcher.
+    ///
+    /// tag_regex specifies which tags are checked. Matches when one value of one matching tag is
+    /// `1`, `true`, `yes` or `on`, ignoring case. tag_regex is expanded with anchors to match the
+    /// whole tag.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use xtag::Searcher;
+    /// # use xtag::XTags;
+    /// let mut tags: XTags = HashMap::new();
+    /// tags.insert("foo".to_string(), Some("Yes".to_string()));
+    /// let search = Searcher::new_is_true("foo").unwrap();
+    /// assert!(search.is_match(&tags) == true);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if tag_regex is not a valid regular expression
+    pub fn new_is_true(tag_regex: &str) -> Result<Self> {
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        Ok(Searcher::IsTrue { tag_regex })
+    }
+
+    /// Returns new is false Searcher.
+    ///
+    /// tag_regex specifies which tags are checked. Matches when one value of one matching tag is
+    /// `0`, `false`, `no` or `off`, ignoring case. tag_regex is expanded with anchors to match the
+    /// whole tag.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use xtag::Searcher;
+    /// # use xtag::XTags;
+    /// let mut tags: XTags = HashMap::new();
+    /// tags.insert("foo".to_string(), Some("off".to_string()));
+    /// let search = Searcher::new_is_false("foo").unwrap();
+    /// assert!(search.is_match(&tags) == true);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if tag_regex is not a valid regular expression
+    pub fn new_is_false(tag_regex: &str) -> Result<Self> {
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        Ok(Searcher::IsFalse { tag_regex })
+    }
+
+    /// Returns new Searcher matching tags with a value.
+    ///
+    /// tag_regex is expanded with anchors to match the whole tag. Written as `tag?` in search
+    /// terms, so regular expressions ending in `?` have to be written as alternation, like
+    /// `tag(s|)` instead of `tags?`, to match tags.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use xtag::Searcher;
+    /// let tags = xtag::csl_to_map("rating=4,draft").unwrap();
+    /// assert!(Searcher::new_has_value("rating").unwrap().is_match(&tags));
+    /// assert!(!Searcher::new_has_value("draft").unwrap().is_match(&tags));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if tag_regex is not a valid regular expression
+    pub fn new_has_value(tag_regex: &str) -> Result<Self> {
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        Ok(Searcher::HasValue { tag_regex })
+    }
+
+    /// Returns new Searcher matching tags without a value.
+    ///
+    /// tag_regex is expanded with anchors to match the whole tag. Written as `tag!` in search
+    /// terms.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use xtag::Searcher;
+    /// let tags = xtag::csl_to_map("rating=4,draft").unwrap();
+    /// assert!(Searcher::new_valueless_tag("draft").unwrap().is_match(&tags));
+    /// assert!(!Searcher::new_valueless_tag("rating").unwrap().is_match(&tags));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Regex if tag_regex is not a valid regular expression
+    pub fn new_valueless_tag(tag_regex: &str) -> Result<Self> {
+        let tag_regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+        Ok(Searcher::ValuelessTag { tag_regex })
+    }
+
+    /// Returns new Searcher matching if the number of tags is in range.
+    ///
+    /// Written as `#tags >= 5` in search terms, with any comparison operator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use xtag::Searcher;
+    /// let tags = xtag::csl_to_map("a,b,c").unwrap();
+    /// assert!(Searcher::new_tag_count(2..).is_match(&tags));
+    /// assert!(!Searcher::new_tag_count(..3).is_match(&tags));
+    /// ```
+    pub fn new_tag_count(range: impl RangeBounds<usize>) -> Self {
+        let min = match range.start_bound() {
+            Bound::Included(min) => *min,
+            Bound::Excluded(min) => min.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let max = match range.end_bound() {
+            Bound::Included(max) => Some(*max),
+            // Empty range, nothing matches
+            Bound::Excluded(0) => {
+                return Searcher::TagCount {
+                    min: 1,
+                    max: Some(0),
+                }
+            }
+            Bound::Excluded(max) => Some(max - 1),
+            Bound::Unbounded => None,
+        };
+        Searcher::TagCount { min, max }
+    }
+
+    /// Returns new folded Searcher.
+    ///
+    /// Tags and values are folded with fold before lhs is evaluated against them. Regular
+    /// expressions of lhs have to be case-insensitive and free of diacritics to match, like those
+    /// of compile_search_with with SearchOptions::fold.
+    pub fn new_folded(lhs: Searcher) -> Searcher {
+        Searcher::Folded { lhs: Box::new(lhs) }
+    }
+
+    /// Returns new Searcher marking lhs as expansion of bookmark reference name.
+    ///
+    /// Matches like lhs. compile_search wraps every `{name}` of a term in it, so explain and
+    /// visitors show which part of a search came from which bookmark.
+    pub fn new_bookmark(name: &str, lhs: Searcher) -> Searcher {
+        Searcher::Bookmark {
+            name: name.to_string(),
+            lhs: Box::new(lhs),
+        }
+    }
+
     /// Evaluates Searcher against tags.
-    pub fn is_match(&self, tags: &XTags) -> bool {
+    ///
+    /// Tags may be XTags, or XTagsRef for testing many files without copying their tags.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let search = xtag::compile_search("rating > 3").unwrap();
+    /// assert!(search.is_match(&xtag::csl_to_map("rating=4").unwrap()));
+    /// assert!(search.is_match(&xtag::XTagsRef::parse("rating=4").unwrap()));
+    /// ```
+    pub fn is_match<T: TagPairs>(&self, tags: &T) -> bool {
+        self.evaluate(tags)
+    }
+
+    /// Evaluates Searcher against tags with several values.
+    ///
+    /// Terms testing values match if one of the values of a tag matches, so `person == alice and
+    /// person == bob` matches a tag with both values. Tags without values are valueless, aliases
+    /// merge their values into those of their canonical tag.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let tags = xtag::csl_to_multi_map("person=alice,person=bob").unwrap();
+    /// let search = xtag::compile_search("person == alice and person == bob").unwrap();
+    /// assert!(search.is_match_multi(&tags));
+    /// ```
+    pub fn is_match_multi(&self, tags: &MultiXTags) -> bool {
+        self.evaluate(tags)
+    }
+
+    fn evaluate<T: TagPairs>(&self, tags: &T) -> bool {
         match self {
             Searcher::And { lhs, rhs } => {
-                let l = lhs.is_match(tags);
+                let l = lhs.evaluate(tags);
                 if l {
-                    rhs.is_match(tags)
+                    rhs.evaluate(tags)
                 } else {
                     // short-circuit
                     l
                 }
             }
             Searcher::Or { lhs, rhs } => {
-                let l = lhs.is_match(tags);
+                let l = lhs.evaluate(tags);
                 if l {
                     // short-circuit
                     l
                 } else {
-                    rhs.is_match(tags)
+                    rhs.evaluate(tags)
                 }
             }
-            Searcher::Not { lhs } => !lhs.is_match(tags),
-            Searcher::Tag { regex } => !get_values_by_tag_regex(tags, regex).is_empty(),
+            Searcher::Not { lhs } => !lhs.evaluate(tags),
+            Searcher::Tag { regex } => tags
+                .pairs()
+                .any(|(tag, _)| regex.is_match(split_weight(tag).0)),
             Searcher::Equal {
                 tag_regex,
                 value_regex,
             } => check_values_by_tag_regex(tags, tag_regex, |tag_value: &str| -> bool {
                 value_regex.is_match(tag_value)
             }),
+            Searcher::EqualCaptures {
+                tag_regex,
+                value_template,
+            } => is_match_captures(tags, tag_regex, value_template),
+            Searcher::AnyValue { value_regex } => tags
+                .pairs()
+                .filter_map(|(_, value)| value)
+                .any(|value| value_regex.is_match(split_weight(value).0)),
             Searcher::Less { tag_regex, value } => {
                 check_values_by_tag_regex(tags, tag_regex, |tag_value: &str| -> bool {
-                    if let Ok(tag_value) = tag_value.parse::<i32>() {
-                        return tag_value < *value;
-                    }
-                    false
+                    value
+                        .compare(tag_value)
+                        .is_some_and(|ordering| ordering.is_lt())
                 })
             }
             Searcher::LessEqual { tag_regex, value } => {
                 check_values_by_tag_regex(tags, tag_regex, |tag_value: &str| -> bool {
-                    if let Ok(tag_value) = tag_value.parse::<i32>() {
-                        return tag_value <= *value;
-                    }
-                    false
+                    value
+                        .compare(tag_value)
+                        .is_some_and(|ordering| ordering.is_le())
                 })
             }
             Searcher::Greater { tag_regex, value } => {
                 check_values_by_tag_regex(tags, tag_regex, |tag_value: &str| -> bool {
-                    if let Ok(tag_value) = tag_value.parse::<i32>() {
-                        return tag_value > *value;
-                    }
-                    false
+                    value
+                        .compare(tag_value)
+                        .is_some_and(|ordering| ordering.is_gt())
                 })
             }
             Searcher::GreaterEqual { tag_regex, value } => {
                 check_values_by_tag_regex(tags, tag_regex, |tag_value: &str| -> bool {
-                    if let Ok(tag_value) = tag_value.parse::<i32>() {
-                        return tag_value >= *value;
-                    }
-                    false
+                    value
+                        .compare(tag_value)
+                        .is_some_and(|ordering| ordering.is_ge())
                 })
             }
+            Searcher::IsTrue { tag_regex } => {
+                check_values_by_tag_regex(tags, tag_regex, |tag_value: &str| -> bool {
+                    is_one_of(tag_value, &TRUE_VALUES)
+                })
+            }
+            Searcher::IsFalse { tag_regex } => {
+                check_values_by_tag_regex(tags, tag_regex, |tag_value: &str| -> bool {
+                    is_one_of(tag_value, &FALSE_VALUES)
+                })
+            }
+            Searcher::Folded { lhs } => lhs.evaluate(&tags.folded()),
+            Searcher::Descendant { regex, separator } => tags
+                .pairs()
+                .any(|(tag, _)| is_under(split_weight(tag).0, regex, *separator)),
+            Searcher::Canonical { lhs, aliases } => lhs.evaluate(&tags.canonicalized(aliases)),
+            Searcher::HasValue { tag_regex } => tags
+                .pairs()
+                .any(|(tag, value)| value.is_some() && tag_regex.is_match(split_weight(tag).0)),
+            Searcher::ValuelessTag { tag_regex } => tags
+                .pairs()
+                .any(|(tag, value)| value.is_none() && tag_regex.is_match(split_weight(tag).0)),
+            Searcher::TagCount { min, max } => {
+                let count = tags.tag_count();
+                count >= *min && max.is_none_or(|max| count <= max)
+            }
+            Searcher::Bookmark { lhs, .. } => lhs.evaluate(tags),
         }
     }
+
+    /// Number of nested levels, 1 for a single term.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let search = xtag::compile_search("a and (b or not c)").unwrap();
+    /// assert_eq!(search.depth(), 4);
+    /// ```
+    pub fn depth(&self) -> usize {
+        // visit doesn't recurse, so even degenerate trees can be measured
+        let mut depth = Depth::default();
+        self.visit(&mut depth);
+        depth.max
+    }
+
+    /// Reads tags of file and evaluates Searcher against them.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// let search = xtag::compile_search("photo").unwrap();
+    /// if search.is_match_path(Path::new("a.jpg")).unwrap() {
+    ///     println!("a.jpg is a photo");
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Same as get_tags
+    pub fn is_match_path(&self, path: &Path) -> Result<bool> {
+        with_tags_ref(path, |tags| self.is_match(tags))
+    }
+
+    /// Like is_match_path, but takes tags from cache if the file is unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Same as get_tags
+    pub fn is_match_path_cached(&self, path: &Path, cache: &mut TagCache) -> Result<bool> {
+        Ok(self.is_match(cache.get_tags(path)?))
+    }
+}
+
+// Deepest nesting of the visited nodes
+#[derive(Default)]
+struct Depth {
+    current: usize,
+    max: usize,
+}
+
+impl SearcherVisitor<'_> for Depth {
+    fn enter(&mut self, _: &Searcher) -> bool {
+        self.current += 1;
+        self.max = self.max.max(self.current);
+        true
+    }
+
+    fn leave(&mut self, _: &Searcher) {
+        self.current -= 1;
+    }
+}
+
+// Expression as accepted by the grammar, other characters are written like `\x{20}`
+struct Term<'a>(&'a str);
+
+impl Term<'_> {
+    fn is_plain(c: char) -> bool {
+        c.is_alphanumeric() || ":_-/.+*?^$[]{}|\\()".contains(c)
+    }
+}
+
+impl fmt::Display for Term<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut chars = self.0.chars();
+        while let Some(c) = chars.next() {
+            // Escaped characters stay escaped, \x{..} is a literal in classes as well
+            let (escaped, c) = match c {
+                '\\' => match chars.next() {
+                    Some(next) => (true, next),
+                    None => return write!(f, "\\"),
+                },
+                c => (false, c),
+            };
+            match (escaped, Term::is_plain(c)) {
+                (_, false) => write!(f, "\\x{{{:x}}}", u32::from(c))?,
+                (true, true) => write!(f, "\\{c}")?,
+                (false, true) => write!(f, "{c}")?,
+            }
+        }
+        Ok(())
+    }
 }
 
 impl fmt::Display for Searcher {
-    /// Doesn't necessarily reproduce the exact term this Searcher resulted from.
+    /// Doesn't necessarily reproduce the exact term this Searcher resulted from, but parses to
+    /// the same search. Characters the grammar doesn't allow in expressions, like spaces and
+    /// commas, are written as escapes like `\x{20}`. Folding, aliases and bookmark references
+    /// aren't shown, bookmarks are shown expanded.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Searcher::And { lhs, rhs } => write!(f, "({}) AND ({})", lhs, rhs),
             Searcher::Or { lhs, rhs } => write!(f, "({}) OR ({})", lhs, rhs),
             Searcher::Not { lhs } => write!(f, "NOT ({})", lhs),
-            Searcher::Tag { regex } => write!(f, "{}", regex),
+            Searcher::Tag { regex } => write!(f, "{}", Term(regex.as_str())),
             Searcher::Equal {
                 tag_regex,
                 value_regex,
-            } => write!(f, "{} == {}", tag_regex, value_regex),
-            Searcher::Less { tag_regex, value } => write!(f, "{} < {}", tag_regex, value),
-            Searcher::LessEqual { tag_regex, value } => write!(f, "{} <= {}", tag_regex, value),
-            Searcher::Greater { tag_regex, value } => write!(f, "{} > {}", tag_regex, value),
-            Searcher::GreaterEqual { tag_regex, value } => write!(f, "{} >= {}", tag_regex, value),
+            } => write!(
+                f,
+                "{} == {}",
+                Term(tag_regex.as_str()),
+                Term(value_regex.as_str())
+            ),
+            Searcher::EqualCaptures {
+                tag_regex,
+                value_template,
+            } => write!(
+                f,
+                "{} == {}",
+                Term(tag_regex.as_str()),
+                Term(value_template.as_str())
+            ),
+            Searcher::AnyValue { value_regex } => write!(f, "* == {}", Term(value_regex.as_str())),
+            Searcher::Less { tag_regex, value } => {
+                write!(f, "{} < {}", Term(tag_regex.as_str()), value)
+            }
+            Searcher::LessEqual { tag_regex, value } => {
+                write!(f, "{} <= {}", Term(tag_regex.as_str()), value)
+            }
+            Searcher::Greater { tag_regex, value } => {
+                write!(f, "{} > {}", Term(tag_regex.as_str()), value)
+            }
+            Searcher::GreaterEqual { tag_regex, value } => {
+                write!(f, "{} >= {}", Term(tag_regex.as_str()), value)
+            }
+            Searcher::IsTrue { tag_regex } => write!(f, "ISTRUE({})", Term(tag_regex.as_str())),
+            Searcher::IsFalse { tag_regex } => write!(f, "ISFALSE({})", Term(tag_regex.as_str())),
+            // Folding and aliases can't be written in terms, they are shown so searches with
+            // and without them stay apart, like in the keys of SearchCache
+            Searcher::Folded { lhs } => write!(f, "FOLD({})", lhs),
+            Searcher::Descendant { regex, separator } => {
+                write!(f, "UNDER({}, '{}')", Term(regex.as_str()), separator)
+            }
+            Searcher::Canonical { lhs, aliases } => {
+                let mut pairs: Vec<_> = aliases.iter().collect();
+                pairs.sort();
+                write!(f, "CANONICAL({}", lhs)?;
+                for (index, (alias, canonical)) in pairs.into_iter().enumerate() {
+                    let separator = if index == 0 { "; " } else { ", " };
+                    write!(f, "{separator}{alias}={canonical}")?;
+                }
+                write!(f, ")")
+            }
+            Searcher::Bookmark { lhs, .. } => write!(f, "{}", lhs),
+            Searcher::HasValue { tag_regex } => write!(f, "{}?", Term(tag_regex.as_str())),
+            Searcher::ValuelessTag { tag_regex } => write!(f, "{}!", Term(tag_regex.as_str())),
+            Searcher::TagCount { min, max: None } => write!(f, "#tags >= {}", min),
+            Searcher::TagCount {
+                min,
+                max: Some(max),
+            } if min == max => {
+                write!(f, "#tags == {}", min)
+            }
+            Searcher::TagCount {
+                min: 0,
+                max: Some(max),
+            } => write!(f, "#tags <= {}", max),
+            Searcher::TagCount {
+                min,
+                max: Some(max),
+            } => write!(f, "(#tags >= {}) AND (#tags <= {})", min, max),
         }
     }
 }
 
-// Returnvalue references keys in @tags
-fn get_values_by_tag_regex<'a>(tags: &'a XTags, tag_regex: &Regex) -> Vec<&'a Option<String>> {
-    let mut result: Vec<&'a Option<String>> = Vec::new();
+// Returnvalue references tags and values in @tags, weights are left out
+pub(crate) fn get_values_by_tag_regex<'a>(
+    tags: &'a XTags,
+    tag_regex: &Regex,
+) -> Vec<(&'a str, Option<&'a str>)> {
+    let mut result: Vec<(&'a str, Option<&'a str>)> = Vec::new();
     for (tag, value) in tags {
+        let tag = split_weight(tag).0;
         if tag_regex.is_match(tag) {
-            result.push(value);
+            result.push((tag, value.as_deref().map(|value| split_weight(value).0)));
         }
     }
     result
 }
 
 // Returns true if one value of matching tags passes test
-fn check_values_by_tag_regex<F>(tags: &XTags, tag_regex: &Regex, test: F) -> bool
+fn check_values_by_tag_regex<T, F>(tags: &T, tag_regex: &Regex, test: F) -> bool
 where
+    T: TagPairs,
     F: Fn(&str) -> bool,
 {
-    let values = get_values_by_tag_regex(tags, tag_regex);
-    for value in values {
-        match value {
-            Some(tag_value) => {
-                if test(tag_value) {
-                    // short-circuit on match
-                    return true;
-                }
-            }
-            None => (), // This tag has no value, go on
-        }
-    }
-    false
+    // Tags without value are skipped, short-circuits on match
+    tags.pairs().any(|(tag, value)| match value {
+        Some(value) => tag_regex.is_match(split_weight(tag).0) && test(split_weight(value).0),
+        None => false,
+    })
+}
+
+pub(crate) fn is_one_of(value: &str, spellings: &[&str]) -> bool {
+    spellings
+        .iter()
+        .any(|spelling| value.eq_ignore_ascii_case(spelling))
 }
 
 /// Expand regex with anchors to match whole string
 ///
-/// Doesn't do anything if first and last characters are matching anchors.
-// TODO Should regex be put in non-capture-group (?: ) for safety?
+/// Doesn't do anything if first and last characters are matching anchors. Otherwise regex is put
+/// in a non-capture group, so alternations like `a|b` are anchored as a whole.
 pub fn expand_regex(regex: &str) -> String {
     if regex.starts_with('^') && regex.ends_with('$') {
         regex.to_owned()
     } else {
-        format!("^{regex}$")
+        format!("^(?:{regex})$")
     }
 }
 
+// Inverse of expand_regex for expressions it anchored
+pub(crate) fn unexpand_regex(regex: &str) -> &str {
+    regex
+        .strip_prefix("^(?:")
+        .and_then(|regex| regex.strip_suffix(")$"))
+        .unwrap_or(regex)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
@@ -436,10 +1032,46 @@ mod tests {
         assert_eq!(term2, term3);
     }
 
+    #[test]
+    fn searcher_can_be_shared_between_threads() {
+        let searcher = compile_search("a and b == 1").unwrap();
+        let tags = csl_to_map("a,b=1").unwrap();
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| searcher.is_match(&tags)))
+                .collect();
+            for handle in handles {
+                assert!(handle.join().unwrap());
+            }
+        });
+    }
+
+    #[test]
+    fn alternations_are_anchored_as_a_whole() {
+        let tags = csl_to_map("ab").unwrap();
+        assert!(!compile_search("a|b").unwrap().is_match(&tags));
+        assert!(compile_search("a|ab").unwrap().is_match(&tags));
+        assert_eq!(super::expand_regex("^a|b$"), "^a|b$");
+    }
+
     #[test]
     fn display_is_stable() {
         test_stability("a or b and c");
         test_stability("(a or b) and c");
         test_stability("a or (b and c)");
+        test_stability("istrue(a) and not isfalse(b)");
+        test_stability("under(a/b) or c");
+        test_stability("under(a:b, ':') or c");
+        test_stability("* == invoice and x");
+        test_stability("a? and b! or #tags >= 2 and #tags < 5");
+    }
+
+    #[test]
+    fn display_shows_folding_and_aliases() {
+        let folded = Searcher::new_folded(compile_search("a").unwrap());
+        assert_eq!(folded.to_string(), "FOLD(^(?:a)$)");
+        let aliases = AliasMap::from_csv("b,y,x\n").unwrap();
+        let canonical = Searcher::new_canonical(compile_search("b").unwrap(), aliases);
+        assert_eq!(canonical.to_string(), "CANONICAL(^(?:b)$; x=b, y=b)");
     }
 }
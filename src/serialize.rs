@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::captures::equal_searcher;
+use crate::{AliasMap, Comparand, Searcher, XTagError};
+
+// Structured form of Searcher, regular expressions are kept as expanded by the constructors
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Node {
+    And {
+        lhs: Box<Node>,
+        rhs: Box<Node>,
+    },
+    Or {
+        lhs: Box<Node>,
+        rhs: Box<Node>,
+    },
+    Not {
+        lhs: Box<Node>,
+    },
+    Tag {
+        regex: String,
+    },
+    Equal {
+        tag_regex: String,
+        value_regex: String,
+    },
+    AnyValue {
+        value_regex: String,
+    },
+    Less {
+        tag_regex: String,
+        value: Value,
+    },
+    LessEqual {
+        tag_regex: String,
+        value: Value,
+    },
+    Greater {
+        tag_regex: String,
+        value: Value,
+    },
+    GreaterEqual {
+        tag_regex: String,
+        value: Value,
+    },
+    IsTrue {
+        tag_regex: String,
+    },
+    IsFalse {
+        tag_regex: String,
+    },
+    Folded {
+        lhs: Box<Node>,
+    },
+    Descendant {
+        regex: String,
+        separator: char,
+    },
+    Canonical {
+        lhs: Box<Node>,
+        aliases: HashMap<String, String>,
+    },
+    HasValue {
+        tag_regex: String,
+    },
+    ValuelessTag {
+        tag_regex: String,
+    },
+    TagCount {
+        min: usize,
+        max: Option<usize>,
+    },
+    Bookmark {
+        name: String,
+        lhs: Box<Node>,
+    },
+}
+
+// Right hand side of comparisons as plain number or date string
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Value {
+    Int(i32),
+    Float(f64),
+    Date(String),
+}
+
+impl From<&Comparand> for Value {
+    fn from(comparand: &Comparand) -> Self {
+        match comparand {
+            Comparand::Int(value) => Value::Int(*value),
+            Comparand::Float(value) => Value::Float(*value),
+            Comparand::Date(value) => Value::Date(value.to_string()),
+        }
+    }
+}
+
+impl TryFrom<Value> for Comparand {
+    type Error = XTagError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Ok(match value {
+            Value::Int(value) => Comparand::Int(value),
+            Value::Float(value) => Comparand::float(value)?,
+            Value::Date(value) => Comparand::Date(value.parse()?),
+        })
+    }
+}
+
+impl From<&Searcher> for Node {
+    fn from(searcher: &Searcher) -> Self {
+        let node = |searcher: &Searcher| Box::new(Node::from(searcher));
+        let string = |regex: &Regex| regex.as_str().to_string();
+        match searcher {
+            Searcher::And { lhs, rhs } => Node::And {
+                lhs: node(lhs),
+                rhs: node(rhs),
+            },
+            Searcher::Or { lhs, rhs } => Node::Or {
+                lhs: node(lhs),
+                rhs: node(rhs),
+            },
+            Searcher::Not { lhs } => Node::Not { lhs: node(lhs) },
+            Searcher::Tag { regex } => Node::Tag {
+                regex: string(regex),
+            },
+            Searcher::Equal {
+                tag_regex,
+                value_regex,
+            } => Node::Equal {
+                tag_regex: string(tag_regex),
+                value_regex: string(value_regex),
+            },
+            Searcher::EqualCaptures {
+                tag_regex,
+                value_template,
+            } => Node::Equal {
+                tag_regex: string(tag_regex),
+                value_regex: value_template.as_str().to_string(),
+            },
+            Searcher::AnyValue { value_regex } => Node::AnyValue {
+                value_regex: string(value_regex),
+            },
+            Searcher::Less { tag_regex, value } => Node::Less {
+                tag_regex: string(tag_regex),
+                value: Value::from(value),
+            },
+            Searcher::LessEqual { tag_regex, value } => Node::LessEqual {
+                tag_regex: string(tag_regex),
+                value: Value::from(value),
+            },
+            Searcher::Greater { tag_regex, value } => Node::Greater {
+                tag_regex: string(tag_regex),
+                value: Value::from(value),
+            },
+            Searcher::GreaterEqual { tag_regex, value } => Node::GreaterEqual {
+                tag_regex: string(tag_regex),
+                value: Value::from(value),
+            },
+            Searcher::IsTrue { tag_regex } => Node::IsTrue {
+                tag_regex: string(tag_regex),
+            },
+            Searcher::IsFalse { tag_regex } => Node::IsFalse {
+                tag_regex: string(tag_regex),
+            },
+            Searcher::Folded { lhs } => Node::Folded { lhs: node(lhs) },
+            Searcher::Descendant { regex, separator } => Node::Descendant {
+                regex: string(regex),
+                separator: *separator,
+            },
+            Searcher::Canonical { lhs, aliases } => Node::Canonical {
+                lhs: node(lhs),
+                aliases: aliases
+                    .iter()
+                    .map(|(alias, canonical)| (alias.to_string(), canonical.to_string()))
+                    .collect(),
+            },
+            Searcher::HasValue { tag_regex } => Node::HasValue {
+                tag_regex: string(tag_regex),
+            },
+            Searcher::ValuelessTag { tag_regex } => Node::ValuelessTag {
+                tag_regex: string(tag_regex),
+            },
+            Searcher::TagCount { min, max } => Node::TagCount {
+                min: *min,
+                max: *max,
+            },
+            Searcher::Bookmark { name, lhs } => Node::Bookmark {
+                name: name.clone(),
+                lhs: node(lhs),
+            },
+        }
+    }
+}
+
+impl TryFrom<Node> for Searcher {
+    type Error = XTagError;
+
+    fn try_from(node: Node) -> Result<Self, Self::Error> {
+        let searcher = |node: Box<Node>| Searcher::try_from(*node).map(Box::new);
+        Ok(match node {
+            Node::And { lhs, rhs } => Searcher::And {
+                lhs: searcher(lhs)?,
+                rhs: searcher(rhs)?,
+            },
+            Node::Or { lhs, rhs } => Searcher::Or {
+                lhs: searcher(lhs)?,
+                rhs: searcher(rhs)?,
+            },
+            Node::Not { lhs } => Searcher::Not {
+                lhs: searcher(lhs)?,
+            },
+            Node::Tag { regex } => Searcher::Tag {
+                regex: Regex::new(&regex)?,
+            },
+            Node::Equal {
+                tag_regex,
+                value_regex,
+            } => equal_searcher(Regex::new(&tag_regex)?, &value_regex)?,
+            Node::AnyValue { value_regex } => Searcher::AnyValue {
+                value_regex: Regex::new(&value_regex)?,
+            },
+            Node::Less { tag_regex, value } => Searcher::Less {
+                tag_regex: Regex::new(&tag_regex)?,
+                value: Comparand::try_from(value)?,
+            },
+            Node::LessEqual { tag_regex, value } => Searcher::LessEqual {
+                tag_regex: Regex::new(&tag_regex)?,
+                value: Comparand::try_from(value)?,
+            },
+            Node::Greater { tag_regex, value } => Searcher::Greater {
+                tag_regex: Regex::new(&tag_regex)?,
+                value: Comparand::try_from(value)?,
+            },
+            Node::GreaterEqual { tag_regex, value } => Searcher::GreaterEqual {
+                tag_regex: Regex::new(&tag_regex)?,
+                value: Comparand::try_from(value)?,
+            },
+            Node::IsTrue { tag_regex } => Searcher::IsTrue {
+                tag_regex: Regex::new(&tag_regex)?,
+            },
+            Node::IsFalse { tag_regex } => Searcher::IsFalse {
+                tag_regex: Regex::new(&tag_regex)?,
+            },
+            Node::Folded { lhs } => Searcher::Folded {
+                lhs: searcher(lhs)?,
+            },
+            Node::Descendant { regex, separator } => Searcher::Descendant {
+                regex: Regex::new(&regex)?,
+                separator,
+            },
+            Node::Canonical { lhs, aliases } => {
+                let mut map = AliasMap::new();
+                for (alias, canonical) in &aliases {
+                    map.insert(alias, canonical)?;
+                }
+                Searcher::Canonical {
+                    lhs: searcher(lhs)?,
+                    aliases: map,
+                }
+            }
+            Node::HasValue { tag_regex } => Searcher::HasValue {
+                tag_regex: Regex::new(&tag_regex)?,
+            },
+            Node::ValuelessTag { tag_regex } => Searcher::ValuelessTag {
+                tag_regex: Regex::new(&tag_regex)?,
+            },
+            Node::TagCount { min, max } => Searcher::TagCount { min, max },
+            Node::Bookmark { name, lhs } => Searcher::Bookmark {
+                name,
+                lhs: searcher(lhs)?,
+            },
+        })
+    }
+}
+
+/// Serializes the tree of Searcher elements.
+///
+/// Every element is a map with its variant in `op`, like
+/// `{"op":"tag","regex":"^(?:photo)$"}`. Regular expressions are stored as expanded, so
+/// deserializing doesn't need the search grammar.
+impl Serialize for Searcher {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Node::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Searcher {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Searcher::try_from(Node::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compile_search, csl_to_map, ResultSet, Searcher};
+
+    #[test]
+    fn searcher_round_trips() {
+        let searcher =
+            compile_search("(a or b == c or := f) and not istrue(d) and e >= 2").unwrap();
+        let json = serde_json::to_string(&searcher).unwrap();
+        assert!(json.starts_with(r#"{"op":"and","#));
+        let restored: Searcher = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.to_string(), searcher.to_string());
+        assert!(restored.is_match(&csl_to_map("b=c,e=3").unwrap()));
+
+        let searcher = compile_search("a < 2.5 and b >= 2023-06-01").unwrap();
+        let json = serde_json::to_string(&searcher).unwrap();
+        let restored: Searcher = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.to_string(), searcher.to_string());
+
+        let invalid = r#"{"op":"tag","regex":"("}"#;
+        assert!(serde_json::from_str::<Searcher>(invalid).is_err());
+    }
+
+    #[test]
+    fn result_set_is_a_list_of_paths() {
+        let result: ResultSet = ["a", "b"].iter().map(Into::into).collect();
+        let json = serde_json::to_string(&result).unwrap();
+        assert_eq!(json, r#"["a","b"]"#);
+        assert_eq!(serde_json::from_str::<ResultSet>(&json).unwrap(), result);
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::{compile_search, get_bookmark, Result, ResultSet, Searcher};
+
+/// State of an interactive query session.
+///
+/// Keeps compiled searches for reuse and remembers the result of the last search, so that
+/// following queries can refine it. Meant as backend for REPL-style frontends.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::PathBuf;
+/// let mut session = xtag::Session::new();
+/// let files = vec![PathBuf::from("a.jpg"), PathBuf::from("b.jpg")];
+/// session.search("photo", files).unwrap();
+/// let result = session.refine("rating > 3").unwrap();
+/// println!("{} files left", result.len());
+/// ```
+#[derive(Default)]
+pub struct Session {
+    searchers: HashMap<String, Searcher>,
+    bookmarks: HashMap<PathBuf, Searcher>,
+    last_result: ResultSet,
+}
+
+impl Session {
+    /// Returns new Session with empty cache and result.
+    pub fn new() -> Self {
+        Session::default()
+    }
+
+    /// Compiles term or returns the Searcher compiled earlier from the same term.
+    ///
+    /// Bookmarks in term are resolved when the term is compiled for the first time. Later
+    /// changes to the bookmark are not seen until the cache is cleared.
+    ///
+    /// # Errors
+    ///
+    /// Same as compile_search
+    pub fn compile(&mut self, term: &str) -> Result<&Searcher> {
+        if !self.searchers.contains_key(term) {
+            let searcher = compile_search(term)?;
+            self.searchers.insert(term.to_string(), searcher);
+        }
+        Ok(&self.searchers[term])
+    }
+
+    /// Compiles the search stored in bookmark at path, see get_bookmark.
+    ///
+    /// Cached under path like terms, changes to the bookmark are not seen until the cache is
+    /// cleared.
+    ///
+    /// # Errors
+    ///
+    /// Same as get_bookmark
+    pub fn compile_bookmark(&mut self, path: &Path) -> Result<&Searcher> {
+        if !self.bookmarks.contains_key(path) {
+            let searcher = get_bookmark(path)?;
+            self.bookmarks.insert(path.to_path_buf(), searcher);
+        }
+        Ok(&self.bookmarks[path])
+    }
+
+    /// Evaluates term against the tags of paths.
+    ///
+    /// Matching paths replace the last result which is also returned.
+    ///
+    /// # Errors
+    ///
+    /// Same as compile_search and get_tags
+    pub fn search<I, P>(&mut self, term: &str, paths: I) -> Result<&ResultSet>
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        let searcher = self.compile(term)?;
+        self.last_result = matching(searcher, paths)?;
+        Ok(&self.last_result)
+    }
+
+    /// Evaluates term against the last result.
+    ///
+    /// Equivalent to and-ing term with the term of the last search. Tags are read again so that
+    /// changes since the last search are honored. On errors the last result is kept.
+    ///
+    /// # Errors
+    ///
+    /// Same as compile_search and get_tags
+    pub fn refine(&mut self, term: &str) -> Result<&ResultSet> {
+        self.compile(term)?;
+        self.last_result = matching(&self.searchers[term], &self.last_result)?;
+        Ok(&self.last_result)
+    }
+
+    /// Result of last search or refinement.
+    pub fn last_result(&self) -> &ResultSet {
+        &self.last_result
+    }
+
+    /// Forgets all compiled searches and bookmarks.
+    pub fn clear_cache(&mut self) {
+        self.searchers.clear();
+        self.bookmarks.clear();
+    }
+}
+
+// Paths whose tags match searcher
+fn matching<I, P>(searcher: &Searcher, paths: I) -> Result<ResultSet>
+where
+    I: IntoIterator<Item = P>,
+    P: Into<PathBuf>,
+{
+    let mut result = ResultSet::new();
+    for path in paths {
+        let path = path.into();
+        if searcher.is_match_path(&path)? {
+            result.push(path);
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    use super::Session;
+    use crate::{csl_to_map, Fixture};
+
+    #[test]
+    fn session_caches_compiled_terms() {
+        let mut session = Session::new();
+        let first = session.compile("a and b").unwrap() as *const _;
+        let second = session.compile("a and b").unwrap() as *const _;
+        assert_eq!(first, second);
+        assert!(session.compile("a b").is_err());
+    }
+
+    #[test]
+    fn session_caches_bookmarks_by_path() {
+        let fixture = Fixture::new();
+        let path = fixture.join("odd {name}");
+        symlink("a", &path).unwrap();
+        let mut session = Session::new();
+        let tags = csl_to_map("a").unwrap();
+        assert!(session.compile_bookmark(&path).unwrap().is_match(&tags));
+
+        fs::remove_file(&path).unwrap();
+        symlink("b", &path).unwrap();
+        assert!(session.compile_bookmark(&path).unwrap().is_match(&tags));
+        session.clear_cache();
+        assert!(!session.compile_bookmark(&path).unwrap().is_match(&tags));
+        assert!(session.compile_bookmark(&fixture.join("missing")).is_err());
+    }
+
+    #[test]
+    fn session_refine_without_result_is_empty() {
+        let mut session = Session::new();
+        assert!(session.refine("a").unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_refinements_keep_the_result() {
+        let fixture = Fixture::new().file("a").tags("x,y").file("b").tags("x");
+        let mut session = Session::new();
+        let paths = [fixture.join("a"), fixture.join("b")];
+        assert_eq!(session.search("x", paths).unwrap().len(), 2);
+        assert!(session.refine("y z").is_err());
+        assert_eq!(session.last_result().len(), 2);
+        std::fs::remove_file(fixture.join("b")).unwrap();
+        assert!(session.refine("y").is_err());
+        assert_eq!(session.last_result().len(), 2);
+        std::fs::write(fixture.join("b"), "").unwrap();
+        assert_eq!(session.refine("y").unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,124 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::store::store_or_xattr;
+use crate::walk::TreeTags;
+use crate::{walk, Result, WalkOptions, XTags};
+
+// Tags count as features on their own and together with their value, so equal values weigh more
+fn features(tags: &XTags) -> HashSet<(&str, Option<&str>)> {
+    let mut features = HashSet::with_capacity(tags.len() * 2);
+    for (tag, value) in tags {
+        features.insert((tag.as_str(), None));
+        if let Some(value) = value {
+            features.insert((tag.as_str(), Some(value.as_str())));
+        }
+    }
+    features
+}
+
+/// Jaccard similarity of two tag sets between 0 and 1.
+///
+/// Tags are compared by name and, if they have one, by value. Two files with the same tags but
+/// different values are similar, but less than with equal values. Two empty sets have similarity
+/// 0.
+///
+/// # Example
+///
+/// ```
+/// let a = xtag::csl_to_map("beach,year=2023").unwrap();
+/// let b = xtag::csl_to_map("beach,year=2024").unwrap();
+/// let c = xtag::csl_to_map("beach,year=2023").unwrap();
+/// assert!(xtag::similarity(&a, &c) > xtag::similarity(&a, &b));
+/// assert_eq!(xtag::similarity(&a, &c), 1.0);
+/// ```
+pub fn similarity(tags: &XTags, other: &XTags) -> f64 {
+    let (features, other) = (features(tags), features(other));
+    let union = features.union(&other).count();
+    if union == 0 {
+        return 0.0;
+    }
+    features.intersection(&other).count() as f64 / union as f64
+}
+
+/// Returns up to k files below root whose tags are most similar to those of path.
+///
+/// Files are ranked by similarity, highest first. path itself and files sharing no tag with it
+/// are left out.
+///
+/// # Errors
+///
+/// Same as walk and get_tags, files without attribute support are handled by options
+pub fn similar_to(
+    path: &Path,
+    root: &Path,
+    k: usize,
+    options: &WalkOptions,
+) -> Result<Vec<(PathBuf, f64)>> {
+    let tags = store_or_xattr(&options.store).get_tags(path)?;
+    // Paths like ./a or a link to a directory name the same file differently
+    let path = fs::canonicalize(path)?;
+    let mut similar = Vec::new();
+    let tree = TreeTags::new(root, options);
+    for candidate in walk(root, options) {
+        let candidate = candidate?;
+        if fs::canonicalize(&candidate).is_ok_and(|candidate| candidate == path) {
+            continue;
+        }
+        let Some(other) = tree.get(&candidate)? else {
+            continue;
+        };
+        let score = similarity(&tags, &other);
+        if score > 0.0 {
+            similar.push((candidate, score));
+        }
+    }
+    similar.sort_by(|a, b| {
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    similar.truncate(k);
+    Ok(similar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{similar_to, similarity};
+    use crate::{csl_to_map, Fixture};
+
+    #[test]
+    fn similarity_weighs_values() {
+        let a = csl_to_map("x,y=1").unwrap();
+        assert_eq!(similarity(&a, &csl_to_map("x,y=1").unwrap()), 1.0);
+        assert_eq!(similarity(&a, &csl_to_map("x,y=2").unwrap()), 0.5);
+        assert_eq!(similarity(&a, &csl_to_map("z").unwrap()), 0.0);
+        assert_eq!(
+            similarity(&csl_to_map("").unwrap(), &csl_to_map("").unwrap()),
+            0.0
+        );
+    }
+
+    #[test]
+    fn query_file_is_left_out_however_named() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("x,y")
+            .file("b")
+            .tags("x")
+            .file("sub/c");
+        for path in ["a", "./a", "sub/../a"] {
+            let similar = similar_to(
+                &fixture.join(path),
+                &fixture.join("."),
+                10,
+                &Default::default(),
+            )
+            .unwrap();
+            assert_eq!(similar.len(), 1, "{path}");
+            assert!(similar[0].0.ends_with("b"));
+        }
+    }
+}
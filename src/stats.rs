@@ -0,0 +1,807 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::walk::TreeTags;
+use crate::{get_tags, split_weight, walk, Comparand, Date, Index, Result, WalkOptions, XTags};
+
+/// Separates namespaces in tag names like in `project:xtag:docs`.
+pub const NAMESPACE_SEPARATOR: char = ':';
+
+/// Usage of one tag.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagCount {
+    /// Number of files having the tag.
+    pub files: usize,
+
+    /// Number of files per value.
+    pub values: BTreeMap<String, usize>,
+}
+
+impl TagCount {
+    /// Summary of the integer values, None if there are none.
+    ///
+    /// Every file counts with its value, values that aren't integers are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let tags = [
+    ///     xtag::csl_to_map("rating=2").unwrap(),
+    ///     xtag::csl_to_map("rating=5").unwrap(),
+    ///     xtag::csl_to_map("rating=5").unwrap(),
+    /// ];
+    /// let stats: xtag::TagStats = tags.iter().collect();
+    /// let rating = stats.tag("rating").unwrap().numeric().unwrap();
+    /// assert_eq!((rating.min, rating.max, rating.mean), (2, 5, 4.0));
+    /// ```
+    pub fn numeric(&self) -> Option<NumericSummary> {
+        let mut summary: Option<NumericSummary> = None;
+        let mut skipped = 0;
+        let mut sum = 0.0;
+        for (value, &files) in &self.values {
+            let Ok(number) = split_weight(value).0.parse::<i64>() else {
+                skipped += files;
+                continue;
+            };
+            sum += number as f64 * files as f64;
+            let summary = summary.get_or_insert(NumericSummary {
+                values: 0,
+                skipped: 0,
+                min: number,
+                max: number,
+                mean: 0.0,
+            });
+            summary.values += files;
+            summary.min = summary.min.min(number);
+            summary.max = summary.max.max(number);
+        }
+        summary.map(|summary| NumericSummary {
+            skipped,
+            mean: sum / summary.values as f64,
+            ..summary
+        })
+    }
+}
+
+/// Minimum, maximum and mean of the integer values of a tag.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumericSummary {
+    /// Number of files with an integer value.
+    pub values: usize,
+
+    /// Number of files with a value that isn't an integer.
+    pub skipped: usize,
+
+    /// Smallest value.
+    pub min: i64,
+
+    /// Largest value.
+    pub max: i64,
+
+    /// Average over all files with an integer value.
+    pub mean: f64,
+}
+
+/// Statistics over the tags of many files.
+///
+/// Besides the tags themselves, files are counted per namespace. A namespace is every prefix of a
+/// tag name ending with NAMESPACE_SEPARATOR, so `project:xtag:docs` counts for `project:` and
+/// `project:xtag:`.
+///
+/// # Example
+///
+/// ```
+/// let mut stats = xtag::TagStats::new();
+/// stats.add(&xtag::csl_to_map("project:xtag:docs,project:other").unwrap());
+/// stats.add(&xtag::csl_to_map("project:xtag:code,rating=5").unwrap());
+/// assert_eq!(stats.namespace_files("project:"), 2);
+/// assert_eq!(
+///     stats.children_of("project:"),
+///     vec![("project:other".to_string(), 1), ("project:xtag:".to_string(), 2)]
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagStats {
+    files: usize,
+    tags: BTreeMap<String, TagCount>,
+    namespaces: BTreeMap<String, usize>,
+}
+
+impl TagStats {
+    /// Returns new empty TagStats.
+    pub fn new() -> Self {
+        TagStats::default()
+    }
+
+    /// Adds tags of one file.
+    ///
+    /// Tags and values are counted without their weight.
+    pub fn add(&mut self, tags: &XTags) {
+        self.files += 1;
+        let mut namespaces: BTreeSet<&str> = BTreeSet::new();
+        for (tag, value) in tags {
+            let tag = split_weight(tag).0;
+            let value = value.as_deref().map(|value| split_weight(value).0);
+            // Names are only copied the first time they are seen
+            if !self.tags.contains_key(tag) {
+                self.tags.insert(tag.to_string(), TagCount::default());
+            }
+            let count = self.tags.get_mut(tag).unwrap();
+            count.files += 1;
+            if let Some(value) = value {
+                match count.values.get_mut(value) {
+                    Some(files) => *files += 1,
+                    None => {
+                        count.values.insert(value.to_string(), 1);
+                    }
+                }
+            }
+            for (index, _) in tag.match_indices(NAMESPACE_SEPARATOR) {
+                namespaces.insert(&tag[..index + NAMESPACE_SEPARATOR.len_utf8()]);
+            }
+        }
+        for namespace in namespaces {
+            match self.namespaces.get_mut(namespace) {
+                Some(files) => *files += 1,
+                None => {
+                    self.namespaces.insert(namespace.to_string(), 1);
+                }
+            }
+        }
+    }
+
+    /// Number of files added.
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    /// Usage of tag.
+    pub fn tag(&self, tag: &str) -> Option<&TagCount> {
+        self.tags.get(tag)
+    }
+
+    /// Iterates over all tags in lexicographic order.
+    pub fn tags(&self) -> impl Iterator<Item = (&String, &TagCount)> {
+        self.tags.iter()
+    }
+
+    /// Number of files having at least one tag in namespace.
+    pub fn namespace_files(&self, namespace: &str) -> usize {
+        self.namespaces.get(namespace).copied().unwrap_or(0)
+    }
+
+    /// Returns direct children of namespace with their file counts.
+    ///
+    /// Children are tags and namespaces one level below. Pass an empty string for the top level.
+    pub fn children_of(&self, namespace: &str) -> Vec<(String, usize)> {
+        let mut children: BTreeMap<String, usize> = BTreeMap::new();
+        let tags = self.tags.iter().map(|(tag, count)| (tag, count.files));
+        let namespaces = self.namespaces.iter().map(|(tag, files)| (tag, *files));
+        for (name, files) in tags.chain(namespaces) {
+            let Some(rest) = name.strip_prefix(namespace) else {
+                continue;
+            };
+            if rest.is_empty() {
+                continue;
+            }
+            // Only direct children, deeper levels are represented by their namespace
+            let is_direct = match rest.find(NAMESPACE_SEPARATOR) {
+                None => true,
+                Some(index) => index + NAMESPACE_SEPARATOR.len_utf8() == rest.len(),
+            };
+            if is_direct {
+                children.insert(name.clone(), files);
+            }
+        }
+        children.into_iter().collect()
+    }
+}
+
+impl<'a> Extend<&'a XTags> for TagStats {
+    fn extend<I: IntoIterator<Item = &'a XTags>>(&mut self, iter: I) {
+        for tags in iter {
+            self.add(tags);
+        }
+    }
+}
+
+impl<'a> FromIterator<&'a XTags> for TagStats {
+    fn from_iter<I: IntoIterator<Item = &'a XTags>>(iter: I) -> Self {
+        let mut stats = TagStats::new();
+        stats.extend(iter);
+        stats
+    }
+}
+
+/// Computes TagStats of files in one pass.
+///
+/// # Example
+///
+/// ```no_run
+/// let stats = xtag::collect_stats(["a.jpg", "b.jpg"]).unwrap();
+/// for (tag, count) in stats.tags() {
+///     println!("{tag}: {} files", count.files);
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Same as get_tags
+pub fn collect_stats<I, P>(paths: I) -> Result<TagStats>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut stats = TagStats::new();
+    for path in paths {
+        stats.add(&get_tags(path.as_ref())?);
+    }
+    Ok(stats)
+}
+
+/// Computes TagStats of all files below root.
+///
+/// # Errors
+///
+/// Same as walk and get_tags, files without attribute support are handled by options
+pub fn collect_stats_dir(root: &Path, options: &WalkOptions) -> Result<TagStats> {
+    let mut stats = TagStats::new();
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        if let Some(tags) = tree.get(&path?)? {
+            stats.add(&tags);
+        }
+    }
+    Ok(stats)
+}
+
+/// Tag appearing together with another tag.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelatedTag {
+    /// Name of the related tag.
+    pub tag: String,
+
+    /// Number of files having both tags.
+    pub files: usize,
+
+    /// Ratio of observed to expected common files if tags were independent. Above 1 the tags
+    /// appear together more often than chance.
+    pub lift: f64,
+}
+
+/// Pairwise co-occurrence of tags over many files.
+///
+/// # Example
+///
+/// ```
+/// let tags = [
+///     xtag::csl_to_map("beach,sea,holiday").unwrap(),
+///     xtag::csl_to_map("beach,sea").unwrap(),
+///     xtag::csl_to_map("work").unwrap(),
+/// ];
+/// let cooccurrence: xtag::Cooccurrence = tags.iter().collect();
+/// assert_eq!(cooccurrence.count("sea", "beach"), 2);
+/// assert_eq!(cooccurrence.related("beach", 1)[0].tag, "sea");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cooccurrence {
+    files: usize,
+    tags: BTreeMap<String, usize>,
+    pairs: BTreeMap<(String, String), usize>,
+}
+
+impl Cooccurrence {
+    /// Returns new empty Cooccurrence.
+    pub fn new() -> Self {
+        Cooccurrence::default()
+    }
+
+    /// Adds tags of one file, weights of tags are left out.
+    pub fn add(&mut self, tags: &XTags) {
+        self.files += 1;
+        let mut names: Vec<&str> = tags.keys().map(|tag| split_weight(tag).0).collect();
+        names.sort_unstable();
+        names.dedup();
+        for (index, tag) in names.iter().enumerate() {
+            *self.tags.entry(tag.to_string()).or_default() += 1;
+            for other in &names[index + 1..] {
+                let pair = (tag.to_string(), other.to_string());
+                *self.pairs.entry(pair).or_default() += 1;
+            }
+        }
+    }
+
+    /// Number of files added.
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    /// Number of files having both tags.
+    pub fn count(&self, tag: &str, other: &str) -> usize {
+        let pair = if tag <= other {
+            (tag.to_string(), other.to_string())
+        } else {
+            (other.to_string(), tag.to_string())
+        };
+        self.pairs.get(&pair).copied().unwrap_or(0)
+    }
+
+    /// Lift of both tags, None if one of them was never seen.
+    pub fn lift(&self, tag: &str, other: &str) -> Option<f64> {
+        let files = *self.tags.get(tag)? as f64;
+        let other_files = *self.tags.get(other)? as f64;
+        Some(self.count(tag, other) as f64 * self.files as f64 / (files * other_files))
+    }
+
+    /// Iterates over all pairs of tags appearing together with their number of files.
+    ///
+    /// Each pair is returned once, with the names in lexicographic order.
+    pub fn pairs(&self) -> impl Iterator<Item = (&str, &str, usize)> {
+        self.pairs
+            .iter()
+            .map(|((tag, other), files)| (tag.as_str(), other.as_str(), *files))
+    }
+
+    /// Returns up to k tags appearing together with tag, highest lift first.
+    ///
+    /// Ties are ordered by number of common files, then by name.
+    pub fn related(&self, tag: &str, k: usize) -> Vec<RelatedTag> {
+        let mut related: Vec<RelatedTag> = self
+            .pairs()
+            .filter_map(|(first, second, _)| match (first == tag, second == tag) {
+                (true, _) => Some(second),
+                (_, true) => Some(first),
+                _ => None,
+            })
+            .map(|other| RelatedTag {
+                tag: other.to_string(),
+                files: self.count(tag, other),
+                lift: self.lift(tag, other).unwrap_or(0.0),
+            })
+            .collect();
+        related.sort_by(|a, b| {
+            b.lift
+                .partial_cmp(&a.lift)
+                .unwrap_or(Ordering::Equal)
+                .then(b.files.cmp(&a.files))
+                .then_with(|| a.tag.cmp(&b.tag))
+        });
+        related.truncate(k);
+        related
+    }
+
+    /// Suggests up to k tags missing in tags, best first.
+    ///
+    /// The score of a tag is the mean over all present tags of the share of their files also
+    /// having the suggested tag, between 0 and 1. Ties are ordered by name.
+    pub fn suggest(&self, tags: &XTags, k: usize) -> Vec<(String, f64)> {
+        let present: Vec<(&str, usize)> = tags
+            .keys()
+            .filter_map(|tag| Some((tag.as_str(), *self.tags.get(tag)?)))
+            .collect();
+        if present.is_empty() {
+            return Vec::new();
+        }
+
+        let mut scores: BTreeMap<&str, f64> = BTreeMap::new();
+        for ((first, second), both) in &self.pairs {
+            for (tag, other) in [(first, second), (second, first)] {
+                if tags.contains_key(other.as_str()) {
+                    continue;
+                }
+                if let Some((_, files)) = present.iter().find(|(present, _)| *present == tag) {
+                    *scores.entry(other.as_str()).or_default() += *both as f64 / *files as f64;
+                }
+            }
+        }
+        let mut suggestions: Vec<(String, f64)> = scores
+            .into_iter()
+            .map(|(tag, score)| (tag.to_string(), score / present.len() as f64))
+            .collect();
+        suggestions.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        suggestions.truncate(k);
+        suggestions
+    }
+}
+
+/// Suggests up to k additional tags for file based on cooccurrence.
+///
+/// See Cooccurrence::suggest.
+pub fn suggest_for(
+    path: &Path,
+    cooccurrence: &Cooccurrence,
+    k: usize,
+) -> Result<Vec<(String, f64)>> {
+    Ok(cooccurrence.suggest(&get_tags(path)?, k))
+}
+
+impl<'a> Extend<&'a XTags> for Cooccurrence {
+    fn extend<I: IntoIterator<Item = &'a XTags>>(&mut self, iter: I) {
+        for tags in iter {
+            self.add(tags);
+        }
+    }
+}
+
+impl<'a> FromIterator<&'a XTags> for Cooccurrence {
+    fn from_iter<I: IntoIterator<Item = &'a XTags>>(iter: I) -> Self {
+        let mut cooccurrence = Cooccurrence::new();
+        cooccurrence.extend(iter);
+        cooccurrence
+    }
+}
+
+/// Computes co-occurrence of tags of all files below root.
+///
+/// # Errors
+///
+/// Same as walk and get_tags, files without attribute support are handled by options
+pub fn cooccurrence(root: &Path, options: &WalkOptions) -> Result<Cooccurrence> {
+    let mut cooccurrence = Cooccurrence::new();
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        if let Some(tags) = tree.get(&path?)? {
+            cooccurrence.add(&tags);
+        }
+    }
+    Ok(cooccurrence)
+}
+
+/// Grouping of values in a Histogram.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bucketing {
+    /// One bucket per distinct number.
+    Value,
+
+    /// Numbers in buckets of this width, starting at multiples of it.
+    Width(f64),
+
+    /// ISO-8601 dates per year.
+    Year,
+
+    /// ISO-8601 dates per month.
+    Month,
+
+    /// ISO-8601 dates per day.
+    Day,
+}
+
+impl Bucketing {
+    // Start of the bucket of value, None if value doesn't fit
+    fn start(self, value: &str) -> Option<Comparand> {
+        let number = || value.parse::<f64>().ok().filter(|value| value.is_finite());
+        let date = || value.parse::<Date>().ok();
+        let month = |date: Date, month| Date::new(date.year, month, 1).ok().map(Comparand::Date);
+        match self {
+            Bucketing::Value => number().map(number_comparand),
+            Bucketing::Width(width) => {
+                number().map(|number| number_comparand((number / width).floor() * width))
+            }
+            Bucketing::Year => date().and_then(|date| month(date, 1)),
+            Bucketing::Month => date().and_then(|date| month(date, date.month)),
+            Bucketing::Day => date().map(Comparand::Date),
+        }
+    }
+}
+
+// Whole numbers become integers
+fn number_comparand(number: f64) -> Comparand {
+    if number.fract() == 0.0 && number >= i32::MIN as f64 && number <= i32::MAX as f64 {
+        Comparand::Int(number as i32)
+    } else {
+        Comparand::Float(number)
+    }
+}
+
+/// Group of values in a Histogram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bucket {
+    /// Smallest value of the bucket, the first day for dates.
+    pub start: Comparand,
+
+    /// Number of files with a value in the bucket.
+    pub files: usize,
+}
+
+/// Distribution of the values of one tag.
+///
+/// # Example
+///
+/// ```
+/// let tags = [
+///     xtag::csl_to_map("rating=4").unwrap(),
+///     xtag::csl_to_map("rating=5").unwrap(),
+///     xtag::csl_to_map("rating=4.5").unwrap(),
+/// ];
+/// let stats: xtag::TagStats = tags.iter().collect();
+/// let histogram = xtag::Histogram::from_stats(&stats, "rating", xtag::Bucketing::Width(1.0));
+/// assert_eq!(histogram.buckets[0].start, xtag::Comparand::Int(4));
+/// assert_eq!(histogram.buckets[0].files, 2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    /// Buckets in ascending order, empty buckets are left out.
+    pub buckets: Vec<Bucket>,
+
+    /// Number of files whose value doesn't fit bucketing, like text for numbers.
+    pub skipped: usize,
+}
+
+impl Histogram {
+    /// Returns histogram of tag from counts of stats.
+    ///
+    /// # Panics
+    ///
+    /// If bucketing is Bucketing::Width and the width isn't positive.
+    pub fn from_stats(stats: &TagStats, tag: &str, bucketing: Bucketing) -> Self {
+        let mut histogram = Histogram::default();
+        if let Some(count) = stats.tag(tag) {
+            histogram.extend(
+                bucketing,
+                count.values.iter().map(|(v, n)| (v.as_str(), *n)),
+            );
+        }
+        histogram
+    }
+
+    fn extend<'a, I>(&mut self, bucketing: Bucketing, values: I)
+    where
+        I: IntoIterator<Item = (&'a str, usize)>,
+    {
+        if let Bucketing::Width(width) = bucketing {
+            assert!(width > 0.0, "bucket width must be positive");
+        }
+        for (value, files) in values {
+            match bucketing.start(split_weight(value).0) {
+                Some(start) => match self.buckets.iter_mut().find(|b| b.start == start) {
+                    Some(bucket) => bucket.files += files,
+                    None => self.buckets.push(Bucket { start, files }),
+                },
+                None => self.skipped += files,
+            }
+        }
+        self.buckets
+            .sort_by(|a, b| compare_starts(&a.start, &b.start));
+    }
+}
+
+fn compare_starts(a: &Comparand, b: &Comparand) -> Ordering {
+    let number = |comparand: &Comparand| match comparand {
+        Comparand::Int(value) => Some(f64::from(*value)),
+        Comparand::Float(value) => Some(*value),
+        Comparand::Date(_) => None,
+    };
+    match (a, b) {
+        (Comparand::Date(a), Comparand::Date(b)) => a.cmp(b),
+        _ => number(a)
+            .zip(number(b))
+            .map_or(Ordering::Equal, |(a, b)| a.total_cmp(&b)),
+    }
+}
+
+// Values of tag, also under weighted names like `rating#0.5`
+fn values_of<'a>(tags: &'a XTags, tag: &'a str) -> impl Iterator<Item = &'a str> {
+    tags.iter()
+        .filter(move |(name, _)| split_weight(name).0 == tag)
+        .filter_map(|(_, value)| value.as_deref())
+}
+
+/// Computes Histogram of tag over all files below root.
+///
+/// # Errors
+///
+/// Same as walk and get_tags, files without attribute support are handled by options
+///
+/// # Panics
+///
+/// If bucketing is Bucketing::Width and the width isn't positive.
+pub fn histogram(
+    tag: &str,
+    root: &Path,
+    options: &WalkOptions,
+    bucketing: Bucketing,
+) -> Result<Histogram> {
+    let mut values: Vec<String> = Vec::new();
+    let tree = TreeTags::new(root, options);
+    for path in walk(root, options) {
+        if let Some(tags) = tree.get(&path?)? {
+            values.extend(values_of(&tags, tag).map(str::to_string));
+        }
+    }
+    let mut histogram = Histogram::default();
+    histogram.extend(bucketing, values.iter().map(|value| (value.as_str(), 1)));
+    Ok(histogram)
+}
+
+impl Index {
+    /// Computes Histogram of tag over all indexed files, see histogram.
+    ///
+    /// # Panics
+    ///
+    /// If bucketing is Bucketing::Width and the width isn't positive.
+    pub fn histogram(&self, tag: &str, bucketing: Bucketing) -> Histogram {
+        let values = self.iter().flat_map(|(_, tags)| values_of(tags, tag));
+        let mut histogram = Histogram::default();
+        histogram.extend(bucketing, values.map(|value| (value, 1)));
+        histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        collect_stats, collect_stats_dir, histogram, Bucketing, Cooccurrence, Histogram,
+        NumericSummary, TagStats,
+    };
+    use crate::{csl_to_map, Comparand, Date, Fixture, Index, WalkOptions};
+
+    #[test]
+    fn stats_count_files_once_per_namespace() {
+        let tags = [
+            csl_to_map("a:b:c,a:b:d,a:e=1").unwrap(),
+            csl_to_map("a:e=1,f").unwrap(),
+            csl_to_map("a:e=2").unwrap(),
+        ];
+        let stats: TagStats = tags.iter().collect();
+        assert_eq!(stats.files(), 3);
+        assert_eq!(stats.namespace_files("a:"), 3);
+        assert_eq!(stats.namespace_files("a:b:"), 1);
+        let e = stats.tag("a:e").unwrap();
+        assert_eq!(e.files, 3);
+        assert_eq!(e.values.get("1"), Some(&2));
+        assert_eq!(
+            stats.children_of(""),
+            vec![("a:".to_string(), 3), ("f".to_string(), 1)]
+        );
+        assert_eq!(
+            stats.children_of("a:b:"),
+            vec![("a:b:c".to_string(), 1), ("a:b:d".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn stats_of_files_summarize_integers() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("rating=1,photo")
+            .file("b")
+            .tags("rating=4#0.5")
+            .file("c")
+            .tags("rating=n/a");
+        let stats = collect_stats_dir(fixture.path(), &WalkOptions::default()).unwrap();
+        assert_eq!(stats.files(), 3);
+        assert_eq!(
+            stats.tag("rating").unwrap().numeric(),
+            Some(NumericSummary {
+                values: 2,
+                skipped: 1,
+                min: 1,
+                max: 4,
+                mean: 2.5
+            })
+        );
+        assert!(stats.tag("photo").unwrap().numeric().is_none());
+        let paths = [fixture.join("a"), fixture.join("b"), fixture.join("c")];
+        assert_eq!(collect_stats(&paths).unwrap(), stats);
+        assert!(collect_stats([fixture.join("missing")]).is_err());
+    }
+
+    #[test]
+    fn cooccurrence_ranks_by_lift() {
+        let tags = [
+            csl_to_map("a,b,c").unwrap(),
+            csl_to_map("a,b").unwrap(),
+            csl_to_map("a,c").unwrap(),
+            csl_to_map("d").unwrap(),
+        ];
+        let cooccurrence: Cooccurrence = tags.iter().collect();
+        assert_eq!(cooccurrence.count("b", "a"), 2);
+        assert_eq!(cooccurrence.count("a", "d"), 0);
+        assert_eq!(cooccurrence.lift("b", "c"), Some(1.0));
+        assert!(cooccurrence.lift("a", "x").is_none());
+        let related = cooccurrence.related("b", 5);
+        assert_eq!(related.len(), 2);
+        assert_eq!(related[0].tag, "a");
+        assert_eq!(related[0].files, 2);
+        assert_eq!(cooccurrence.pairs().count(), 3);
+    }
+
+    #[test]
+    fn cooccurrence_leaves_out_weights() {
+        let tags = [
+            csl_to_map("a#2,b").unwrap(),
+            csl_to_map("a,a#0.5,b#1=x").unwrap(),
+        ];
+        let cooccurrence: Cooccurrence = tags.iter().collect();
+        assert_eq!(cooccurrence.count("a", "b"), 2);
+        assert_eq!(cooccurrence.count("a#2", "b"), 0);
+        assert_eq!(cooccurrence.pairs().count(), 1);
+    }
+
+    #[test]
+    fn suggestions_skip_present_tags() {
+        let tags = [
+            csl_to_map("beach,sea,sun").unwrap(),
+            csl_to_map("beach,sea").unwrap(),
+            csl_to_map("beach,city").unwrap(),
+        ];
+        let cooccurrence: Cooccurrence = tags.iter().collect();
+        let suggestions = cooccurrence.suggest(&csl_to_map("beach,sun").unwrap(), 5);
+        assert_eq!(suggestions[0], ("sea".to_string(), (2.0 / 3.0 + 1.0) / 2.0));
+        assert_eq!(suggestions.len(), 2);
+        assert!(suggestions.iter().all(|(tag, _)| tag != "sun"));
+        assert!(cooccurrence
+            .suggest(&csl_to_map("new").unwrap(), 5)
+            .is_empty());
+    }
+
+    #[test]
+    fn histograms_group_numbers_and_dates() {
+        let tags = [
+            csl_to_map("rating=1.5,shot=2023-06-01").unwrap(),
+            csl_to_map("rating=2#0.5,shot=2023-06-30T10:00").unwrap(),
+            csl_to_map("rating=-1,shot=2024-01-01").unwrap(),
+            csl_to_map("rating=high,shot=unknown").unwrap(),
+        ];
+        let stats: TagStats = tags.iter().collect();
+        let ratings = Histogram::from_stats(&stats, "rating", Bucketing::Width(2.0));
+        let starts: Vec<_> = ratings.buckets.iter().map(|b| (b.start, b.files)).collect();
+        assert_eq!(
+            starts,
+            vec![
+                (Comparand::Int(-2), 1),
+                (Comparand::Int(0), 1),
+                (Comparand::Int(2), 1)
+            ]
+        );
+        assert_eq!(ratings.skipped, 1);
+        let months = Histogram::from_stats(&stats, "shot", Bucketing::Month);
+        assert_eq!(months.buckets.len(), 2);
+        assert_eq!(
+            months.buckets[0].start,
+            Comparand::Date(Date::new(2023, 6, 1).unwrap())
+        );
+        assert_eq!(months.buckets[0].files, 2);
+        assert!(Histogram::from_stats(&stats, "missing", Bucketing::Value)
+            .buckets
+            .is_empty());
+
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("year=2023")
+            .file("b")
+            .tags("year=2023")
+            .file("c")
+            .tags("year=2024");
+        let options = WalkOptions::default();
+        let years = histogram("year", fixture.path(), &options, Bucketing::Value).unwrap();
+        assert_eq!(years.buckets[0].files, 2);
+        let index = Index::build(fixture.path(), &options).unwrap();
+        assert_eq!(index.histogram("year", Bucketing::Value), years);
+    }
+
+    #[test]
+    fn histograms_find_weighted_tags() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("rating#0.5=3")
+            .file("b")
+            .tags("rating=3#2")
+            .file("c")
+            .tags("rating#2=4");
+        let options = WalkOptions::default();
+        let ratings = histogram("rating", fixture.path(), &options, Bucketing::Value).unwrap();
+        let starts: Vec<_> = ratings.buckets.iter().map(|b| (b.start, b.files)).collect();
+        assert_eq!(
+            starts,
+            vec![(Comparand::Int(3), 2), (Comparand::Int(4), 1)]
+        );
+        let index = Index::build(fixture.path(), &options).unwrap();
+        assert_eq!(index.histogram("rating", Bucketing::Value), ratings);
+    }
+}
@@ -0,0 +1,568 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+
+use crate::read_only::ensure_writable;
+use crate::{
+    csl_to_map, delete_tags_from, delete_tags_in, get_sidecar_tags, get_tags_from, get_tags_in,
+    map_to_csl, set_sidecar_tags, set_tags_in, set_tags_to, sidecar_path, split_weight,
+    MergePolicy, Namespace, Result, XTagError, XTags, XATTR_NAME,
+};
+
+/// Attribute of the tags of the XDG shared file metadata specification.
+pub const XDG_TAGS_ATTRIBUTE: &str = "user.xdg.tags";
+
+/// Name of the hidden file holding the tags of all files of its directory.
+pub const DIRECTORY_SIDECAR_NAME: &str = ".xtags";
+
+/// Storage backend of tags.
+///
+/// get_tags, set_tags and delete_tags use the `user.xtag` attribute. Functions with the suffix
+/// `_with_store`, like merge_tags_with_store, work on any Store instead, so tags can be kept in
+/// other attributes, sidecar files or databases. Tree operations like search_dir, gc or
+/// export_tree use the store of WalkOptions::store, apply_manifest and bulk_apply the one of
+/// their options.
+///
+/// XattrStore, Namespace, SidecarStore and Stores are the backends of this crate.
+pub trait Store {
+    /// Get tags for file as map, empty if it has none.
+    fn get_tags(&self, path: &Path) -> Result<XTags>;
+
+    /// Replace all tags of file.
+    fn set_tags(&self, path: &Path, tags: &XTags) -> Result<()>;
+
+    /// Delete all tags of file, files without tags are no error.
+    fn delete_tags(&self, path: &Path) -> Result<()>;
+}
+
+impl fmt::Debug for dyn Store + Send + Sync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Store")
+    }
+}
+
+// Backend of get_tags, set_tags and delete_tags
+static XATTR_STORE: LazyLock<XattrStore> = LazyLock::new(XattrStore::default);
+
+// Store of the `user.xtag` attribute, for functions without store of their own
+pub(crate) fn xattr_store() -> &'static dyn Store {
+    &*XATTR_STORE
+}
+
+// Store set in options, the `user.xtag` attribute like get_tags without one
+pub(crate) fn store_or_xattr(store: &Option<Arc<dyn Store + Send + Sync>>) -> &dyn Store {
+    match store {
+        Some(store) => store.as_ref(),
+        None => xattr_store(),
+    }
+}
+
+/// Tags in extended attributes with configurable names.
+///
+/// Tags are written to the first attribute. Reading merges the tags of all attributes, of tags
+/// stored in several attributes the first one wins. Writing also removes the tags left out from
+/// the other attributes, so removed tags don't come back, their remaining tags are kept for the
+/// tools writing them. delete_tags removes all attributes.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// use xtag::Store;
+///
+/// // Write XDG tags, but also see tags written by xtag
+/// let store = xtag::XattrStore::xdg().merging("user.xtag");
+/// let tags = store.get_tags(Path::new("a.jpg")).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XattrStore {
+    attributes: Vec<String>,
+}
+
+impl Default for XattrStore {
+    fn default() -> Self {
+        XattrStore::new(XATTR_NAME)
+    }
+}
+
+impl XattrStore {
+    /// Returns store using attribute, including its namespace like `user.xtag`.
+    pub fn new(attribute: &str) -> Self {
+        XattrStore {
+            attributes: vec![attribute.to_string()],
+        }
+    }
+
+    /// Returns store using the attribute of the XDG specification, see XDG_TAGS_ATTRIBUTE.
+    pub fn xdg() -> Self {
+        XattrStore::new(XDG_TAGS_ATTRIBUTE)
+    }
+
+    /// Adds attribute whose tags are merged in when reading.
+    pub fn merging(mut self, attribute: &str) -> Self {
+        self.attributes.push(attribute.to_string());
+        self
+    }
+
+    /// Attribute tags are written to.
+    pub fn attribute(&self) -> &str {
+        &self.attributes[0]
+    }
+
+    /// All attributes in order of precedence.
+    pub fn attributes(&self) -> impl Iterator<Item = &str> {
+        self.attributes.iter().map(String::as_str)
+    }
+}
+
+impl Store for XattrStore {
+    fn get_tags(&self, path: &Path) -> Result<XTags> {
+        let mut tags = XTags::new();
+        for attribute in self.attributes.iter().rev() {
+            tags.extend(get_tags_from(path, attribute)?);
+        }
+        Ok(tags)
+    }
+
+    fn set_tags(&self, path: &Path, tags: &XTags) -> Result<()> {
+        set_tags_to(path, self.attribute(), tags)?;
+        for attribute in &self.attributes[1..] {
+            let mut merged = get_tags_from(path, attribute)?;
+            let len = merged.len();
+            merged.retain(|tag, _| tags.contains_key(tag));
+            if merged.len() == len {
+                continue;
+            }
+            match merged.is_empty() {
+                true => delete_tags_from(path, attribute)?,
+                false => set_tags_to(path, attribute, &merged)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn delete_tags(&self, path: &Path) -> Result<()> {
+        self.attributes
+            .iter()
+            .try_for_each(|attribute| delete_tags_from(path, attribute))
+    }
+}
+
+impl Store for Namespace {
+    fn get_tags(&self, path: &Path) -> Result<XTags> {
+        get_tags_in(path, *self)
+    }
+
+    fn set_tags(&self, path: &Path, tags: &XTags) -> Result<()> {
+        set_tags_in(path, *self, tags)
+    }
+
+    fn delete_tags(&self, path: &Path) -> Result<()> {
+        delete_tags_in(path, *self)
+    }
+}
+
+/// Where SidecarStore keeps tags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SidecarLayout {
+    /// One file per tagged file, `a.jpg` has its tags in `a.jpg.xtag` like get_sidecar_tags.
+    #[default]
+    PerFile,
+
+    /// One file DIRECTORY_SIDECAR_NAME per directory with lines `<name><TAB><tags>`.
+    PerDirectory,
+}
+
+/// Tags in sidecar files, for file systems without extended attributes like FAT or some network
+/// shares.
+///
+/// With with_xattr, tags are kept in extended attributes and only files whose file system
+/// rejects them with ENOTSUP fall back to sidecar files.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// use xtag::{SidecarLayout, SidecarStore, Store, XattrStore};
+///
+/// let store = SidecarStore::new(SidecarLayout::PerDirectory).with_xattr(XattrStore::default());
+/// xtag::add_tags_with_store(
+///     Path::new("/media/stick/a.jpg"),
+///     &xtag::csl_to_map("photo").unwrap(),
+///     &store,
+/// )
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SidecarStore {
+    layout: SidecarLayout,
+    xattr: Option<XattrStore>,
+}
+
+impl SidecarStore {
+    /// Returns store keeping all tags in sidecar files of layout.
+    pub fn new(layout: SidecarLayout) -> Self {
+        SidecarStore {
+            layout,
+            xattr: None,
+        }
+    }
+
+    /// Uses xattr and sidecar files only for files whose file system doesn't support it.
+    pub fn with_xattr(mut self, xattr: XattrStore) -> Self {
+        self.xattr = Some(xattr);
+        self
+    }
+
+    /// Layout of sidecar files.
+    pub fn layout(&self) -> SidecarLayout {
+        self.layout
+    }
+
+    // Runs access on xattr if set, returns None if sidecar files are to be used instead
+    fn try_xattr<T>(&self, access: impl FnOnce(&XattrStore) -> Result<T>) -> Result<Option<T>> {
+        let Some(xattr) = &self.xattr else {
+            return Ok(None);
+        };
+        match access(xattr) {
+            Err(XTagError::File(err)) if err.kind() == io::ErrorKind::Unsupported => Ok(None),
+            result => result.map(Some),
+        }
+    }
+}
+
+// Directory sidecar of path and the name path has in it
+fn directory_sidecar(path: &Path) -> Result<(PathBuf, String)> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?
+        .to_str()
+        .ok_or_else(|| XTagError::NonUnicodePath(path.to_path_buf()))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    Ok((dir.join(DIRECTORY_SIDECAR_NAME), name.to_string()))
+}
+
+// Names of tagged files with their tags as comma separated list
+fn read_directory_sidecar(sidecar: &Path) -> Result<BTreeMap<String, String>> {
+    let text = match fs::read_to_string(sidecar) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(name, csl)| (name.to_string(), csl.to_string()))
+        .collect())
+}
+
+// Replaces sidecar atomically, removes it if nothing is tagged
+fn write_directory_sidecar(sidecar: &Path, entries: &BTreeMap<String, String>) -> Result<()> {
+    if entries.is_empty() {
+        return match fs::remove_file(sidecar) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        };
+    }
+    let lines: String = entries
+        .iter()
+        .map(|(name, csl)| format!("{name}\t{csl}\n"))
+        .collect();
+    let temp = sidecar.with_extension("tmp");
+    fs::write(&temp, lines)?;
+    fs::rename(temp, sidecar)?;
+    Ok(())
+}
+
+// Changes entry of path in its directory sidecar
+fn update_directory_sidecar(path: &Path, csl: Option<String>) -> Result<()> {
+    ensure_writable(path)?;
+    let (sidecar, name) = directory_sidecar(path)?;
+    if name.contains(['\t', '\n']) {
+        let message = "file name contains tab or line break";
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message).into());
+    }
+    // The directory itself is locked, so updates of sibling files don't lose each other's entries
+    // and no lock file is left behind
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let lock = fs::File::open(dir)?;
+    lock.lock()?;
+    let mut entries = read_directory_sidecar(&sidecar)?;
+    let changed = match csl {
+        Some(csl) => entries.insert(name, csl.clone()).as_ref() != Some(&csl),
+        None => entries.remove(&name).is_some(),
+    };
+    if changed {
+        write_directory_sidecar(&sidecar, &entries)?;
+    }
+    Ok(())
+}
+
+impl Store for SidecarStore {
+    fn get_tags(&self, path: &Path) -> Result<XTags> {
+        if let Some(tags) = self.try_xattr(|xattr| xattr.get_tags(path))? {
+            return Ok(tags);
+        }
+        match self.layout {
+            SidecarLayout::PerFile => Ok(get_sidecar_tags(path)?.unwrap_or_default()),
+            SidecarLayout::PerDirectory => {
+                let (sidecar, name) = directory_sidecar(path)?;
+                let entries = read_directory_sidecar(&sidecar)?;
+                match entries.get(&name) {
+                    Some(csl) => csl_to_map(csl),
+                    None => Ok(XTags::new()),
+                }
+            }
+        }
+    }
+
+    fn set_tags(&self, path: &Path, tags: &XTags) -> Result<()> {
+        if self
+            .try_xattr(|xattr| xattr.set_tags(path, tags))?
+            .is_some()
+        {
+            return Ok(());
+        }
+        match self.layout {
+            SidecarLayout::PerFile => set_sidecar_tags(path, tags),
+            SidecarLayout::PerDirectory => update_directory_sidecar(path, Some(map_to_csl(tags))),
+        }
+    }
+
+    fn delete_tags(&self, path: &Path) -> Result<()> {
+        if self.try_xattr(|xattr| xattr.delete_tags(path))?.is_some() {
+            return Ok(());
+        }
+        ensure_writable(path)?;
+        match self.layout {
+            SidecarLayout::PerFile => match fs::remove_file(sidecar_path(path)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            },
+            SidecarLayout::PerDirectory => update_directory_sidecar(path, None),
+        }
+    }
+}
+
+/// Merge tags into the tags of file in store, see merge_tags.
+pub fn merge_tags_with_store(
+    path: &Path,
+    tags: &XTags,
+    policy: MergePolicy,
+    store: &dyn Store,
+) -> Result<()> {
+    let mut current = store.get_tags(path)?;
+    let mut changed = false;
+    for (tag, value) in tags {
+        // A weight of a tag without value is part of its name, so the plain names are compared
+        let plain = split_weight(tag).0;
+        let existing = current
+            .iter()
+            .find(|(existing, _)| split_weight(existing).0 == plain);
+        match existing {
+            Some(_) if policy == MergePolicy::KeepExisting => {}
+            Some(existing) if existing == (tag, value) => {}
+            existing => {
+                if let Some((existing, _)) = existing {
+                    let existing = existing.clone();
+                    current.remove(&existing);
+                }
+                current.insert(tag.clone(), value.clone());
+                changed = true;
+            }
+        }
+    }
+    if changed {
+        store.set_tags(path, &current)?;
+    }
+    Ok(())
+}
+
+/// Add tags to file in store, see add_tags.
+pub fn add_tags_with_store(path: &Path, tags: &XTags, store: &dyn Store) -> Result<()> {
+    merge_tags_with_store(path, tags, MergePolicy::Overwrite, store)
+}
+
+/// Remove tags from file in store, see remove_tags.
+pub fn remove_tags_with_store(path: &Path, tags: &[&str], store: &dyn Store) -> Result<()> {
+    let mut current = store.get_tags(path)?;
+    let len = current.len();
+    current.retain(|tag, _| !tags.contains(&tag.as_str()) && !tags.contains(&split_weight(tag).0));
+    if current.len() == len {
+        Ok(())
+    } else if current.is_empty() {
+        store.delete_tags(path)
+    } else {
+        store.set_tags(path, &current)
+    }
+}
+
+// Replaces tags of file, deleting them if tags is empty
+fn replace_tags(path: &Path, tags: &XTags, store: &dyn Store) -> Result<()> {
+    if tags.is_empty() {
+        store.delete_tags(path)
+    } else {
+        store.set_tags(path, tags)
+    }
+}
+
+/// Exchange the tags of two files in store, see swap_tags.
+pub fn swap_tags_with_store(a: &Path, b: &Path, store: &dyn Store) -> Result<()> {
+    let tags_a = store.get_tags(a)?;
+    let tags_b = store.get_tags(b)?;
+    if tags_a == tags_b {
+        return Ok(());
+    }
+    replace_tags(a, &tags_b, store)?;
+    if let Err(err) = replace_tags(b, &tags_a, store) {
+        // b is unchanged, a write replaces all tags or fails
+        return match replace_tags(a, &tags_a, store) {
+            Ok(()) => Err(err),
+            Err(rollback) => Err(XTagError::RollbackFailed {
+                path: a.to_path_buf(),
+                tags: map_to_csl(&tags_a),
+                source: Box::new(rollback),
+            }),
+        };
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{
+        add_tags_with_store, remove_tags_with_store, swap_tags_with_store, SidecarLayout,
+        SidecarStore, Store, XattrStore, DIRECTORY_SIDECAR_NAME,
+    };
+    use crate::{csl_to_map, get_tags, set_tags, sidecar_path, swap_tags, Fixture, Result, XTags};
+
+    #[test]
+    fn attribute_stores_merge_attributes() {
+        let fixture = Fixture::new().file("a").tags("photo,rating=3");
+        let path = fixture.join("a");
+        let store = XattrStore::xdg().merging("user.xtag");
+        assert_eq!(store.attribute(), "user.xdg.tags");
+
+        add_tags_with_store(&path, &csl_to_map("rating=5,red").unwrap(), &store).unwrap();
+        assert_eq!(
+            XattrStore::xdg().get_tags(&path).unwrap(),
+            csl_to_map("photo,rating=5,red").unwrap()
+        );
+
+        remove_tags_with_store(&path, &["red"], &store).unwrap();
+        assert_eq!(
+            XattrStore::xdg().get_tags(&path).unwrap(),
+            csl_to_map("photo,rating=5").unwrap()
+        );
+        assert_eq!(
+            get_tags(&path).unwrap(),
+            csl_to_map("photo,rating=3").unwrap()
+        );
+
+        // Removed tags don't come back from the other attribute
+        remove_tags_with_store(&path, &["photo"], &store).unwrap();
+        assert_eq!(
+            store.get_tags(&path).unwrap(),
+            csl_to_map("rating=5").unwrap()
+        );
+        assert_eq!(get_tags(&path).unwrap(), csl_to_map("rating=3").unwrap());
+        store.delete_tags(&path).unwrap();
+        assert!(store.get_tags(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sidecar_stores_keep_tags_next_to_files() {
+        let fixture = Fixture::new().file("a").file("b");
+        let (a, b) = (fixture.join("a"), fixture.join("b"));
+        let tags = csl_to_map("photo,rating=5").unwrap();
+
+        let store = SidecarStore::new(SidecarLayout::PerDirectory);
+        store.set_tags(&a, &tags).unwrap();
+        add_tags_with_store(&b, &csl_to_map("x=1\\,2").unwrap(), &store).unwrap();
+        assert_eq!(store.get_tags(&a).unwrap(), tags);
+        assert_eq!(store.get_tags(&b).unwrap(), csl_to_map("x=1\\,2").unwrap());
+        assert!(get_tags(&a).unwrap().is_empty());
+        store.delete_tags(&a).unwrap();
+        remove_tags_with_store(&b, &["x"], &store).unwrap();
+        assert!(store.get_tags(&b).unwrap().is_empty());
+        assert!(!fixture.join(DIRECTORY_SIDECAR_NAME).exists());
+
+        // Concurrent updates of sibling files keep all entries
+        let names: Vec<String> = (0..8).map(|n| format!("f{n}")).collect();
+        let tags_of = |name: &str| csl_to_map(&format!("name={name}")).unwrap();
+        std::thread::scope(|scope| {
+            for name in &names {
+                let (store, path) = (&store, fixture.join(name));
+                std::fs::write(&path, "").unwrap();
+                scope.spawn(move || store.set_tags(&path, &tags_of(name)).unwrap());
+            }
+        });
+        for name in &names {
+            assert_eq!(store.get_tags(&fixture.join(name)).unwrap(), tags_of(name));
+        }
+
+        let store = SidecarStore::new(SidecarLayout::PerFile);
+        store.set_tags(&a, &tags).unwrap();
+        assert_eq!(store.get_tags(&a).unwrap(), tags);
+        store.delete_tags(&a).unwrap();
+        store.delete_tags(&a).unwrap();
+        assert!(!sidecar_path(&a).exists());
+
+        // Tags stay in attributes where they are supported
+        let store = store.with_xattr(XattrStore::default());
+        store.set_tags(&a, &tags).unwrap();
+        assert_eq!(get_tags(&a).unwrap(), tags);
+        assert!(!sidecar_path(&a).exists());
+    }
+
+    // Fails writing files named b
+    struct FailingStore;
+
+    impl Store for FailingStore {
+        fn get_tags(&self, path: &Path) -> Result<XTags> {
+            get_tags(path)
+        }
+
+        fn set_tags(&self, path: &Path, tags: &XTags) -> Result<()> {
+            if path.ends_with("b") {
+                return Err(std::io::Error::other("read-only").into());
+            }
+            set_tags(path, tags)
+        }
+
+        fn delete_tags(&self, path: &Path) -> Result<()> {
+            XattrStore::default().delete_tags(path)
+        }
+    }
+
+    #[test]
+    fn tags_are_swapped_or_rolled_back() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("old,rating=2")
+            .file("b")
+            .tags("new")
+            .file("c");
+        let (a, b, c) = (fixture.join("a"), fixture.join("b"), fixture.join("c"));
+        swap_tags(&a, &b).unwrap();
+        assert_eq!(get_tags(&a).unwrap(), csl_to_map("new").unwrap());
+        assert_eq!(get_tags(&b).unwrap(), csl_to_map("old,rating=2").unwrap());
+        swap_tags(&b, &c).unwrap();
+        assert!(get_tags(&b).unwrap().is_empty());
+        assert_eq!(get_tags(&c).unwrap(), csl_to_map("old,rating=2").unwrap());
+        assert!(swap_tags(&a, &fixture.join("missing")).is_err());
+        assert_eq!(get_tags(&a).unwrap(), csl_to_map("new").unwrap());
+
+        assert!(swap_tags_with_store(&a, &b, &FailingStore).is_err());
+        assert_eq!(get_tags(&a).unwrap(), csl_to_map("new").unwrap());
+        assert!(get_tags(&b).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,326 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::read_only::ensure_writable;
+use crate::{
+    csl_to_map, delete_tags, get_tags, map_to_csl, set_tags, walk, DegradationPolicy, Index,
+    Result, Searcher, Store, WalkOptions, XTagError, XTags,
+};
+
+/// Extension of sidecar files, `a.jpg` has its tags in `a.jpg.xtag`.
+pub const SIDECAR_EXTENSION: &str = "xtag";
+
+/// Returns path of the sidecar file of path.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_os_string();
+    sidecar.push(".");
+    sidecar.push(SIDECAR_EXTENSION);
+    PathBuf::from(sidecar)
+}
+
+/// Get tags from sidecar file of path, None if there is none
+///
+/// Sidecar files hold tags in the same comma separated format as the attribute, for file systems
+/// or transfers that lose extended attributes.
+pub fn get_sidecar_tags(path: &Path) -> Result<Option<XTags>> {
+    match fs::read_to_string(sidecar_path(path)) {
+        Ok(string) => Ok(Some(csl_to_map(string.trim_end())?)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Set tags in sidecar file of path
+pub fn set_sidecar_tags(path: &Path, tags: &XTags) -> Result<()> {
+    ensure_writable(path)?;
+    fs::write(sidecar_path(path), map_to_csl(tags) + "\n")?;
+    Ok(())
+}
+
+/// Places tags of a file can come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TagStore {
+    /// Extended attribute of the file, like get_tags.
+    Xattr,
+
+    /// Sidecar file next to the file, like get_sidecar_tags.
+    Sidecar,
+
+    /// Index set in Stores.
+    Index,
+}
+
+/// Tag whose value differs between stores.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Conflict {
+    /// Tag as stored.
+    pub tag: String,
+
+    /// Value of tag per store holding it, in order of precedence. The first one won.
+    pub values: Vec<(TagStore, Option<String>)>,
+}
+
+/// Tags of a file merged from all stores.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StoredTags {
+    /// Union of the tags of all stores.
+    pub tags: XTags,
+
+    /// Tags with different values in different stores, sorted by tag.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Combination of tag stores treated as one.
+///
+/// Tags of all stores are merged. If stores disagree about the value of a tag, the store coming
+/// first in precedence wins and the disagreement is reported as Conflict.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let stores = xtag::Stores::default();
+/// let searcher = xtag::compile_search("photo").unwrap();
+/// let options = xtag::WalkOptions::default();
+/// for result in stores.search(Path::new("."), &searcher, &options) {
+///     let (path, conflicts) = result.unwrap();
+///     println!("{} ({} conflicts)", path.display(), conflicts.len());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Stores<'a> {
+    /// Stores to consult, highest precedence first.
+    pub precedence: Vec<TagStore>,
+
+    /// Index consulted for TagStore::Index.
+    pub index: Option<&'a Index>,
+
+    /// Handling of files on file systems without extended attributes. Sidecar and IndexOnly
+    /// leave out the attributes like Skip, the other stores of precedence are still consulted.
+    pub unsupported: DegradationPolicy,
+}
+
+impl Default for Stores<'_> {
+    fn default() -> Self {
+        Stores {
+            precedence: vec![TagStore::Xattr, TagStore::Sidecar],
+            index: None,
+            unsupported: DegradationPolicy::Skip,
+        }
+    }
+}
+
+impl<'a> Stores<'a> {
+    /// Get merged tags of path from all stores.
+    ///
+    /// # Errors
+    ///
+    /// Same as get_tags and get_sidecar_tags
+    pub fn get_tags(&self, path: &Path) -> Result<StoredTags> {
+        let mut sources: Vec<(TagStore, XTags)> = Vec::new();
+        for &store in &self.precedence {
+            let tags = match store {
+                TagStore::Xattr => self.unsupported.handle(get_tags(path))?,
+                TagStore::Sidecar => get_sidecar_tags(path)?,
+                TagStore::Index => self.index.and_then(|index| index.tags(path)).cloned(),
+            };
+            if let Some(tags) = tags {
+                sources.push((store, tags));
+            }
+        }
+        Ok(reconcile(sources))
+    }
+
+    /// Iterates over all files below root whose merged tags match searcher.
+    ///
+    /// Yields every match together with its conflicts. Sidecar files aren't searched themselves
+    /// if the sidecar store is consulted.
+    pub fn search<'b>(
+        &'b self,
+        root: &Path,
+        searcher: &'b Searcher,
+        options: &WalkOptions,
+    ) -> impl Iterator<Item = Result<(PathBuf, Vec<Conflict>)>> + 'b {
+        let skip_sidecars = self.precedence.contains(&TagStore::Sidecar);
+        walk(root, options).filter_map(move |path| {
+            let path = match path {
+                Ok(path) => path,
+                Err(err) => return Some(Err(err)),
+            };
+            if skip_sidecars && is_sidecar(&path) {
+                return None;
+            }
+            match self.get_tags(&path) {
+                Ok(stored) if searcher.is_match(&stored.tags) => Some(Ok((path, stored.conflicts))),
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            }
+        })
+    }
+}
+
+/// Stores as one Store of the merged tags.
+///
+/// Tags are written to the attribute and the sidecar file, whichever of them precedence lists,
+/// so removed tags don't come back from the other one. Attributes are left out as
+/// unsupported says, sidecar files are only written if they exist already or the attribute
+/// wasn't written. The Index isn't written, refresh it to see the changes.
+///
+/// # Errors
+///
+/// - XTagError::ReadOnly if precedence lists no store tags can be written to
+impl Store for Stores<'_> {
+    fn get_tags(&self, path: &Path) -> Result<XTags> {
+        Stores::get_tags(self, path).map(|stored| stored.tags)
+    }
+
+    fn set_tags(&self, path: &Path, tags: &XTags) -> Result<()> {
+        self.write(path, |store| match store {
+            TagStore::Xattr => set_tags(path, tags),
+            _ => set_sidecar_tags(path, tags),
+        })
+    }
+
+    fn delete_tags(&self, path: &Path) -> Result<()> {
+        self.write(path, |store| match store {
+            TagStore::Xattr => delete_tags(path),
+            _ => match fs::remove_file(sidecar_path(path)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            },
+        })
+    }
+}
+
+impl Stores<'_> {
+    // Calls write for the attribute and sidecar file in order of precedence
+    fn write<F>(&self, path: &Path, mut write: F) -> Result<()>
+    where
+        F: FnMut(TagStore) -> Result<()>,
+    {
+        let mut written = false;
+        for &store in &self.precedence {
+            match store {
+                TagStore::Xattr => written |= self.unsupported.handle(write(store))?.is_some(),
+                TagStore::Sidecar if written && get_sidecar_tags(path)?.is_none() => (),
+                TagStore::Sidecar => {
+                    write(store)?;
+                    written = true;
+                }
+                TagStore::Index => (),
+            }
+        }
+        match written {
+            true => Ok(()),
+            false => Err(XTagError::ReadOnly(path.to_path_buf())),
+        }
+    }
+}
+
+// Sidecar files belong to a file next to them
+fn is_sidecar(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == SIDECAR_EXTENSION) && path.with_extension("").exists()
+}
+
+// Merges tags of stores given in order of precedence
+fn reconcile(sources: Vec<(TagStore, XTags)>) -> StoredTags {
+    let mut stored = StoredTags::default();
+    for (index, (_, tags)) in sources.iter().enumerate() {
+        for (tag, value) in tags {
+            if stored.tags.contains_key(tag) {
+                continue;
+            }
+            stored.tags.insert(tag.clone(), value.clone());
+            let values: Vec<(TagStore, Option<String>)> = sources[index..]
+                .iter()
+                .filter_map(|(store, tags)| tags.get(tag).map(|value| (*store, value.clone())))
+                .collect();
+            if values.iter().any(|(_, other)| other != value) {
+                stored.conflicts.push(Conflict {
+                    tag: tag.clone(),
+                    values,
+                });
+            }
+        }
+    }
+    stored.conflicts.sort_by(|a, b| a.tag.cmp(&b.tag));
+    stored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_sidecar_tags, set_sidecar_tags, Stores, TagStore};
+    use crate::{
+        add_tags_with_store, compile_search, csl_to_map, get_tags, remove_tags_with_store, Fixture,
+        Index, Store, WalkOptions, XTagError, XTags,
+    };
+
+    #[test]
+    fn stores_are_merged_by_precedence() {
+        let fixture = Fixture::new()
+            .file("a.jpg")
+            .tags("photo,rating=5")
+            .file("b.jpg");
+        let sidecar_tags = csl_to_map("rating=3,city=Rome").unwrap();
+        set_sidecar_tags(&fixture.join("a.jpg"), &sidecar_tags).unwrap();
+        set_sidecar_tags(&fixture.join("b.jpg"), &csl_to_map("photo").unwrap()).unwrap();
+
+        let stores = Stores::default();
+        let stored = stores.get_tags(&fixture.join("a.jpg")).unwrap();
+        assert_eq!(stored.tags.len(), 3);
+        assert_eq!(stored.tags["rating"].as_deref(), Some("5"));
+        assert_eq!(stored.conflicts.len(), 1);
+        assert_eq!(stored.conflicts[0].values[1].0, TagStore::Sidecar);
+
+        let searcher = compile_search("photo").unwrap();
+        let options = WalkOptions::default();
+        let mut found: Vec<_> = stores
+            .search(fixture.path(), &searcher, &options)
+            .map(Result::unwrap)
+            .collect();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].0, fixture.join("b.jpg"));
+
+        let index = Index::build(fixture.path(), &options).unwrap();
+        let stores = Stores {
+            precedence: vec![TagStore::Sidecar, TagStore::Index],
+            index: Some(&index),
+            ..Default::default()
+        };
+        let stored = stores.get_tags(&fixture.join("a.jpg")).unwrap();
+        assert_eq!(stored.tags["rating"].as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn stores_write_all_their_tags() {
+        let fixture = Fixture::new().file("a").tags("photo").file("b");
+        let (a, b) = (fixture.join("a"), fixture.join("b"));
+        set_sidecar_tags(&a, &csl_to_map("old").unwrap()).unwrap();
+
+        let stores = Stores::default();
+        remove_tags_with_store(&a, &["old"], &stores).unwrap();
+        assert_eq!(
+            Store::get_tags(&stores, &a).unwrap(),
+            csl_to_map("photo").unwrap()
+        );
+        assert_eq!(
+            get_sidecar_tags(&a).unwrap(),
+            Some(csl_to_map("photo").unwrap())
+        );
+        add_tags_with_store(&b, &csl_to_map("new").unwrap(), &stores).unwrap();
+        assert_eq!(get_tags(&b).unwrap(), csl_to_map("new").unwrap());
+        assert_eq!(get_sidecar_tags(&b).unwrap(), None);
+
+        let index_only = Stores {
+            precedence: vec![TagStore::Index],
+            ..Default::default()
+        };
+        let result = index_only.set_tags(&b, &XTags::new());
+        assert!(matches!(result, Err(XTagError::ReadOnly(_))));
+    }
+}
@@ -0,0 +1,210 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::searcher::expand_regex;
+use crate::store::store_or_xattr;
+use crate::walk::TreeTags;
+use crate::weight::split_weight;
+use crate::{
+    merge_tags_with_store, walk, Comparand, MergePolicy, Result, WalkOptions, XTagError, XTags,
+};
+
+/// How values of a tag are combined for the directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    /// Tag is set if any file has it. Its value is kept if all files having it agree on it.
+    Union,
+
+    /// Smallest value, compared as integer, float or ISO-8601 date like `<` of searches.
+    Min,
+
+    /// Largest value, compared like Min.
+    Max,
+}
+
+/// Options for summarize_dir.
+#[derive(Debug, Clone, Default)]
+pub struct SummaryOptions {
+    /// Tag regular expressions with their aggregate, the first matching one applies. Tags
+    /// matching none are left out of the summary.
+    pub rules: Vec<(String, Aggregate)>,
+
+    /// Merge the summary into the tags of the directory, replacing values of the same tags.
+    pub store: bool,
+}
+
+impl SummaryOptions {
+    /// Adds rule aggregating tags matching tag_regex.
+    pub fn rule(mut self, tag_regex: &str, aggregate: Aggregate) -> Self {
+        self.rules.push((tag_regex.to_string(), aggregate));
+        self
+    }
+}
+
+// Aggregated value of one tag so far
+struct Summary {
+    aggregate: Aggregate,
+    value: Option<String>,
+    // Union only, false once files disagree on the value
+    agreed: bool,
+}
+
+impl Summary {
+    fn new(aggregate: Aggregate, value: Option<&str>) -> Self {
+        let value = match aggregate {
+            Aggregate::Union => value,
+            Aggregate::Min | Aggregate::Max => value.filter(|value| is_comparable(value)),
+        };
+        Summary {
+            aggregate,
+            value: value.map(str::to_string),
+            agreed: true,
+        }
+    }
+
+    fn add(&mut self, value: Option<&str>) {
+        let wanted = match self.aggregate {
+            Aggregate::Union => {
+                self.agreed &= self.value.as_deref() == value;
+                return;
+            }
+            Aggregate::Min => Ordering::Less,
+            Aggregate::Max => Ordering::Greater,
+        };
+        let Some(value) = value.filter(|value| is_comparable(value)) else {
+            return;
+        };
+        let replace = match &self.value {
+            Some(current) => current
+                .parse::<Comparand>()
+                .is_ok_and(|current| current.compare(value) == Some(wanted)),
+            None => true,
+        };
+        if replace {
+            self.value = Some(value.to_string());
+        }
+    }
+
+    // None if Min or Max found no comparable value
+    fn finish(self) -> Option<Option<String>> {
+        match self.aggregate {
+            Aggregate::Union if self.agreed => Some(self.value),
+            Aggregate::Union => Some(None),
+            Aggregate::Min | Aggregate::Max => self.value.map(Some),
+        }
+    }
+}
+
+fn is_comparable(value: &str) -> bool {
+    value.parse::<Comparand>().is_ok()
+}
+
+/// Computes tags of directory from the tags of the files below it.
+///
+/// Tags are aggregated per rule of options, like the union of all `project` tags or the
+/// earliest `shot` date. Weights of tags and values are ignored. options.store writes the
+/// summary to the directory itself, so searches for folders stay consistent with their
+/// contents. walk_options.max_depth of 1 only includes files directly in dir.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// use xtag::{Aggregate, SummaryOptions};
+///
+/// let options = SummaryOptions::default()
+///     .rule("project", Aggregate::Union)
+///     .rule("shot", Aggregate::Min);
+/// let summary =
+///     xtag::summarize_dir(Path::new("photos"), &options, &Default::default()).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - XTagError::Regex if a rule isn't a valid regular expression
+/// - Same as walk and get_tags, files without attribute support are handled by walk_options
+pub fn summarize_dir(
+    dir: &Path,
+    options: &SummaryOptions,
+    walk_options: &WalkOptions,
+) -> Result<XTags> {
+    let rules = options
+        .rules
+        .iter()
+        .map(|(tag_regex, aggregate)| {
+            let regex = Regex::new(&expand_regex(tag_regex)).map_err(XTagError::Regex)?;
+            Ok((regex, *aggregate))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut summaries: HashMap<String, Summary> = HashMap::new();
+    let tree = TreeTags::new(dir, walk_options);
+    for path in walk(dir, walk_options) {
+        let Some(tags) = tree.get(&path?)? else {
+            continue;
+        };
+        for (tag, value) in &tags {
+            let tag = split_weight(tag).0;
+            let Some((_, aggregate)) = rules.iter().find(|(regex, _)| regex.is_match(tag)) else {
+                continue;
+            };
+            let value = value.as_deref().map(|value| split_weight(value).0);
+            match summaries.get_mut(tag) {
+                Some(summary) => summary.add(value),
+                None => {
+                    summaries.insert(tag.to_string(), Summary::new(*aggregate, value));
+                }
+            }
+        }
+    }
+
+    let summary: XTags = summaries
+        .into_iter()
+        .filter_map(|(tag, summary)| Some((tag, summary.finish()?)))
+        .collect();
+    if options.store && !summary.is_empty() {
+        let store = store_or_xattr(&walk_options.store);
+        merge_tags_with_store(dir, &summary, MergePolicy::Overwrite, store)?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{summarize_dir, Aggregate, SummaryOptions};
+    use crate::{csl_to_map, get_tags, Fixture, WalkOptions};
+
+    #[test]
+    fn directories_summarize_their_files() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("project=x,client=acme,shot=2023-05-02,rating=4,private")
+            .file("b")
+            .tags("project=x,client=other#0.5,shot=2023-04-30,rating=4.5")
+            .file("sub/c")
+            .tags("project=x,shot=2022-01-01,rating=bad");
+        let options = SummaryOptions::default()
+            .rule("project|client", Aggregate::Union)
+            .rule("shot", Aggregate::Min)
+            .rule("rating", Aggregate::Max);
+        let shallow = WalkOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            summarize_dir(fixture.path(), &options, &shallow).unwrap(),
+            csl_to_map("project=x,client,shot=2023-04-30,rating=4.5").unwrap()
+        );
+
+        let options = SummaryOptions {
+            store: true,
+            ..options
+        };
+        let summary = summarize_dir(fixture.path(), &options, &WalkOptions::default()).unwrap();
+        assert_eq!(summary.get("shot"), Some(&Some("2022-01-01".to_string())));
+        assert_eq!(get_tags(fixture.path()).unwrap(), summary);
+    }
+}
@@ -0,0 +1,155 @@
+use std::borrow::Cow;
+
+use crate::fold::fold_tags;
+use crate::parse_tags::{parse_csl_ref, write_csl};
+use crate::searcher::TagPairs;
+use crate::{AliasMap, Result, XTags};
+
+/// Tags borrowed from the comma separated list they were parsed from.
+///
+/// Unlike csl_to_map, parsing copies no tags and only values with escaped characters, which
+/// makes XTagsRef the cheaper choice for testing many files with Searcher::is_match. Of
+/// repeated tags the last value is kept.
+///
+/// # Example
+///
+/// ```
+/// let tags = xtag::XTagsRef::parse("photo,place=Rome\\, Italy").unwrap();
+/// assert_eq!(tags.get("photo"), Some(None));
+/// assert_eq!(tags.get("place"), Some(Some("Rome, Italy")));
+/// assert_eq!(tags.to_csl(), "photo,place=Rome\\, Italy");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct XTagsRef<'a> {
+    pairs: Vec<(Cow<'a, str>, Option<Cow<'a, str>>)>,
+}
+
+impl<'a> XTagsRef<'a> {
+    /// Parses comma separated list like csl_to_map.
+    ///
+    /// # Errors
+    ///
+    /// Same as csl_to_map
+    pub fn parse(csl: &'a str) -> Result<Self> {
+        let mut pairs: Vec<(Cow<str>, Option<Cow<str>>)> = Vec::new();
+        parse_csl_ref(csl, |tag, value| {
+            match pairs.iter_mut().find(|(known, _)| known == tag) {
+                Some((_, known)) => *known = value,
+                None => pairs.push((Cow::Borrowed(tag), value)),
+            }
+        })?;
+        Ok(XTagsRef { pairs })
+    }
+
+    /// Value of tag, None if the file hasn't the tag and Some(None) if the tag has no value.
+    pub fn get(&self, tag: &str) -> Option<Option<&str>> {
+        self.pairs
+            .iter()
+            .find(|(known, _)| known == tag)
+            .map(|(_, value)| value.as_deref())
+    }
+
+    /// Number of tags.
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Returns true if there are no tags.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Iterates over tags and their values in order of the list.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.pairs
+            .iter()
+            .map(|(tag, value)| (tag.as_ref(), value.as_deref()))
+    }
+
+    /// Copies tags into XTags.
+    pub fn to_xtags(&self) -> XTags {
+        self.iter()
+            .map(|(tag, value)| (tag.to_string(), value.map(str::to_string)))
+            .collect()
+    }
+
+    /// Comma separated list like map_to_csl, with tags sorted.
+    pub fn to_csl(&self) -> String {
+        write_csl(self.iter())
+    }
+}
+
+impl<'a> From<&'a XTags> for XTagsRef<'a> {
+    fn from(tags: &'a XTags) -> Self {
+        let pairs = tags
+            .iter()
+            .map(|(tag, value)| {
+                (
+                    Cow::Borrowed(tag.as_str()),
+                    value.as_deref().map(Cow::Borrowed),
+                )
+            })
+            .collect();
+        XTagsRef { pairs }
+    }
+}
+
+impl From<XTags> for XTagsRef<'_> {
+    fn from(tags: XTags) -> Self {
+        let pairs = tags
+            .into_iter()
+            .map(|(tag, value)| (Cow::Owned(tag), value.map(Cow::Owned)))
+            .collect();
+        XTagsRef { pairs }
+    }
+}
+
+impl TagPairs for XTagsRef<'_> {
+    fn pairs(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.iter()
+    }
+
+    fn tag_count(&self) -> usize {
+        self.len()
+    }
+
+    // Rare enough to go through XTags
+    fn folded(&self) -> Self {
+        fold_tags(&self.to_xtags()).into()
+    }
+
+    fn canonicalized(&self, aliases: &AliasMap) -> Self {
+        aliases.canonicalize(&self.to_xtags()).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::XTagsRef;
+    use crate::{compile_search_with, csl_to_map, map_to_csl, AliasMap, SearchOptions};
+
+    #[test]
+    fn borrowed_tags_match_like_owned_ones() {
+        let csl = "b=x\\,y,a,rating=4,b=z";
+        let tags = XTagsRef::parse(csl).unwrap();
+        assert_eq!(tags.len(), 3);
+        assert_eq!(tags.get("b"), Some(Some("z")));
+        assert_eq!(tags.to_xtags(), csl_to_map(csl).unwrap());
+        assert_eq!(tags.to_csl(), "a,b=z,rating=4");
+        assert_eq!(map_to_csl(&tags.to_xtags()), tags.to_csl());
+
+        let mut aliases = AliasMap::new();
+        aliases.insert("rating", "score").unwrap();
+        let options = SearchOptions {
+            fold: true,
+            tag_aliases: Some(aliases),
+            ..Default::default()
+        };
+        for term in ["a and score >= 4", "not (b == x)", "A", "#tags == 3"] {
+            let search = compile_search_with(term, &options).unwrap();
+            assert!(search.is_match(&tags), "{term}");
+            assert!(search.is_match(&tags.to_xtags()), "{term}");
+        }
+        assert!(XTagsRef::parse("a=b=c").is_err());
+    }
+}
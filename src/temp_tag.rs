@@ -0,0 +1,134 @@
+use std::path::{Path, PathBuf};
+
+use crate::{delete_tags, get_tags, set_tags, Result};
+
+/// Tag that is removed again when the guard goes out of scope.
+///
+/// Meant for pipelines marking files as in progress. On drop, also while unwinding from a panic,
+/// the tag is restored to its state before: removed if it was new, otherwise set back to its
+/// previous value. Drop can't report errors, call release to see them.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let path = Path::new("a.jpg");
+/// let guard = xtag::TempTag::new(path, "processing").unwrap();
+/// // Work on a.jpg, other tools can search for processing meanwhile
+/// guard.release().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct TempTag {
+    path: PathBuf,
+    tag: String,
+    previous: Option<Option<String>>,
+    active: bool,
+}
+
+impl TempTag {
+    /// Adds tag without value to file at path.
+    ///
+    /// # Errors
+    ///
+    /// Same as get_tags and set_tags
+    pub fn new(path: &Path, tag: &str) -> Result<Self> {
+        TempTag::with_value(path, tag, None)
+    }
+
+    /// Adds tag with value to file at path.
+    ///
+    /// # Errors
+    ///
+    /// Same as get_tags and set_tags
+    pub fn with_value(path: &Path, tag: &str, value: Option<&str>) -> Result<Self> {
+        let mut tags = get_tags(path)?;
+        let previous = tags.insert(tag.to_string(), value.map(str::to_string));
+        set_tags(path, &tags)?;
+        Ok(TempTag {
+            path: path.to_path_buf(),
+            tag: tag.to_string(),
+            previous,
+            active: true,
+        })
+    }
+
+    /// Path of the tagged file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Restores the tag now.
+    ///
+    /// # Errors
+    ///
+    /// Same as get_tags, set_tags and delete_tags
+    pub fn release(mut self) -> Result<()> {
+        self.active = false;
+        self.restore()
+    }
+
+    /// Keeps the tag on the file, nothing is restored.
+    pub fn keep(mut self) {
+        self.active = false;
+    }
+
+    fn restore(&self) -> Result<()> {
+        let mut tags = get_tags(&self.path)?;
+        match &self.previous {
+            Some(value) => {
+                tags.insert(self.tag.clone(), value.clone());
+            }
+            None => {
+                tags.remove(&self.tag);
+            }
+        }
+        if tags.is_empty() {
+            delete_tags(&self.path)
+        } else {
+            set_tags(&self.path, &tags)
+        }
+    }
+}
+
+impl Drop for TempTag {
+    fn drop(&mut self) {
+        if self.active {
+            // Best effort, there is no way to report errors here
+            let _ = self.restore();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::TempTag;
+    use crate::{csl_to_map, get_tags, Fixture};
+
+    #[test]
+    fn temp_tags_are_restored() {
+        let fixture = Fixture::new().file("a").tags("photo,state=new").file("b");
+        let a = fixture.join("a");
+        let b = fixture.join("b");
+
+        let guard = TempTag::with_value(&a, "state", Some("busy")).unwrap();
+        assert_eq!(get_tags(&a).unwrap()["state"].as_deref(), Some("busy"));
+        drop(guard);
+        assert_eq!(
+            get_tags(&a).unwrap(),
+            csl_to_map("photo,state=new").unwrap()
+        );
+
+        let result = panic::catch_unwind(|| {
+            let _guard = TempTag::new(&b, "processing").unwrap();
+            assert!(get_tags(&b).unwrap().contains_key("processing"));
+            panic!("worker failed");
+        });
+        assert!(result.is_err());
+        assert!(get_tags(&b).unwrap().is_empty());
+
+        TempTag::new(&b, "done").unwrap().keep();
+        assert!(get_tags(&b).unwrap().contains_key("done"));
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::{Result, Store, XTagError, XTags};
+
+/// Store giving up after a timeout, accessing the wrapped store.
+///
+/// Attribute calls on a hung network mount can block forever. Operations run on a pool of worker
+/// threads shared by clones of the store, if one doesn't finish within timeout XTagError::Timeout
+/// is returned. Its worker is left behind and rejoins the pool if the mount ever comes back. At
+/// most workers threads are started, once all of them hang further operations time out as well.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use std::time::Duration;
+/// use xtag::{Store, TimeoutStore, XattrStore};
+///
+/// let store = TimeoutStore::new(XattrStore::default(), Duration::from_secs(30), 8);
+/// let tags = store.get_tags(Path::new("/mnt/nas/a.jpg")).unwrap();
+/// ```
+pub struct TimeoutStore<S> {
+    /// Longest wait for a single operation.
+    pub timeout: Duration,
+
+    store: Arc<S>,
+    pool: Arc<Pool>,
+}
+
+// Clones share store and workers, so no Clone of S is needed
+impl<S> Clone for TimeoutStore<S> {
+    fn clone(&self) -> Self {
+        TimeoutStore {
+            timeout: self.timeout,
+            store: Arc::clone(&self.store),
+            pool: Arc::clone(&self.pool),
+        }
+    }
+}
+
+impl<S> fmt::Debug for TimeoutStore<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeoutStore")
+            .field("timeout", &self.timeout)
+            .field("workers", &self.pool.workers)
+            .finish()
+    }
+}
+
+impl<S> Default for TimeoutStore<S>
+where
+    S: Store + Default + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        TimeoutStore::new(S::default(), Duration::from_secs(30), 8)
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct Queue {
+    jobs: VecDeque<Job>,
+    idle: usize,
+    threads: usize,
+    closed: bool,
+}
+
+type Shared = (Mutex<Queue>, Condvar);
+
+// Workers are started on demand and stop once the last store using them is dropped
+struct Pool {
+    workers: usize,
+    shared: Arc<Shared>,
+}
+
+impl Pool {
+    fn submit(&self, job: Job) {
+        let (queue, ready) = &*self.shared;
+        let mut queue = queue.lock().unwrap_or_else(|err| err.into_inner());
+        queue.jobs.push_back(job);
+        if queue.idle < queue.jobs.len() && queue.threads < self.workers {
+            queue.threads += 1;
+            let shared = Arc::clone(&self.shared);
+            thread::spawn(move || work(&shared));
+        }
+        ready.notify_one();
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        let (queue, ready) = &*self.shared;
+        queue.lock().unwrap_or_else(|err| err.into_inner()).closed = true;
+        ready.notify_all();
+    }
+}
+
+fn work(shared: &Shared) {
+    let (queue, ready) = shared;
+    let mut guard = queue.lock().unwrap_or_else(|err| err.into_inner());
+    loop {
+        if let Some(job) = guard.jobs.pop_front() {
+            drop(guard);
+            job();
+            guard = queue.lock().unwrap_or_else(|err| err.into_inner());
+        } else if guard.closed {
+            guard.threads -= 1;
+            return;
+        } else {
+            guard.idle += 1;
+            guard = ready.wait(guard).unwrap_or_else(|err| err.into_inner());
+            guard.idle -= 1;
+        }
+    }
+}
+
+impl<S> TimeoutStore<S>
+where
+    S: Store + Send + Sync + 'static,
+{
+    /// Returns store accessing store, waiting at most timeout for an operation and running them
+    /// on up to workers threads.
+    pub fn new(store: S, timeout: Duration, workers: usize) -> Self {
+        let pool = Pool {
+            workers: workers.max(1),
+            shared: Arc::default(),
+        };
+        TimeoutStore {
+            timeout,
+            store: Arc::new(store),
+            pool: Arc::new(pool),
+        }
+    }
+
+    /// Runs operation on a worker, waiting at most timeout for its result.
+    ///
+    /// A panic of operation is resumed in the calling thread.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Timeout if operation didn't finish in time
+    /// - Errors of operation are returned unchanged
+    pub fn run<T, F>(&self, operation: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        self.pool.submit(Box::new(move || {
+            // Nobody listens anymore after a timeout
+            let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(operation)));
+        }));
+        match receiver.recv_timeout(self.timeout) {
+            Ok(Ok(result)) => result,
+            Ok(Err(payload)) => panic::resume_unwind(payload),
+            Err(RecvTimeoutError::Timeout) => Err(XTagError::Timeout(self.timeout)),
+            Err(RecvTimeoutError::Disconnected) => unreachable!("workers answer every operation"),
+        }
+    }
+}
+
+impl<S> Store for TimeoutStore<S>
+where
+    S: Store + Send + Sync + 'static,
+{
+    fn get_tags(&self, path: &Path) -> Result<XTags> {
+        let (store, path) = (Arc::clone(&self.store), path.to_path_buf());
+        self.run(move || store.get_tags(&path))
+    }
+
+    fn set_tags(&self, path: &Path, tags: &XTags) -> Result<()> {
+        let (store, path, tags) = (Arc::clone(&self.store), path.to_path_buf(), tags.clone());
+        self.run(move || store.set_tags(&path, &tags))
+    }
+
+    fn delete_tags(&self, path: &Path) -> Result<()> {
+        let (store, path) = (Arc::clone(&self.store), path.to_path_buf());
+        self.run(move || store.delete_tags(&path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::TimeoutStore;
+    use crate::{csl_to_map, Fixture, SidecarLayout, SidecarStore, Store, XTagError, XattrStore};
+
+    #[test]
+    fn slow_operations_time_out() {
+        let store = TimeoutStore::new(XattrStore::default(), Duration::from_millis(20), 2);
+        let result = store.run(|| {
+            thread::sleep(Duration::from_secs(1));
+            Ok(())
+        });
+        assert!(matches!(result, Err(XTagError::Timeout(_))));
+
+        let fixture = Fixture::new().file("a").tags("x");
+        let tags = csl_to_map("y").unwrap();
+        store.set_tags(&fixture.join("a"), &tags).unwrap();
+        assert_eq!(store.get_tags(&fixture.join("a")).unwrap(), tags);
+        store.delete_tags(&fixture.join("a")).unwrap();
+        assert!(store.get_tags(&fixture.join("a")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn workers_are_reused_and_panics_resumed() {
+        let store = TimeoutStore::new(XattrStore::default(), Duration::from_secs(5), 2);
+        for n in 0..20 {
+            assert_eq!(store.run(move || Ok(n)).unwrap(), n);
+        }
+        let payload =
+            panic::catch_unwind(|| store.run::<(), _>(|| panic!("hung mount"))).unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"hung mount"));
+        assert_eq!(store.run(|| Ok(1)).unwrap(), 1);
+        let (queue, _) = &*store.pool.shared;
+        assert!(queue.lock().unwrap().threads <= 2);
+
+        // Hanging workers aren't replaced beyond the limit
+        let store = TimeoutStore::new(XattrStore::default(), Duration::from_millis(50), 1);
+        let result = store.run(|| {
+            thread::sleep(Duration::from_secs(1));
+            Ok(())
+        });
+        assert!(matches!(result, Err(XTagError::Timeout(_))));
+        assert!(matches!(store.run(|| Ok(())), Err(XTagError::Timeout(_))));
+    }
+
+    #[test]
+    fn wrapped_store_is_used() {
+        let fixture = Fixture::new().file("a");
+        let sidecar = SidecarStore::new(SidecarLayout::PerFile);
+        let store = TimeoutStore::new(sidecar.clone(), Duration::from_secs(5), 1);
+        let tags = csl_to_map("photo").unwrap();
+        store.set_tags(&fixture.join("a"), &tags).unwrap();
+        assert_eq!(sidecar.get_tags(&fixture.join("a")).unwrap(), tags);
+        assert!(crate::get_tags(&fixture.join("a")).unwrap().is_empty());
+        assert_eq!(store.clone().get_tags(&fixture.join("a")).unwrap(), tags);
+    }
+}
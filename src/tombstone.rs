@@ -0,0 +1,209 @@
+use std::iter;
+use std::path::Path;
+use std::str;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::parse_tags::write_csl;
+use crate::read_only::ensure_writable;
+use crate::{
+    csl_to_map, delete_tags_from, get_tags, set_tags, Result, XTagError, XTags, XATTR_NAME,
+};
+
+// Name is reserved, no tag group may use it
+pub(crate) static TOMBSTONES_NAME: &str = "tombstones";
+
+fn tombstones_attribute() -> String {
+    format!("{XATTR_NAME}.{TOMBSTONES_NAME}")
+}
+
+/// Tag removed by soft_delete_tags, kept until its retention period is over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tombstone {
+    /// Removed tag.
+    pub tag: String,
+
+    /// Value the tag had.
+    pub value: Option<String>,
+
+    /// When the tag was removed, in whole seconds.
+    pub removed: SystemTime,
+}
+
+impl Tombstone {
+    fn is_expired(&self, retention: Duration, now: SystemTime) -> bool {
+        self.removed + retention <= now
+    }
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// One tombstone per line as seconds since 1970 and the tag like in a list, newest first
+fn parse_line(line: &str) -> Result<Tombstone> {
+    let invalid = || XTagError::InvalidTags(line.to_string());
+    let (removed, pair) = line.split_once(' ').ok_or_else(invalid)?;
+    let removed = removed.parse().map_err(|_| invalid())?;
+    let (tag, value) = csl_to_map(pair)?.into_iter().next().ok_or_else(invalid)?;
+    Ok(Tombstone {
+        tag,
+        value,
+        removed: UNIX_EPOCH + Duration::from_secs(removed),
+    })
+}
+
+// Drops tombstones of tags the file has again and those older than retention
+fn write_tombstones(
+    path: &Path,
+    mut tombstones: Vec<Tombstone>,
+    tags: &XTags,
+    retention: Duration,
+) -> Result<()> {
+    let now = SystemTime::now();
+    tombstones.retain(|tombstone| {
+        !tags.contains_key(&tombstone.tag) && !tombstone.is_expired(retention, now)
+    });
+    if tombstones.is_empty() {
+        return delete_tags_from(path, &tombstones_attribute());
+    }
+    ensure_writable(path)?;
+    let lines: Vec<String> = tombstones
+        .iter()
+        .map(|tombstone| {
+            let pair = (tombstone.tag.as_str(), tombstone.value.as_deref());
+            format!(
+                "{} {}",
+                seconds(tombstone.removed),
+                write_csl(iter::once(pair))
+            )
+        })
+        .collect();
+    xattr::set(path, tombstones_attribute(), lines.join("\n").as_bytes())?;
+    Ok(())
+}
+
+/// Tombstones of file, newest first.
+///
+/// # Errors
+///
+/// - XTagError::InvalidTags if the attribute `user.xtag.tombstones` is damaged
+pub fn tombstones(path: &Path) -> Result<Vec<Tombstone>> {
+    let Some(value) = xattr::get(path, tombstones_attribute())? else {
+        return Ok(Vec::new());
+    };
+    str::from_utf8(&value)?.lines().map(parse_line).collect()
+}
+
+/// Removes tags from file, keeping them as tombstones for the retention period.
+///
+/// Tombstones are stored in the attribute `user.xtag.tombstones`, so sync tools can tell
+/// removed tags from ones never seen, and restore_tombstone brings tags back. Every change of
+/// the tombstones drops those older than retention and those of tags the file has again. Tags
+/// the file doesn't have are ignored.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use std::time::Duration;
+/// let month = Duration::from_secs(30 * 24 * 60 * 60);
+/// xtag::soft_delete_tags(Path::new("a.jpg"), &["draft"], month).unwrap();
+/// xtag::restore_tombstone(Path::new("a.jpg"), "draft", month).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Same as tombstones, get_tags and set_tags
+pub fn soft_delete_tags(path: &Path, tags: &[&str], retention: Duration) -> Result<()> {
+    let mut current = get_tags(path)?;
+    let removed = SystemTime::now();
+    let mut buried = tombstones(path)?;
+    buried.retain(|tombstone| !tags.contains(&tombstone.tag.as_str()));
+    for tag in tags.iter().rev() {
+        if let Some(value) = current.remove(*tag) {
+            let tombstone = Tombstone {
+                tag: tag.to_string(),
+                value,
+                removed: UNIX_EPOCH + Duration::from_secs(seconds(removed)),
+            };
+            buried.insert(0, tombstone);
+        }
+    }
+    // Tombstones first, so tags are never lost
+    write_tombstones(path, buried, &current, retention)?;
+    set_tags(path, &current)
+}
+
+/// Gives tag its value from its tombstone back and removes the tombstone.
+///
+/// # Errors
+///
+/// - XTagError::NoTombstone if there is no tombstone of tag
+/// - Same as soft_delete_tags
+pub fn restore_tombstone(path: &Path, tag: &str, retention: Duration) -> Result<()> {
+    let mut buried = tombstones(path)?;
+    let index = buried
+        .iter()
+        .position(|tombstone| tombstone.tag == tag)
+        .ok_or_else(|| XTagError::NoTombstone(tag.to_string()))?;
+    let tombstone = buried.remove(index);
+    let mut current = get_tags(path)?;
+    current.insert(tombstone.tag, tombstone.value);
+    set_tags(path, &current)?;
+    write_tombstones(path, buried, &current, retention)
+}
+
+/// Drops tombstones of file older than retention, returns the number left.
+///
+/// # Errors
+///
+/// Same as soft_delete_tags
+pub fn purge_tombstones(path: &Path, retention: Duration) -> Result<usize> {
+    let current = get_tags(path)?;
+    write_tombstones(path, tombstones(path)?, &current, retention)?;
+    Ok(tombstones(path)?.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{purge_tombstones, restore_tombstone, soft_delete_tags, tombstones};
+    use crate::{csl_to_map, get_tags, list_groups, set_tags, Fixture, XTagError};
+
+    #[test]
+    fn removed_tags_can_be_restored() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("photo,place=Rome\\, Italy,draft");
+        let file = fixture.join("a");
+        let day = Duration::from_secs(86_400);
+        soft_delete_tags(&file, &["place", "draft", "missing"], day).unwrap();
+        assert_eq!(get_tags(&file).unwrap(), csl_to_map("photo").unwrap());
+        let buried = tombstones(&file).unwrap();
+        assert_eq!(buried.len(), 2);
+        assert_eq!(buried[0].tag, "place");
+        assert_eq!(buried[0].value.as_deref(), Some("Rome, Italy"));
+        assert!(list_groups(&file).unwrap().is_empty());
+
+        restore_tombstone(&file, "place", day).unwrap();
+        assert_eq!(
+            get_tags(&file).unwrap()["place"].as_deref(),
+            Some("Rome, Italy")
+        );
+        assert!(matches!(
+            restore_tombstone(&file, "place", day),
+            Err(XTagError::NoTombstone(_))
+        ));
+
+        // Tags set again lose their tombstones
+        set_tags(&file, &csl_to_map("draft").unwrap()).unwrap();
+        assert_eq!(purge_tombstones(&file, day).unwrap(), 0);
+        soft_delete_tags(&file, &["draft"], day).unwrap();
+        assert_eq!(purge_tombstones(&file, day).unwrap(), 1);
+        assert_eq!(purge_tombstones(&file, Duration::ZERO).unwrap(), 0);
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::read_only::ensure_writable;
+use crate::{csl_from_bytes, delete_tags, sidecar_path, Result, XTagError, XATTR_NAME};
+
+fn is_unsupported(err: &XTagError) -> bool {
+    matches!(err, XTagError::File(err) if err.kind() == io::ErrorKind::Unsupported)
+}
+
+// Raw attribute of path, or the contents of its sidecar file if its file system has no
+// attributes, None if untagged
+fn read_raw(path: &Path) -> Result<Option<Vec<u8>>> {
+    match xattr::get(path, XATTR_NAME) {
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+            match fs::read(sidecar_path(path)) {
+                Ok(csl) => Ok(Some(csl.trim_ascii_end().to_vec())),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err.into()),
+            }
+        }
+        result => result.map_err(XTagError::File),
+    }
+}
+
+// Stores raw attribute for path, in a sidecar file if its file system has no attributes
+fn write_raw(path: &Path, value: Option<&[u8]>) -> Result<()> {
+    ensure_writable(path)?;
+    let result = match value {
+        Some(value) => xattr::set(path, XATTR_NAME, value).map_err(XTagError::File),
+        None => delete_tags(path),
+    };
+    match (result, value) {
+        (Err(err), Some(value)) if is_unsupported(&err) => {
+            fs::write(sidecar_path(path), csl_from_bytes(value)? + "\n")?;
+            Ok(())
+        }
+        (Err(err), None) if is_unsupported(&err) => remove_sidecar(path),
+        (result, _) => result,
+    }
+}
+
+pub(crate) fn remove_sidecar(path: &Path) -> Result<()> {
+    match fs::remove_file(sidecar_path(path)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Gives dst the same tags as src, removing tags of dst if src has none.
+///
+/// The attribute is copied unchanged, so repeated tags of MultiXTags survive. Tags are read
+/// from the sidecar file of src if its file system has no extended attributes, and written to
+/// the sidecar file of dst if its file system has none.
+///
+/// # Errors
+///
+/// - XTagError::File if reading or writing fails
+pub fn copy_tags(src: &Path, dst: &Path) -> Result<()> {
+    write_raw(dst, read_raw(src)?.as_deref())
+}
+
+/// Copies file src to dst with its tags, returns the number of bytes copied.
+///
+/// Unlike std::fs::copy, tags are kept on all platforms and file systems, see copy_tags.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// xtag::copy_with_tags(Path::new("a.jpg"), Path::new("/media/stick/a.jpg")).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Same as std::fs::copy and copy_tags
+pub fn copy_with_tags(src: &Path, dst: &Path) -> Result<u64> {
+    ensure_writable(dst)?;
+    let tags = read_raw(src)?;
+    let bytes = fs::copy(src, dst)?;
+    write_raw(dst, tags.as_deref())?;
+    Ok(bytes)
+}
+
+/// Moves file src to dst with its tags.
+///
+/// Within a file system the file is renamed, taking its sidecar file along. Across file systems
+/// it is copied like copy_with_tags and src is removed once dst has its tags.
+///
+/// # Errors
+///
+/// Same as std::fs::rename and copy_with_tags
+pub fn move_with_tags(src: &Path, dst: &Path) -> Result<()> {
+    ensure_writable(src)?;
+    let tags = read_raw(src)?;
+    match fs::rename(src, dst) {
+        Ok(()) => {
+            // Attributes move with the file, sidecar files don't
+            let sidecar = sidecar_path(src);
+            if sidecar.exists() {
+                fs::rename(sidecar, sidecar_path(dst))?;
+            }
+            Ok(())
+        }
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(src, dst)?;
+            write_raw(dst, tags.as_deref())?;
+            fs::remove_file(src)?;
+            remove_sidecar(src)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{copy_tags, copy_with_tags, move_with_tags};
+    use crate::{csl_to_multi_map, get_multi_tags, get_tags, set_multi_tags, Fixture};
+
+    #[test]
+    fn tags_follow_copied_and_moved_files() {
+        let fixture = Fixture::new()
+            .file("a")
+            .file("b")
+            .tags("old")
+            .file("untagged");
+        let tags = csl_to_multi_map("person=alice,person=bob,photo").unwrap();
+        set_multi_tags(&fixture.join("a"), &tags).unwrap();
+
+        assert_eq!(
+            copy_with_tags(&fixture.join("a"), &fixture.join("copy")).unwrap(),
+            0
+        );
+        assert_eq!(get_multi_tags(&fixture.join("copy")).unwrap(), tags);
+        copy_tags(&fixture.join("a"), &fixture.join("b")).unwrap();
+        assert_eq!(get_multi_tags(&fixture.join("b")).unwrap(), tags);
+
+        move_with_tags(&fixture.join("copy"), &fixture.join("moved")).unwrap();
+        assert!(!fixture.join("copy").exists());
+        assert_eq!(get_multi_tags(&fixture.join("moved")).unwrap(), tags);
+
+        copy_tags(&fixture.join("untagged"), &fixture.join("b")).unwrap();
+        assert!(get_tags(&fixture.join("b")).unwrap().is_empty());
+    }
+}
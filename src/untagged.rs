@@ -0,0 +1,122 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::walk::TreeTags;
+use crate::{walk, Result, WalkOptions, XATTR_NAME};
+
+/// Restrictions for find_untagged.
+#[derive(Debug, Clone, Default)]
+pub struct UntaggedFilter {
+    /// Only files with one of these extensions, compared case-insensitive and without dot. Empty
+    /// for all files.
+    pub extensions: Vec<String>,
+
+    /// Only files with at least this many bytes.
+    pub min_size: Option<u64>,
+
+    /// Only files with at most this many bytes.
+    pub max_size: Option<u64>,
+}
+
+impl UntaggedFilter {
+    fn accepts(&self, path: &Path) -> Result<bool> {
+        if !self.extensions.is_empty() {
+            let extension = path.extension().and_then(|ext| ext.to_str());
+            let known = extension.is_some_and(|extension| {
+                self.extensions
+                    .iter()
+                    .any(|wanted| wanted.eq_ignore_ascii_case(extension))
+            });
+            if !known {
+                return Ok(false);
+            }
+        }
+        if self.min_size.is_some() || self.max_size.is_some() {
+            let size = fs::metadata(path)?.len();
+            if self.min_size.is_some_and(|min| size < min)
+                || self.max_size.is_some_and(|max| size > max)
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+// Only lists attribute names, the tags are never read or parsed
+pub(crate) fn has_tags(path: &Path) -> Result<bool> {
+    Ok(xattr::list(path)?.any(|name| name == XATTR_NAME))
+}
+
+/// Iterates over all files below root without tags.
+///
+/// Files count as untagged if they have no `user.xtag` attribute. Errors are returned as items,
+/// files without attribute support are handled by options.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let filter = xtag::UntaggedFilter {
+///     extensions: vec!["jpg".to_string(), "png".to_string()],
+///     ..Default::default()
+/// };
+/// for path in xtag::find_untagged(Path::new("."), &filter, &xtag::WalkOptions::default()) {
+///     println!("{}", path.unwrap().display());
+/// }
+/// ```
+pub fn find_untagged<'a>(
+    root: &Path,
+    filter: &'a UntaggedFilter,
+    options: &WalkOptions,
+) -> impl Iterator<Item = Result<PathBuf>> + 'a {
+    let tree = TreeTags::new(root, options);
+    walk(root, options).filter_map(move |path| {
+        let path = match path {
+            Ok(path) => path,
+            Err(err) => return Some(Err(err)),
+        };
+        let tagged = filter.accepts(&path).and_then(|accepted| match accepted {
+            true => tree.has_tags(&path),
+            false => Ok(None),
+        });
+        match tagged {
+            Ok(Some(false)) => Some(Ok(path)),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_untagged, UntaggedFilter};
+    use crate::{Fixture, WalkOptions};
+
+    #[test]
+    fn only_untagged_files_are_found() {
+        let fixture = Fixture::new()
+            .file("a.jpg")
+            .tags("photo")
+            .file("b.JPG")
+            .file_with_content("c.jpg", "content")
+            .file("d.txt");
+        let options = WalkOptions::default();
+
+        let mut all: Vec<_> = find_untagged(fixture.path(), &UntaggedFilter::default(), &options)
+            .map(Result::unwrap)
+            .collect();
+        all.sort();
+        assert_eq!(all.len(), 3);
+
+        let filter = UntaggedFilter {
+            extensions: vec!["jpg".to_string()],
+            max_size: Some(0),
+            ..Default::default()
+        };
+        let paths: Vec<_> = find_untagged(fixture.path(), &filter, &options)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(paths, vec![fixture.join("b.JPG")]);
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str;
+
+use crate::read_only::ensure_writable;
+use crate::walk::search_tree;
+use crate::{
+    compile_search, delete_tags_from, Result, ResultSet, WalkOptions, XTagError, XATTR_NAME,
+};
+
+// Name is reserved, no tag group may use it
+pub(crate) static VIEWS_NAME: &str = "views";
+
+fn views_attribute() -> String {
+    format!("{XATTR_NAME}.{VIEWS_NAME}")
+}
+
+// One view per line, name and term separated by tab
+pub(crate) fn read_views(dir: &Path) -> Result<BTreeMap<String, String>> {
+    let Some(value) = xattr::get(dir, views_attribute())? else {
+        return Ok(BTreeMap::new());
+    };
+    Ok(str::from_utf8(&value)?
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(name, term)| (name.to_string(), term.to_string()))
+        .collect())
+}
+
+pub(crate) fn write_views(dir: &Path, views: &BTreeMap<String, String>) -> Result<()> {
+    if views.is_empty() {
+        return delete_tags_from(dir, &views_attribute());
+    }
+    ensure_writable(dir)?;
+    let value: String = views
+        .iter()
+        .map(|(name, term)| format!("{name}\t{term}\n"))
+        .collect();
+    xattr::set(dir, views_attribute(), value.as_bytes())?;
+    Ok(())
+}
+
+/// Saved searches of directory as pairs of name and term, sorted by name
+///
+/// Views are stored in the attribute `user.xtag.views` of the directory itself, so they move
+/// and get copied together with the collection.
+pub fn list_views(dir: &Path) -> Result<Vec<(String, String)>> {
+    Ok(read_views(dir)?.into_iter().collect())
+}
+
+/// Save search term as view of directory, replacing an existing view of the same name
+///
+/// # Errors
+///
+/// - XTagError::InvalidView if name is empty or contains tabs or line breaks
+/// - XTagError::Parser if term is no valid search
+pub fn set_view(dir: &Path, name: &str, term: &str) -> Result<()> {
+    if name.is_empty() || name.contains(['\t', '\n', '\r']) {
+        return Err(XTagError::InvalidView(name.to_string()));
+    }
+    compile_search(term)?;
+    let mut views = read_views(dir)?;
+    views.insert(name.to_string(), term.to_string());
+    write_views(dir, &views)
+}
+
+/// Delete view of directory, deleting a missing view is no error
+pub fn delete_view(dir: &Path, name: &str) -> Result<()> {
+    let mut views = read_views(dir)?;
+    if views.remove(name).is_some() {
+        write_views(dir, &views)?;
+    }
+    Ok(())
+}
+
+/// Run view of directory over all files below it
+///
+/// # Errors
+///
+/// - XTagError::UnknownView if the directory has no view of that name
+/// - Same as walk and get_tags, files without attribute support are handled by options
+pub fn run_view(dir: &Path, name: &str, options: &WalkOptions) -> Result<ResultSet> {
+    let views = read_views(dir)?;
+    let term = views
+        .get(name)
+        .ok_or_else(|| XTagError::UnknownView(name.to_string()))?;
+    search_tree(&compile_search(term)?, dir, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{delete_view, list_views, run_view, set_view};
+    use crate::{Fixture, WalkOptions};
+
+    #[test]
+    fn views_are_stored_with_directory() {
+        let fixture = Fixture::new().file("a").tags("photo,year=2023");
+        let dir = fixture.path();
+
+        set_view(dir, "recent photos", "photo and year > 2020").unwrap();
+        set_view(dir, "old", "year < 2000").unwrap();
+        assert!(set_view(dir, "broken", "a b").is_err());
+        assert!(set_view(dir, "tab\tname", "a").is_err());
+        let views = list_views(dir).unwrap();
+        assert_eq!(views.len(), 2);
+        assert_eq!(views[0].0, "old");
+
+        let options = WalkOptions::default();
+        assert_eq!(run_view(dir, "recent photos", &options).unwrap().len(), 1);
+        assert!(run_view(dir, "old", &options).unwrap().is_empty());
+        assert!(run_view(dir, "missing", &options).is_err());
+
+        delete_view(dir, "old").unwrap();
+        delete_view(dir, "recent photos").unwrap();
+        assert!(list_views(dir).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use crate::{get_tags, Result, Searcher, XTags};
+
+/// Source of tags computed from files instead of stored with them.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let providers: [&dyn xtag::VirtualTagProvider; 2] =
+///     [&xtag::ExtensionProvider, &xtag::SizeProvider];
+/// let search = xtag::compile_search("photo and ext == jpg and size == large").unwrap();
+/// let is_match = search.is_match_file(Path::new("a.jpg"), &providers).unwrap();
+/// ```
+pub trait VirtualTagProvider {
+    /// Returns computed tags of file, empty if the provider knows nothing about it.
+    fn tags(&self, path: &Path) -> Result<XTags>;
+}
+
+/// Provides lowercase file extension as tag `ext`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExtensionProvider;
+
+impl VirtualTagProvider for ExtensionProvider {
+    fn tags(&self, path: &Path) -> Result<XTags> {
+        let mut tags = XTags::new();
+        if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+            tags.insert("ext".to_string(), Some(extension.to_lowercase()));
+        }
+        Ok(tags)
+    }
+}
+
+/// Provides size bucket of file as tag `size`.
+///
+/// Buckets are `empty`, `tiny` below 16 KiB, `small` below 1 MiB, `medium` below 128 MiB,
+/// `large` below 4 GiB and `huge`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeProvider;
+
+static SIZE_BUCKETS: [(u64, &str); 5] = [
+    (1, "empty"),
+    (16 << 10, "tiny"),
+    (1 << 20, "small"),
+    (128 << 20, "medium"),
+    (4 << 30, "large"),
+];
+
+impl VirtualTagProvider for SizeProvider {
+    fn tags(&self, path: &Path) -> Result<XTags> {
+        let size = fs::metadata(path)?.len();
+        let bucket = SIZE_BUCKETS
+            .iter()
+            .find(|(limit, _)| size < *limit)
+            .map_or("huge", |(_, bucket)| bucket);
+        let mut tags = XTags::new();
+        tags.insert("size".to_string(), Some(bucket.to_string()));
+        Ok(tags)
+    }
+}
+
+/// Provides dimensions of PNG, GIF and JPEG images as tags `image:width` and `image:height`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImageDimensionsProvider;
+
+// JPEG dimensions may come after large metadata blocks
+const IMAGE_HEADER_SIZE: u64 = 256 * 1024;
+
+impl VirtualTagProvider for ImageDimensionsProvider {
+    fn tags(&self, path: &Path) -> Result<XTags> {
+        let mut header = Vec::new();
+        File::open(path)?
+            .take(IMAGE_HEADER_SIZE)
+            .read_to_end(&mut header)?;
+        let mut tags = XTags::new();
+        if let Some((width, height)) = image_dimensions(&header) {
+            tags.insert("image:width".to_string(), Some(width.to_string()));
+            tags.insert("image:height".to_string(), Some(height.to_string()));
+        }
+        Ok(tags)
+    }
+}
+
+fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let u16_be = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?));
+    let u16_le = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?));
+    let u32_be = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1A\n") && bytes.get(12..16) == Some(b"IHDR") {
+        return Some((u32_be(16)?, u32_be(20)?));
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some((u32::from(u16_le(6)?), u32::from(u16_le(8)?)));
+    }
+    if bytes.starts_with(b"\xFF\xD8") {
+        // Walk segments up to a start of frame marker
+        let mut at = 2;
+        while *bytes.get(at)? == 0xFF {
+            let marker = *bytes.get(at + 1)?;
+            let is_start_of_frame =
+                (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker);
+            if is_start_of_frame {
+                return Some((u32::from(u16_be(at + 7)?), u32::from(u16_be(at + 5)?)));
+            }
+            at += 2 + usize::from(u16_be(at + 2)?);
+        }
+    }
+    None
+}
+
+/// Get stored tags of file together with tags of providers
+///
+/// Stored tags win over computed ones of the same name, earlier providers over later ones.
+pub fn get_tags_with(path: &Path, providers: &[&dyn VirtualTagProvider]) -> Result<XTags> {
+    let mut tags = XTags::new();
+    for provider in providers.iter().rev() {
+        tags.extend(provider.tags(path)?);
+    }
+    tags.extend(get_tags(path)?);
+    Ok(tags)
+}
+
+impl Searcher {
+    /// Evaluates Searcher against stored tags of file and tags of providers.
+    ///
+    /// See get_tags_with.
+    pub fn is_match_file(
+        &self,
+        path: &Path,
+        providers: &[&dyn VirtualTagProvider],
+    ) -> Result<bool> {
+        Ok(self.is_match(&get_tags_with(path, providers)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::image_dimensions;
+
+    #[test]
+    fn image_dimensions_are_read_from_headers() {
+        let png = b"\x89PNG\r\n\x1A\n\0\0\0\x0DIHDR\0\0\x01\x00\0\0\0\x80";
+        assert_eq!(image_dimensions(png), Some((256, 128)));
+        let gif = b"GIF89a\x40\x01\xF0\x00";
+        assert_eq!(image_dimensions(gif), Some((320, 240)));
+        let jpeg = b"\xFF\xD8\xFF\xE0\x00\x04ab\xFF\xC0\x00\x11\x08\x01\xE0\x02\x80";
+        assert_eq!(image_dimensions(jpeg), Some((640, 480)));
+        assert_eq!(image_dimensions(b"\xFF\xD8\xFF\xE0\x00"), None);
+        assert_eq!(image_dimensions(b"text"), None);
+    }
+}
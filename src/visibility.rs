@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use crate::{compile_search_with, Param, Result, SearchOptions, Searcher};
+
+/// Mandatory predicate joined to every search of a user, for services shared by several users.
+///
+/// The predicate may reference the user as `$user`, like `not private or owner == $user`. User
+/// terms are compiled on their own and joined to the predicate by `and` as trees, never as text,
+/// so no term, however it uses parentheses and operators, matches files the predicate hides.
+///
+/// # Example
+///
+/// ```
+/// let filter = xtag::VisibilityFilter::new("not private or owner == $user").unwrap();
+/// let search = filter.compile("photo or private", "alice").unwrap();
+/// assert!(search.is_match(&xtag::csl_to_map("private,owner=alice").unwrap()));
+/// assert!(!search.is_match(&xtag::csl_to_map("private,owner=bob").unwrap()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct VisibilityFilter {
+    predicate: String,
+    options: SearchOptions,
+}
+
+impl VisibilityFilter {
+    /// Returns filter of predicate.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Parser if predicate is no valid search term
+    pub fn new(predicate: &str) -> Result<Self> {
+        let filter = VisibilityFilter {
+            predicate: predicate.to_string(),
+            options: SearchOptions::default(),
+        };
+        filter.compile_predicate("")?;
+        Ok(filter)
+    }
+
+    /// Compiles user terms with options, like limits for untrusted terms.
+    ///
+    /// The predicate is always compiled with default options and only `$user` bound.
+    pub fn with_options(mut self, options: SearchOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Predicate as given to new.
+    pub fn predicate(&self) -> &str {
+        &self.predicate
+    }
+
+    fn compile_predicate(&self, user: &str) -> Result<Searcher> {
+        let options = SearchOptions {
+            params: HashMap::from([("user".to_string(), Param::from(user))]),
+            ..Default::default()
+        };
+        compile_search_with(&self.predicate, &options)
+    }
+
+    /// Compiles term of user, restricted to the files the predicate lets user see.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Parser if term is no valid search term
+    /// - Same as compile_search_with with the options of the filter
+    pub fn compile(&self, term: &str, user: &str) -> Result<Searcher> {
+        self.restrict(compile_search_with(term, &self.options)?, user)
+    }
+
+    /// Restricts compiled search of user to the files the predicate lets user see.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Parser if the predicate can't be compiled for user
+    pub fn restrict(&self, searcher: Searcher, user: &str) -> Result<Searcher> {
+        Ok(Searcher::new_and(self.compile_predicate(user)?, searcher))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VisibilityFilter;
+    use crate::{csl_to_map, XTags};
+
+    #[test]
+    fn user_terms_cannot_escape_the_predicate() {
+        let filter = VisibilityFilter::new("not private or owner == $user").unwrap();
+        let files: Vec<XTags> = [
+            "photo",
+            "photo,private,owner=alice",
+            "photo,private,owner=bob",
+        ]
+        .into_iter()
+        .map(|tags| csl_to_map(tags).unwrap())
+        .collect();
+        let visible = |term: &str, user: &str| -> Vec<usize> {
+            let search = filter.compile(term, user).unwrap();
+            (0..files.len())
+                .filter(|&index| search.is_match(&files[index]))
+                .collect()
+        };
+        for term in [
+            "photo",
+            "photo or private",
+            "private or photo",
+            "not video or private",
+            "owner == bob or not (owner == bob)",
+            "photo or (private and owner == bob)",
+            "#tags >= 0",
+        ] {
+            assert_eq!(visible(term, "alice"), [0, 1], "{term}");
+        }
+        assert_eq!(visible("private", "bob"), [2]);
+        // Users named like regular expressions only see their own files
+        assert_eq!(visible("photo", ".*"), [0]);
+
+        for term in ["photo) or (private", "photo or private)", "(photo"] {
+            assert!(filter.compile(term, "alice").is_err(), "{term}");
+        }
+        assert!(VisibilityFilter::new("not (private").is_err());
+    }
+}
@@ -0,0 +1,454 @@
+use std::borrow::Cow;
+
+use regex::Regex;
+
+use crate::fold::fold_tags;
+use crate::searcher::unexpand_regex;
+use crate::{Searcher, XTags};
+
+/// Visitor of the nodes of a Searcher, see Searcher::visit.
+///
+/// # Example
+///
+/// ```
+/// use xtag::{Searcher, SearcherVisitor};
+///
+/// // Counts negations
+/// struct Nots(usize);
+///
+/// impl SearcherVisitor<'_> for Nots {
+///     fn enter(&mut self, searcher: &Searcher) -> bool {
+///         self.0 += usize::from(matches!(searcher, Searcher::Not { .. }));
+///         true
+///     }
+/// }
+///
+/// let mut nots = Nots(0);
+/// xtag::compile_search("a and not (b or not c)").unwrap().visit(&mut nots);
+/// assert_eq!(nots.0, 2);
+/// ```
+///
+/// Nodes are borrowed for the lifetime `'a` of the visited Searcher, so visitors can keep them.
+pub trait SearcherVisitor<'a> {
+    /// Called for node before its operands, returning false skips them.
+    fn enter(&mut self, searcher: &'a Searcher) -> bool {
+        let _ = searcher;
+        true
+    }
+
+    /// Called for node after its operands, also if they were skipped.
+    fn leave(&mut self, searcher: &'a Searcher) {
+        let _ = searcher;
+    }
+}
+
+// Computes a value per node from the values of its operands, see Searcher::fold_nodes_with
+struct Fold<T, D, F> {
+    descend: D,
+    f: F,
+    // Number of values before each entered node, the values after it belong to its operands
+    starts: Vec<usize>,
+    values: Vec<T>,
+}
+
+impl<'a, T, D, F> SearcherVisitor<'a> for Fold<T, D, F>
+where
+    D: FnMut(&'a Searcher) -> bool,
+    F: FnMut(&'a Searcher, Vec<T>) -> T,
+{
+    fn enter(&mut self, searcher: &'a Searcher) -> bool {
+        self.starts.push(self.values.len());
+        (self.descend)(searcher)
+    }
+
+    fn leave(&mut self, searcher: &'a Searcher) {
+        let start = self.starts.pop().expect("node was entered");
+        let operands = self.values.split_off(start);
+        let value = (self.f)(searcher, operands);
+        self.values.push(value);
+    }
+}
+
+// Hooks of Searcher::evaluate_with, ids number the nodes in pre-order like visit
+pub(crate) trait Evaluator {
+    // Whether and and or skip their right operand once the left one decides, like is_match.
+    // Skipped nodes get no calls.
+    fn short_circuit(&self) -> bool {
+        true
+    }
+
+    // Called before the operands of node are evaluated
+    fn enter(&mut self, id: usize, node: &Searcher) {
+        let _ = (id, node);
+    }
+
+    // Result of a node without operands
+    fn term(&mut self, id: usize, term: &Searcher, tags: &XTags) -> bool {
+        let _ = id;
+        term.is_match(tags)
+    }
+
+    // Called with the result of every evaluated node
+    fn leave(&mut self, id: usize, node: &Searcher, matched: bool) {
+        let _ = (id, node, matched);
+    }
+}
+
+// Tag expressions of terms without anchors, see Searcher::referenced_tag_patterns
+struct TagPatterns<'a>(Vec<&'a str>);
+
+impl<'a> SearcherVisitor<'a> for TagPatterns<'a> {
+    fn enter(&mut self, searcher: &'a Searcher) -> bool {
+        if let Some(regex) = searcher.tag_regex() {
+            let pattern = unexpand_regex(regex.as_str());
+            if !self.0.contains(&pattern) {
+                self.0.push(pattern);
+            }
+        }
+        true
+    }
+}
+
+impl Searcher {
+    /// Operands of and, or and not, and the search wrapped by folding, aliases or a bookmark.
+    ///
+    /// Empty for terms.
+    pub fn operands(&self) -> Vec<&Searcher> {
+        match self {
+            Searcher::And { lhs, rhs } | Searcher::Or { lhs, rhs } => vec![lhs, rhs],
+            Searcher::Not { lhs }
+            | Searcher::Folded { lhs }
+            | Searcher::Canonical { lhs, .. }
+            | Searcher::Bookmark { lhs, .. } => vec![lhs],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Regular expression for the tags of a term, with the anchors added on compilation.
+    ///
+    /// None for operators and terms comparing values of any tag.
+    pub fn tag_regex(&self) -> Option<&Regex> {
+        match self {
+            Searcher::Tag { regex } | Searcher::Descendant { regex, .. } => Some(regex),
+            Searcher::Equal { tag_regex, .. }
+            | Searcher::EqualCaptures { tag_regex, .. }
+            | Searcher::Less { tag_regex, .. }
+            | Searcher::LessEqual { tag_regex, .. }
+            | Searcher::Greater { tag_regex, .. }
+            | Searcher::GreaterEqual { tag_regex, .. }
+            | Searcher::IsTrue { tag_regex }
+            | Searcher::IsFalse { tag_regex }
+            | Searcher::HasValue { tag_regex }
+            | Searcher::ValuelessTag { tag_regex } => Some(tag_regex),
+            Searcher::And { .. }
+            | Searcher::Or { .. }
+            | Searcher::Not { .. }
+            | Searcher::AnyValue { .. }
+            | Searcher::Folded { .. }
+            | Searcher::Canonical { .. }
+            | Searcher::Bookmark { .. }
+            | Searcher::TagCount { .. } => None,
+        }
+    }
+
+    /// Walks all nodes depth-first, operands from left to right.
+    pub fn visit<'a>(&'a self, visitor: &mut impl SearcherVisitor<'a>) {
+        // Without recursion, so even degenerate trees can be visited
+        let mut pending = vec![(self, false)];
+        while let Some((searcher, entered)) = pending.pop() {
+            if entered {
+                visitor.leave(searcher);
+                continue;
+            }
+            pending.push((searcher, true));
+            if visitor.enter(searcher) {
+                let operands = searcher.operands().into_iter().rev();
+                pending.extend(operands.map(|operand| (operand, false)));
+            }
+        }
+    }
+
+    // Computes a value of every node from the values of its operands, bottom-up. Operands of
+    // nodes descend rejects aren't visited, f gets no values for them.
+    pub(crate) fn fold_nodes_with<'a, T, D, F>(&'a self, descend: D, f: F) -> T
+    where
+        D: FnMut(&'a Searcher) -> bool,
+        F: FnMut(&'a Searcher, Vec<T>) -> T,
+    {
+        let mut fold = Fold {
+            descend,
+            f,
+            starts: Vec::new(),
+            values: Vec::new(),
+        };
+        self.visit(&mut fold);
+        fold.values.pop().expect("root has a value")
+    }
+
+    // Same as fold_nodes_with, visiting all nodes
+    pub(crate) fn fold_nodes<'a, T, F>(&'a self, f: F) -> T
+    where
+        F: FnMut(&'a Searcher, Vec<T>) -> T,
+    {
+        self.fold_nodes_with(|_| true, f)
+    }
+
+    // Number of nodes
+    pub(crate) fn size(&self) -> usize {
+        self.fold_nodes(|_, operands: Vec<usize>| 1 + operands.iter().sum::<usize>())
+    }
+
+    // Term as displayed, or the operator like `and` for nodes with operands
+    pub(crate) fn label(&self) -> String {
+        match self {
+            Searcher::And { .. } => "and".to_string(),
+            Searcher::Or { .. } => "or".to_string(),
+            Searcher::Not { .. } => "not".to_string(),
+            Searcher::Folded { .. } => "folded".to_string(),
+            Searcher::Canonical { .. } => "aliases".to_string(),
+            Searcher::Bookmark { name, .. } => format!("{{{name}}}"),
+            term => term.to_string(),
+        }
+    }
+
+    // Tags the operands of self are evaluated against, folded or canonicalized by wrappers
+    pub(crate) fn operand_tags<'t>(&self, tags: &'t XTags) -> Cow<'t, XTags> {
+        match self {
+            Searcher::Folded { .. } => Cow::Owned(fold_tags(tags)),
+            Searcher::Canonical { aliases, .. } => Cow::Owned(aliases.canonicalize(tags)),
+            _ => Cow::Borrowed(tags),
+        }
+    }
+
+    // Result of a node with operands from their results, taken from operand on demand. And and
+    // or short-circuit like is_match, wrappers pass the result of their operand through.
+    pub(crate) fn combine(&self, mut operand: impl FnMut(usize) -> bool) -> bool {
+        match self {
+            Searcher::And { .. } => operand(0) && operand(1),
+            Searcher::Or { .. } => operand(0) || operand(1),
+            Searcher::Not { .. } => !operand(0),
+            _ => operand(0),
+        }
+    }
+
+    // Evaluates self against tags like is_match, calling evaluator for every evaluated node
+    pub(crate) fn evaluate_with(&self, tags: &XTags, evaluator: &mut impl Evaluator) -> bool {
+        self.evaluate_node(tags, evaluator, &mut 0)
+    }
+
+    // next is the id of self, afterwards the id following its nodes
+    fn evaluate_node<E: Evaluator>(
+        &self,
+        tags: &XTags,
+        evaluator: &mut E,
+        next: &mut usize,
+    ) -> bool {
+        let id = *next;
+        *next += 1;
+        evaluator.enter(id, self);
+        let operands = self.operands();
+        let matched = if operands.is_empty() {
+            evaluator.term(id, self, tags)
+        } else {
+            let tags = self.operand_tags(tags);
+            let mut evaluated = 0;
+            let matched = if evaluator.short_circuit() {
+                self.combine(|index| {
+                    evaluated += 1;
+                    operands[index].evaluate_node(&tags, evaluator, next)
+                })
+            } else {
+                let results: Vec<bool> = operands
+                    .iter()
+                    .map(|operand| operand.evaluate_node(&tags, evaluator, next))
+                    .collect();
+                evaluated = operands.len();
+                self.combine(|index| results[index])
+            };
+            // Only trailing operands are skipped, ids of their nodes are left out
+            *next += operands[evaluated..]
+                .iter()
+                .map(|operand| operand.size())
+                .sum::<usize>();
+            matched
+        };
+        evaluator.leave(id, self, matched);
+        matched
+    }
+
+    /// Tag expressions of all terms in term order without the anchors added on compilation.
+    ///
+    /// Expressions occurring in several terms are returned once. Folded searches return the
+    /// folded expressions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let search = xtag::compile_search("photo and (year > 2020 or photo == raw)").unwrap();
+    /// assert_eq!(search.referenced_tag_patterns(), ["photo", "year"]);
+    /// ```
+    pub fn referenced_tag_patterns(&self) -> Vec<&str> {
+        let mut patterns = TagPatterns(Vec::new());
+        self.visit(&mut patterns);
+        patterns.0
+    }
+
+    /// Returns copy of Searcher with the tag expression of every term replaced by f.
+    ///
+    /// f gets the expressions with the anchors added on compilation, see tag_regex. Values,
+    /// operators, folding and aliases are kept.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use regex::Regex;
+    ///
+    /// let search = xtag::compile_search("year > 2020 and photo").unwrap();
+    /// let prefixed = search.map_tags(|regex| match regex.as_str() {
+    ///     "^(?:year)$" => Regex::new("^(?:exif:year)$").unwrap(),
+    ///     _ => regex.clone(),
+    /// });
+    /// let tags = xtag::csl_to_map("exif:year=2023,photo").unwrap();
+    /// assert!(prefixed.is_match(&tags));
+    /// ```
+    pub fn map_tags<F>(&self, mut f: F) -> Searcher
+    where
+        F: FnMut(&Regex) -> Regex,
+    {
+        self.fold_nodes(|node, operands| node.map_node(operands, &mut f))
+    }
+
+    // Copy of node with operands replaced in order and the tag expression of terms by f
+    pub(crate) fn map_node<F>(&self, operands: Vec<Searcher>, f: &mut F) -> Searcher
+    where
+        F: FnMut(&Regex) -> Regex,
+    {
+        let mut operands = operands.into_iter();
+        let mut operand = || operands.next().expect("one value per operand");
+        match self {
+            Searcher::And { .. } => Searcher::new_and(operand(), operand()),
+            Searcher::Or { .. } => Searcher::new_or(operand(), operand()),
+            Searcher::Not { .. } => Searcher::new_not(operand()),
+            Searcher::Tag { regex } => Searcher::Tag { regex: f(regex) },
+            Searcher::Equal {
+                tag_regex,
+                value_regex,
+            } => Searcher::Equal {
+                tag_regex: f(tag_regex),
+                value_regex: value_regex.clone(),
+            },
+            Searcher::EqualCaptures {
+                tag_regex,
+                value_template,
+            } => Searcher::EqualCaptures {
+                tag_regex: f(tag_regex),
+                value_template: value_template.clone(),
+            },
+            Searcher::AnyValue { value_regex } => Searcher::AnyValue {
+                value_regex: value_regex.clone(),
+            },
+            Searcher::Less { tag_regex, value } => Searcher::Less {
+                tag_regex: f(tag_regex),
+                value: *value,
+            },
+            Searcher::LessEqual { tag_regex, value } => Searcher::LessEqual {
+                tag_regex: f(tag_regex),
+                value: *value,
+            },
+            Searcher::Greater { tag_regex, value } => Searcher::Greater {
+                tag_regex: f(tag_regex),
+                value: *value,
+            },
+            Searcher::GreaterEqual { tag_regex, value } => Searcher::GreaterEqual {
+                tag_regex: f(tag_regex),
+                value: *value,
+            },
+            Searcher::IsTrue { tag_regex } => Searcher::IsTrue {
+                tag_regex: f(tag_regex),
+            },
+            Searcher::IsFalse { tag_regex } => Searcher::IsFalse {
+                tag_regex: f(tag_regex),
+            },
+            Searcher::Folded { .. } => Searcher::new_folded(operand()),
+            Searcher::Canonical { aliases, .. } => {
+                Searcher::new_canonical(operand(), aliases.clone())
+            }
+            Searcher::Descendant { regex, separator } => Searcher::Descendant {
+                regex: f(regex),
+                separator: *separator,
+            },
+            Searcher::HasValue { tag_regex } => Searcher::HasValue {
+                tag_regex: f(tag_regex),
+            },
+            Searcher::ValuelessTag { tag_regex } => Searcher::ValuelessTag {
+                tag_regex: f(tag_regex),
+            },
+            Searcher::TagCount { min, max } => Searcher::TagCount {
+                min: *min,
+                max: *max,
+            },
+            Searcher::Bookmark { name, .. } => Searcher::new_bookmark(name, operand()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SearcherVisitor;
+    use crate::{compile_search, csl_to_map, Searcher};
+
+    // Records nodes as entered and left, skipping the operands of or
+    #[derive(Default)]
+    struct Trace(Vec<String>);
+
+    impl SearcherVisitor<'_> for Trace {
+        fn enter(&mut self, searcher: &Searcher) -> bool {
+            let operands = searcher.operands().len();
+            self.0.push(format!("enter {operands}"));
+            !matches!(searcher, Searcher::Or { .. })
+        }
+
+        fn leave(&mut self, searcher: &Searcher) {
+            let tag = searcher.tag_regex().map(|regex| regex.as_str().to_string());
+            self.0.push(format!("leave {}", tag.unwrap_or_default()));
+        }
+    }
+
+    #[test]
+    fn searchers_are_visited_and_mapped() {
+        let search = compile_search("a and not (b or c) and under(x/y)").unwrap();
+        let mut trace = Trace::default();
+        search.visit(&mut trace);
+        assert_eq!(
+            trace.0,
+            [
+                "enter 2",
+                "enter 2",
+                "enter 0",
+                "leave ^(?:a)$",
+                "enter 1",
+                "enter 2",
+                "leave ",
+                "leave ",
+                "leave ",
+                "enter 0",
+                "leave ^(?:x/y)$",
+                "leave ",
+            ]
+        );
+        assert_eq!(search.referenced_tag_patterns(), ["a", "b", "c", "x/y"]);
+
+        let mut count = 0;
+        let mapped = search.map_tags(|regex| {
+            count += 1;
+            regex::Regex::new(&regex.as_str().replace('a', "z")).unwrap()
+        });
+        assert_eq!(count, 4);
+        assert!(mapped.is_match(&csl_to_map("z,x/y/w").unwrap()));
+        assert!(!mapped.is_match(&csl_to_map("a,x/y").unwrap()));
+        assert!(compile_search("* == a")
+            .unwrap()
+            .referenced_tag_patterns()
+            .is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+use std::cmp::Reverse;
+use std::path::Path;
+
+use crate::searcher::{is_one_of, FALSE_VALUES, TRUE_VALUES};
+use crate::{collect_stats_dir, Date, Index, Result, TagCount, TagStats, WalkOptions};
+
+/// Type of the values of a tag, inferred from all observed values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ValueType {
+    /// Tag never has a value.
+    Flag,
+
+    /// All values are integers.
+    Integer,
+
+    /// All values are numbers, some with fraction.
+    Float,
+
+    /// All values are ISO-8601 dates.
+    Date,
+
+    /// All values are spellings of true and false, like for istrue().
+    Boolean,
+
+    /// Anything else.
+    Text,
+}
+
+impl ValueType {
+    /// Infers type of values, the first fitting type of integer, float, date and boolean.
+    pub fn infer<'a, I>(values: I) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+        I::IntoIter: Clone,
+    {
+        let values = values.into_iter();
+        if values.clone().next().is_none() {
+            return ValueType::Flag;
+        }
+        let all = |test: fn(&str) -> bool| values.clone().all(test);
+        if all(|value| value.parse::<i64>().is_ok()) {
+            ValueType::Integer
+        } else if all(|value| value.parse::<f64>().is_ok_and(f64::is_finite)) {
+            ValueType::Float
+        } else if all(|value| value.parse::<Date>().is_ok()) {
+            ValueType::Date
+        } else if all(|value| is_one_of(value, &TRUE_VALUES) || is_one_of(value, &FALSE_VALUES)) {
+            ValueType::Boolean
+        } else {
+            ValueType::Text
+        }
+    }
+}
+
+/// Description of one tag in a Vocabulary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VocabularyEntry {
+    /// Name of the tag.
+    pub tag: String,
+
+    /// Inferred type of its values.
+    pub value_type: ValueType,
+
+    /// Number of files having the tag.
+    pub files: usize,
+
+    /// Number of distinct values.
+    pub values: usize,
+
+    /// Most used values with their number of files, most used first.
+    pub examples: Vec<(String, usize)>,
+}
+
+impl VocabularyEntry {
+    fn new(tag: &str, count: &TagCount, examples: usize) -> Self {
+        let mut used: Vec<(String, usize)> = count
+            .values
+            .iter()
+            .map(|(value, files)| (value.clone(), *files))
+            .collect();
+        // Stable sort keeps values with equal use in lexicographic order
+        used.sort_by_key(|(_, files)| Reverse(*files));
+        used.truncate(examples);
+        VocabularyEntry {
+            tag: tag.to_string(),
+            value_type: ValueType::infer(count.values.keys().map(String::as_str)),
+            files: count.files,
+            values: count.values.len(),
+            examples: used,
+        }
+    }
+}
+
+/// Report of all tags in use, for documentation or as start of a schema.
+///
+/// # Example
+///
+/// ```
+/// let tags = [
+///     xtag::csl_to_map("photo,rating=4,shot=2023-06-01").unwrap(),
+///     xtag::csl_to_map("photo,rating=5").unwrap(),
+/// ];
+/// let stats: xtag::TagStats = tags.iter().collect();
+/// let vocabulary = xtag::Vocabulary::from_stats(&stats, 3);
+/// let rating = vocabulary.entry("rating").unwrap();
+/// assert_eq!(rating.value_type, xtag::ValueType::Integer);
+/// assert_eq!(rating.examples.len(), 2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vocabulary {
+    /// Number of files looked at.
+    pub files: usize,
+
+    /// Tags in lexicographic order.
+    pub entries: Vec<VocabularyEntry>,
+}
+
+impl Vocabulary {
+    /// Returns Vocabulary of stats with up to examples example values per tag.
+    pub fn from_stats(stats: &TagStats, examples: usize) -> Self {
+        Vocabulary {
+            files: stats.files(),
+            entries: stats
+                .tags()
+                .map(|(tag, count)| VocabularyEntry::new(tag, count, examples))
+                .collect(),
+        }
+    }
+
+    /// Entry of tag, None if it isn't used.
+    pub fn entry(&self, tag: &str) -> Option<&VocabularyEntry> {
+        self.entries
+            .binary_search_by(|entry| entry.tag.as_str().cmp(tag))
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+}
+
+/// Computes Vocabulary of all files below root.
+///
+/// # Errors
+///
+/// Same as walk and get_tags, files without attribute support are handled by options
+pub fn vocabulary(root: &Path, options: &WalkOptions, examples: usize) -> Result<Vocabulary> {
+    let stats = collect_stats_dir(root, options)?;
+    Ok(Vocabulary::from_stats(&stats, examples))
+}
+
+impl Index {
+    /// Computes Vocabulary of all indexed files, see vocabulary.
+    pub fn vocabulary(&self, examples: usize) -> Vocabulary {
+        let stats: TagStats = self.iter().map(|(_, tags)| tags).collect();
+        Vocabulary::from_stats(&stats, examples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{vocabulary, ValueType};
+    use crate::{Fixture, Index, WalkOptions};
+
+    #[test]
+    fn value_types_are_inferred() {
+        let infer = |values: &[&str]| ValueType::infer(values.iter().copied());
+        assert_eq!(infer(&[]), ValueType::Flag);
+        assert_eq!(infer(&["1", "-2"]), ValueType::Integer);
+        assert_eq!(infer(&["1", "2.5"]), ValueType::Float);
+        assert_eq!(infer(&["2023-06-01", "2024-01-31T10:00"]), ValueType::Date);
+        assert_eq!(infer(&["1", "no"]), ValueType::Boolean);
+        assert_eq!(infer(&["red", "1"]), ValueType::Text);
+    }
+
+    #[test]
+    fn vocabulary_of_tree_and_index_agree() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("photo,color=red")
+            .file("b")
+            .tags("color=blue")
+            .file("c")
+            .tags("color=red");
+        let options = WalkOptions::default();
+        let report = vocabulary(fixture.path(), &options, 1).unwrap();
+        assert_eq!(report.files, 3);
+        let color = report.entry("color").unwrap();
+        assert_eq!(color.value_type, ValueType::Text);
+        assert_eq!(color.values, 2);
+        assert_eq!(color.examples, vec![("red".to_string(), 2)]);
+        assert_eq!(report.entry("photo").unwrap().value_type, ValueType::Flag);
+
+        let index = Index::build(fixture.path(), &options).unwrap();
+        assert_eq!(index.vocabulary(1), report);
+    }
+}
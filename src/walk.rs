@@ -0,0 +1,645 @@
+use std::collections::{HashSet, VecDeque};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ignore::WalkBuilder;
+use itertools::Either;
+
+use crate::reader::{TagReader, BATCHED};
+use crate::result_set::file_id;
+use crate::store::store_or_xattr;
+use crate::untagged::has_tags;
+use crate::{
+    get_sidecar_tags, set_sidecar_tags, sidecar_path, Index, Result, ResultSet, Searcher, Store,
+    XTagError, XTags,
+};
+
+/// What tree-wide operations do with files whose file system doesn't support tags.
+///
+/// Only errors of kind Unsupported are handled, other errors stop the operation anyway.
+/// export_setfattr_script needs the attributes themselves and leaves such files out with Sidecar and
+/// IndexOnly as with Skip, import_tree takes no options and always stops.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DegradationPolicy {
+    /// Stop with the error.
+    #[default]
+    Error,
+
+    /// Leave the file out and go on.
+    Skip,
+
+    /// Keep the tags of the file in its sidecar file, see get_sidecar_tags and
+    /// set_sidecar_tags. Files without sidecar file have no tags.
+    Sidecar,
+
+    /// Read the tags of the file from the Index saved in the walked root, files not indexed
+    /// have no tags. Changes of the file are left out, as with Skip.
+    IndexOnly,
+}
+
+impl DegradationPolicy {
+    // Returns Ok(None) if result is to be skipped, for operations without fallback
+    pub(crate) fn handle<T>(self, result: Result<T>) -> Result<Option<T>> {
+        match result {
+            Err(err) if self != DegradationPolicy::Error && is_unsupported(&err) => Ok(None),
+            result => result.map(Some),
+        }
+    }
+}
+
+// Returns true if err is caused by a file system without extended attributes
+fn is_unsupported(err: &XTagError) -> bool {
+    matches!(err, XTagError::File(err) if err.kind() == std::io::ErrorKind::Unsupported)
+}
+
+// Tags of the files below root for tree-wide operations, falling back as DegradationPolicy
+// says for files without attribute support. The default has no fallback, for single files.
+#[derive(Default)]
+pub(crate) struct TreeTags {
+    root: PathBuf,
+    unsupported: DegradationPolicy,
+    store: Option<Arc<dyn Store + Send + Sync>>,
+
+    // Index of root for IndexOnly, loaded on first need
+    index: OnceLock<Index>,
+}
+
+impl TreeTags {
+    pub(crate) fn new(root: &Path, options: &WalkOptions) -> Self {
+        TreeTags {
+            root: root.to_path_buf(),
+            unsupported: options.unsupported,
+            store: options.store.clone(),
+            index: OnceLock::new(),
+        }
+    }
+
+    // Tags of path, None if it is to be skipped
+    pub(crate) fn get(&self, path: &Path) -> Result<Option<XTags>> {
+        self.recover(path, store_or_xattr(&self.store).get_tags(path))
+    }
+
+    // Tags of path read by other means, None if it is to be skipped
+    pub(crate) fn recover(&self, path: &Path, result: Result<XTags>) -> Result<Option<XTags>> {
+        match result {
+            Err(err) if is_unsupported(&err) => self.fallback(path, err),
+            result => result.map(Some),
+        }
+    }
+
+    // Returns true if path has tags, None if it is to be skipped
+    pub(crate) fn has_tags(&self, path: &Path) -> Result<Option<bool>> {
+        if self.store.is_some() {
+            return Ok(self.get(path)?.map(|tags| !tags.is_empty()));
+        }
+        match has_tags(path) {
+            Err(err) if is_unsupported(&err) => {
+                Ok(self.fallback(path, err)?.map(|tags| !tags.is_empty()))
+            }
+            result => result.map(Some),
+        }
+    }
+
+    // Returns true if path matches searcher, None if it is to be skipped
+    pub(crate) fn is_match(&self, searcher: &Searcher, path: &Path) -> Result<Option<bool>> {
+        if self.store.is_some() {
+            return Ok(self.get(path)?.map(|tags| searcher.is_match(&tags)));
+        }
+        match searcher.is_match_path(path) {
+            Err(err) if is_unsupported(&err) => Ok(self
+                .fallback(path, err)?
+                .map(|tags| searcher.is_match(&tags))),
+            result => result.map(Some),
+        }
+    }
+
+    // Replaces tags of path, returns false if it was skipped
+    pub(crate) fn set(&self, path: &Path, tags: &XTags) -> Result<bool> {
+        match store_or_xattr(&self.store).set_tags(path, tags) {
+            Err(err) if is_unsupported(&err) => match self.unsupported {
+                DegradationPolicy::Error => Err(err),
+                DegradationPolicy::Skip | DegradationPolicy::IndexOnly => Ok(false),
+                DegradationPolicy::Sidecar => set_sidecar_tags(path, tags).map(|_| true),
+            },
+            result => result.map(|_| true),
+        }
+    }
+
+    // Deletes tags of path, returns false if it was skipped
+    pub(crate) fn delete(&self, path: &Path) -> Result<bool> {
+        match store_or_xattr(&self.store).delete_tags(path) {
+            Err(err) if is_unsupported(&err) => match self.unsupported {
+                DegradationPolicy::Error => Err(err),
+                DegradationPolicy::Skip | DegradationPolicy::IndexOnly => Ok(false),
+                DegradationPolicy::Sidecar => match std::fs::remove_file(sidecar_path(path)) {
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+                    _ => Ok(true),
+                },
+            },
+            result => result.map(|_| true),
+        }
+    }
+
+    // Tags of path whose attributes failed with err
+    fn fallback(&self, path: &Path, err: XTagError) -> Result<Option<XTags>> {
+        match self.unsupported {
+            DegradationPolicy::Error => Err(err),
+            DegradationPolicy::Skip => Ok(None),
+            DegradationPolicy::Sidecar => Ok(Some(get_sidecar_tags(path)?.unwrap_or_default())),
+            DegradationPolicy::IndexOnly => {
+                let index = match self.index.get() {
+                    Some(index) => index,
+                    None => {
+                        let index = Index::load(&self.root)?;
+                        self.index.get_or_init(|| index)
+                    }
+                };
+                Ok(Some(index.tags(path).cloned().unwrap_or_default()))
+            }
+        }
+    }
+}
+
+/// Limits for background scans, so they don't slow down interactive use of the disk.
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    /// Number of files returned between pauses, values below 1 count as 1.
+    pub batch_size: usize,
+
+    /// Sleep after every batch.
+    pub pause: Duration,
+
+    /// Lower the IO priority of the walking thread to idle on Linux, ignored elsewhere.
+    ///
+    /// The priority stays lowered for the rest of the thread's life, so better walk in a thread
+    /// of its own.
+    pub idle_io_priority: bool,
+}
+
+// Files walked ahead of the consumer with a timeout
+const WALK_CHANNEL_CAPACITY: usize = 256;
+
+/// Name of files listing paths to leave out of tree operations, in .gitignore format.
+pub const IGNORE_FILE_NAME: &str = ".xtagignore";
+
+/// Options for walking directory trees.
+#[derive(Debug, Clone)]
+pub struct WalkOptions {
+    /// Follow symbolic links to directories and files.
+    pub follow_symlinks: bool,
+
+    /// Maximum depth below root, None for unlimited. Files directly in root have depth 1.
+    pub max_depth: Option<usize>,
+
+    /// Skip files and directories whose name starts with a dot.
+    pub skip_hidden: bool,
+
+    /// Handling of files on file systems without extended attributes.
+    pub unsupported: DegradationPolicy,
+
+    /// Slow down walking, None for full speed.
+    pub throttle: Option<Throttle>,
+
+    /// Honor IGNORE_FILE_NAME files in walked directories, enabled by default.
+    pub xtagignore: bool,
+
+    /// Honor .gitignore files in walked directories, also outside of git repositories.
+    pub gitignore: bool,
+
+    /// Return every file only once, even if reachable by hard links or bind mounts. Only the
+    /// first path found is returned.
+    pub dedup_inodes: bool,
+
+    /// Give up walking after this time, None to wait forever.
+    ///
+    /// The tree is walked in a thread of its own, so even a hung network mount can't block the
+    /// caller longer. Files found in time are returned, then the walk ends with
+    /// XTagError::Timeout.
+    pub timeout: Option<Duration>,
+
+    /// Number of threads of multithreaded operations like search_dir_par and Index::build,
+    /// None for the global rayon pool with one thread per core.
+    pub threads: Option<usize>,
+
+    /// Thread pool of multithreaded operations, takes precedence over threads.
+    #[cfg(feature = "parallel")]
+    pub pool: Option<Arc<rayon::ThreadPool>>,
+
+    /// Backend of the tags of walked files, None for the `user.xtag` attribute like get_tags.
+    ///
+    /// Files the store fails with errors of kind Unsupported for are handled by unsupported.
+    pub store: Option<Arc<dyn Store + Send + Sync>>,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions {
+            follow_symlinks: false,
+            max_depth: None,
+            skip_hidden: false,
+            unsupported: DegradationPolicy::default(),
+            throttle: None,
+            xtagignore: true,
+            gitignore: false,
+            dedup_inodes: false,
+            timeout: None,
+            threads: None,
+            #[cfg(feature = "parallel")]
+            pool: None,
+            store: None,
+        }
+    }
+}
+
+/// Iterates over all files below root.
+///
+/// Only regular files are returned, in no guaranteed order. Errors like unreadable directories
+/// are returned as items, walking goes on afterwards.
+pub fn walk(root: &Path, options: &WalkOptions) -> impl Iterator<Item = Result<PathBuf>> {
+    match options.timeout {
+        Some(timeout) => Either::Left(walk_with_timeout(root, options, timeout)),
+        None => Either::Right(walk_unbounded(root, options)),
+    }
+}
+
+// Walks in a thread of its own and stops waiting for it after timeout
+fn walk_with_timeout(
+    root: &Path,
+    options: &WalkOptions,
+    timeout: Duration,
+) -> impl Iterator<Item = Result<PathBuf>> {
+    let (sender, receiver) = mpsc::sync_channel(WALK_CHANNEL_CAPACITY);
+    let root = root.to_path_buf();
+    let options = options.clone();
+    // Stops at the next file once the receiver is gone, unless it hangs forever
+    thread::spawn(move || {
+        for item in walk_unbounded(&root, &options) {
+            if sender.send(item).is_err() {
+                break;
+            }
+        }
+    });
+    let deadline = Instant::now() + timeout;
+    let mut timed_out = false;
+    iter::from_fn(move || {
+        if timed_out {
+            return None;
+        }
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(item) => Some(item),
+            Err(RecvTimeoutError::Timeout) => {
+                timed_out = true;
+                Some(Err(XTagError::Timeout(timeout)))
+            }
+            Err(RecvTimeoutError::Disconnected) => None,
+        }
+    })
+}
+
+fn walk_unbounded(root: &Path, options: &WalkOptions) -> impl Iterator<Item = Result<PathBuf>> {
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .standard_filters(false)
+        .follow_links(options.follow_symlinks)
+        .max_depth(options.max_depth)
+        .hidden(options.skip_hidden)
+        .git_ignore(options.gitignore)
+        .require_git(false);
+    if options.xtagignore {
+        builder.add_custom_ignore_filename(IGNORE_FILE_NAME);
+    }
+    let dedup_inodes = options.dedup_inodes;
+    let mut seen = HashSet::new();
+    let throttle = options.throttle.clone();
+    if throttle
+        .as_ref()
+        .is_some_and(|throttle| throttle.idle_io_priority)
+    {
+        set_idle_io_priority();
+    }
+    builder
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry)
+                if entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_file()) =>
+            {
+                Some(Ok(entry.into_path()))
+            }
+            Ok(_) => None,
+            Err(err) => Some(Err(into_io_error(err).into())),
+        })
+        .filter(move |path| match path {
+            Ok(path) if dedup_inodes => match file_id(path) {
+                Ok(id) => seen.insert(id),
+                Err(_) => true,
+            },
+            _ => true,
+        })
+        .enumerate()
+        .map(move |(index, item)| {
+            if let Some(throttle) = &throttle {
+                if index > 0 && index % throttle.batch_size.max(1) == 0 {
+                    thread::sleep(throttle.pause);
+                }
+            }
+            item
+        })
+}
+
+// Best effort, walking works the same without
+#[cfg(target_os = "linux")]
+fn set_idle_io_priority() {
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+    // Process id 0 means the calling thread
+    unsafe {
+        libc::syscall(
+            libc::SYS_ioprio_set,
+            IOPRIO_WHO_PROCESS,
+            0,
+            IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_idle_io_priority() {}
+
+/// Iterates over all files below root matching searcher.
+///
+/// Walks the tree like walk and reads the tags of every file, in batches like read_tags with the
+/// `uring` feature. Errors are returned as items, searching goes on afterwards. Files without
+/// attribute support are handled by options.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// let searcher = xtag::compile_search("photo and rating >= 4").unwrap();
+/// let options = xtag::WalkOptions {
+///     skip_hidden: true,
+///     ..Default::default()
+/// };
+/// for path in xtag::search_dir(Path::new("."), &searcher, &options) {
+///     println!("{}", path.unwrap().display());
+/// }
+/// ```
+pub fn search_dir<'a>(
+    root: &Path,
+    searcher: &'a Searcher,
+    options: &WalkOptions,
+) -> impl Iterator<Item = Result<PathBuf>> + 'a {
+    let tags = TreeTags::new(root, options);
+    if BATCHED && options.store.is_none() {
+        return Either::Left(search_batched(walk(root, options), searcher, tags));
+    }
+    Either::Right(walk(root, options).filter_map(move |path| {
+        let path = match path {
+            Ok(path) => path,
+            Err(err) => return Some(Err(err)),
+        };
+        match tags.is_match(searcher, &path) {
+            Ok(Some(true)) => Some(Ok(path)),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }))
+}
+
+// Like search_dir, with the tags read in batches by TagReader. Errors of the walk come up to one
+// batch earlier than the files walked before them.
+fn search_batched<'a, W>(
+    paths: W,
+    searcher: &'a Searcher,
+    tags: TreeTags,
+) -> impl Iterator<Item = Result<PathBuf>> + 'a
+where
+    W: Iterator<Item = Result<PathBuf>> + 'a,
+{
+    let errors = Arc::new(Mutex::new(VecDeque::new()));
+    let walk_errors = errors.clone();
+    let paths = paths.filter_map(move |path| match path {
+        Ok(path) => Some(path),
+        Err(err) => {
+            walk_errors.lock().unwrap().push_back(err);
+            None
+        }
+    });
+    let batch_errors = errors.clone();
+    TagReader::new(paths, None)
+        .flat_map(move |(path, result)| {
+            let mut found: Vec<Result<PathBuf>> =
+                batch_errors.lock().unwrap().drain(..).map(Err).collect();
+            match tags.recover(&path, result) {
+                Ok(Some(file_tags)) if searcher.is_match(&file_tags) => found.push(Ok(path)),
+                Ok(_) => (),
+                Err(err) => found.push(Err(err)),
+            }
+            found
+        })
+        .chain(iter::from_fn(move || errors.lock().unwrap().pop_front()).map(Err))
+}
+
+// Returns files below root matching searcher
+pub(crate) fn search_tree(
+    searcher: &Searcher,
+    root: &Path,
+    options: &WalkOptions,
+) -> Result<ResultSet> {
+    search_dir(root, searcher, options).collect()
+}
+
+fn into_io_error(err: ignore::Error) -> std::io::Error {
+    if err.io_error().is_some() {
+        err.into_io_error().unwrap()
+    } else {
+        std::io::Error::other(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{search_dir, walk, DegradationPolicy, Throttle, TreeTags, WalkOptions};
+    use crate::{csl_to_map, set_sidecar_tags, Fixture, Index, Store, XTagError, XattrStore};
+
+    fn failure(kind: io::ErrorKind) -> crate::Result<()> {
+        Err(XTagError::File(io::Error::from(kind)))
+    }
+
+    #[test]
+    fn skip_only_skips_unsupported() {
+        let skip = DegradationPolicy::Skip;
+        assert!(skip
+            .handle(failure(io::ErrorKind::Unsupported))
+            .unwrap()
+            .is_none());
+        assert!(skip.handle(failure(io::ErrorKind::NotFound)).is_err());
+        assert!(DegradationPolicy::Error
+            .handle(failure(io::ErrorKind::Unsupported))
+            .is_err());
+    }
+
+    #[test]
+    fn unsupported_files_fall_back() {
+        let fixture = Fixture::new().file("a").tags("x=1").file("b");
+        let (a, b) = (fixture.join("a"), fixture.join("b"));
+        let unsupported = || Err(XTagError::File(io::ErrorKind::Unsupported.into()));
+        let tree = |unsupported| {
+            let options = WalkOptions {
+                unsupported,
+                ..Default::default()
+            };
+            TreeTags::new(fixture.path(), &options)
+        };
+
+        let sidecar = tree(DegradationPolicy::Sidecar);
+        set_sidecar_tags(&b, &csl_to_map("y").unwrap()).unwrap();
+        assert_eq!(
+            sidecar.recover(&b, unsupported()).unwrap(),
+            Some(csl_to_map("y").unwrap())
+        );
+        assert_eq!(
+            sidecar.recover(&a, unsupported()).unwrap(),
+            Some(Default::default())
+        );
+
+        let index_only = tree(DegradationPolicy::IndexOnly);
+        assert!(index_only.recover(&a, unsupported()).is_err());
+        Index::build(fixture.path(), &WalkOptions::default())
+            .unwrap()
+            .save()
+            .unwrap();
+        let index_only = tree(DegradationPolicy::IndexOnly);
+        assert_eq!(
+            index_only.recover(&a, unsupported()).unwrap(),
+            Some(csl_to_map("x=1").unwrap())
+        );
+        assert_eq!(
+            index_only.recover(&b, unsupported()).unwrap(),
+            Some(Default::default())
+        );
+
+        assert_eq!(
+            tree(DegradationPolicy::Skip)
+                .recover(&a, unsupported())
+                .unwrap(),
+            None
+        );
+        assert!(tree(DegradationPolicy::Error)
+            .recover(&a, unsupported())
+            .is_err());
+    }
+
+    #[test]
+    fn stores_of_options_are_used() {
+        let fixture = Fixture::new().file("a").tags("photo").file("b");
+        let b = fixture.join("b");
+        XattrStore::xdg()
+            .set_tags(&b, &csl_to_map("photo,old").unwrap())
+            .unwrap();
+        let options = WalkOptions {
+            store: Some(Arc::new(XattrStore::xdg())),
+            ..Default::default()
+        };
+        let searcher = crate::compile_search("photo").unwrap();
+        let found: Vec<_> = search_dir(fixture.path(), &searcher, &options)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(found, [fixture.join("b")]);
+
+        let tree = TreeTags::new(fixture.path(), &options);
+        assert!(tree.set(&b, &csl_to_map("photo").unwrap()).unwrap());
+        assert_eq!(tree.has_tags(&fixture.join("a")).unwrap(), Some(false));
+        assert_eq!(
+            XattrStore::xdg().get_tags(&b).unwrap(),
+            csl_to_map("photo").unwrap()
+        );
+    }
+
+    #[test]
+    fn ignore_files_are_honored() {
+        let fixture = Fixture::new()
+            .file_with_content(".xtagignore", "node_modules/\n*.tmp\n")
+            .file("node_modules/a")
+            .file("b.tmp")
+            .file("c");
+
+        let options = WalkOptions {
+            skip_hidden: true,
+            ..Default::default()
+        };
+        let paths: Vec<_> = walk(fixture.path(), &options).map(Result::unwrap).collect();
+        assert_eq!(paths, vec![fixture.join("c")]);
+        let options = WalkOptions {
+            xtagignore: false,
+            ..Default::default()
+        };
+        assert_eq!(walk(fixture.path(), &options).count(), 4);
+    }
+
+    #[test]
+    fn search_dir_yields_matches() {
+        let fixture = Fixture::new()
+            .file("a.jpg")
+            .tags("photo")
+            .file("sub/b.jpg")
+            .tags("photo,rating=5")
+            .file(".hidden/c.jpg")
+            .tags("photo")
+            .file("d.txt");
+        let searcher = crate::compile_search("photo").unwrap();
+
+        let mut paths: Vec<_> = search_dir(fixture.path(), &searcher, &WalkOptions::default())
+            .map(Result::unwrap)
+            .collect();
+        paths.sort();
+        assert_eq!(paths.len(), 3);
+        let options = WalkOptions {
+            skip_hidden: true,
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let paths: Vec<_> = search_dir(fixture.path(), &searcher, &options)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(paths, vec![fixture.join("a.jpg")]);
+    }
+
+    #[test]
+    fn walks_with_timeout_end_with_error() {
+        let fixture = Fixture::new().file("a").file("b").file("c");
+        let options = WalkOptions {
+            timeout: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        assert_eq!(
+            walk(fixture.path(), &options).map(Result::unwrap).count(),
+            3
+        );
+
+        // Second file only comes after a pause way longer than the timeout
+        let options = WalkOptions {
+            timeout: Some(Duration::from_millis(200)),
+            throttle: Some(Throttle {
+                batch_size: 1,
+                pause: Duration::from_secs(2),
+                idle_io_priority: false,
+            }),
+            ..Default::default()
+        };
+        let items: Vec<_> = walk(fixture.path(), &options).collect();
+        assert!(items.len() < 4);
+        assert!(matches!(items.last(), Some(Err(XTagError::Timeout(_)))));
+    }
+}
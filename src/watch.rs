@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::index::is_index_file;
+use crate::walk::TreeTags;
+use crate::{walk, Daemon, Index, Result, SearchCache, TagCache, WalkOptions, XTagError, XTags};
+
+/// Change of a file below a watched directory, see TagWatcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagEvent {
+    /// File appeared, by creation or by moving it into the tree.
+    Created { path: PathBuf, tags: XTags },
+
+    /// Tags of file changed.
+    TagsChanged {
+        path: PathBuf,
+        old: XTags,
+        tags: XTags,
+    },
+
+    /// File was deleted or moved out of the tree.
+    Removed { path: PathBuf },
+}
+
+impl TagEvent {
+    /// Path of the changed file.
+    pub fn path(&self) -> &Path {
+        match self {
+            TagEvent::Created { path, .. }
+            | TagEvent::TagsChanged { path, .. }
+            | TagEvent::Removed { path } => path,
+        }
+    }
+}
+
+/// Watches a directory tree for changes of files and their tags.
+///
+/// The tags of all files are read once when watching starts. Afterwards only files the
+/// operating system reports as changed are read again, and events are only returned if their
+/// tags really changed, so changes of content or permissions are ignored. Index files and, with
+/// WalkOptions::skip_hidden, hidden files are left out.
+///
+/// Events keep an Index, a TagCache, a SearchCache or a Daemon up to date through their
+/// apply_events, sync does so for an Index.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use std::time::Duration;
+/// let root = Path::new("photos");
+/// let options = xtag::WalkOptions::default();
+/// let mut index = xtag::Index::build(root, &options).unwrap();
+/// let mut watcher = xtag::TagWatcher::new(root, &options).unwrap();
+/// loop {
+///     let events = watcher.sync(&mut index, Duration::from_secs(1)).unwrap();
+///     if !events.is_empty() {
+///         index.save().unwrap();
+///     }
+/// }
+/// ```
+pub struct TagWatcher {
+    root: PathBuf,
+    canonical_root: PathBuf,
+    options: WalkOptions,
+    tree: TreeTags,
+    known: HashMap<PathBuf, XTags>,
+    receiver: Receiver<notify::Result<Event>>,
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+}
+
+fn watch_error(err: notify::Error) -> XTagError {
+    XTagError::Watch(err.to_string())
+}
+
+impl TagWatcher {
+    /// Starts watching all files below root.
+    ///
+    /// Paths of events start with root as given.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Watch if the operating system refuses to watch root
+    /// - Same as walk and get_tags, files without attribute support are handled by options
+    pub fn new(root: &Path, options: &WalkOptions) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+        // Watch before reading the tags, so no change in between is missed
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .map_err(watch_error)?;
+        let mut known = HashMap::new();
+        let tree = TreeTags::new(root, options);
+        for path in walk(root, options) {
+            let path = path?;
+            if is_index_file(root, &path) {
+                continue;
+            }
+            if let Some(tags) = tree.get(&path)? {
+                known.insert(path, tags);
+            }
+        }
+        Ok(TagWatcher {
+            root: root.to_path_buf(),
+            canonical_root: fs::canonicalize(root)?,
+            options: options.clone(),
+            tree,
+            known,
+            receiver,
+            _watcher: watcher,
+        })
+    }
+
+    /// Waits up to timeout for changes and returns them, empty if there were none.
+    ///
+    /// Changes reported together by the operating system are returned together, a file changed
+    /// several times gives one event.
+    ///
+    /// # Errors
+    ///
+    /// - XTagError::Watch if the operating system reports an error, like too many watches
+    /// - Same as get_tags
+    pub fn poll(&mut self, timeout: Duration) -> Result<Vec<TagEvent>> {
+        let first = match self.receiver.recv_timeout(timeout) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => return Ok(Vec::new()),
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(XTagError::Watch("watcher stopped".to_string()))
+            }
+        };
+        // Changed paths in order, with whether directories have to be walked
+        let mut changed: Vec<(PathBuf, bool)> = Vec::new();
+        for event in std::iter::once(first).chain(self.receiver.try_iter()) {
+            let event = event.map_err(watch_error)?;
+            let appeared = matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+            );
+            for path in event.paths {
+                let path = self.local(&path);
+                match changed.iter_mut().find(|(known, _)| *known == path) {
+                    Some((_, walk_dir)) => *walk_dir |= appeared,
+                    None => changed.push((path, appeared)),
+                }
+            }
+        }
+        let mut events = Vec::new();
+        for (path, walk_dir) in changed {
+            self.check(&path, walk_dir, &mut events)?;
+        }
+        Ok(events)
+    }
+
+    /// Waits for changes like poll and brings index up to date with them.
+    ///
+    /// The index isn't saved, so callers decide how often to write it.
+    ///
+    /// # Errors
+    ///
+    /// Same as poll and Index::apply_events
+    pub fn sync(&mut self, index: &mut Index, timeout: Duration) -> Result<Vec<TagEvent>> {
+        let events = self.poll(timeout)?;
+        index.apply_events(&events)?;
+        Ok(events)
+    }
+
+    // Path of event below root as given, the operating system reports canonical paths
+    fn local(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.canonical_root) {
+            Ok(relative) => self.root.join(relative),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+
+    fn is_skipped(&self, path: &Path) -> bool {
+        if is_index_file(&self.root, path) {
+            return true;
+        }
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        self.options.skip_hidden
+            && relative
+                .components()
+                .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+    }
+
+    fn check(&mut self, path: &Path, walk_dir: bool, events: &mut Vec<TagEvent>) -> Result<()> {
+        if self.is_skipped(path) {
+            return Ok(());
+        }
+        let metadata = match self.options.follow_symlinks {
+            true => fs::metadata(path),
+            false => fs::symlink_metadata(path),
+        };
+        match metadata {
+            Err(_) => self.remove_below(path, events),
+            // Directories only matter when they appear with files inside
+            Ok(metadata) if metadata.is_dir() => {
+                if walk_dir {
+                    for file in walk(path, &self.options) {
+                        self.check_file(&file?, events)?;
+                    }
+                }
+            }
+            Ok(_) => self.check_file(path, events)?,
+        }
+        Ok(())
+    }
+
+    fn check_file(&mut self, path: &Path, events: &mut Vec<TagEvent>) -> Result<()> {
+        let tags = match self.tree.get(path) {
+            Ok(Some(tags)) => tags,
+            Ok(None) => return Ok(()),
+            // Removed since the event
+            Err(XTagError::File(err)) if err.kind() == io::ErrorKind::NotFound => {
+                self.remove_below(path, events);
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+        let path = path.to_path_buf();
+        match self.known.insert(path.clone(), tags.clone()) {
+            None => events.push(TagEvent::Created { path, tags }),
+            Some(old) if old != tags => events.push(TagEvent::TagsChanged { path, old, tags }),
+            Some(_) => (),
+        }
+        Ok(())
+    }
+
+    fn remove_below(&mut self, path: &Path, events: &mut Vec<TagEvent>) {
+        let mut removed: Vec<PathBuf> = self
+            .known
+            .keys()
+            .filter(|known| known.starts_with(path))
+            .cloned()
+            .collect();
+        removed.sort();
+        for path in removed {
+            self.known.remove(&path);
+            events.push(TagEvent::Removed { path });
+        }
+    }
+}
+
+impl Index {
+    /// Brings index up to date with events of a TagWatcher.
+    ///
+    /// # Errors
+    ///
+    /// Same as update
+    pub fn apply_events(&mut self, events: &[TagEvent]) -> Result<()> {
+        self.update(events.iter().map(TagEvent::path))
+    }
+}
+
+impl TagCache {
+    /// Forgets tags of the files of events of a TagWatcher.
+    ///
+    /// Changes within the resolution of change times aren't noticed by the cache itself.
+    pub fn apply_events(&mut self, events: &[TagEvent]) {
+        for event in events {
+            self.invalidate(event.path());
+        }
+    }
+}
+
+impl SearchCache {
+    /// Forgets outcomes for the files of events of a TagWatcher, see TagCache::apply_events.
+    pub fn apply_events(&mut self, events: &[TagEvent]) {
+        for event in events {
+            self.invalidate(event.path());
+        }
+    }
+}
+
+impl Daemon {
+    /// Brings tags in memory up to date with events of a TagWatcher.
+    ///
+    /// Events carry the new tags, so no file is read again.
+    pub fn apply_events(&self, events: &[TagEvent]) {
+        let mut known = self.tags.write().unwrap();
+        for event in events {
+            match event {
+                TagEvent::Created { path, tags } | TagEvent::TagsChanged { path, tags, .. } => {
+                    known.insert(path.clone(), tags.clone());
+                }
+                TagEvent::Removed { path } => {
+                    known.remove(path);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    use super::{TagEvent, TagWatcher};
+    use crate::{
+        compile_search, csl_to_map, set_tags, Daemon, Fixture, Index, TagCache, WalkOptions,
+    };
+
+    #[test]
+    fn watcher_reports_tag_changes_and_updates_index() {
+        let fixture = Fixture::new()
+            .file("a")
+            .tags("photo")
+            .file("sub/b")
+            .tags("photo");
+        let options = WalkOptions::default();
+        let mut index = Index::build(fixture.path(), &options).unwrap();
+        let daemon = Daemon::new(fixture.path(), &options).unwrap();
+        let mut cache = TagCache::new(10);
+        cache.get_tags(&fixture.join("a")).unwrap();
+        let mut watcher = TagWatcher::new(fixture.path(), &options).unwrap();
+
+        set_tags(&fixture.join("a"), &csl_to_map("video").unwrap()).unwrap();
+        fs::write(fixture.join("sub/b"), "content only").unwrap();
+        fs::write(fixture.join("c"), "").unwrap();
+        set_tags(&fixture.join("c"), &csl_to_map("photo").unwrap()).unwrap();
+        fs::remove_dir_all(fixture.join("sub")).unwrap();
+
+        let mut events = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while events.len() < 3 && Instant::now() < deadline {
+            events.extend(
+                watcher
+                    .sync(&mut index, Duration::from_millis(200))
+                    .unwrap(),
+            );
+        }
+        let a_changed = TagEvent::TagsChanged {
+            path: fixture.join("a"),
+            old: csl_to_map("photo").unwrap(),
+            tags: csl_to_map("video").unwrap(),
+        };
+        assert!(events.contains(&a_changed), "{events:?}");
+        let b_removed = TagEvent::Removed {
+            path: fixture.join("sub/b"),
+        };
+        assert!(events.contains(&b_removed), "{events:?}");
+        assert!(events.iter().any(
+            |event| matches!(event, TagEvent::Created { .. } if event.path() == fixture.join("c"))
+        ));
+        assert!(!events.iter().any(
+            |event| matches!(event, TagEvent::TagsChanged { path, .. } if path.ends_with("b"))
+        ));
+
+        let result = index.query(&compile_search("photo").unwrap());
+        assert_eq!(result.paths(), [fixture.join("c")]);
+        daemon.apply_events(&events);
+        cache.apply_events(&events);
+        assert!(cache.is_empty());
+        assert_eq!(daemon.search("photo").unwrap().paths(), [fixture.join("c")]);
+    }
+}
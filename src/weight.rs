@@ -0,0 +1,207 @@
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+use crate::visit::Evaluator;
+use crate::{get_tags, Result, Searcher, XTags};
+
+/// Weight of tags without annotation.
+pub const DEFAULT_WEIGHT: f64 = 1.0;
+
+/// Splits weight annotation like `#0.8` off tag or value.
+///
+/// Returns the plain tag or value and the weight if there is a valid one. Only the last `#` and a
+/// number at the end count as weight. A value ending in `#` and a number of its own, like
+/// `bug#12`, has to be written with a weight to keep it, like `bug#12#1` from with_weight. The
+/// weight of a tag without value is part of its key in XTags, like `classic#2`, functions
+/// comparing tags by name like remove_tags and TagStats leave it out.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(xtag::split_weight("noir#0.8"), ("noir", Some(0.8)));
+/// assert_eq!(xtag::split_weight("noir"), ("noir", None));
+/// assert_eq!(xtag::split_weight("bug#12#1"), ("bug#12", Some(1.0)));
+/// ```
+pub fn split_weight(string: &str) -> (&str, Option<f64>) {
+    if let Some((plain, weight)) = string.rsplit_once('#') {
+        let is_number = !weight.is_empty()
+            && !weight.starts_with('.')
+            && weight.bytes().all(|b| b.is_ascii_digit() || b == b'.');
+        if let (true, Ok(weight)) = (is_number, weight.parse()) {
+            return (plain, Some(weight));
+        }
+    }
+    (string, None)
+}
+
+/// Appends weight annotation to tag or value.
+///
+/// Also keeps a `#` and number at the end of a value, see split_weight.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(xtag::with_weight("noir", 0.8), "noir#0.8");
+/// assert_eq!(xtag::with_weight("noir", 2.0), "noir#2");
+/// ```
+pub fn with_weight(string: &str, weight: f64) -> String {
+    format!("{string}#{weight}")
+}
+
+// Weight of one entry, annotated at the value or, for tags without value, at the tag
+fn entry_weight(tag: &str, value: &Option<String>) -> f64 {
+    let weight = match value {
+        Some(value) => split_weight(value).1,
+        None => split_weight(tag).1,
+    };
+    weight.unwrap_or(DEFAULT_WEIGHT)
+}
+
+// Scores of evaluated nodes, None for nodes not matching. Both operands of or are evaluated, so
+// the better one counts.
+#[derive(Default)]
+struct Scorer {
+    // Number of scores before each entered node, the scores after it belong to its operands
+    starts: Vec<usize>,
+    scores: Vec<Option<f64>>,
+    term: f64,
+}
+
+impl Evaluator for Scorer {
+    fn short_circuit(&self) -> bool {
+        false
+    }
+
+    fn enter(&mut self, _: usize, _: &Searcher) {
+        self.starts.push(self.scores.len());
+    }
+
+    fn term(&mut self, _: usize, term: &Searcher, tags: &XTags) -> bool {
+        let matched = term.is_match(tags);
+        // All other terms match if one single tag does
+        if matched {
+            self.term = term.term_score(tags);
+        }
+        matched
+    }
+
+    fn leave(&mut self, _: usize, node: &Searcher, matched: bool) {
+        let start = self.starts.pop().expect("node was entered");
+        let operands = self.scores.split_off(start);
+        let score = match node {
+            _ if !matched => None,
+            Searcher::And { .. } => Some(operands.iter().flatten().sum()),
+            Searcher::Or { .. } => operands.into_iter().flatten().reduce(f64::max),
+            Searcher::Not { .. } => Some(0.0),
+            _ if operands.is_empty() => Some(std::mem::take(&mut self.term)),
+            _ => operands[0],
+        };
+        self.scores.push(score);
+    }
+}
+
+impl Searcher {
+    /// Returns relevance of tags for this Searcher, None if they don't match.
+    ///
+    /// Terms matching single tags score the highest weight among their matching tags. And adds
+    /// the scores of its operands, or takes the better one and not always scores 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let search = xtag::compile_search("theme == noir or b-movie").unwrap();
+    /// let tags = xtag::csl_to_map("theme=noir#0.8,b-movie#0.3").unwrap();
+    /// assert_eq!(search.score(&tags), Some(0.8));
+    /// ```
+    pub fn score(&self, tags: &XTags) -> Option<f64> {
+        let mut scorer = Scorer::default();
+        self.evaluate_with(tags, &mut scorer);
+        scorer.scores.pop().flatten()
+    }
+
+    // Highest weight among the tags matching term on their own
+    fn term_score(&self, tags: &XTags) -> f64 {
+        tags.iter()
+            .filter(|(tag, value)| {
+                let single: XTags = [(tag.to_string(), Option::clone(value))].into();
+                self.is_match(&single)
+            })
+            .map(|(tag, value)| entry_weight(tag, value))
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Ranks files by their score for searcher, best first.
+///
+/// Files not matching are left out. Ties keep the order of paths.
+pub fn rank<I, P>(searcher: &Searcher, paths: I) -> Result<Vec<(PathBuf, f64)>>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut ranked = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        if let Some(score) = searcher.score(&get_tags(path)?) {
+            ranked.push((path.to_path_buf(), score));
+        }
+    }
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    Ok(ranked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_weight, with_weight, DEFAULT_WEIGHT};
+    use crate::{
+        add_tags, compile_search, csl_to_map, get_tags, map_to_csl, remove_tags, set_tags, Fixture,
+        TagStats, XTags,
+    };
+
+    #[test]
+    fn weights_are_parsed_and_ignored_by_matching() {
+        assert_eq!(split_weight("a#1"), ("a", Some(1.0)));
+        assert_eq!(split_weight("a#"), ("a#", None));
+        assert_eq!(split_weight("a#.5"), ("a#.5", None));
+        let tags = csl_to_map("theme=noir#0.5,classic#2,year=1950#0.1").unwrap();
+        assert_eq!(tags.get("theme"), Some(&Some("noir#0.5".to_string())));
+        let search = compile_search("theme == noir and classic and year < 1960").unwrap();
+        assert_eq!(search.score(&tags), Some(2.6));
+        assert!(compile_search("other").unwrap().score(&tags).is_none());
+        assert!(csl_to_map("a#b").is_err());
+    }
+
+    #[test]
+    fn valueless_weighted_tags_are_found_by_name() {
+        let fixture = Fixture::new().file("a").tags("classic#2,theme=noir#0.5");
+        let path = fixture.join("a");
+        let stats: TagStats = [get_tags(&path).unwrap()].iter().collect();
+        assert_eq!(stats.tag("classic").unwrap().files, 1);
+        assert_eq!(stats.tag("theme").unwrap().values.get("noir"), Some(&1));
+
+        add_tags(&path, &csl_to_map("classic#3").unwrap()).unwrap();
+        assert_eq!(
+            get_tags(&path).unwrap(),
+            csl_to_map("classic#3,theme=noir#0.5").unwrap()
+        );
+        remove_tags(&path, &["classic"]).unwrap();
+        assert_eq!(
+            get_tags(&path).unwrap(),
+            csl_to_map("theme=noir#0.5").unwrap()
+        );
+    }
+
+    #[test]
+    fn values_keep_hashes_with_explicit_weight() {
+        let fixture = Fixture::new().file("a");
+        let path = fixture.join("a");
+        let value = with_weight("bug#12", DEFAULT_WEIGHT);
+        let tags: XTags = [("ticket".to_string(), Some(value))].into();
+        set_tags(&path, &tags).unwrap();
+        assert_eq!(map_to_csl(&get_tags(&path).unwrap()), "ticket=bug#12#1");
+        let search = compile_search("ticket == bug.12").unwrap();
+        assert_eq!(search.score(&get_tags(&path).unwrap()), Some(1.0));
+        let stats: TagStats = [get_tags(&path).unwrap()].iter().collect();
+        assert!(stats.tag("ticket").unwrap().values.contains_key("bug#12"));
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::bool_assert_comparison, clippy::single_component_path_imports)]
+
 use xtag;
 
 fn find_in_string(term: &str, string: &str) -> bool {